@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use bytes::Bytes;
+use mini_redis_tls::db::DEFAULT_SHARDS;
+use mini_redis_tls::Db;
+
+/// Operations each thread runs per iteration, enough to hide the cost of
+/// spawning the threads.
+const OPS_PER_THREAD: usize = 2000;
+
+/// Distinct keys each thread cycles through.
+const KEYS_PER_THREAD: usize = 1024;
+
+/// Runs `OPS_PER_THREAD` operations on each of `keys.len()` threads at once.
+/// Every `write_every`th operation is a SET, the rest are GETs.
+fn run(db: &Db, keys: &[Vec<String>], value: &Bytes, write_every: usize) {
+    std::thread::scope(|scope| {
+        for keys in keys {
+            scope.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    let key = &keys[i % keys.len()];
+                    if i % write_every == 0 {
+                        db.set(key.clone(), value.clone());
+                    } else {
+                        std::hint::black_box(db.get(key));
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn contention_benchmark(c: &mut Criterion) {
+    let value = Bytes::from("benchmark_value");
+    let workloads = [("set", 1), ("mixed_80_get_20_set", 5)];
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+
+    for (name, write_every) in workloads {
+        let mut group = c.benchmark_group(format!("contention_{}", name));
+
+        for threads in [1, 4, 8, parallelism.max(8) * 2] {
+            let keys: Vec<Vec<String>> = (0..threads)
+                .map(|t| (0..KEYS_PER_THREAD).map(|i| format!("key:{}:{}", t, i)).collect())
+                .collect();
+            group.throughput(Throughput::Elements((threads * OPS_PER_THREAD) as u64));
+
+            // One shard is the old single `RwLock` around the whole keyspace
+            for shards in [1, DEFAULT_SHARDS] {
+                // Populated up front so GETs hit
+                let db = Db::with_shards(shards);
+                run(&db, &keys, &value, 1);
+
+                group.bench_with_input(
+                    BenchmarkId::new(format!("{}_shards", shards), threads),
+                    &keys,
+                    |b, keys| b.iter(|| run(&db, keys, &value, write_every)),
+                );
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, contention_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use bytes::{Buf, BytesMut};
+use mini_redis_tls::codec::Decoder;
+use std::io::Cursor;
+
+/// `*3\r\n$3\r\nSET\r\n$<n>\r\n<key>\r\n$<n>\r\n<value>\r\n`
+fn set_command(key: &str, value_len: usize) -> Vec<u8> {
+    let mut buf = format!("*3\r\n$3\r\nSET\r\n${}\r\n{}\r\n${}\r\n", key.len(), key, value_len).into_bytes();
+    buf.resize(buf.len() + value_len, b'x');
+    buf.extend_from_slice(b"\r\n");
+    buf
+}
+
+/// Today's parser as `Connection::parse_frame` drives it: every read checks
+/// the whole buffer for a complete frame, then parses it a second time.
+fn check_then_parse(buf: &mut BytesMut) -> Option<mini_redis::Frame> {
+    let mut cursor = Cursor::new(&buf[..]);
+    match mini_redis::check(&mut cursor) {
+        Ok(()) => {
+            let len = cursor.position() as usize;
+            cursor.set_position(0);
+            let frame = mini_redis::parse(&mut cursor).unwrap();
+            buf.advance(len);
+            Some(frame)
+        }
+        Err(mini_redis::Error::Incomplete) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Feeds `input` to `decode` in reads of `chunk` bytes, like a socket
+/// delivering it, and returns the number of frames decoded.
+fn feed<F, T>(input: &[u8], chunk: usize, mut decode: F) -> usize
+where
+    F: FnMut(&mut BytesMut) -> Option<T>,
+{
+    let mut buf = BytesMut::with_capacity(4096);
+    let mut frames = 0;
+    for piece in input.chunks(chunk) {
+        buf.extend_from_slice(piece);
+        while decode(&mut buf).is_some() {
+            frames += 1;
+        }
+    }
+    frames
+}
+
+fn parse_benchmark(c: &mut Criterion) {
+    let pipeline: Vec<u8> = (0..100).flat_map(|i| set_command(&format!("key:{}", i), 16)).collect();
+    let cases = [
+        ("pipeline_100_small_sets", pipeline, 100),
+        ("set_64kb", set_command("key", 64 * 1024), 1),
+        ("set_1mb", set_command("key", 1024 * 1024), 1),
+    ];
+
+    for (name, input, expected) in &cases {
+        let mut group = c.benchmark_group(*name);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        // Everything already buffered, and the same input arriving in 16 KiB
+        // reads, where frames larger than a read are retried repeatedly
+        for chunk in [input.len(), 16 * 1024] {
+            let label = if chunk == input.len() { "buffered".to_string() } else { format!("{}b_reads", chunk) };
+
+            group.bench_with_input(BenchmarkId::new("check_then_parse", &label), input, |b, input| {
+                b.iter(|| assert_eq!(feed(input, chunk, check_then_parse), *expected))
+            });
+            group.bench_with_input(BenchmarkId::new("incremental", &label), input, |b, input| {
+                b.iter(|| {
+                    let mut decoder = Decoder::new();
+                    assert_eq!(feed(input, chunk, |buf| decoder.decode(buf).unwrap()), *expected)
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse_benchmark);
+criterion_main!(benches);
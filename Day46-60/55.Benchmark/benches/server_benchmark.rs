@@ -0,0 +1,122 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use bytes::Bytes;
+use mini_redis_tls::{server, Client, Config, FsyncPolicy};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+
+/// Requests each client sends per iteration.
+const OPS_PER_CLIENT: usize = 200;
+
+/// Distinct keys each client cycles through.
+const KEYS_PER_CLIENT: usize = 64;
+
+/// Starts a server on a free port, logging to a fresh AOF as a production
+/// server would, and returns its address along with the handle that shuts it
+/// down.
+async fn start_server(name: &str) -> (String, broadcast::Sender<()>) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = std::env::temp_dir();
+    let aof = dir.join(format!("mini-redis-bench-{}-{}.aof", name, std::process::id()));
+    let rdb = dir.join(format!("mini-redis-bench-{}-{}.rdb", name, std::process::id()));
+    let _ = std::fs::remove_file(&aof);
+    let _ = std::fs::remove_file(&rdb);
+
+    let config = Config {
+        port,
+        appendfilename: aof.to_str().unwrap().to_string(),
+        dbfilename: rdb.to_str().unwrap().to_string(),
+        appendfsync: FsyncPolicy::EverySec,
+        // Keep the AOF growing, so every run appends the same way
+        auto_aof_rewrite_percentage: 0,
+        ..Config::default()
+    };
+    let addr = config.addr();
+
+    let (tx, rx) = broadcast::channel(1);
+    tokio::spawn(async move {
+        server::run(config, rx).await.unwrap();
+    });
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(&addr).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    (addr, tx)
+}
+
+/// Sends `OPS_PER_CLIENT` requests on each connection at once and waits for
+/// every reply. Every `write_every`th request is a SET, the rest are GETs.
+async fn run(clients: Vec<(Client, Vec<String>)>, value: &Bytes, write_every: usize) -> Vec<(Client, Vec<String>)> {
+    let tasks: Vec<_> = clients
+        .into_iter()
+        .map(|(mut client, keys)| {
+            let value = value.clone();
+            tokio::spawn(async move {
+                for i in 0..OPS_PER_CLIENT {
+                    let key = &keys[i % keys.len()];
+                    if i % write_every == 0 {
+                        client.set(key, value.clone()).await.unwrap();
+                    } else {
+                        std::hint::black_box(client.get(key).await.unwrap());
+                    }
+                }
+                (client, keys)
+            })
+        })
+        .collect();
+
+    let mut clients = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        clients.push(task.await.unwrap());
+    }
+    clients
+}
+
+/// Many clients writing to a server at once, through the whole request path:
+/// parsing, ACL checks, applying to the sharded keyspace and appending to the
+/// AOF. Unlike `contention_benchmark`, this shows whether writes to different
+/// shards really run side by side once the server is involved.
+fn server_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let value = Bytes::from("benchmark_value");
+    let workloads = [("set", 1), ("mixed_80_get_20_set", 5)];
+
+    for (name, write_every) in workloads {
+        let mut group = c.benchmark_group(format!("server_{}", name));
+        group.sample_size(20);
+        let (addr, shutdown) = runtime.block_on(start_server(name));
+
+        for connections in [1, 8, 32, 128] {
+            let mut clients = Some(runtime.block_on(async {
+                let mut clients = Vec::with_capacity(connections);
+                for c in 0..connections {
+                    let keys = (0..KEYS_PER_CLIENT).map(|i| format!("key:{}:{}", c, i)).collect();
+                    clients.push((Client::connect(&addr).await.unwrap(), keys));
+                }
+                // Populated up front so GETs hit
+                run(clients, &value, 1).await
+            }));
+            group.throughput(Throughput::Elements((connections * OPS_PER_CLIENT) as u64));
+
+            group.bench_function(BenchmarkId::new("clients", connections), |b| {
+                b.iter_custom(|iters| {
+                    runtime.block_on(async {
+                        let start = Instant::now();
+                        for _ in 0..iters {
+                            clients = Some(run(clients.take().unwrap(), &value, write_every).await);
+                        }
+                        start.elapsed()
+                    })
+                })
+            });
+        }
+
+        let _ = shutdown.send(());
+        group.finish();
+    }
+}
+
+criterion_group!(benches, server_benchmark);
+criterion_main!(benches);
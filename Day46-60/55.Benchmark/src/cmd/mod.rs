@@ -6,10 +6,10 @@ pub mod unknown;
 pub mod into_frame;
 
 use crate::{Frame, Error};
-use self::get::Get;
-use self::set::Set;
-use self::publish::Publish;
-use self::subscribe::Subscribe;
+pub use self::get::Get;
+pub use self::set::Set;
+pub use self::publish::Publish;
+pub use self::subscribe::Subscribe;
 use self::unknown::Unknown;
 
 #[derive(Debug, Clone)]
@@ -1,3 +1,13 @@
+//! A single-process stand-in for Sentinel: one ping per interval, and on the
+//! first failure it points its own `master_addr` at the next replica. There
+//! is no quorum, the replicas are not reconfigured, and clients must share
+//! the `SentinelService` to learn the master.
+//!
+//! The real thing — sentinels gossiping over pub/sub, SDOWN/ODOWN with a
+//! quorum, leader election, `REPLICAOF NO ONE` for the promoted replica and
+//! `SENTINEL get-master-addr-by-name` for clients — is built on top of
+//! replication in `58.TLS/src/sentinel.rs`.
+
 use crate::Client;
 use tokio::sync::Mutex;
 use std::sync::Arc;
@@ -1,7 +1,7 @@
-use mini_redis_tls::{server, Client};
+use mini_redis_tls::{server, Client, Config};
 use tokio::sync::broadcast;
-use tokio_rustls::rustls::{ServerConfig, RootCertStore, ClientConfig};
-use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_rustls::rustls::{RootCertStore, ClientConfig};
+use tokio_rustls::TlsConnector;
 use std::sync::Arc;
 use std::fs::File;
 use std::io::BufReader;
@@ -13,27 +13,18 @@ use bytes::Bytes;
 async fn main() -> anyhow::Result<()> {
     // tracing_subscriber::fmt::init();
 
-    // 1. Load Server Certs
-    let cert_file = &mut BufReader::new(File::open("certs/server.cert")?);
-    let key_file = &mut BufReader::new(File::open("certs/server.key")?);
-
-    let cert_chain = certs(cert_file).collect::<Result<Vec<_>, _>>()?;
-
-    // Find the first valid private key
-    let key = rustls_pemfile::private_key(key_file)?
-        .ok_or_else(|| anyhow::anyhow!("No private key found in certs/server.key"))?;
-
-    let server_config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(cert_chain, key.into())?;
-
-    let tls_acceptor = TlsAcceptor::from(Arc::new(server_config));
+    // 1. Point the server at its certificate and key
+    let config = Config {
+        tls_cert_file: "certs/server.cert".to_string(),
+        tls_key_file: "certs/server.key".to_string(),
+        ..Config::default()
+    };
 
     // 2. Start Server
     let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
 
     tokio::spawn(async move {
-        if let Err(e) = server::run("127.0.0.1:6379", "appendonly.aof", shutdown_rx, Some(tls_acceptor)).await {
+        if let Err(e) = server::run(config, shutdown_rx).await {
             eprintln!("Server error: {:?}", e);
         }
     });
@@ -0,0 +1,483 @@
+//! Redis 6 style access control lists: users with passwords, the commands
+//! they may run and the keys they may touch.
+//!
+//! Users are described by the same rules `ACL SETUSER` takes, e.g.
+//! `on >secret ~cache:* +@read -keys`. Rules are applied left to right. The
+//! ACL file holds one `user <name> <rules...>` line per user, as printed by
+//! `ACL LIST`.
+//!
+//! Passwords are only kept as SHA-256 hashes, so the file never contains
+//! them in clear.
+
+use crate::glob;
+use anyhow::Context;
+use aws_lc_rs::digest::{digest, SHA256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Every command with its categories. Permissions are checked against the
+/// name the command was invoked with, so aliases such as `incr` are listed
+/// on their own. `command|subcommand` entries can be allowed separately
+/// from the rest of their command.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("get", &["read", "string", "fast"]),
+    ("set", &["write", "string", "slow"]),
+    ("publish", &["pubsub", "fast"]),
+    ("subscribe", &["pubsub", "slow"]),
+    ("psubscribe", &["pubsub", "slow"]),
+    ("unsubscribe", &["pubsub", "slow"]),
+    ("punsubscribe", &["pubsub", "slow"]),
+    ("pubsub", &["pubsub", "slow"]),
+    ("ping", &["connection", "fast"]),
+    ("expire", &["write", "keyspace", "fast"]),
+    ("pexpire", &["write", "keyspace", "fast"]),
+    ("expireat", &["write", "keyspace", "fast"]),
+    ("pexpireat", &["write", "keyspace", "fast"]),
+    ("ttl", &["read", "keyspace", "fast"]),
+    ("pttl", &["read", "keyspace", "fast"]),
+    ("persist", &["write", "keyspace", "fast"]),
+    ("bgrewriteaof", &["admin", "slow", "dangerous"]),
+    ("save", &["admin", "slow", "dangerous"]),
+    ("bgsave", &["admin", "slow", "dangerous"]),
+    ("del", &["write", "keyspace", "slow"]),
+    ("unlink", &["write", "keyspace", "fast"]),
+    ("exists", &["read", "keyspace", "fast"]),
+    ("type", &["read", "keyspace", "fast"]),
+    ("rename", &["write", "keyspace", "slow"]),
+    ("renamenx", &["write", "keyspace", "fast"]),
+    ("dbsize", &["read", "keyspace", "fast"]),
+    ("flushdb", &["write", "keyspace", "slow", "dangerous"]),
+    ("keys", &["read", "keyspace", "slow", "dangerous"]),
+    ("scan", &["read", "keyspace", "slow"]),
+    ("incr", &["write", "string", "fast"]),
+    ("decr", &["write", "string", "fast"]),
+    ("incrby", &["write", "string", "fast"]),
+    ("decrby", &["write", "string", "fast"]),
+    ("incrbyfloat", &["write", "string", "fast"]),
+    ("append", &["write", "string", "fast"]),
+    ("strlen", &["read", "string", "fast"]),
+    ("mget", &["read", "string", "fast"]),
+    ("mset", &["write", "string", "slow"]),
+    ("msetnx", &["write", "string", "slow"]),
+    ("getset", &["write", "string", "fast"]),
+    ("getdel", &["write", "string", "fast"]),
+    ("setrange", &["write", "string", "slow"]),
+    ("getrange", &["read", "string", "slow"]),
+    ("lpush", &["write", "list", "fast"]),
+    ("rpush", &["write", "list", "fast"]),
+    ("lpop", &["write", "list", "fast"]),
+    ("rpop", &["write", "list", "fast"]),
+    ("blpop", &["write", "list", "slow", "blocking"]),
+    ("brpop", &["write", "list", "slow", "blocking"]),
+    ("lrange", &["read", "list", "slow"]),
+    ("hset", &["write", "hash", "fast"]),
+    ("hget", &["read", "hash", "fast"]),
+    ("hgetall", &["read", "hash", "slow"]),
+    ("hdel", &["write", "hash", "fast"]),
+    ("sadd", &["write", "set", "fast"]),
+    ("srem", &["write", "set", "fast"]),
+    ("smembers", &["read", "set", "slow"]),
+    ("sismember", &["read", "set", "fast"]),
+    ("zadd", &["write", "sortedset", "fast"]),
+    ("zrange", &["read", "sortedset", "slow"]),
+    ("zrank", &["read", "sortedset", "fast"]),
+    ("zscore", &["read", "sortedset", "fast"]),
+    ("xadd", &["write", "stream", "fast"]),
+    ("xlen", &["read", "stream", "fast"]),
+    ("xrange", &["read", "stream", "slow"]),
+    ("xrevrange", &["read", "stream", "slow"]),
+    ("xread", &["read", "stream", "slow", "blocking"]),
+    ("xreadgroup", &["write", "stream", "slow", "blocking"]),
+    ("xgroup", &["write", "stream", "slow"]),
+    ("xack", &["write", "stream", "fast"]),
+    ("xpending", &["read", "stream", "slow"]),
+    ("xclaim", &["write", "stream", "fast"]),
+    ("xsetid", &["write", "stream", "fast"]),
+    ("multi", &["transaction", "fast"]),
+    ("exec", &["transaction", "slow"]),
+    ("discard", &["transaction", "fast"]),
+    ("watch", &["transaction", "fast"]),
+    ("unwatch", &["transaction", "fast"]),
+    ("hello", &["connection", "fast"]),
+    ("auth", &["connection", "fast"]),
+    ("client", &["connection", "slow"]),
+    ("info", &["slow", "dangerous"]),
+    ("config", &["admin", "slow", "dangerous"]),
+    ("acl", &["admin", "slow", "dangerous"]),
+    ("acl|whoami", &["slow"]),
+    ("acl|cat", &["slow"]),
+    ("replicaof", &["admin", "slow", "dangerous"]),
+    ("slaveof", &["admin", "slow", "dangerous"]),
+    ("replconf", &["admin", "slow", "dangerous"]),
+    ("psync", &["admin", "slow", "dangerous"]),
+];
+
+/// The categories `+@<category>` and `-@<category>` accept, besides `all`.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace", "read", "write", "string", "list", "hash", "set", "sortedset", "stream", "pubsub", "admin", "fast",
+    "slow", "blocking", "dangerous", "connection", "transaction",
+];
+
+/// Commands anyone may run before authenticating.
+const NO_AUTH: &[&str] = &["auth", "hello"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    /// Any password is accepted.
+    pub nopass: bool,
+    /// SHA-256 hashes of the accepted passwords.
+    passwords: Vec<[u8; 32]>,
+    commands: BTreeSet<&'static str>,
+    /// Glob patterns of the keys the user may access.
+    keys: Vec<String>,
+}
+
+impl User {
+    /// A new user is disabled and may do nothing until rules say otherwise.
+    pub fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            commands: BTreeSet::new(),
+            keys: vec![],
+        }
+    }
+
+    /// Applies one `ACL SETUSER` rule.
+    pub fn apply(&mut self, rule: &str) -> Result<(), String> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" => self.commands = all_commands(),
+            "nocommands" => self.commands.clear(),
+            "reset" => *self = User::new(&self.name),
+            _ => return self.apply_argument(rule),
+        }
+        Ok(())
+    }
+
+    /// Applies a rule that takes an argument: a password, key pattern,
+    /// command or category.
+    fn apply_argument(&mut self, rule: &str) -> Result<(), String> {
+        let (prefix, arg) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match prefix {
+            ">" => {
+                let hash = hash(arg.as_bytes());
+                if !self.passwords.contains(&hash) {
+                    self.passwords.push(hash);
+                }
+                self.nopass = false;
+            }
+            "<" => self.remove_password(hash(arg.as_bytes()))?,
+            "#" => {
+                let hash = parse_hash(arg)?;
+                if !self.passwords.contains(&hash) {
+                    self.passwords.push(hash);
+                }
+                self.nopass = false;
+            }
+            "!" => self.remove_password(parse_hash(arg)?)?,
+            "~" if !arg.is_empty() => {
+                if !self.keys.iter().any(|pattern| pattern == arg) {
+                    self.keys.push(arg.to_string());
+                }
+            }
+            "+" | "-" => {
+                let commands = match arg.strip_prefix('@') {
+                    Some(category) => category_commands(&category.to_lowercase())?,
+                    None => {
+                        let name = arg.to_lowercase();
+                        let command = COMMANDS
+                            .iter()
+                            .find(|(command, _)| *command == name)
+                            .ok_or("Unknown command or category name in ACL")?;
+                        BTreeSet::from([command.0])
+                    }
+                };
+                if prefix == "+" {
+                    self.commands.extend(commands);
+                } else {
+                    // Denying a command denies its subcommands too
+                    self.commands.retain(|command| {
+                        let parent = command.split_once('|').map_or(*command, |(parent, _)| parent);
+                        !commands.contains(command) && !commands.contains(parent)
+                    });
+                }
+            }
+            _ => return Err("Syntax error".to_string()),
+        }
+        Ok(())
+    }
+
+    fn remove_password(&mut self, hash: [u8; 32]) -> Result<(), String> {
+        let len = self.passwords.len();
+        self.passwords.retain(|password| *password != hash);
+        if self.passwords.len() == len {
+            return Err("The password you are trying to remove from the user does not exist".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        self.nopass || self.passwords.contains(&hash(password))
+    }
+
+    /// Allowing a command allows all its subcommands.
+    pub fn can_run(&self, command: &str) -> bool {
+        self.commands.contains(command)
+            || command.split_once('|').is_some_and(|(parent, _)| self.commands.contains(parent))
+    }
+
+    pub fn can_access(&self, key: &str) -> bool {
+        self.keys.iter().any(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes()))
+    }
+
+    /// Hex encoded hashes of the user's passwords.
+    pub fn password_hashes(&self) -> Vec<String> {
+        self.passwords.iter().map(|hash| hex(hash)).collect()
+    }
+
+    /// The commands the user may run, as the shortest rules that allow them.
+    pub fn command_rules(&self) -> String {
+        if self.commands.is_empty() {
+            return "-@all".to_string();
+        }
+        if self.commands == all_commands() {
+            return "+@all".to_string();
+        }
+        let mut rules = vec!["-@all".to_string()];
+        rules.extend(self.commands.iter().map(|command| format!("+{}", command)));
+        rules.join(" ")
+    }
+
+    /// The key patterns, as `~pattern` rules.
+    pub fn key_rules(&self) -> String {
+        self.keys.iter().map(|pattern| format!("~{}", pattern)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The rules that recreate the user from scratch, as `ACL LIST` shows
+    /// them.
+    pub fn describe(&self) -> String {
+        let mut rules = vec![format!("user {}", self.name), if self.enabled { "on" } else { "off" }.to_string()];
+        if self.nopass {
+            rules.push("nopass".to_string());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hex(hash))));
+        if !self.keys.is_empty() {
+            rules.push(self.key_rules());
+        }
+        rules.push(self.command_rules());
+        rules.join(" ")
+    }
+}
+
+/// All users, by name. There is always a `default` user, which connections
+/// are authenticated as until they `AUTH` as someone else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Acl {
+    users: BTreeMap<String, User>,
+}
+
+impl Default for Acl {
+    fn default() -> Acl {
+        Acl::new()
+    }
+}
+
+impl Acl {
+    /// Only the `default` user, allowed to do anything without a password.
+    pub fn new() -> Acl {
+        let mut default = User::new("default");
+        for rule in ["on", "nopass", "allkeys", "allcommands"] {
+            default.apply(rule).unwrap();
+        }
+        Acl { users: BTreeMap::from([("default".to_string(), default)]) }
+    }
+
+    /// Reads an ACL file. Users it doesn't define, other than `default`, don't
+    /// exist.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Acl> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut acl = Acl::new();
+        let mut defined = BTreeSet::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, e);
+
+            let mut words = line.split_whitespace();
+            let (Some("user"), Some(name)) = (words.next(), words.next()) else {
+                return Err(error("expected 'user <name> <rules...>'".to_string()));
+            };
+            if !defined.insert(name.to_string()) {
+                return Err(error(format!("duplicate user '{}'", name)));
+            }
+            let rules: Vec<String> = std::iter::once("reset").chain(words).map(str::to_string).collect();
+            acl.set_user(name, &rules).map_err(error)?;
+        }
+        Ok(acl)
+    }
+
+    /// Writes every user to an ACL file, replacing it atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let mut contents = self.list().join("\n");
+        contents.push('\n');
+        let temp_path = path.with_extension("acl.tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, path)
+    }
+
+    /// Creates the user if needed and applies `rules` to it. Either every
+    /// rule applies or, if one is invalid, none does.
+    pub fn set_user(&mut self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut user = self.users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply(rule).map_err(|e| format!("Error in ACL SETUSER modifier '{}': {}", rule, e))?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    /// Deletes the users, returning how many existed.
+    pub fn del_users(&mut self, names: &[String]) -> Result<usize, String> {
+        if names.iter().any(|name| name == "default") {
+            return Err("The 'default' user cannot be removed".to_string());
+        }
+        Ok(names.iter().filter(|name| self.users.remove(name.as_str()).is_some()).count())
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.users.keys().cloned().collect()
+    }
+
+    /// Every user, as an `ACL LIST` line.
+    pub fn list(&self) -> Vec<String> {
+        self.users.values().map(User::describe).collect()
+    }
+
+    /// Makes the `default` user require `password`, or no password at all if
+    /// it's empty, like the `requirepass` option.
+    pub fn set_requirepass(&mut self, password: &str) {
+        let rules = match password {
+            "" => vec!["nopass".to_string()],
+            password => vec!["resetpass".to_string(), format!(">{}", password)],
+        };
+        self.set_user("default", &rules).unwrap();
+    }
+
+    /// The user new connections are authenticated as, if `default` needs no
+    /// password.
+    pub fn initial_user(&self) -> Option<String> {
+        let default = &self.users["default"];
+        (default.enabled && default.nopass).then(|| default.name.clone())
+    }
+
+    /// Returns `true` if `password` is one of the enabled user's passwords.
+    pub fn authenticate(&self, username: &str, password: &[u8]) -> bool {
+        self.users.get(username).is_some_and(|user| user.enabled && user.check_password(password))
+    }
+
+    /// Checks that the connection is authenticated as an existing user.
+    /// Commands the server doesn't know only need this, so they are reported
+    /// as unknown.
+    pub fn check_authenticated(&self, user: Option<&str>) -> Result<(), String> {
+        self.authenticated(user).map(|_| ())
+    }
+
+    fn authenticated(&self, user: Option<&str>) -> Result<&User, String> {
+        user.and_then(|name| self.users.get(name)).ok_or_else(|| "NOAUTH Authentication required.".to_string())
+    }
+
+    /// Checks that `user` may run `command` on `keys`. Returns the error to
+    /// reply with if not.
+    pub fn check(&self, user: Option<&str>, command: &str, keys: &[&str]) -> Result<(), String> {
+        if NO_AUTH.contains(&command) {
+            return Ok(());
+        }
+        let user = self.authenticated(user)?;
+        // A command missing from the table can't have been granted, so it is
+        // denied rather than let through
+        if !COMMANDS.iter().any(|(name, _)| *name == command) || !user.can_run(command) {
+            return Err(format!("NOPERM User {} has no permissions to run the '{}' command", user.name, command));
+        }
+        if !keys.iter().all(|key| user.can_access(key)) {
+            return Err("NOPERM No permissions to access a key".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The name permissions are checked against: `command|subcommand` if the
+/// subcommand has its own entry, otherwise the lowercase command name.
+pub fn command_id(name: &str, first_arg: Option<&str>) -> String {
+    let name = name.to_lowercase();
+    if let Some(arg) = first_arg {
+        let id = format!("{}|{}", name, arg.to_lowercase());
+        if COMMANDS.iter().any(|(command, _)| *command == id) {
+            return id;
+        }
+    }
+    name
+}
+
+/// The commands in a category, for `ACL CAT <category>` and `+@<category>`.
+pub fn category_commands(category: &str) -> Result<BTreeSet<&'static str>, String> {
+    if category == "all" {
+        return Ok(all_commands());
+    }
+    if !CATEGORIES.contains(&category) {
+        return Err("Unknown command or category name in ACL".to_string());
+    }
+    Ok(COMMANDS
+        .iter()
+        .filter(|(_, categories)| categories.contains(&category))
+        .map(|(name, _)| *name)
+        .collect())
+}
+
+fn all_commands() -> BTreeSet<&'static str> {
+    COMMANDS.iter().map(|(name, _)| *name).collect()
+}
+
+fn hash(password: &[u8]) -> [u8; 32] {
+    digest(&SHA256, password).as_ref().try_into().unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hash(hex: &str) -> Result<[u8; 32], String> {
+    let invalid = || "The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string();
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return Err(invalid());
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
@@ -1,60 +1,578 @@
 use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncWriteExt, BufWriter};
-use crate::{Command, Frame};
-use std::path::Path;
-use async_recursion::async_recursion;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use crate::{codec, rdb, Command, Db, Frame, Protocol};
+use crate::replication::Replication;
+use crate::codec::Decoder;
+use crate::cmd::expire::Expiry;
+use crate::db::{Entry, SetCondition, Value};
+use crate::stream::{ClaimOptions, NewId, Stream, StreamId};
+use crate::cmd::set::Set;
+use crate::cmd::expire::Expire;
+use crate::cmd::list::Push;
+use crate::cmd::hash::HSet;
+use crate::cmd::sets::SAdd;
+use crate::cmd::zset::ZAdd;
+use crate::cmd::stream::{XAdd, XClaim, XGroupCommand, XSetId};
+use crate::cmd::transaction::{Exec, Multi};
+use crate::cmd::keys::FlushDb;
+use bytes::BytesMut;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use tracing::{info, warn, error};
+
+/// When appended commands are forced to disk, mirroring Redis' `appendfsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FsyncPolicy {
+    /// Flush and fsync after every command. Slowest, but an acknowledged
+    /// write is never lost.
+    Always,
+    /// Buffer commands in memory and flush + fsync them once per second from
+    /// a background task. At most about one second of writes can be lost.
+    #[default]
+    EverySec,
+    /// Hand every command to the OS but never fsync; the kernel decides when
+    /// data reaches the disk.
+    No,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(FsyncPolicy::Always),
+            "everysec" => Ok(FsyncPolicy::EverySec),
+            "no" => Ok(FsyncPolicy::No),
+            _ => Err(anyhow::anyhow!("invalid appendfsync policy '{}', expected always, everysec or no", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for FsyncPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsyncPolicy::Always => write!(f, "always"),
+            FsyncPolicy::EverySec => write!(f, "everysec"),
+            FsyncPolicy::No => write!(f, "no"),
+        }
+    }
+}
 
 pub struct Aof {
     writer: BufWriter<File>,
+    path: PathBuf,
+    fsync: FsyncPolicy,
+    /// Set when commands have been written since the last fsync.
+    dirty: bool,
+    /// Current size of the file in bytes.
+    size: u64,
+    /// Size of the file after startup or the last rewrite, used as the
+    /// baseline for the automatic rewrite trigger.
+    base_size: u64,
+    /// Trigger a rewrite once the file has grown by this many percent over
+    /// `base_size`. Zero disables automatic rewrites.
+    auto_rewrite_percentage: u64,
+    /// Never trigger an automatic rewrite for files smaller than this.
+    auto_rewrite_min_size: u64,
+    /// Frames appended while a rewrite is in progress. They are copied to the
+    /// new file before it replaces the current one.
+    rewrite_buffer: Option<Vec<Frame>>,
+    /// Start rewritten files with a binary snapshot instead of one SET per key.
+    use_rdb_preamble: bool,
+    /// Where appended writes are streamed to replicas.
+    replication: Option<Arc<Replication>>,
 }
 
 impl Aof {
+    /// Replays the AOF at `path` into `db`, returning the number of commands applied.
+    ///
+    /// A missing file is treated as an empty log. A file produced by a rewrite
+    /// may start with a binary snapshot (see `rdb`), which is loaded first.
+    /// If the file ends with an incomplete frame (e.g. the server crashed
+    /// mid-write), the file is truncated back to the last complete frame when
+    /// `load_truncated` is set, otherwise loading fails so the operator can
+    /// inspect the file. The commands of a `MULTI` / `EXEC` block are only
+    /// applied once its `EXEC` has been read; a block cut short counts as an
+    /// incomplete tail.
+    pub async fn load(path: impl AsRef<Path>, db: &Db, load_truncated: bool) -> anyhow::Result<usize> {
+        let path = path.as_ref();
+        let mut file = match File::open(path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut buffer = BytesMut::with_capacity(4096);
+        let mut decoder = Decoder::new();
+        // Offset just past the last frame that was applied successfully.
+        let mut offset: u64 = 0;
+        let mut applied = 0;
+        // Commands of an open `MULTI` block
+        let mut transaction: Option<Vec<Command>> = None;
+
+        if let Some(len) = load_preamble(path, db).await? {
+            file.seek(SeekFrom::Start(len)).await?;
+            offset = len;
+        }
+        // Offset of the first byte the decoder hasn't consumed, and where the
+        // frame it is decoding starts
+        let mut position = offset;
+        let mut frame_start = offset;
+
+        loop {
+            loop {
+                let before = buffer.len();
+                let frame = decoder
+                    .decode(&mut buffer)
+                    .map_err(|e| anyhow::anyhow!("corrupt AOF {} at offset {}: {}", path.display(), frame_start, e))?;
+                position += (before - buffer.len()) as u64;
+                let Some(frame) = frame else { break };
+
+                let command = Command::from_frame(frame)
+                    .map_err(|e| anyhow::anyhow!("corrupt AOF {} at offset {}: {}", path.display(), frame_start, e))?;
+                match (command, &mut transaction) {
+                    (Command::Multi(_), None) => transaction = Some(vec![]),
+                    (Command::Exec(_), Some(_)) => {
+                        let commands = transaction.take().unwrap_or_default();
+                        applied += commands.len();
+                        for command in commands {
+                            command.apply(db);
+                        }
+                        offset = position;
+                    }
+                    (command, Some(commands)) if command.is_write() => commands.push(command),
+                    (command, None) if command.is_write() => {
+                        command.apply(db);
+                        offset = position;
+                        applied += 1;
+                    }
+                    (command, _) => {
+                        anyhow::bail!("unexpected command in AOF {} at offset {}: {:?}", path.display(), frame_start, command);
+                    }
+                }
+                frame_start = position;
+            }
+
+            if 0 == file.read_buf(&mut buffer).await? {
+                break;
+            }
+        }
+
+        // Everything past the last applied frame, including an unterminated
+        // block, is an incomplete tail
+        let trailing = position + buffer.len() as u64 - offset;
+        if trailing > 0 {
+            if !load_truncated {
+                anyhow::bail!(
+                    "AOF {} ends with an incomplete frame at offset {} ({} trailing bytes); \
+                     repair or truncate the file before restarting",
+                    path.display(),
+                    offset,
+                    trailing
+                );
+            }
+
+            warn!(
+                "AOF {} ends with an incomplete frame, truncating {} trailing bytes at offset {}",
+                path.display(),
+                trailing,
+                offset
+            );
+            let file = OpenOptions::new().write(true).open(path).await?;
+            file.set_len(offset).await?;
+            file.sync_all().await?;
+        }
+
+        info!("Loaded {} commands from AOF {}", applied, path.display());
+        Ok(applied)
+    }
+
     pub async fn new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open(path)
-            .await?;
+        let path = path.as_ref().to_path_buf();
+        let file = open_append(&path).await?;
+        let size = file.metadata().await?.len();
 
         Ok(Aof {
             writer: BufWriter::new(file),
+            path,
+            fsync: FsyncPolicy::default(),
+            dirty: false,
+            size,
+            base_size: size,
+            auto_rewrite_percentage: 100,
+            auto_rewrite_min_size: 64 * 1024 * 1024,
+            rewrite_buffer: None,
+            use_rdb_preamble: true,
+            replication: None,
         })
     }
 
+    /// Controls whether rewrites start the file with a binary snapshot,
+    /// like Redis' `aof-use-rdb-preamble`.
+    pub fn set_rdb_preamble(&mut self, enabled: bool) {
+        self.use_rdb_preamble = enabled;
+    }
+
+    /// Configures the automatic rewrite trigger, in the style of Redis'
+    /// `auto-aof-rewrite-percentage` and `auto-aof-rewrite-min-size`.
+    pub fn set_auto_rewrite(&mut self, percentage: u64, min_size: u64) {
+        self.auto_rewrite_percentage = percentage;
+        self.auto_rewrite_min_size = min_size;
+    }
+
+    /// Feeds every appended write to the replication stream of `replication`.
+    pub fn set_replication(&mut self, replication: Arc<Replication>) {
+        self.replication = Some(replication);
+    }
+
+    /// Sets the fsync policy used for subsequent appends.
+    pub fn set_fsync_policy(&mut self, fsync: FsyncPolicy) {
+        self.fsync = fsync;
+    }
+
+    pub fn fsync_policy(&self) -> FsyncPolicy {
+        self.fsync
+    }
+
     pub async fn append(&mut self, cmd: Command) -> anyhow::Result<()> {
-        let frame = cmd.into_frame();
-        self.write_frame(&frame).await?;
+        self.append_frames(vec![cmd.into_frame()]).await
+    }
+
+    /// Appends the write commands of an `EXEC` block between `MULTI` and
+    /// `EXEC`, so a replay applies either all of them or none.
+    pub async fn append_transaction(&mut self, commands: Vec<Command>) -> anyhow::Result<()> {
+        let mut frames = vec![Multi.into_frame()];
+        frames.extend(commands.into_iter().map(Command::into_frame));
+        frames.push(Exec.into_frame());
+        self.append_frames(frames).await
+    }
+
+    /// Appends already built frames, such as writes received from a master,
+    /// and feeds them to the replication stream.
+    pub(crate) async fn append_frames(&mut self, frames: Vec<Frame>) -> anyhow::Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let mut encoded = BytesMut::new();
+        for frame in &frames {
+            codec::encode(frame, Protocol::Resp2, &mut encoded);
+        }
+        // Replicas get the write even if the file can't be written, as it
+        // has been applied already
+        if let Some(replication) = &self.replication {
+            replication.feed(&encoded);
+        }
+        self.write_encoded(&encoded, frames).await
+    }
+
+    /// Logs the dataset a replica received from its master in place of its
+    /// own, without replicating it: the replicas of this node get it through
+    /// their own full synchronization.
+    pub(crate) async fn log_dataset(&mut self, entries: &[(String, Entry)]) -> anyhow::Result<()> {
+        let mut frames = vec![FlushDb.into_frame()];
+        for (key, entry) in entries {
+            frames.extend(rebuild_commands(key.clone(), entry.clone()).into_iter().map(Command::into_frame));
+        }
+        let mut encoded = BytesMut::new();
+        for frame in &frames {
+            codec::encode(frame, Protocol::Resp2, &mut encoded);
+        }
+        self.write_encoded(&encoded, frames).await
+    }
+
+    async fn write_encoded(&mut self, encoded: &[u8], frames: Vec<Frame>) -> anyhow::Result<()> {
+        self.writer.write_all(encoded).await?;
+        self.size += encoded.len() as u64;
+        self.dirty = true;
+
+        match self.fsync {
+            FsyncPolicy::Always => self.sync().await?,
+            // Left in the buffer for the background task to flush
+            FsyncPolicy::EverySec => {}
+            FsyncPolicy::No => self.writer.flush().await?,
+        }
+
+        if let Some(buffer) = &mut self.rewrite_buffer {
+            buffer.extend(frames);
+        }
+        Ok(())
+    }
+
+    /// Flushes buffered commands and forces them to disk.
+    pub async fn sync(&mut self) -> anyhow::Result<()> {
         self.writer.flush().await?;
+        if self.dirty {
+            self.writer.get_ref().sync_data().await?;
+            self.dirty = false;
+        }
         Ok(())
     }
 
-    #[async_recursion]
-    async fn write_frame(&mut self, frame: &Frame) -> anyhow::Result<()> {
-        match frame {
-            Frame::Array(val) => {
-                self.writer.write_u8(b'*').await?;
-                self.writer.write_all(val.len().to_string().as_bytes()).await?;
-                self.writer.write_all(b"\r\n").await?;
-                for entry in val {
-                    self.write_frame(entry).await?;
+    /// Spawns the task that implements the `everysec` policy: once per second
+    /// it flushes buffered commands and fsyncs the file.
+    ///
+    /// The fsync itself runs on a cloned file handle outside the lock so that
+    /// writers are only blocked for the flush. The task exits once the `Aof`
+    /// has been dropped.
+    pub fn start_fsync_task(aof: &Arc<Mutex<Aof>>) -> JoinHandle<()> {
+        let aof: Weak<Mutex<Aof>> = Arc::downgrade(aof);
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+
+                let Some(aof) = aof.upgrade() else { break };
+                let file = {
+                    let mut guard = aof.lock().await;
+                    if guard.fsync != FsyncPolicy::EverySec || !guard.dirty {
+                        continue;
+                    }
+                    if let Err(e) = guard.writer.flush().await {
+                        error!("Failed to flush AOF: {:?}", e);
+                        continue;
+                    }
+                    guard.dirty = false;
+                    guard.writer.get_ref().try_clone().await
+                };
+
+                match file {
+                    Ok(file) => {
+                        if let Err(e) = file.sync_data().await {
+                            error!("Failed to fsync AOF: {:?}", e);
+                        }
+                    }
+                    Err(e) => error!("Failed to clone AOF handle for fsync: {:?}", e),
                 }
             }
-            Frame::Bulk(val) => {
-                self.writer.write_u8(b'$').await?;
-                self.writer.write_all(val.len().to_string().as_bytes()).await?;
-                self.writer.write_all(b"\r\n").await?;
-                self.writer.write_all(val).await?;
-                self.writer.write_all(b"\r\n").await?;
+        })
+    }
+
+    /// Returns `true` while a background rewrite is running.
+    pub fn is_rewriting(&self) -> bool {
+        self.rewrite_buffer.is_some()
+    }
+
+    /// Returns `true` if the file has grown enough to warrant an automatic rewrite.
+    pub fn should_rewrite(&self) -> bool {
+        if self.auto_rewrite_percentage == 0 || self.is_rewriting() || self.size < self.auto_rewrite_min_size {
+            return false;
+        }
+        let growth = (self.size - self.base_size.min(self.size)) * 100;
+        growth >= self.base_size * self.auto_rewrite_percentage
+    }
+
+    /// Current size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Rewrites the AOF in the background so it only contains the commands
+    /// needed to rebuild the current contents of `db`.
+    ///
+    /// The dataset is snapshotted immediately. While the snapshot is written to
+    /// a temporary file, new appends keep going to the current file and are also
+    /// buffered; once the snapshot is done the buffer is copied over and the
+    /// temporary file atomically replaces the current one.
+    pub async fn rewrite_in_background(aof: Arc<Mutex<Aof>>, db: Db) -> anyhow::Result<JoinHandle<()>> {
+        let (snapshot, temp_path, preamble) = {
+            let mut guard = aof.lock().await;
+            if guard.is_rewriting() {
+                anyhow::bail!("Background append only file rewriting already in progress");
             }
-            Frame::Simple(val) => {
-                self.writer.write_u8(b'+').await?;
-                self.writer.write_all(val.as_bytes()).await?;
-                self.writer.write_all(b"\r\n").await?;
+            // Writes already in the snapshot still go to the current file,
+            // but not to the rewrite buffer
+            let (unlogged, snapshot) = {
+                let _access = db.exclusive_access();
+                (db.take_unlogged(), db.snapshot())
+            };
+            guard.append_frames(unlogged).await?;
+            guard.rewrite_buffer = Some(Vec::new());
+            (snapshot, guard.path.with_extension("aof.rewrite"), guard.use_rdb_preamble)
+        };
+
+        info!("Background AOF rewrite started with {} keys", snapshot.len());
+
+        Ok(tokio::spawn(async move {
+            if let Err(e) = Aof::rewrite(&aof, snapshot, &temp_path, preamble).await {
+                error!("Background AOF rewrite failed: {:?}", e);
+                aof.lock().await.rewrite_buffer = None;
+                let _ = tokio::fs::remove_file(&temp_path).await;
+            }
+        }))
+    }
+
+    async fn rewrite(aof: &Mutex<Aof>, snapshot: Vec<(String, Entry)>, temp_path: &Path, preamble: bool) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(temp_path).await?);
+        if preamble {
+            let encoded = tokio::task::spawn_blocking(move || {
+                let mut encoded = Vec::new();
+                rdb::encode(&mut encoded, &snapshot).map(|_| encoded)
+            })
+            .await??;
+            writer.write_all(&encoded).await?;
+        } else {
+            for (key, entry) in snapshot {
+                for command in rebuild_commands(key, entry) {
+                    write_frame(&mut writer, &command.into_frame()).await?;
+                }
             }
-            // Other types omitted for brevity
-            _ => {}
         }
+        writer.flush().await?;
+
+        // Hold the lock from here on so no append can slip in between copying
+        // the buffer and swapping the files.
+        let mut aof = aof.lock().await;
+        let buffered = aof.rewrite_buffer.take().unwrap_or_default();
+        for frame in &buffered {
+            write_frame(&mut writer, frame).await?;
+        }
+        writer.flush().await?;
+        writer.get_ref().sync_all().await?;
+        drop(writer);
+
+        // Anything still buffered for the old file is already in `buffered`
+        aof.writer.flush().await?;
+        tokio::fs::rename(temp_path, &aof.path).await?;
+
+        let file = open_append(&aof.path).await?;
+        aof.size = file.metadata().await?.len();
+        aof.base_size = aof.size;
+        aof.writer = BufWriter::new(file);
+        aof.dirty = false;
+
+        info!("Background AOF rewrite finished ({} bytes, {} buffered commands)", aof.size, buffered.len());
         Ok(())
     }
 }
+
+/// Returns the commands that recreate `entry`, for rewrites without an RDB
+/// preamble.
+fn rebuild_commands(key: String, entry: Entry) -> Vec<Command> {
+    let expiry = entry.expires_at.map(|when| Expiry::PxAt(when as i64));
+    let command = match entry.value {
+        Value::String(value) => {
+            let mut set = Set::new(key, value);
+            set.expire = expiry;
+            return vec![Command::Set(set)];
+        }
+        Value::List(list) => Command::Push(Push { key: key.clone(), values: list.into(), left: false }),
+        Value::Hash(hash) => Command::HSet(HSet { key: key.clone(), pairs: hash.into_iter().collect() }),
+        Value::Set(set) => Command::SAdd(SAdd { key: key.clone(), members: set.into_iter().collect() }),
+        Value::ZSet(zset) => Command::ZAdd(ZAdd {
+            key: key.clone(),
+            members: zset.iter().map(|(member, score)| (score, member.clone())).collect(),
+            condition: SetCondition::Always,
+            changed: false,
+        }),
+        Value::Stream(stream) => {
+            let mut commands = rebuild_stream(&key, &stream);
+            commands.extend(expiry.map(|expiry| Command::Expire(Expire { key, expiry })));
+            return commands;
+        }
+    };
+
+    match expiry {
+        Some(expiry) => vec![command, Command::Expire(Expire { key, expiry })],
+        None => vec![command],
+    }
+}
+
+/// Returns the commands that recreate a stream along with its consumer groups
+/// and their pending entries.
+fn rebuild_stream(key: &str, stream: &Stream) -> Vec<Command> {
+    let xadd = |id, fields, maxlen| Command::XAdd(XAdd { key: key.to_string(), id: NewId::Explicit(id), fields, maxlen, no_mkstream: false });
+    let mut commands: Vec<Command> = stream.iter().map(|(id, fields)| xadd(*id, fields.clone(), None)).collect();
+    if stream.is_empty() {
+        // There is no command creating an empty stream without a group, so
+        // add an entry that is trimmed right away
+        commands.push(xadd(StreamId::new(0, 1), vec![Default::default()], Some(0)));
+    }
+    commands.push(Command::XSetId(XSetId { key: key.to_string(), id: stream.last_id() }));
+
+    for (name, group) in stream.groups() {
+        commands.push(Command::XGroup(XGroupCommand::Create {
+            key: key.to_string(),
+            group: name.clone(),
+            id: Some(group.last_delivered),
+            mkstream: false,
+        }));
+        for consumer in group.consumers.keys() {
+            commands.push(Command::XGroup(XGroupCommand::CreateConsumer {
+                key: key.to_string(),
+                group: name.clone(),
+                consumer: consumer.clone(),
+            }));
+        }
+        for (id, pending) in &group.pending {
+            commands.push(Command::XClaim(XClaim {
+                key: key.to_string(),
+                group: name.clone(),
+                consumer: pending.consumer.clone(),
+                min_idle: 0,
+                ids: vec![*id],
+                options: ClaimOptions {
+                    time: Some(pending.delivered_at),
+                    retry_count: Some(pending.deliveries),
+                    force: true,
+                    just_id: true,
+                    ..ClaimOptions::default()
+                },
+            }));
+        }
+    }
+    commands
+}
+
+async fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Writes `frame` to `writer`, returning the number of bytes written.
+async fn write_frame<W>(writer: &mut W, frame: &Frame) -> anyhow::Result<u64>
+where W: AsyncWrite + Unpin
+{
+    let mut encoded = BytesMut::new();
+    codec::encode(frame, Protocol::Resp2, &mut encoded);
+    writer.write_all(&encoded).await?;
+    Ok(encoded.len() as u64)
+}
+
+/// Loads the snapshot at the start of the AOF at `path`, if there is one,
+/// returning its length in bytes.
+async fn load_preamble(path: &Path, db: &Db) -> anyhow::Result<Option<u64>> {
+    let path = path.to_path_buf();
+    let db = db.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&path)?);
+
+        let mut magic = Vec::with_capacity(rdb::MAGIC.len());
+        (&mut reader).take(rdb::MAGIC.len() as u64).read_to_end(&mut magic)?;
+        if magic != rdb::MAGIC {
+            return Ok(None);
+        }
+
+        let reader = Read::chain(&magic[..], reader);
+        let mut keys = 0;
+        let len = rdb::decode(reader, |key, entry| {
+            db.restore(key, entry);
+            keys += 1;
+        })
+        .map_err(|e| anyhow::anyhow!("corrupt snapshot preamble in AOF {}: {}", path.display(), e))?;
+
+        info!("Loaded {} keys from the snapshot preamble of AOF {}", keys, path.display());
+        Ok(Some(len))
+    })
+    .await?
+}
@@ -1,7 +1,23 @@
-use crate::cmd::{get::Get, set::Set, publish::Publish, ping::Ping};
-use crate::{Connection, Frame, Error};
+use crate::cmd::{get::Get, set::Set, expire::{Expire, Expiry}, ttl::Ttl, persist::Persist, publish::Publish, ping::Ping, bgrewriteaof::BgRewriteAof, save::Save, bgsave::BgSave};
+use crate::cmd::keys::{Del, Exists, Type, Rename, DbSize, FlushDb, Keys, Scan};
+use crate::cmd::list::{Push, Pop, BPop, LRange};
+use crate::cmd::hash::{HSet, HGet, HGetAll, HDel};
+use crate::cmd::sets::{SAdd, SRem, SMembers, SIsMember};
+use crate::cmd::zset::{ZAdd, ZRange, ZRank, ZScore};
+use crate::cmd::stream::{XAck, XAdd, XLen, XRange};
+use crate::cmd::connection::{Auth, Hello};
+use crate::cmd::acl::AclCommand;
+use crate::cmd::string::IncrBy;
+use crate::cmd::info::Info;
+use crate::cmd::config::ConfigCommand;
+use crate::cmd::replication::ReplicaOf;
+use crate::cmd::sentinel::SentinelCommand;
+use crate::db::SetCondition;
+use crate::stream::{Fields, NewId, StreamId};
+use crate::{Command, Connection, Frame, Error, Protocol};
 use bytes::Bytes;
 use tokio::net::TcpStream;
+use std::time::Duration;
 use tokio_rustls::TlsConnector;
 use tokio_rustls::rustls::pki_types::ServerName;
 
@@ -47,6 +63,77 @@ impl Client {
         }
     }
 
+    /// Switch the connection to protocol version `protover` (2 or 3).
+    /// Returns the server's details as field / value pairs.
+    pub async fn hello(&mut self, protover: u8) -> Result<Vec<(String, Frame)>, Error> {
+        let frame = Hello { protover: Some(protover as i64), auth: None, setname: None }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        let pairs = match self.read_response().await? {
+            Frame::Map(pairs) => pairs,
+            Frame::Array(items) => {
+                let mut items = items.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(field), Some(value)) = (items.next(), items.next()) {
+                    pairs.push((field, value));
+                }
+                pairs
+            }
+            Frame::Error(msg) => return Err(Error::Other(msg)),
+            frame => return Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        };
+
+        self.connection.set_protocol(if protover == 3 { Protocol::Resp3 } else { Protocol::Resp2 });
+        pairs
+            .into_iter()
+            .map(|(field, value)| match field {
+                Frame::Bulk(field) => Ok((into_string(field)?, value)),
+                frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+            })
+            .collect()
+    }
+
+    /// Authenticate the connection, as `default` if no username is given.
+    pub async fn auth(&mut self, username: Option<&str>, password: &str) -> Result<(), Error> {
+        let frame = Auth { username: username.map(str::to_string), password: Bytes::from(password.to_string()) }.into_frame();
+        self.simple_request(frame).await.map(|_| ())
+    }
+
+    /// Create or modify a user with `ACL SETUSER` rules.
+    pub async fn acl_setuser(&mut self, name: &str, rules: &[&str]) -> Result<(), Error> {
+        let rules = rules.iter().map(|rule| rule.to_string()).collect();
+        let frame = AclCommand::SetUser { name: name.to_string(), rules }.into_frame();
+        self.simple_request(frame).await.map(|_| ())
+    }
+
+    /// Delete users, returning how many existed.
+    pub async fn acl_deluser(&mut self, names: &[&str]) -> Result<u64, Error> {
+        let frame = AclCommand::DelUser(names.iter().map(|name| name.to_string()).collect()).into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Get every user, described by the rules that recreate it.
+    pub async fn acl_list(&mut self) -> Result<Vec<String>, Error> {
+        let users = self.array_request(AclCommand::List.into_frame()).await?;
+        users.into_iter().map(into_string).collect()
+    }
+
+    /// Get the user the connection is authenticated as.
+    pub async fn acl_whoami(&mut self) -> Result<String, Error> {
+        self.connection.write_frame(&AclCommand::WhoAmI.into_frame()).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(name) => into_string(name),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Write the users to the server's ACL file.
+    pub async fn acl_save(&mut self) -> Result<(), Error> {
+        self.simple_request(AclCommand::Save.into_frame()).await.map(|_| ())
+    }
+
     /// Get the value of key.
     pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
         let frame = Get {
@@ -65,19 +152,376 @@ impl Client {
 
     /// Set key to hold the string value.
     pub async fn set(&mut self, key: &str, value: Bytes) -> Result<(), Error> {
-        let frame = Set {
+        let frame = Set::new(key, value).into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Set key to hold the string value, expiring after `expiration`.
+    pub async fn set_expires(&mut self, key: &str, value: Bytes, expiration: Duration) -> Result<(), Error> {
+        let mut set = Set::new(key, value);
+        set.expire = Some(Expiry::Px(expiration.as_millis() as i64));
+
+        self.connection.write_frame(&set.into_frame()).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Set a timeout on key. Returns `false` if the key does not exist.
+    pub async fn expire(&mut self, key: &str, expiration: Duration) -> Result<bool, Error> {
+        let frame = Expire {
             key: key.to_string(),
-            value,
+            expiry: Expiry::Px(expiration.as_millis() as i64),
         }.into_frame();
 
         self.connection.write_frame(&frame).await?;
 
         match self.read_response().await? {
-            Frame::Simple(response) if response == "OK" => Ok(()),
+            Frame::Integer(response) => Ok(response == 1),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Get the remaining time to live of key. Returns `None` if the key does
+    /// not exist and `Some(None)` if it exists but has no timeout.
+    pub async fn ttl(&mut self, key: &str) -> Result<Option<Option<Duration>>, Error> {
+        let frame = Ttl {
+            key: key.to_string(),
+            millis: true,
+        }.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(-2) => Ok(None),
+            Frame::Integer(-1) => Ok(Some(None)),
+            Frame::Integer(ms) if ms >= 0 => Ok(Some(Some(Duration::from_millis(ms as u64)))),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Remove the timeout on key. Returns `false` if the key does not exist
+    /// or has no timeout.
+    pub async fn persist(&mut self, key: &str) -> Result<bool, Error> {
+        let frame = Persist {
+            key: key.to_string(),
+        }.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(response) => Ok(response == 1),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Delete keys. Returns the number of keys that existed.
+    pub async fn del(&mut self, keys: &[&str]) -> Result<u64, Error> {
+        let frame = Del { keys: keys.iter().map(|key| key.to_string()).collect(), unlink: false }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Delete keys, freeing their values in the background.
+    pub async fn unlink(&mut self, keys: &[&str]) -> Result<u64, Error> {
+        let frame = Del { keys: keys.iter().map(|key| key.to_string()).collect(), unlink: true }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Count how many of `keys` exist.
+    pub async fn exists(&mut self, keys: &[&str]) -> Result<u64, Error> {
+        let frame = Exists { keys: keys.iter().map(|key| key.to_string()).collect() }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Get the type of the value stored at key (`"none"` if it is missing).
+    pub async fn key_type(&mut self, key: &str) -> Result<String, Error> {
+        let frame = Type { key: key.to_string() }.into_frame();
+        self.simple_request(frame).await
+    }
+
+    /// Rename key to `new_key`, overwriting `new_key` if it exists.
+    pub async fn rename(&mut self, key: &str, new_key: &str) -> Result<(), Error> {
+        let frame = Rename { key: key.to_string(), new_key: new_key.to_string(), nx: false }.into_frame();
+        self.simple_request(frame).await.map(|_| ())
+    }
+
+    /// Rename key to `new_key` if `new_key` does not exist yet.
+    pub async fn renamenx(&mut self, key: &str, new_key: &str) -> Result<bool, Error> {
+        let frame = Rename { key: key.to_string(), new_key: new_key.to_string(), nx: true }.into_frame();
+        self.integer_request(frame).await.map(|n| n == 1)
+    }
+
+    /// Get the number of keys in the database.
+    pub async fn dbsize(&mut self) -> Result<u64, Error> {
+        self.integer_request(DbSize.into_frame()).await.map(|n| n as u64)
+    }
+
+    /// Delete every key in the database.
+    pub async fn flushdb(&mut self) -> Result<(), Error> {
+        self.simple_request(FlushDb.into_frame()).await.map(|_| ())
+    }
+
+    /// Get every key matching the glob `pattern`.
+    pub async fn keys(&mut self, pattern: &str) -> Result<Vec<String>, Error> {
+        let frame = Keys { pattern: pattern.to_string() }.into_frame();
+        let keys = self.array_request(frame).await?;
+        keys.into_iter().map(into_string).collect()
+    }
+
+    /// Get the next batch of keys matching `pattern` from `cursor`. Returns
+    /// the cursor for the next call, which is `0` once the scan is complete.
+    pub async fn scan(&mut self, cursor: u64, pattern: Option<&str>, count: usize) -> Result<(u64, Vec<String>), Error> {
+        let frame = Scan { cursor, pattern: pattern.map(|p| p.to_string()), count }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(mut items) if items.len() == 2 => match (items.remove(0), items.remove(0)) {
+                (Frame::Bulk(cursor), Frame::Array(keys)) => {
+                    let cursor = into_string(cursor)?
+                        .parse()
+                        .map_err(|_| Error::Other("invalid cursor".into()))?;
+                    let keys = keys
+                        .into_iter()
+                        .map(|key| match key {
+                            Frame::Bulk(key) => into_string(key),
+                            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Ok((cursor, keys))
+                }
+                frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+            },
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Insert values at the head of the list. Returns the new length.
+    pub async fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> Result<u64, Error> {
+        let frame = Push { key: key.to_string(), values, left: true }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Insert values at the tail of the list. Returns the new length.
+    pub async fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> Result<u64, Error> {
+        let frame = Push { key: key.to_string(), values, left: false }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Remove and return the first element of the list.
+    pub async fn lpop(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        let frame = Pop { key: key.to_string(), count: None, left: true }.into_frame();
+        self.optional_bulk_request(frame).await
+    }
+
+    /// Remove and return the last element of the list.
+    pub async fn rpop(&mut self, key: &str) -> Result<Option<Bytes>, Error> {
+        let frame = Pop { key: key.to_string(), count: None, left: false }.into_frame();
+        self.optional_bulk_request(frame).await
+    }
+
+    /// Remove and return the first element of the first non-empty list among
+    /// `keys`, waiting up to `timeout` for one to be pushed (`None` waits
+    /// forever). Returns the key and the element, or `None` on timeout.
+    pub async fn blpop(&mut self, keys: &[&str], timeout: Option<Duration>) -> Result<Option<(String, Bytes)>, Error> {
+        self.blocking_pop(keys, timeout, true).await
+    }
+
+    /// Like `blpop`, but pops from the tail of the lists.
+    pub async fn brpop(&mut self, keys: &[&str], timeout: Option<Duration>) -> Result<Option<(String, Bytes)>, Error> {
+        self.blocking_pop(keys, timeout, false).await
+    }
+
+    async fn blocking_pop(&mut self, keys: &[&str], timeout: Option<Duration>, left: bool) -> Result<Option<(String, Bytes)>, Error> {
+        let frame = BPop {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            timeout,
+            left,
+        }.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(items) => match <[Frame; 2]>::try_from(items) {
+                Ok([Frame::Bulk(key), Frame::Bulk(value)]) => {
+                    Ok(Some((into_string(key)?, value)))
+                }
+                Ok(items) => Err(Error::Other(format!("unexpected frame: {:?}", items))),
+                Err(items) => Err(Error::Other(format!("unexpected frame: {:?}", items))),
+            },
+            Frame::Null => Ok(None),
+            Frame::Error(msg) => Err(Error::Other(msg)),
             frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
         }
     }
 
+    /// Get the elements of the list between `start` and `stop`, inclusive.
+    pub async fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, Error> {
+        let frame = LRange { key: key.to_string(), start, stop }.into_frame();
+        self.array_request(frame).await
+    }
+
+    /// Set fields of the hash. Returns the number of fields added.
+    pub async fn hset(&mut self, key: &str, pairs: Vec<(Bytes, Bytes)>) -> Result<u64, Error> {
+        let frame = HSet { key: key.to_string(), pairs }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Get the value of a field of the hash.
+    pub async fn hget(&mut self, key: &str, field: Bytes) -> Result<Option<Bytes>, Error> {
+        let frame = HGet { key: key.to_string(), field }.into_frame();
+        self.optional_bulk_request(frame).await
+    }
+
+    /// Get every field and value of the hash.
+    pub async fn hgetall(&mut self, key: &str) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let frame = HGetAll { key: key.to_string() }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        // A map on RESP3 connections, a flat array on RESP2 ones
+        match self.read_response().await? {
+            Frame::Map(pairs) => pairs
+                .into_iter()
+                .map(|pair| match pair {
+                    (Frame::Bulk(field), Frame::Bulk(value)) => Ok((field, value)),
+                    pair => Err(Error::Other(format!("unexpected frame: {:?}", pair))),
+                })
+                .collect(),
+            Frame::Array(items) => {
+                let mut items = into_bulks(items)?.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(field), Some(value)) = (items.next(), items.next()) {
+                    pairs.push((field, value));
+                }
+                Ok(pairs)
+            }
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Delete fields of the hash. Returns the number of fields removed.
+    pub async fn hdel(&mut self, key: &str, fields: Vec<Bytes>) -> Result<u64, Error> {
+        let frame = HDel { key: key.to_string(), fields }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Add members to the set. Returns the number of members added.
+    pub async fn sadd(&mut self, key: &str, members: Vec<Bytes>) -> Result<u64, Error> {
+        let frame = SAdd { key: key.to_string(), members }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Remove members from the set. Returns the number of members removed.
+    pub async fn srem(&mut self, key: &str, members: Vec<Bytes>) -> Result<u64, Error> {
+        let frame = SRem { key: key.to_string(), members }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Get every member of the set.
+    pub async fn smembers(&mut self, key: &str) -> Result<Vec<Bytes>, Error> {
+        let frame = SMembers { key: key.to_string() }.into_frame();
+        self.array_request(frame).await
+    }
+
+    /// Check whether `member` belongs to the set.
+    pub async fn sismember(&mut self, key: &str, member: Bytes) -> Result<bool, Error> {
+        let frame = SIsMember { key: key.to_string(), member }.into_frame();
+        self.integer_request(frame).await.map(|n| n == 1)
+    }
+
+    /// Add members to the sorted set or update their scores. Returns the
+    /// number of members added.
+    pub async fn zadd(&mut self, key: &str, members: Vec<(f64, Bytes)>) -> Result<u64, Error> {
+        let frame = ZAdd {
+            key: key.to_string(),
+            members,
+            condition: SetCondition::Always,
+            changed: false,
+        }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
+    /// Get the members of the sorted set with their scores, by rank between
+    /// `start` and `stop`, inclusive.
+    pub async fn zrange(&mut self, key: &str, start: i64, stop: i64) -> Result<Vec<(Bytes, f64)>, Error> {
+        let frame = ZRange { key: key.to_string(), start, stop, with_scores: true }.into_frame();
+        let items = self.array_request(frame).await?;
+        let mut items = items.into_iter();
+        let mut members = Vec::new();
+        while let (Some(member), Some(score)) = (items.next(), items.next()) {
+            members.push((member, parse_score(&score)?));
+        }
+        Ok(members)
+    }
+
+    /// Get the rank of `member` in the sorted set, lowest score first.
+    pub async fn zrank(&mut self, key: &str, member: Bytes) -> Result<Option<u64>, Error> {
+        let frame = ZRank { key: key.to_string(), member }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(rank) => Ok(Some(rank as u64)),
+            Frame::Null => Ok(None),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Get the score of `member` in the sorted set.
+    pub async fn zscore(&mut self, key: &str, member: Bytes) -> Result<Option<f64>, Error> {
+        let frame = ZScore { key: key.to_string(), member }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Double(score) => Ok(Some(score)),
+            Frame::Bulk(score) => parse_score(&score).map(Some),
+            Frame::Null => Ok(None),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Append an entry to the stream, returning the ID it was given.
+    pub async fn xadd(&mut self, key: &str, fields: Fields) -> Result<StreamId, Error> {
+        let frame = XAdd { key: key.to_string(), id: NewId::Auto, fields, maxlen: None, no_mkstream: false }.into_frame();
+        let id = self.optional_bulk_request(frame).await?;
+        id.as_deref().and_then(parse_stream_id).ok_or_else(|| Error::Other(format!("invalid stream ID: {:?}", id)))
+    }
+
+    /// Get the number of entries in the stream.
+    pub async fn xlen(&mut self, key: &str) -> Result<u64, Error> {
+        self.integer_request(XLen { key: key.to_string() }.into_frame()).await.map(|n| n as u64)
+    }
+
+    /// Get the entries of the stream with IDs between `start` and `end`,
+    /// inclusive.
+    pub async fn xrange(&mut self, key: &str, start: StreamId, end: StreamId) -> Result<Vec<(StreamId, Fields)>, Error> {
+        let frame = XRange { key: key.to_string(), start, end, count: None, rev: false }.into_frame();
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(entries) => entries.into_iter().map(into_entry).collect(),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Acknowledge entries delivered to the consumer group, returning how
+    /// many were pending.
+    pub async fn xack(&mut self, key: &str, group: &str, ids: &[StreamId]) -> Result<u64, Error> {
+        let frame = XAck { key: key.to_string(), group: group.to_string(), ids: ids.to_vec() }.into_frame();
+        self.integer_request(frame).await.map(|n| n as u64)
+    }
+
     /// Publish a message to the channel.
     pub async fn publish(&mut self, channel: &str, message: Bytes) -> Result<u64, Error> {
         let frame = Publish {
@@ -93,6 +537,206 @@ impl Client {
         }
     }
 
+    /// Ask the server to compact its append-only file in the background.
+    pub async fn bgrewriteaof(&mut self) -> Result<(), Error> {
+        let frame = BgRewriteAof.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Synchronously save a snapshot of the dataset on the server.
+    pub async fn save(&mut self) -> Result<(), Error> {
+        let frame = Save.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Ask the server to save a snapshot of the dataset in the background.
+    pub async fn bgsave(&mut self) -> Result<(), Error> {
+        let frame = BgSave.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Get the server's `INFO` report, limited to `section` if given.
+    pub async fn info(&mut self, section: Option<&str>) -> Result<String, Error> {
+        let frame = Info { sections: section.into_iter().map(str::to_string).collect() }.into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Verbatim(_, text) | Frame::Bulk(text) => into_string(text),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Get the configuration parameters matching the glob `pattern`.
+    pub async fn config_get(&mut self, pattern: &str) -> Result<Vec<(String, String)>, Error> {
+        let frame = ConfigCommand::Get(vec![pattern.to_string()]).into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        let pairs = match self.read_response().await? {
+            Frame::Map(pairs) => pairs,
+            Frame::Array(items) => {
+                let mut items = items.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(name), Some(value)) = (items.next(), items.next()) {
+                    pairs.push((name, value));
+                }
+                pairs
+            }
+            Frame::Error(msg) => return Err(Error::Other(msg)),
+            frame => return Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        };
+        pairs
+            .into_iter()
+            .map(|pair| match pair {
+                (Frame::Bulk(name), Frame::Bulk(value)) => Ok((into_string(name)?, into_string(value)?)),
+                pair => Err(Error::Other(format!("unexpected frame: {:?}", pair))),
+            })
+            .collect()
+    }
+
+    /// Change a configuration parameter at runtime.
+    pub async fn config_set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let frame = ConfigCommand::Set(vec![(name.to_string(), value.to_string())]).into_frame();
+        self.simple_request(frame).await.map(|_| ())
+    }
+
+    /// Write the running configuration back to the server's config file.
+    pub async fn config_rewrite(&mut self) -> Result<(), Error> {
+        self.simple_request(ConfigCommand::Rewrite.into_frame()).await.map(|_| ())
+    }
+
+    /// Make the server a replica of `master`, given as host and port, or a
+    /// master again with `None`.
+    pub async fn replicaof(&mut self, master: Option<(&str, u16)>) -> Result<(), Error> {
+        let frame = ReplicaOf { master: master.map(|(host, port)| (host.to_string(), port)) }.into_frame();
+        self.simple_request(frame).await.map(|_| ())
+    }
+
+    /// Ask a sentinel for the address of the current master of the master
+    /// group `name`, or `None` if it doesn't monitor one by that name.
+    pub async fn sentinel_get_master_addr_by_name(&mut self, name: &str) -> Result<Option<(String, u16)>, Error> {
+        let frame = SentinelCommand::GetMasterAddrByName(name.to_string()).into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(items) => match <[Frame; 2]>::try_from(items) {
+                Ok([Frame::Bulk(host), Frame::Bulk(port)]) => {
+                    let port = into_string(port)?.parse().map_err(|_| Error::Other("invalid port".into()))?;
+                    Ok(Some((into_string(host)?, port)))
+                }
+                Ok(items) => Err(Error::Other(format!("unexpected frame: {:?}", items))),
+                Err(items) => Err(Error::Other(format!("unexpected frame: {:?}", items))),
+            },
+            Frame::Null => Ok(None),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Ask a sentinel for the state of the master group `name`, as field /
+    /// value pairs.
+    pub async fn sentinel_master(&mut self, name: &str) -> Result<Vec<(String, String)>, Error> {
+        let frame = SentinelCommand::Master(name.to_string()).into_frame();
+
+        self.connection.write_frame(&frame).await?;
+
+        let pairs = match self.read_response().await? {
+            Frame::Map(pairs) => pairs,
+            Frame::Array(items) => {
+                let mut items = items.into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(field), Some(value)) = (items.next(), items.next()) {
+                    pairs.push((field, value));
+                }
+                pairs
+            }
+            Frame::Error(msg) => return Err(Error::Other(msg)),
+            frame => return Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        };
+        pairs
+            .into_iter()
+            .map(|pair| match pair {
+                (Frame::Bulk(field), Frame::Bulk(value)) => Ok((into_string(field)?, into_string(value)?)),
+                pair => Err(Error::Other(format!("unexpected frame: {:?}", pair))),
+            })
+            .collect()
+    }
+
+    /// Start a pipeline. Its commands are sent together and the replies are
+    /// read back in one go, instead of waiting a round trip for each.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline { client: self, frames: vec![] }
+    }
+
+    /// Send `frame` and expect a simple string reply.
+    async fn simple_request(&mut self, frame: Frame) -> Result<String, Error> {
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) => Ok(response),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Send `frame` and expect an integer reply.
+    async fn integer_request(&mut self, frame: Frame) -> Result<i64, Error> {
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Send `frame` and expect a bulk or null reply.
+    async fn optional_bulk_request(&mut self, frame: Frame) -> Result<Option<Bytes>, Error> {
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
+    /// Send `frame` and expect an array (or set) of bulk strings.
+    async fn array_request(&mut self, frame: Frame) -> Result<Vec<Bytes>, Error> {
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(items) | Frame::Set(items) => into_bulks(items),
+            Frame::Error(msg) => Err(Error::Other(msg)),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        }
+    }
+
     /// Read a single response frame.
     async fn read_response(&mut self) -> Result<Frame, Error> {
         let response = self.connection.read_frame().await?;
@@ -105,3 +749,114 @@ impl Client {
         }
     }
 }
+
+/// Commands queued by `Client::pipeline`.
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    frames: Vec<Frame>,
+}
+
+impl Pipeline<'_> {
+    /// Queue any command.
+    pub fn command(&mut self, command: Command) -> &mut Self {
+        self.frames.push(command.into_frame());
+        self
+    }
+
+    pub fn get(&mut self, key: &str) -> &mut Self {
+        self.frames.push(Get { key: key.to_string() }.into_frame());
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: Bytes) -> &mut Self {
+        self.frames.push(Set::new(key, value).into_frame());
+        self
+    }
+
+    pub fn del(&mut self, keys: &[&str]) -> &mut Self {
+        self.frames.push(Del { keys: keys.iter().map(|key| key.to_string()).collect(), unlink: false }.into_frame());
+        self
+    }
+
+    pub fn incr_by(&mut self, key: &str, delta: i64) -> &mut Self {
+        self.frames.push(IncrBy { key: key.to_string(), delta }.into_frame());
+        self
+    }
+
+    pub fn lpush(&mut self, key: &str, values: Vec<Bytes>) -> &mut Self {
+        self.frames.push(Push { key: key.to_string(), values, left: true }.into_frame());
+        self
+    }
+
+    pub fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> &mut Self {
+        self.frames.push(Push { key: key.to_string(), values, left: false }.into_frame());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Send the queued commands and return their replies, in order. A command
+    /// that fails has its `Frame::Error` in its slot; it doesn't stop the
+    /// others. The pipeline is empty afterwards and can be reused.
+    pub async fn execute(&mut self) -> Result<Vec<Frame>, Error> {
+        let frames = std::mem::take(&mut self.frames);
+        for frame in &frames {
+            self.client.connection.buffer_frame(frame).await?;
+        }
+
+        // Reading the first reply flushes the whole batch
+        let mut replies = Vec::with_capacity(frames.len());
+        for _ in 0..frames.len() {
+            replies.push(self.client.read_response().await?);
+        }
+        Ok(replies)
+    }
+}
+
+fn into_bulks(items: Vec<Frame>) -> Result<Vec<Bytes>, Error> {
+    items
+        .into_iter()
+        .map(|item| match item {
+            Frame::Bulk(value) => Ok(value),
+            frame => Err(Error::Other(format!("unexpected frame: {:?}", frame))),
+        })
+        .collect()
+}
+
+fn into_string(value: Bytes) -> Result<String, Error> {
+    String::from_utf8(value.to_vec()).map_err(|_| Error::Other("invalid UTF-8 in reply".into()))
+}
+
+fn parse_stream_id(id: &[u8]) -> Option<StreamId> {
+    StreamId::parse(std::str::from_utf8(id).ok()?, 0)
+}
+
+/// Turns `[id, [field, value, ...]]` into a stream entry.
+fn into_entry(frame: Frame) -> Result<(StreamId, Fields), Error> {
+    let unexpected = |frame| Error::Other(format!("unexpected frame: {:?}", frame));
+    let Frame::Array(mut parts) = frame else { return Err(unexpected(frame)) };
+    let (Some(Frame::Array(fields)), Some(Frame::Bulk(id)), None) = (parts.pop(), parts.pop(), parts.pop()) else {
+        return Err(unexpected(Frame::Array(parts)));
+    };
+
+    let id = parse_stream_id(&id).ok_or_else(|| Error::Other(format!("invalid stream ID: {:?}", id)))?;
+    let mut fields = into_bulks(fields)?.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+        pairs.push((field, value));
+    }
+    Ok((id, pairs))
+}
+
+fn parse_score(score: &[u8]) -> Result<f64, Error> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Error::Other(format!("invalid score: {:?}", score)))
+}
@@ -0,0 +1,87 @@
+//! `ACL`. Users are server state, so the server executes it.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub enum AclCommand {
+    SetUser { name: String, rules: Vec<String> },
+    GetUser(String),
+    DelUser(Vec<String>),
+    List,
+    Users,
+    WhoAmI,
+    /// The categories, or the commands in one of them.
+    Cat(Option<String>),
+    Load,
+    Save,
+}
+
+impl AclCommand {
+    pub fn parse_frames(parse: &mut Parse) -> Result<AclCommand, Error> {
+        let subcommand = parse.next_string()?;
+        let mut rest = || -> Result<Vec<String>, Error> {
+            let mut args = vec![];
+            while parse.remaining() > 0 {
+                args.push(parse.next_string()?);
+            }
+            Ok(args)
+        };
+        Ok(match subcommand.to_uppercase().as_str() {
+            "SETUSER" => {
+                let mut args = rest()?;
+                if args.is_empty() {
+                    return Err(Error::Other("wrong number of arguments for 'acl|setuser' command".into()));
+                }
+                let name = args.remove(0);
+                AclCommand::SetUser { name, rules: args }
+            }
+            "GETUSER" => AclCommand::GetUser(parse.next_string()?),
+            "DELUSER" => {
+                let names = rest()?;
+                if names.is_empty() {
+                    return Err(Error::Other("wrong number of arguments for 'acl|deluser' command".into()));
+                }
+                AclCommand::DelUser(names)
+            }
+            "LIST" => AclCommand::List,
+            "USERS" => AclCommand::Users,
+            "WHOAMI" => AclCommand::WhoAmI,
+            "CAT" => AclCommand::Cat(rest()?.into_iter().next()),
+            "LOAD" => AclCommand::Load,
+            "SAVE" => AclCommand::Save,
+            _ => return Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+        let mut frames = vec![bulk("ACL")];
+        match self {
+            AclCommand::SetUser { name, rules } => {
+                frames.push(bulk("SETUSER"));
+                frames.push(bulk(&name));
+                frames.extend(rules.iter().map(|rule| bulk(rule)));
+            }
+            AclCommand::GetUser(name) => {
+                frames.push(bulk("GETUSER"));
+                frames.push(bulk(&name));
+            }
+            AclCommand::DelUser(names) => {
+                frames.push(bulk("DELUSER"));
+                frames.extend(names.iter().map(|name| bulk(name)));
+            }
+            AclCommand::List => frames.push(bulk("LIST")),
+            AclCommand::Users => frames.push(bulk("USERS")),
+            AclCommand::WhoAmI => frames.push(bulk("WHOAMI")),
+            AclCommand::Cat(category) => {
+                frames.push(bulk("CAT"));
+                frames.extend(category.iter().map(|category| bulk(category)));
+            }
+            AclCommand::Load => frames.push(bulk("LOAD")),
+            AclCommand::Save => frames.push(bulk("SAVE")),
+        }
+        Frame::Array(frames)
+    }
+}
@@ -0,0 +1,16 @@
+use super::Parse;
+use crate::Error;
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct BgRewriteAof;
+
+impl BgRewriteAof {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<BgRewriteAof, Error> {
+        Ok(BgRewriteAof)
+    }
+
+    pub fn into_frame(self) -> crate::Frame {
+        crate::Frame::Array(vec![crate::Frame::Bulk(Bytes::from("BGREWRITEAOF"))])
+    }
+}
@@ -0,0 +1,16 @@
+use super::Parse;
+use crate::Error;
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct BgSave;
+
+impl BgSave {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<BgSave, Error> {
+        Ok(BgSave)
+    }
+
+    pub fn into_frame(self) -> crate::Frame {
+        crate::Frame::Array(vec![crate::Frame::Bulk(Bytes::from("BGSAVE"))])
+    }
+}
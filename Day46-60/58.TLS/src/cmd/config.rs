@@ -0,0 +1,62 @@
+//! `CONFIG`. It reads and changes the server configuration, so the server
+//! executes it.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub enum ConfigCommand {
+    /// Parameters matching any of the glob patterns.
+    Get(Vec<String>),
+    /// Name / value pairs, applied all together or not at all.
+    Set(Vec<(String, String)>),
+    Rewrite,
+    ResetStat,
+}
+
+impl ConfigCommand {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ConfigCommand, Error> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_uppercase().as_str() {
+            "GET" => {
+                let mut patterns = vec![parse.next_string()?];
+                while parse.remaining() > 0 {
+                    patterns.push(parse.next_string()?);
+                }
+                Ok(ConfigCommand::Get(patterns))
+            }
+            "SET" => {
+                let mut pairs = vec![(parse.next_string()?, parse.next_string()?)];
+                while parse.remaining() > 0 {
+                    pairs.push((parse.next_string()?, parse.next_string()?));
+                }
+                Ok(ConfigCommand::Set(pairs))
+            }
+            "REWRITE" => Ok(ConfigCommand::Rewrite),
+            "RESETSTAT" => Ok(ConfigCommand::ResetStat),
+            _ => Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+        let mut frames = vec![bulk("CONFIG")];
+        match self {
+            ConfigCommand::Get(patterns) => {
+                frames.push(bulk("GET"));
+                frames.extend(patterns.iter().map(|pattern| bulk(pattern)));
+            }
+            ConfigCommand::Set(pairs) => {
+                frames.push(bulk("SET"));
+                for (name, value) in &pairs {
+                    frames.push(bulk(name));
+                    frames.push(bulk(value));
+                }
+            }
+            ConfigCommand::Rewrite => frames.push(bulk("REWRITE")),
+            ConfigCommand::ResetStat => frames.push(bulk("RESETSTAT")),
+        }
+        Frame::Array(frames)
+    }
+}
@@ -0,0 +1,130 @@
+//! `HELLO`, `AUTH` and `CLIENT`. They act on the connection itself, so the
+//! server executes them.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct Hello {
+    /// The requested protocol version; `None` keeps the current one.
+    pub protover: Option<i64>,
+    /// Username and password to authenticate with.
+    pub auth: Option<(String, Bytes)>,
+    pub setname: Option<String>,
+}
+
+/// `AUTH [username] password`. Without a username, authenticates as
+/// `default`.
+#[derive(Debug, Clone)]
+pub struct Auth {
+    pub username: Option<String>,
+    pub password: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub enum Client {
+    Id,
+    GetName,
+    SetName(String),
+}
+
+impl Hello {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Hello, Error> {
+        let mut hello = Hello { protover: None, auth: None, setname: None };
+        if parse.remaining() == 0 {
+            return Ok(hello);
+        }
+
+        hello.protover = Some(
+            parse
+                .next_int()
+                .map_err(|_| Error::Other("Protocol version is not an integer or out of range".into()))?,
+        );
+
+        while parse.remaining() > 0 {
+            let option = parse.next_string()?;
+            match option.to_uppercase().as_str() {
+                "AUTH" if parse.remaining() >= 2 => {
+                    hello.auth = Some((parse.next_string()?, parse.next_bytes()?));
+                }
+                "SETNAME" if parse.remaining() >= 1 => hello.setname = Some(parse.next_string()?),
+                _ => return Err(Error::Other(format!("Syntax error in HELLO option '{}'", option))),
+            }
+        }
+
+        Ok(hello)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("HELLO"))];
+        if let Some(protover) = self.protover {
+            frames.push(Frame::Bulk(Bytes::from(protover.to_string())));
+            if let Some((username, password)) = self.auth {
+                frames.push(Frame::Bulk(Bytes::from("AUTH")));
+                frames.push(Frame::Bulk(Bytes::from(username)));
+                frames.push(Frame::Bulk(password));
+            }
+            if let Some(name) = self.setname {
+                frames.push(Frame::Bulk(Bytes::from("SETNAME")));
+                frames.push(Frame::Bulk(Bytes::from(name)));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl Auth {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Auth, Error> {
+        let first = parse.next_bytes()?;
+        if parse.remaining() == 0 {
+            return Ok(Auth { username: None, password: first });
+        }
+        let username = String::from_utf8(first.to_vec()).map_err(|_| Error::Other("invalid username".into()))?;
+        Ok(Auth { username: Some(username), password: parse.next_bytes()? })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("AUTH"))];
+        if let Some(username) = self.username {
+            frames.push(Frame::Bulk(Bytes::from(username)));
+        }
+        frames.push(Frame::Bulk(self.password));
+        Frame::Array(frames)
+    }
+}
+
+impl Client {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Client, Error> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_uppercase().as_str() {
+            "ID" => Ok(Client::Id),
+            "GETNAME" => Ok(Client::GetName),
+            "SETNAME" => Ok(Client::SetName(parse.next_string()?)),
+            _ => Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("CLIENT"))];
+        match self {
+            Client::Id => frames.push(Frame::Bulk(Bytes::from("ID"))),
+            Client::GetName => frames.push(Frame::Bulk(Bytes::from("GETNAME"))),
+            Client::SetName(name) => {
+                frames.push(Frame::Bulk(Bytes::from("SETNAME")));
+                frames.push(Frame::Bulk(Bytes::from(name)));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
+
+/// Checks a name given to `CLIENT SETNAME` or `HELLO ... SETNAME`. Names show
+/// up in space separated listings, so they must be a single printable word.
+pub(crate) fn validate_name(name: &str) -> Result<(), Frame> {
+    if name.bytes().all(|b| b.is_ascii_graphic()) {
+        Ok(())
+    } else {
+        Err(Frame::Error("ERR Client names cannot contain spaces, newlines or special characters.".to_string()))
+    }
+}
@@ -0,0 +1,85 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+/// An expiration time, as given to `SET` or the `EXPIRE` family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Relative, in seconds (`EX` / `EXPIRE`).
+    Ex(i64),
+    /// Relative, in milliseconds (`PX` / `PEXPIRE`).
+    Px(i64),
+    /// Absolute Unix time in seconds (`EXAT` / `EXPIREAT`).
+    ExAt(i64),
+    /// Absolute Unix time in milliseconds (`PXAT` / `PEXPIREAT`).
+    PxAt(i64),
+}
+
+impl Expiry {
+    /// Resolves the expiration to an absolute Unix time in milliseconds.
+    /// Returns `None` if the result overflows.
+    pub fn deadline(self, now: u64) -> Option<i64> {
+        match self {
+            Expiry::Ex(secs) => secs.checked_mul(1000)?.checked_add(now as i64),
+            Expiry::Px(ms) => ms.checked_add(now as i64),
+            Expiry::ExAt(secs) => secs.checked_mul(1000),
+            Expiry::PxAt(ms) => Some(ms),
+        }
+    }
+
+    /// Converts a relative expiration into the equivalent `PxAt`, so that
+    /// replaying it from the AOF later yields the same deadline.
+    pub fn to_absolute(self, now: u64) -> Expiry {
+        match self.deadline(now) {
+            Some(when) => Expiry::PxAt(when),
+            None => self,
+        }
+    }
+
+    fn value(self) -> i64 {
+        match self {
+            Expiry::Ex(v) | Expiry::Px(v) | Expiry::ExAt(v) | Expiry::PxAt(v) => v,
+        }
+    }
+}
+
+/// `EXPIRE`, `PEXPIRE`, `EXPIREAT` and `PEXPIREAT`.
+#[derive(Debug, Clone)]
+pub struct Expire {
+    pub key: String,
+    pub expiry: Expiry,
+}
+
+impl Expire {
+    pub fn parse_frames(parse: &mut Parse, expiry: fn(i64) -> Expiry) -> Result<Expire, Error> {
+        let key = parse.next_string()?;
+        let expiry = expiry(parse.next_int()?);
+
+        Ok(Expire { key, expiry })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self.expiry.deadline(crate::db::now_ms()) {
+            Some(when) => Frame::Integer(db.expire_at(&self.key, when) as i64),
+            None => Frame::Error(format!("ERR invalid expire time in '{}' command", self.command_name().to_lowercase())),
+        }
+    }
+
+    fn command_name(&self) -> &'static str {
+        match self.expiry {
+            Expiry::Ex(_) => "EXPIRE",
+            Expiry::Px(_) => "PEXPIRE",
+            Expiry::ExAt(_) => "EXPIREAT",
+            Expiry::PxAt(_) => "PEXPIREAT",
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let frames = vec![
+            Frame::Bulk(Bytes::from(self.command_name())),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(Bytes::from(self.expiry.value().to_string())),
+        ];
+        Frame::Array(frames)
+    }
+}
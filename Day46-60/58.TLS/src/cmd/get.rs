@@ -1,5 +1,5 @@
 use super::Parse;
-use crate::Error;
+use crate::{Db, Error, Frame};
 
 #[derive(Debug, Clone)]
 pub struct Get {
@@ -12,4 +12,12 @@ impl Get {
 
         Ok(Get { key })
     }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.get_string(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct HSet {
+    pub key: String,
+    pub pairs: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HGet {
+    pub key: String,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct HGetAll {
+    pub key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct HDel {
+    pub key: String,
+    pub fields: Vec<Bytes>,
+}
+
+impl HSet {
+    pub fn parse_frames(parse: &mut Parse) -> Result<HSet, Error> {
+        let key = parse.next_string()?;
+        if parse.remaining() == 0 || !parse.remaining().is_multiple_of(2) {
+            return Err(Error::Other("wrong number of arguments for 'hset' command".into()));
+        }
+
+        let mut pairs = Vec::with_capacity(parse.remaining() / 2);
+        while parse.remaining() > 0 {
+            pairs.push((parse.next_bytes()?, parse.next_bytes()?));
+        }
+
+        Ok(HSet { key, pairs })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.hset(&self.key, self.pairs) {
+            Ok(added) => Frame::Integer(added as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("HSET")), Frame::Bulk(Bytes::from(self.key))];
+        for (field, value) in self.pairs {
+            frames.push(Frame::Bulk(field));
+            frames.push(Frame::Bulk(value));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl HGet {
+    pub fn parse_frames(parse: &mut Parse) -> Result<HGet, Error> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+
+        Ok(HGet { key, field })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.hget(&self.key, &self.field) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("HGET")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(self.field),
+        ])
+    }
+}
+
+impl HGetAll {
+    pub fn parse_frames(parse: &mut Parse) -> Result<HGetAll, Error> {
+        let key = parse.next_string()?;
+
+        Ok(HGetAll { key })
+    }
+
+    /// Replies with a map of fields to values, which RESP2 clients receive as
+    /// a flat array of alternating fields and values.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.hgetall(&self.key) {
+            Ok(pairs) => Frame::Map(
+                pairs
+                    .into_iter()
+                    .map(|(field, value)| (Frame::Bulk(field), Frame::Bulk(value)))
+                    .collect(),
+            ),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("HGETALL")),
+            Frame::Bulk(Bytes::from(self.key)),
+        ])
+    }
+}
+
+impl HDel {
+    pub fn parse_frames(parse: &mut Parse) -> Result<HDel, Error> {
+        let key = parse.next_string()?;
+        let mut fields = vec![parse.next_bytes()?];
+        while parse.remaining() > 0 {
+            fields.push(parse.next_bytes()?);
+        }
+
+        Ok(HDel { key, fields })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.hdel(&self.key, &self.fields) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("HDEL")), Frame::Bulk(Bytes::from(self.key))];
+        frames.extend(self.fields.into_iter().map(Frame::Bulk));
+        Frame::Array(frames)
+    }
+}
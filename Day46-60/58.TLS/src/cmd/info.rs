@@ -0,0 +1,128 @@
+//! `INFO`. Most of what it reports is server state, so the server executes
+//! it.
+
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+use std::fmt::Write;
+
+/// `INFO [section ...]`. Without a section, or with `all` / `default` /
+/// `everything`, every section is reported.
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub sections: Vec<String>,
+}
+
+/// The figures `INFO` reports that the server keeps track of, rather than the
+/// keyspace.
+#[derive(Debug, Default)]
+pub(crate) struct ServerInfo {
+    pub(crate) tcp_port: u16,
+    pub(crate) uptime_in_seconds: u64,
+    pub(crate) config_file: String,
+    pub(crate) connected_clients: usize,
+    pub(crate) maxclients: usize,
+    pub(crate) total_connections_received: u64,
+    pub(crate) total_commands_processed: u64,
+    pub(crate) rejected_connections: u64,
+    pub(crate) pubsub_lag_events: u64,
+    pub(crate) pubsub_dropped_messages: u64,
+    pub(crate) pubsub_lag_disconnections: u64,
+    pub(crate) sync_full: u64,
+    pub(crate) sync_partial_ok: u64,
+    pub(crate) sync_partial_err: u64,
+    pub(crate) replication: Vec<(String, String)>,
+    pub(crate) aof_current_size: u64,
+    pub(crate) aof_rewrite_in_progress: bool,
+    pub(crate) rdb_bgsave_in_progress: bool,
+    pub(crate) rdb_last_save_time: u64,
+}
+
+impl Info {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Info, Error> {
+        let mut sections = vec![];
+        while parse.remaining() > 0 {
+            sections.push(parse.next_string()?.to_lowercase());
+        }
+
+        Ok(Info { sections })
+    }
+
+    pub(crate) fn apply(self, db: &Db, server: &ServerInfo) -> Frame {
+        let all = self.sections.is_empty()
+            || self.sections.iter().any(|s| matches!(s.as_str(), "all" | "default" | "everything"));
+        let wanted = |section: &str| all || self.sections.iter().any(|s| s == section);
+
+        let mut info = String::new();
+        let mut section = |name: &str, fields: &[(&str, String)]| {
+            if !wanted(&name.to_lowercase()) {
+                return;
+            }
+            let _ = write!(info, "# {}\r\n", name);
+            for (field, value) in fields {
+                let _ = write!(info, "{}:{}\r\n", field, value);
+            }
+            info.push_str("\r\n");
+        };
+
+        section("Server", &[
+            ("redis_version", env!("CARGO_PKG_VERSION").to_string()),
+            ("redis_mode", "standalone".to_string()),
+            ("process_id", std::process::id().to_string()),
+            ("tcp_port", server.tcp_port.to_string()),
+            ("uptime_in_seconds", server.uptime_in_seconds.to_string()),
+            ("uptime_in_days", (server.uptime_in_seconds / 86400).to_string()),
+            ("config_file", server.config_file.clone()),
+        ]);
+        section("Clients", &[
+            ("connected_clients", server.connected_clients.to_string()),
+            ("maxclients", server.maxclients.to_string()),
+        ]);
+        section("Memory", &[
+            ("used_memory", db.used_memory().to_string()),
+            ("maxmemory", db.maxmemory().to_string()),
+            ("maxmemory_policy", db.eviction_policy().to_string()),
+        ]);
+        section("Persistence", &[
+            ("rdb_bgsave_in_progress", flag(server.rdb_bgsave_in_progress)),
+            ("rdb_last_save_time", server.rdb_last_save_time.to_string()),
+            ("aof_enabled", "1".to_string()),
+            ("aof_rewrite_in_progress", flag(server.aof_rewrite_in_progress)),
+            ("aof_current_size", server.aof_current_size.to_string()),
+        ]);
+        section("Stats", &[
+            ("total_connections_received", server.total_connections_received.to_string()),
+            ("total_commands_processed", server.total_commands_processed.to_string()),
+            ("rejected_connections", server.rejected_connections.to_string()),
+            ("evicted_keys", db.evicted_keys().to_string()),
+            ("pubsub_channels", db.pub_sub().channels(None).len().to_string()),
+            ("pubsub_patterns", db.pub_sub().numpat().to_string()),
+            ("pubsub_lag_events", server.pubsub_lag_events.to_string()),
+            ("pubsub_dropped_messages", server.pubsub_dropped_messages.to_string()),
+            ("pubsub_lag_disconnections", server.pubsub_lag_disconnections.to_string()),
+            ("sync_full", server.sync_full.to_string()),
+            ("sync_partial_ok", server.sync_partial_ok.to_string()),
+            ("sync_partial_err", server.sync_partial_err.to_string()),
+        ]);
+        let replication: Vec<(&str, String)> = server.replication.iter().map(|(field, value)| (field.as_str(), value.clone())).collect();
+        section("Replication", &replication);
+        let keys = db.dbsize();
+        let keyspace = match keys {
+            0 => vec![],
+            _ => vec![("db0", format!("keys={},expires={}", keys, db.expires()))],
+        };
+        section("Keyspace", &keyspace);
+
+        Frame::Verbatim("txt".to_string(), Bytes::from(info))
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("INFO"))];
+        frames.extend(self.sections.into_iter().map(|s| Frame::Bulk(Bytes::from(s))));
+        Frame::Array(frames)
+    }
+}
+
+fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
@@ -1,4 +1,6 @@
 use crate::cmd::{Get, Set, Unknown, Command};
+use crate::cmd::expire::Expiry;
+use crate::db::SetCondition;
 use crate::Frame;
 use bytes::Bytes;
 
@@ -9,7 +11,72 @@ impl Command {
             Command::Set(cmd) => cmd.into_frame(),
             Command::Publish(cmd) => cmd.into_frame(),
             Command::Subscribe(cmd) => cmd.into_frame(),
+            Command::Unsubscribe(cmd) => cmd.into_frame(),
+            Command::PubSub(cmd) => cmd.into_frame(),
             Command::Ping(cmd) => cmd.into_frame(),
+            Command::Expire(cmd) => cmd.into_frame(),
+            Command::Ttl(cmd) => cmd.into_frame(),
+            Command::Persist(cmd) => cmd.into_frame(),
+            Command::BgRewriteAof(cmd) => cmd.into_frame(),
+            Command::Save(cmd) => cmd.into_frame(),
+            Command::BgSave(cmd) => cmd.into_frame(),
+            Command::Del(cmd) => cmd.into_frame(),
+            Command::Exists(cmd) => cmd.into_frame(),
+            Command::Type(cmd) => cmd.into_frame(),
+            Command::Rename(cmd) => cmd.into_frame(),
+            Command::DbSize(cmd) => cmd.into_frame(),
+            Command::FlushDb(cmd) => cmd.into_frame(),
+            Command::Keys(cmd) => cmd.into_frame(),
+            Command::Scan(cmd) => cmd.into_frame(),
+            Command::IncrBy(cmd) => cmd.into_frame(),
+            Command::IncrByFloat(cmd) => cmd.into_frame(),
+            Command::Append(cmd) => cmd.into_frame(),
+            Command::Strlen(cmd) => cmd.into_frame(),
+            Command::MGet(cmd) => cmd.into_frame(),
+            Command::MSet(cmd) => cmd.into_frame(),
+            Command::GetSet(cmd) => cmd.into_frame(),
+            Command::GetDel(cmd) => cmd.into_frame(),
+            Command::SetRange(cmd) => cmd.into_frame(),
+            Command::GetRange(cmd) => cmd.into_frame(),
+            Command::Push(cmd) => cmd.into_frame(),
+            Command::Pop(cmd) => cmd.into_frame(),
+            Command::BPop(cmd) => cmd.into_frame(),
+            Command::LRange(cmd) => cmd.into_frame(),
+            Command::HSet(cmd) => cmd.into_frame(),
+            Command::HGet(cmd) => cmd.into_frame(),
+            Command::HGetAll(cmd) => cmd.into_frame(),
+            Command::HDel(cmd) => cmd.into_frame(),
+            Command::SAdd(cmd) => cmd.into_frame(),
+            Command::SRem(cmd) => cmd.into_frame(),
+            Command::SMembers(cmd) => cmd.into_frame(),
+            Command::SIsMember(cmd) => cmd.into_frame(),
+            Command::ZAdd(cmd) => cmd.into_frame(),
+            Command::ZRange(cmd) => cmd.into_frame(),
+            Command::ZRank(cmd) => cmd.into_frame(),
+            Command::ZScore(cmd) => cmd.into_frame(),
+            Command::XAdd(cmd) => cmd.into_frame(),
+            Command::XLen(cmd) => cmd.into_frame(),
+            Command::XRange(cmd) => cmd.into_frame(),
+            Command::XRead(cmd) => cmd.into_frame(),
+            Command::XGroup(cmd) => cmd.into_frame(),
+            Command::XAck(cmd) => cmd.into_frame(),
+            Command::XPending(cmd) => cmd.into_frame(),
+            Command::XClaim(cmd) => cmd.into_frame(),
+            Command::XSetId(cmd) => cmd.into_frame(),
+            Command::Multi(cmd) => cmd.into_frame(),
+            Command::Exec(cmd) => cmd.into_frame(),
+            Command::Discard(cmd) => cmd.into_frame(),
+            Command::Watch(cmd) => cmd.into_frame(),
+            Command::Unwatch(cmd) => cmd.into_frame(),
+            Command::Hello(cmd) => cmd.into_frame(),
+            Command::Client(cmd) => cmd.into_frame(),
+            Command::Info(cmd) => cmd.into_frame(),
+            Command::Config(cmd) => cmd.into_frame(),
+            Command::Auth(cmd) => cmd.into_frame(),
+            Command::Acl(cmd) => cmd.into_frame(),
+            Command::ReplicaOf(cmd) => cmd.into_frame(),
+            Command::ReplConf(cmd) => cmd.into_frame(),
+            Command::Psync(cmd) => cmd.into_frame(),
             Command::Unknown(cmd) => cmd.into_frame(),
         }
     }
@@ -17,19 +84,39 @@ impl Command {
 
 impl Get {
     pub fn into_frame(self) -> Frame {
-        let mut frames = Vec::new();
-        frames.push(Frame::Bulk(Bytes::from("GET")));
-        frames.push(Frame::Bulk(Bytes::from(self.key)));
+        let frames = vec![
+            Frame::Bulk(Bytes::from("GET")),
+            Frame::Bulk(Bytes::from(self.key)),
+        ];
         Frame::Array(frames)
     }
 }
 
 impl Set {
     pub fn into_frame(self) -> Frame {
-        let mut frames = Vec::new();
-        frames.push(Frame::Bulk(Bytes::from("SET")));
-        frames.push(Frame::Bulk(Bytes::from(self.key)));
-        frames.push(Frame::Bulk(self.value));
+        let mut frames = vec![
+            Frame::Bulk(Bytes::from("SET")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(self.value),
+        ];
+        match self.condition {
+            SetCondition::Always => {}
+            SetCondition::IfNotExists => frames.push(Frame::Bulk(Bytes::from("NX"))),
+            SetCondition::IfExists => frames.push(Frame::Bulk(Bytes::from("XX"))),
+        }
+        if let Some(expire) = self.expire {
+            let (option, n) = match expire {
+                Expiry::Ex(n) => ("EX", n),
+                Expiry::Px(n) => ("PX", n),
+                Expiry::ExAt(n) => ("EXAT", n),
+                Expiry::PxAt(n) => ("PXAT", n),
+            };
+            frames.push(Frame::Bulk(Bytes::from(option)));
+            frames.push(Frame::Bulk(Bytes::from(n.to_string())));
+        }
+        if self.keep_ttl {
+            frames.push(Frame::Bulk(Bytes::from("KEEPTTL")));
+        }
         Frame::Array(frames)
     }
 }
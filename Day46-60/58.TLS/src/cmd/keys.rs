@@ -0,0 +1,231 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+/// `DEL` and `UNLINK`. Values are always freed after the `Db` lock has been
+/// released, so the two behave the same.
+#[derive(Debug, Clone)]
+pub struct Del {
+    pub keys: Vec<String>,
+    pub unlink: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exists {
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Type {
+    pub key: String,
+}
+
+/// `RENAME` and `RENAMENX`.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub key: String,
+    pub new_key: String,
+    pub nx: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DbSize;
+
+#[derive(Debug, Clone)]
+pub struct FlushDb;
+
+#[derive(Debug, Clone)]
+pub struct Keys {
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub cursor: u64,
+    pub pattern: Option<String>,
+    pub count: usize,
+}
+
+/// Parses `key [key ...]`.
+fn parse_keys(parse: &mut Parse) -> Result<Vec<String>, Error> {
+    let mut keys = vec![parse.next_string()?];
+    while parse.remaining() > 0 {
+        keys.push(parse.next_string()?);
+    }
+    Ok(keys)
+}
+
+fn keys_frame(name: &str, keys: Vec<String>) -> Frame {
+    let mut frames = vec![Frame::Bulk(Bytes::from(name.to_string()))];
+    frames.extend(keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))));
+    Frame::Array(frames)
+}
+
+impl Del {
+    pub fn parse_frames(parse: &mut Parse, unlink: bool) -> Result<Del, Error> {
+        let keys = parse_keys(parse)?;
+
+        Ok(Del { keys, unlink })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.del(&self.keys) as i64)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame(if self.unlink { "UNLINK" } else { "DEL" }, self.keys)
+    }
+}
+
+impl Exists {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Exists, Error> {
+        let keys = parse_keys(parse)?;
+
+        Ok(Exists { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.exists(&self.keys) as i64)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("EXISTS", self.keys)
+    }
+}
+
+impl Type {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Type, Error> {
+        let key = parse.next_string()?;
+
+        Ok(Type { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Simple(db.key_type(&self.key).to_string())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("TYPE", vec![self.key])
+    }
+}
+
+impl Rename {
+    pub fn parse_frames(parse: &mut Parse, nx: bool) -> Result<Rename, Error> {
+        let key = parse.next_string()?;
+        let new_key = parse.next_string()?;
+
+        Ok(Rename { key, new_key, nx })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.rename(&self.key, &self.new_key, self.nx) {
+            Ok(renamed) if self.nx => Frame::Integer(renamed as i64),
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame(if self.nx { "RENAMENX" } else { "RENAME" }, vec![self.key, self.new_key])
+    }
+}
+
+impl DbSize {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<DbSize, Error> {
+        Ok(DbSize)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.dbsize() as i64)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("DBSIZE", vec![])
+    }
+}
+
+impl FlushDb {
+    /// Accepts and ignores the `ASYNC` / `SYNC` modifiers.
+    pub fn parse_frames(parse: &mut Parse) -> Result<FlushDb, Error> {
+        if parse.remaining() > 0 {
+            let mode = parse.next_string()?;
+            if !mode.eq_ignore_ascii_case("async") && !mode.eq_ignore_ascii_case("sync") {
+                return Err(Error::Other("syntax error".into()));
+            }
+        }
+
+        Ok(FlushDb)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.flush();
+        Frame::Simple("OK".to_string())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("FLUSHDB", vec![])
+    }
+}
+
+impl Keys {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Keys, Error> {
+        let pattern = parse.next_string()?;
+
+        Ok(Keys { pattern })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let keys = db.keys(&self.pattern);
+        Frame::Array(keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))).collect())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        keys_frame("KEYS", vec![self.pattern])
+    }
+}
+
+impl Scan {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Scan, Error> {
+        let cursor = parse
+            .next_string()?
+            .parse()
+            .map_err(|_| Error::Other("invalid cursor".into()))?;
+        let mut pattern = None;
+        let mut count = 10;
+
+        while parse.remaining() > 0 {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "MATCH" => pattern = Some(parse.next_string()?),
+                "COUNT" => {
+                    count = match parse.next_int()? {
+                        n if n >= 1 => n as usize,
+                        _ => return Err(Error::Other("syntax error".into())),
+                    }
+                }
+                _ => return Err(Error::Other("syntax error".into())),
+            }
+        }
+
+        Ok(Scan { cursor, pattern, count })
+    }
+
+    /// Replies with `[next cursor, [key ...]]`.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let (next, keys) = db.scan(self.cursor, self.pattern.as_deref(), self.count);
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(next.to_string())),
+            Frame::Array(keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))).collect()),
+        ])
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut args = vec![self.cursor.to_string()];
+        if let Some(pattern) = self.pattern {
+            args.push("MATCH".to_string());
+            args.push(pattern);
+        }
+        args.push("COUNT".to_string());
+        args.push(self.count.to_string());
+        keys_frame("SCAN", args)
+    }
+}
@@ -0,0 +1,196 @@
+use super::Parse;
+use crate::db::DbError;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// `LPUSH` and `RPUSH`.
+#[derive(Debug, Clone)]
+pub struct Push {
+    pub key: String,
+    pub values: Vec<Bytes>,
+    /// Push onto the head of the list (`LPUSH`) rather than the tail.
+    pub left: bool,
+}
+
+/// `LPOP` and `RPOP`.
+#[derive(Debug, Clone)]
+pub struct Pop {
+    pub key: String,
+    /// With a count the reply is an array, even for a single element.
+    pub count: Option<usize>,
+    pub left: bool,
+}
+
+/// `BLPOP` and `BRPOP`. Executed by the server, which parks the connection
+/// until one of the lists has a value or the timeout elapses.
+#[derive(Debug, Clone)]
+pub struct BPop {
+    pub keys: Vec<String>,
+    /// `None` blocks indefinitely (a timeout of `0`).
+    pub timeout: Option<Duration>,
+    pub left: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LRange {
+    pub key: String,
+    pub start: i64,
+    pub stop: i64,
+}
+
+impl Push {
+    pub fn parse_frames(parse: &mut Parse, left: bool) -> Result<Push, Error> {
+        let key = parse.next_string()?;
+        let mut values = vec![parse.next_bytes()?];
+        while parse.remaining() > 0 {
+            values.push(parse.next_bytes()?);
+        }
+
+        Ok(Push { key, values, left })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.push(&self.key, self.values, self.left) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let name = if self.left { "LPUSH" } else { "RPUSH" };
+        let mut frames = vec![Frame::Bulk(Bytes::from(name)), Frame::Bulk(Bytes::from(self.key))];
+        frames.extend(self.values.into_iter().map(Frame::Bulk));
+        Frame::Array(frames)
+    }
+}
+
+impl Pop {
+    pub fn parse_frames(parse: &mut Parse, left: bool) -> Result<Pop, Error> {
+        let key = parse.next_string()?;
+        let count = if parse.remaining() > 0 {
+            let count = parse.next_int()?;
+            if count < 0 {
+                return Err(Error::Other("value is out of range, must be positive".into()));
+            }
+            Some(count as usize)
+        } else {
+            None
+        };
+
+        Ok(Pop { key, count, left })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.pop(&self.key, self.count.unwrap_or(1), self.left) {
+            Ok(None) => Frame::Null,
+            Ok(Some(values)) if self.count.is_some() => Frame::Array(values.into_iter().map(Frame::Bulk).collect()),
+            Ok(Some(mut values)) => values.pop().map_or(Frame::Null, Frame::Bulk),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let name = if self.left { "LPOP" } else { "RPOP" };
+        let mut frames = vec![Frame::Bulk(Bytes::from(name)), Frame::Bulk(Bytes::from(self.key))];
+        if let Some(count) = self.count {
+            frames.push(Frame::Bulk(Bytes::from(count.to_string())));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl BPop {
+    /// Pops without blocking, as `BLPOP` / `BRPOP` do inside `MULTI`. Replies
+    /// with `[key, value]` for the first non-empty list, or null.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        self.pop(|key| Ok(db.pop(key, 1, self.left)?.and_then(|mut values| values.pop())))
+    }
+
+    /// Pops for `waiter`, a client blocked in this command, which only gets
+    /// a value once the clients that blocked before it have theirs.
+    pub(crate) fn apply_waiting(self, db: &Db, waiter: &Arc<Notify>) -> Frame {
+        self.pop(|key| db.pop_waiting(key, self.left, waiter))
+    }
+
+    fn pop(&self, mut pop: impl FnMut(&str) -> Result<Option<Bytes>, DbError>) -> Frame {
+        for key in &self.keys {
+            match pop(key) {
+                Ok(Some(value)) => return Frame::Array(vec![Frame::Bulk(Bytes::from(key.clone())), Frame::Bulk(value)]),
+                Ok(None) => {}
+                Err(e) => return Frame::Error(e.to_string()),
+            }
+        }
+        Frame::Null
+    }
+
+    /// The `LPOP` / `RPOP` that has the same effect as this command had when
+    /// it replied with `response`, for the AOF.
+    pub(crate) fn as_pop(&self, response: &Frame) -> Option<Pop> {
+        match response {
+            Frame::Array(items) => match items.first() {
+                Some(Frame::Bulk(key)) => Some(Pop {
+                    key: String::from_utf8(key.to_vec()).ok()?,
+                    count: None,
+                    left: self.left,
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn parse_frames(parse: &mut Parse, left: bool) -> Result<BPop, Error> {
+        let mut keys = vec![parse.next_string()?];
+        let mut timeout = parse.next_string()?;
+        while parse.remaining() > 0 {
+            keys.push(std::mem::replace(&mut timeout, parse.next_string()?));
+        }
+
+        let timeout = match timeout.parse::<f64>() {
+            Ok(secs) if secs < 0.0 => return Err(Error::Other("timeout is negative".into())),
+            Ok(0.0) => None,
+            Ok(secs) if secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+            _ => return Err(Error::Other("timeout is not a float or out of range".into())),
+        };
+
+        Ok(BPop { keys, timeout, left })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let name = if self.left { "BLPOP" } else { "BRPOP" };
+        let mut frames = vec![Frame::Bulk(Bytes::from(name))];
+        frames.extend(self.keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))));
+        let timeout = self.timeout.map_or(0.0, |t| t.as_secs_f64());
+        frames.push(Frame::Bulk(Bytes::from(timeout.to_string())));
+        Frame::Array(frames)
+    }
+}
+
+impl LRange {
+    pub fn parse_frames(parse: &mut Parse) -> Result<LRange, Error> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+
+        Ok(LRange { key, start, stop })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.lrange(&self.key, self.start, self.stop) {
+            Ok(values) => Frame::Array(values.into_iter().map(Frame::Bulk).collect()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("LRANGE")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(Bytes::from(self.start.to_string())),
+            Frame::Bulk(Bytes::from(self.stop.to_string())),
+        ])
+    }
+}
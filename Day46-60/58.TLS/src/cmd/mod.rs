@@ -2,17 +2,58 @@ pub mod get;
 pub mod set;
 pub mod publish;
 pub mod subscribe;
+pub mod pubsub;
 pub mod unknown;
 pub mod ping;
+pub mod expire;
+pub mod ttl;
+pub mod persist;
+pub mod bgrewriteaof;
+pub mod save;
+pub mod bgsave;
+pub mod string;
+pub mod keys;
+pub mod list;
+pub mod hash;
+pub mod sets;
+pub mod zset;
+pub mod stream;
+pub mod transaction;
+pub mod connection;
+pub mod info;
+pub mod config;
+pub mod acl;
+pub mod replication;
+pub mod sentinel;
 pub mod into_frame;
 
-use crate::{Frame, Error};
+use crate::{Db, Frame, Error};
 use self::get::Get;
 use self::set::Set;
 use self::publish::Publish;
-use self::subscribe::Subscribe;
+use self::subscribe::{Subscribe, Unsubscribe};
+use self::pubsub::PubSubCommand;
 use self::unknown::Unknown;
 use self::ping::Ping;
+use self::expire::{Expire, Expiry};
+use self::ttl::Ttl;
+use self::persist::Persist;
+use self::bgrewriteaof::BgRewriteAof;
+use self::save::Save;
+use self::bgsave::BgSave;
+use self::string::{IncrBy, IncrByFloat, Append, Strlen, MGet, MSet, GetSet, GetDel, SetRange, GetRange};
+use self::keys::{Del, Exists, Type, Rename, DbSize, FlushDb, Keys, Scan};
+use self::transaction::{Multi, Exec, Discard, Watch, Unwatch};
+use self::connection::{Hello, Auth, Client};
+use self::info::Info;
+use self::config::ConfigCommand;
+use self::acl::AclCommand;
+use self::replication::{ReplicaOf, ReplConf, Psync};
+use self::list::{Push, Pop, BPop, LRange};
+use self::hash::{HSet, HGet, HGetAll, HDel};
+use self::sets::{SAdd, SRem, SMembers, SIsMember};
+use self::zset::{ZAdd, ZRange, ZRank, ZScore};
+use self::stream::{XAdd, XLen, XRange, XRead, XGroupCommand, XAck, XPending, XClaim, XSetId};
 
 #[derive(Debug, Clone)]
 pub enum Command {
@@ -20,7 +61,72 @@ pub enum Command {
     Set(Set),
     Publish(Publish),
     Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PubSub(PubSubCommand),
     Ping(Ping),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    BgRewriteAof(BgRewriteAof),
+    Save(Save),
+    BgSave(BgSave),
+    Del(Del),
+    Exists(Exists),
+    Type(Type),
+    Rename(Rename),
+    DbSize(DbSize),
+    FlushDb(FlushDb),
+    Keys(Keys),
+    Scan(Scan),
+    IncrBy(IncrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    Strlen(Strlen),
+    MGet(MGet),
+    MSet(MSet),
+    GetSet(GetSet),
+    GetDel(GetDel),
+    SetRange(SetRange),
+    GetRange(GetRange),
+    Push(Push),
+    Pop(Pop),
+    BPop(BPop),
+    LRange(LRange),
+    HSet(HSet),
+    HGet(HGet),
+    HGetAll(HGetAll),
+    HDel(HDel),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    ZAdd(ZAdd),
+    ZRange(ZRange),
+    ZRank(ZRank),
+    ZScore(ZScore),
+    XAdd(XAdd),
+    XLen(XLen),
+    XRange(XRange),
+    XRead(XRead),
+    XGroup(XGroupCommand),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XSetId(XSetId),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Hello(Hello),
+    Auth(Auth),
+    Client(Client),
+    Info(Info),
+    Config(ConfigCommand),
+    Acl(AclCommand),
+    ReplicaOf(ReplicaOf),
+    ReplConf(ReplConf),
+    Psync(Psync),
     Unknown(Unknown),
 }
 
@@ -34,8 +140,90 @@ impl Command {
             "get" => Command::Get(Get::parse_frames(&mut parse)?),
             "set" => Command::Set(Set::parse_frames(&mut parse)?),
             "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
-            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse, false)?),
+            "psubscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse, true)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse, false)?),
+            "punsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse, true)?),
+            "pubsub" => Command::PubSub(PubSubCommand::parse_frames(&mut parse)?),
             "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(&mut parse, Expiry::Ex)?),
+            "pexpire" => Command::Expire(Expire::parse_frames(&mut parse, Expiry::Px)?),
+            "expireat" => Command::Expire(Expire::parse_frames(&mut parse, Expiry::ExAt)?),
+            "pexpireat" => Command::Expire(Expire::parse_frames(&mut parse, Expiry::PxAt)?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(&mut parse, false)?),
+            "pttl" => Command::Ttl(Ttl::parse_frames(&mut parse, true)?),
+            "persist" => Command::Persist(Persist::parse_frames(&mut parse)?),
+            "bgrewriteaof" => Command::BgRewriteAof(BgRewriteAof::parse_frames(&mut parse)?),
+            "save" => Command::Save(Save::parse_frames(&mut parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(&mut parse)?),
+            "del" => Command::Del(Del::parse_frames(&mut parse, false)?),
+            "unlink" => Command::Del(Del::parse_frames(&mut parse, true)?),
+            "exists" => Command::Exists(Exists::parse_frames(&mut parse)?),
+            "type" => Command::Type(Type::parse_frames(&mut parse)?),
+            "rename" => Command::Rename(Rename::parse_frames(&mut parse, false)?),
+            "renamenx" => Command::Rename(Rename::parse_frames(&mut parse, true)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(&mut parse)?),
+            "flushdb" => Command::FlushDb(FlushDb::parse_frames(&mut parse)?),
+            "keys" => Command::Keys(Keys::parse_frames(&mut parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(&mut parse)?),
+            "incr" => Command::IncrBy(IncrBy::parse_unit(&mut parse, 1)?),
+            "decr" => Command::IncrBy(IncrBy::parse_unit(&mut parse, -1)?),
+            "incrby" => Command::IncrBy(IncrBy::parse_frames(&mut parse, false)?),
+            "decrby" => Command::IncrBy(IncrBy::parse_frames(&mut parse, true)?),
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(&mut parse)?),
+            "append" => Command::Append(Append::parse_frames(&mut parse)?),
+            "strlen" => Command::Strlen(Strlen::parse_frames(&mut parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(&mut parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(&mut parse, false)?),
+            "msetnx" => Command::MSet(MSet::parse_frames(&mut parse, true)?),
+            "getset" => Command::GetSet(GetSet::parse_frames(&mut parse)?),
+            "getdel" => Command::GetDel(GetDel::parse_frames(&mut parse)?),
+            "setrange" => Command::SetRange(SetRange::parse_frames(&mut parse)?),
+            "getrange" => Command::GetRange(GetRange::parse_frames(&mut parse)?),
+            "lpush" => Command::Push(Push::parse_frames(&mut parse, true)?),
+            "rpush" => Command::Push(Push::parse_frames(&mut parse, false)?),
+            "lpop" => Command::Pop(Pop::parse_frames(&mut parse, true)?),
+            "rpop" => Command::Pop(Pop::parse_frames(&mut parse, false)?),
+            "blpop" => Command::BPop(BPop::parse_frames(&mut parse, true)?),
+            "brpop" => Command::BPop(BPop::parse_frames(&mut parse, false)?),
+            "lrange" => Command::LRange(LRange::parse_frames(&mut parse)?),
+            "hset" => Command::HSet(HSet::parse_frames(&mut parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(&mut parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(&mut parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(&mut parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(&mut parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(&mut parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(&mut parse)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(&mut parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(&mut parse)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(&mut parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(&mut parse)?),
+            "zscore" => Command::ZScore(ZScore::parse_frames(&mut parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(&mut parse)?),
+            "xlen" => Command::XLen(XLen::parse_frames(&mut parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(&mut parse, false)?),
+            "xrevrange" => Command::XRange(XRange::parse_frames(&mut parse, true)?),
+            "xread" => Command::XRead(XRead::parse_frames(&mut parse, false)?),
+            "xreadgroup" => Command::XRead(XRead::parse_frames(&mut parse, true)?),
+            "xgroup" => Command::XGroup(XGroupCommand::parse_frames(&mut parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(&mut parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(&mut parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(&mut parse)?),
+            "xsetid" => Command::XSetId(XSetId::parse_frames(&mut parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(&mut parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(&mut parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(&mut parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(&mut parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(&mut parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(&mut parse)?),
+            "client" => Command::Client(Client::parse_frames(&mut parse)?),
+            "info" => Command::Info(Info::parse_frames(&mut parse)?),
+            "config" => Command::Config(ConfigCommand::parse_frames(&mut parse)?),
+            "acl" => Command::Acl(AclCommand::parse_frames(&mut parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(&mut parse)?),
+            "replconf" => Command::ReplConf(ReplConf::parse_frames(&mut parse)?),
+            "psync" => Command::Psync(Psync::parse_frames(&mut parse)?),
             _ => {
                 return Ok(Command::Unknown(Unknown::new(command_name)));
             }
@@ -45,6 +233,317 @@ impl Command {
 
         Ok(command)
     }
+    /// Executes a command that only touches the keyspace and returns the reply.
+    ///
+    /// Commands that need the connection or server state (`SUBSCRIBE`,
+    /// persistence commands, ...) are handled by the server itself.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Command::Get(cmd) => cmd.apply(db),
+            Command::Set(cmd) => cmd.apply(db),
+            Command::Publish(cmd) => cmd.apply(db),
+            Command::PubSub(cmd) => cmd.apply(db),
+            Command::Ping(cmd) => cmd.apply(),
+            Command::Expire(cmd) => cmd.apply(db),
+            Command::Ttl(cmd) => cmd.apply(db),
+            Command::Persist(cmd) => cmd.apply(db),
+            Command::Del(cmd) => cmd.apply(db),
+            Command::Exists(cmd) => cmd.apply(db),
+            Command::Type(cmd) => cmd.apply(db),
+            Command::Rename(cmd) => cmd.apply(db),
+            Command::DbSize(cmd) => cmd.apply(db),
+            Command::FlushDb(cmd) => cmd.apply(db),
+            Command::Keys(cmd) => cmd.apply(db),
+            Command::Scan(cmd) => cmd.apply(db),
+            Command::IncrBy(cmd) => cmd.apply(db),
+            Command::IncrByFloat(cmd) => cmd.apply(db),
+            Command::Append(cmd) => cmd.apply(db),
+            Command::Strlen(cmd) => cmd.apply(db),
+            Command::MGet(cmd) => cmd.apply(db),
+            Command::MSet(cmd) => cmd.apply(db),
+            Command::GetSet(cmd) => cmd.apply(db),
+            Command::GetDel(cmd) => cmd.apply(db),
+            Command::SetRange(cmd) => cmd.apply(db),
+            Command::GetRange(cmd) => cmd.apply(db),
+            Command::Push(cmd) => cmd.apply(db),
+            Command::Pop(cmd) => cmd.apply(db),
+            Command::BPop(cmd) => cmd.apply(db),
+            Command::LRange(cmd) => cmd.apply(db),
+            Command::HSet(cmd) => cmd.apply(db),
+            Command::HGet(cmd) => cmd.apply(db),
+            Command::HGetAll(cmd) => cmd.apply(db),
+            Command::HDel(cmd) => cmd.apply(db),
+            Command::SAdd(cmd) => cmd.apply(db),
+            Command::SRem(cmd) => cmd.apply(db),
+            Command::SMembers(cmd) => cmd.apply(db),
+            Command::SIsMember(cmd) => cmd.apply(db),
+            Command::ZAdd(cmd) => cmd.apply(db),
+            Command::ZRange(cmd) => cmd.apply(db),
+            Command::ZRank(cmd) => cmd.apply(db),
+            Command::ZScore(cmd) => cmd.apply(db),
+            Command::XAdd(cmd) => cmd.apply(db),
+            Command::XLen(cmd) => cmd.apply(db),
+            Command::XRange(cmd) => cmd.apply(db),
+            Command::XRead(cmd) => cmd.apply(db),
+            Command::XGroup(cmd) => cmd.apply(db),
+            Command::XAck(cmd) => cmd.apply(db),
+            Command::XPending(cmd) => cmd.apply(db),
+            Command::XClaim(cmd) => cmd.apply(db),
+            Command::XSetId(cmd) => cmd.apply(db),
+            Command::Unknown(cmd) => cmd.apply(),
+            cmd => Frame::Error(format!("ERR '{}' is not allowed in this context", cmd.name())),
+        }
+    }
+
+    /// Returns `true` for commands that modify the keyspace and therefore
+    /// have to be written to the AOF.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_)
+                | Command::Expire(_)
+                | Command::Persist(_)
+                | Command::Del(_)
+                | Command::Rename(_)
+                | Command::FlushDb(_)
+                | Command::IncrBy(_)
+                | Command::IncrByFloat(_)
+                | Command::Append(_)
+                | Command::MSet(_)
+                | Command::GetSet(_)
+                | Command::GetDel(_)
+                | Command::SetRange(_)
+                | Command::Push(_)
+                | Command::Pop(_)
+                | Command::HSet(_)
+                | Command::HDel(_)
+                | Command::SAdd(_)
+                | Command::SRem(_)
+                | Command::ZAdd(_)
+                | Command::XAdd(_)
+                | Command::XGroup(_)
+                | Command::XAck(_)
+                | Command::XClaim(_)
+                | Command::XSetId(_)
+        ) || matches!(self, Command::XRead(cmd) if cmd.group.is_some())
+    }
+
+    /// Returns `true` for commands that may need more memory. Over
+    /// `maxmemory`, keys are evicted before they run, or they are refused with
+    /// an `OOM` error if nothing can be evicted.
+    pub fn uses_memory(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_)
+                | Command::IncrBy(_)
+                | Command::IncrByFloat(_)
+                | Command::Append(_)
+                | Command::MSet(_)
+                | Command::GetSet(_)
+                | Command::SetRange(_)
+                | Command::Push(_)
+                | Command::HSet(_)
+                | Command::SAdd(_)
+                | Command::ZAdd(_)
+                | Command::XAdd(_)
+        )
+    }
+
+    /// Rewrites relative expiration times into absolute ones, so the command
+    /// has the same effect whenever it is replayed from the AOF.
+    pub fn with_absolute_expiry(self, now: u64) -> Command {
+        match self {
+            Command::Set(mut cmd) => {
+                cmd.expire = cmd.expire.map(|e| e.to_absolute(now));
+                Command::Set(cmd)
+            }
+            Command::Expire(mut cmd) => {
+                cmd.expiry = cmd.expiry.to_absolute(now);
+                Command::Expire(cmd)
+            }
+            Command::XClaim(mut cmd) => {
+                if let Some(idle) = cmd.options.idle.take() {
+                    cmd.options.time = Some(now.saturating_sub(idle));
+                }
+                Command::XClaim(cmd)
+            }
+            cmd => cmd,
+        }
+    }
+
+    /// The command to log to the AOF for a write that replied with
+    /// `response`, or `None` if it had no effect worth replaying. Commands
+    /// whose effect depends on when they ran are pinned to what they did.
+    pub fn with_outcome(self, response: &Frame) -> Option<Command> {
+        match self {
+            Command::XAdd(cmd) => cmd.with_outcome(response).map(Command::XAdd),
+            Command::XClaim(cmd) => cmd.with_outcome(response).map(Command::XClaim),
+            Command::XRead(mut cmd) => {
+                cmd.block = None;
+                Some(Command::XRead(cmd))
+            }
+            cmd => Some(cmd),
+        }
+    }
+
+    /// The keys the command reads or writes.
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::Get(cmd) => vec![&cmd.key],
+            Command::Set(cmd) => vec![&cmd.key],
+            Command::Expire(cmd) => vec![&cmd.key],
+            Command::Ttl(cmd) => vec![&cmd.key],
+            Command::Persist(cmd) => vec![&cmd.key],
+            Command::Del(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::Exists(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::Type(cmd) => vec![&cmd.key],
+            Command::Rename(cmd) => vec![&cmd.key, &cmd.new_key],
+            Command::IncrBy(cmd) => vec![&cmd.key],
+            Command::IncrByFloat(cmd) => vec![&cmd.key],
+            Command::Append(cmd) => vec![&cmd.key],
+            Command::Strlen(cmd) => vec![&cmd.key],
+            Command::MGet(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::MSet(cmd) => cmd.pairs.iter().map(|(key, _)| key.as_str()).collect(),
+            Command::GetSet(cmd) => vec![&cmd.key],
+            Command::GetDel(cmd) => vec![&cmd.key],
+            Command::SetRange(cmd) => vec![&cmd.key],
+            Command::GetRange(cmd) => vec![&cmd.key],
+            Command::Push(cmd) => vec![&cmd.key],
+            Command::Pop(cmd) => vec![&cmd.key],
+            Command::BPop(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::LRange(cmd) => vec![&cmd.key],
+            Command::HSet(cmd) => vec![&cmd.key],
+            Command::HGet(cmd) => vec![&cmd.key],
+            Command::HGetAll(cmd) => vec![&cmd.key],
+            Command::HDel(cmd) => vec![&cmd.key],
+            Command::SAdd(cmd) => vec![&cmd.key],
+            Command::SRem(cmd) => vec![&cmd.key],
+            Command::SMembers(cmd) => vec![&cmd.key],
+            Command::SIsMember(cmd) => vec![&cmd.key],
+            Command::ZAdd(cmd) => vec![&cmd.key],
+            Command::ZRange(cmd) => vec![&cmd.key],
+            Command::ZRank(cmd) => vec![&cmd.key],
+            Command::ZScore(cmd) => vec![&cmd.key],
+            Command::XAdd(cmd) => vec![&cmd.key],
+            Command::XLen(cmd) => vec![&cmd.key],
+            Command::XRange(cmd) => vec![&cmd.key],
+            Command::XRead(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::XGroup(cmd) => vec![cmd.key()],
+            Command::XAck(cmd) => vec![&cmd.key],
+            Command::XPending(cmd) => vec![&cmd.key],
+            Command::XClaim(cmd) => vec![&cmd.key],
+            Command::XSetId(cmd) => vec![&cmd.key],
+            Command::Watch(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::Publish(_)
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PubSub(_)
+            | Command::Ping(_)
+            | Command::BgRewriteAof(_)
+            | Command::Save(_)
+            | Command::BgSave(_)
+            | Command::DbSize(_)
+            | Command::FlushDb(_)
+            | Command::Keys(_)
+            | Command::Scan(_)
+            | Command::Multi(_)
+            | Command::Exec(_)
+            | Command::Discard(_)
+            | Command::Unwatch(_)
+            | Command::Hello(_)
+            | Command::Client(_)
+            | Command::Info(_)
+            | Command::Config(_)
+            | Command::Auth(_)
+            | Command::Acl(_)
+            | Command::ReplicaOf(_)
+            | Command::ReplConf(_)
+            | Command::Psync(_)
+            | Command::Unknown(_) => vec![],
+        }
+    }
+
+    /// The lowercase command name.
+    pub fn name(&self) -> &str {
+        match self {
+            Command::Get(_) => "get",
+            Command::Set(_) => "set",
+            Command::Publish(_) => "publish",
+            Command::Subscribe(cmd) => if cmd.pattern { "psubscribe" } else { "subscribe" },
+            Command::Unsubscribe(cmd) => if cmd.pattern { "punsubscribe" } else { "unsubscribe" },
+            Command::PubSub(_) => "pubsub",
+            Command::Ping(_) => "ping",
+            Command::Expire(cmd) => match cmd.expiry {
+                Expiry::Ex(_) => "expire",
+                Expiry::Px(_) => "pexpire",
+                Expiry::ExAt(_) => "expireat",
+                Expiry::PxAt(_) => "pexpireat",
+            },
+            Command::Ttl(cmd) => if cmd.millis { "pttl" } else { "ttl" },
+            Command::Persist(_) => "persist",
+            Command::BgRewriteAof(_) => "bgrewriteaof",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::Del(cmd) => if cmd.unlink { "unlink" } else { "del" },
+            Command::Exists(_) => "exists",
+            Command::Type(_) => "type",
+            Command::Rename(cmd) => if cmd.nx { "renamenx" } else { "rename" },
+            Command::DbSize(_) => "dbsize",
+            Command::FlushDb(_) => "flushdb",
+            Command::Keys(_) => "keys",
+            Command::Scan(_) => "scan",
+            Command::IncrBy(_) => "incrby",
+            Command::IncrByFloat(_) => "incrbyfloat",
+            Command::Append(_) => "append",
+            Command::Strlen(_) => "strlen",
+            Command::MGet(_) => "mget",
+            Command::MSet(cmd) => if cmd.nx { "msetnx" } else { "mset" },
+            Command::GetSet(_) => "getset",
+            Command::GetDel(_) => "getdel",
+            Command::SetRange(_) => "setrange",
+            Command::GetRange(_) => "getrange",
+            Command::Push(cmd) => if cmd.left { "lpush" } else { "rpush" },
+            Command::Pop(cmd) => if cmd.left { "lpop" } else { "rpop" },
+            Command::BPop(cmd) => if cmd.left { "blpop" } else { "brpop" },
+            Command::LRange(_) => "lrange",
+            Command::HSet(_) => "hset",
+            Command::HGet(_) => "hget",
+            Command::HGetAll(_) => "hgetall",
+            Command::HDel(_) => "hdel",
+            Command::SAdd(_) => "sadd",
+            Command::SRem(_) => "srem",
+            Command::SMembers(_) => "smembers",
+            Command::SIsMember(_) => "sismember",
+            Command::ZAdd(_) => "zadd",
+            Command::ZRange(_) => "zrange",
+            Command::ZRank(_) => "zrank",
+            Command::ZScore(_) => "zscore",
+            Command::XAdd(_) => "xadd",
+            Command::XLen(_) => "xlen",
+            Command::XRange(cmd) => if cmd.rev { "xrevrange" } else { "xrange" },
+            Command::XRead(cmd) => if cmd.group.is_some() { "xreadgroup" } else { "xread" },
+            Command::XGroup(_) => "xgroup",
+            Command::XAck(_) => "xack",
+            Command::XPending(_) => "xpending",
+            Command::XClaim(_) => "xclaim",
+            Command::XSetId(_) => "xsetid",
+            Command::Multi(_) => "multi",
+            Command::Exec(_) => "exec",
+            Command::Discard(_) => "discard",
+            Command::Watch(_) => "watch",
+            Command::Unwatch(_) => "unwatch",
+            Command::Hello(_) => "hello",
+            Command::Client(_) => "client",
+            Command::Info(_) => "info",
+            Command::Config(_) => "config",
+            Command::Auth(_) => "auth",
+            Command::Acl(_) => "acl",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::ReplConf(_) => "replconf",
+            Command::Psync(_) => "psync",
+            Command::Unknown(cmd) => &cmd.command_name,
+        }
+    }
 }
 
 pub struct Parse {
@@ -63,12 +562,12 @@ impl Parse {
         })
     }
 
-    pub fn next(&mut self) -> Result<Frame, Error> {
+    pub fn next_frame(&mut self) -> Result<Frame, Error> {
         self.parts.next().ok_or(Error::Other("protocol error; expected frame".into()))
     }
 
     pub fn next_string(&mut self) -> Result<String, Error> {
-        match self.next()? {
+        match self.next_frame()? {
             Frame::Simple(s) => Ok(s),
             Frame::Bulk(data) => std::str::from_utf8(&data[..])
                 .map(|s| s.to_string())
@@ -78,13 +577,31 @@ impl Parse {
     }
 
     pub fn next_bytes(&mut self) -> Result<bytes::Bytes, Error> {
-        match self.next()? {
+        match self.next_frame()? {
             Frame::Simple(s) => Ok(bytes::Bytes::from(s)),
             Frame::Bulk(data) => Ok(data),
             frame => Err(Error::Other(format!("protocol error; expected simple frame or bulk frame, got {:?}", frame))),
         }
     }
 
+    pub fn next_int(&mut self) -> Result<i64, Error> {
+        let invalid = || Error::Other("value is not an integer or out of range".into());
+        match self.next_frame()? {
+            Frame::Integer(n) => Ok(n),
+            Frame::Simple(s) => s.parse().map_err(|_| invalid()),
+            Frame::Bulk(data) => std::str::from_utf8(&data[..])
+                .map_err(|_| invalid())?
+                .parse()
+                .map_err(|_| invalid()),
+            frame => Err(Error::Other(format!("protocol error; expected int frame but got {:?}", frame))),
+        }
+    }
+
+    /// Number of frames that have not been consumed yet.
+    pub fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
     pub fn finish(&mut self) -> Result<(), Error> {
         if self.parts.next().is_none() {
             Ok(())
@@ -0,0 +1,27 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct Persist {
+    pub key: String,
+}
+
+impl Persist {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Persist, Error> {
+        let key = parse.next_string()?;
+
+        Ok(Persist { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.persist(&self.key) as i64)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("PERSIST")),
+            Frame::Bulk(Bytes::from(self.key)),
+        ])
+    }
+}
@@ -16,6 +16,13 @@ impl Ping {
         }
     }
 
+    pub(crate) fn apply(self) -> crate::Frame {
+        match self.msg {
+            Some(msg) => crate::Frame::Bulk(msg),
+            None => crate::Frame::Simple("PONG".to_string()),
+        }
+    }
+
     pub fn into_frame(self) -> crate::Frame {
         let mut frames = Vec::new();
         frames.push(crate::Frame::Bulk(Bytes::from("PING")));
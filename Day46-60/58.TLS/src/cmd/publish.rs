@@ -16,11 +16,17 @@ impl Publish {
         Ok(Publish { channel, message })
     }
 
+    pub(crate) fn apply(self, db: &crate::Db) -> crate::Frame {
+        let count = db.publish(&self.channel, self.message);
+        crate::Frame::Integer(count as i64)
+    }
+
     pub fn into_frame(self) -> crate::Frame {
-        let mut frames = Vec::new();
-        frames.push(crate::Frame::Bulk(Bytes::from("PUBLISH")));
-        frames.push(crate::Frame::Bulk(Bytes::from(self.channel)));
-        frames.push(crate::Frame::Bulk(self.message));
+        let frames = vec![
+            crate::Frame::Bulk(Bytes::from("PUBLISH")),
+            crate::Frame::Bulk(Bytes::from(self.channel)),
+            crate::Frame::Bulk(self.message),
+        ];
         crate::Frame::Array(frames)
     }
 }
@@ -0,0 +1,70 @@
+//! `PUBSUB`, inspecting which channels and patterns are subscribed to.
+
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub enum PubSubCommand {
+    /// Active channels, optionally only those matching a glob pattern.
+    Channels(Option<String>),
+    /// The number of subscribers of each channel.
+    NumSub(Vec<String>),
+    /// The number of patterns subscribed to.
+    NumPat,
+}
+
+impl PubSubCommand {
+    pub fn parse_frames(parse: &mut Parse) -> Result<PubSubCommand, Error> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_uppercase().as_str() {
+            "CHANNELS" => match parse.remaining() {
+                0 => Ok(PubSubCommand::Channels(None)),
+                _ => Ok(PubSubCommand::Channels(Some(parse.next_string()?))),
+            },
+            "NUMSUB" => {
+                let mut channels = Vec::new();
+                while parse.remaining() > 0 {
+                    channels.push(parse.next_string()?);
+                }
+                Ok(PubSubCommand::NumSub(channels))
+            }
+            "NUMPAT" => Ok(PubSubCommand::NumPat),
+            _ => Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let bulk = |s: String| Frame::Bulk(Bytes::from(s));
+        match self {
+            PubSubCommand::Channels(pattern) => {
+                Frame::Array(db.pub_sub().channels(pattern.as_deref()).into_iter().map(bulk).collect())
+            }
+            PubSubCommand::NumSub(channels) => Frame::Array(
+                db.pub_sub()
+                    .numsub(&channels)
+                    .into_iter()
+                    .flat_map(|(channel, count)| [bulk(channel), Frame::Integer(count as i64)])
+                    .collect(),
+            ),
+            PubSubCommand::NumPat => Frame::Integer(db.pub_sub().numpat() as i64),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+        let mut frames = vec![bulk("PUBSUB")];
+        match self {
+            PubSubCommand::Channels(pattern) => {
+                frames.push(bulk("CHANNELS"));
+                frames.extend(pattern.as_deref().map(bulk));
+            }
+            PubSubCommand::NumSub(channels) => {
+                frames.push(bulk("NUMSUB"));
+                frames.extend(channels.iter().map(|channel| bulk(channel)));
+            }
+            PubSubCommand::NumPat => frames.push(bulk("NUMPAT")),
+        }
+        Frame::Array(frames)
+    }
+}
@@ -0,0 +1,96 @@
+//! `REPLICAOF`, `REPLCONF` and `PSYNC`. They change the role of the server or
+//! turn the connection into a replication link, so the server executes them.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+/// `REPLICAOF host port` (alias `SLAVEOF`) starts replicating `host:port`;
+/// `REPLICAOF NO ONE` turns a replica into a master.
+#[derive(Debug, Clone)]
+pub struct ReplicaOf {
+    pub master: Option<(String, u16)>,
+}
+
+/// `REPLCONF option value [option value ...]`, sent by replicas to describe
+/// themselves and to acknowledge the offset they processed.
+#[derive(Debug, Clone)]
+pub struct ReplConf {
+    pub options: Vec<(String, String)>,
+}
+
+/// `PSYNC replid offset`. A replica asks to continue the replication stream
+/// of `replid` from `offset`, the first byte it is missing. `? -1` asks for a
+/// full synchronization.
+#[derive(Debug, Clone)]
+pub struct Psync {
+    pub replid: String,
+    pub offset: i64,
+}
+
+impl ReplicaOf {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ReplicaOf, Error> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf { master: None });
+        }
+        let port = port.parse().map_err(|_| Error::Other("Invalid master port".into()))?;
+        Ok(ReplicaOf { master: Some((host, port)) })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let (host, port) = match self.master {
+            Some((host, port)) => (host, port.to_string()),
+            None => ("NO".to_string(), "ONE".to_string()),
+        };
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("REPLICAOF")),
+            Frame::Bulk(Bytes::from(host)),
+            Frame::Bulk(Bytes::from(port)),
+        ])
+    }
+}
+
+impl ReplConf {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ReplConf, Error> {
+        let mut options = vec![];
+        while parse.remaining() > 0 {
+            options.push((parse.next_string()?.to_lowercase(), parse.next_string()?));
+        }
+        if options.is_empty() {
+            return Err(Error::Other("wrong number of arguments for 'replconf' command".into()));
+        }
+        Ok(ReplConf { options })
+    }
+
+    /// The value of `option`, if given.
+    pub fn get(&self, option: &str) -> Option<&str> {
+        self.options.iter().find(|(name, _)| name == option).map(|(_, value)| value.as_str())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("REPLCONF"))];
+        for (option, value) in self.options {
+            frames.push(Frame::Bulk(Bytes::from(option)));
+            frames.push(Frame::Bulk(Bytes::from(value)));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl Psync {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Psync, Error> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_int()?;
+        Ok(Psync { replid, offset })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("PSYNC")),
+            Frame::Bulk(Bytes::from(self.replid)),
+            Frame::Bulk(Bytes::from(self.offset.to_string())),
+        ])
+    }
+}
@@ -0,0 +1,75 @@
+//! `SENTINEL`. Only sentinels answer it; see `crate::sentinel`.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub enum SentinelCommand {
+    /// The address of the current master of the named master group.
+    GetMasterAddrByName(String),
+    /// Asked by sentinels of each other: whether the master at `ip:port` is
+    /// down for the receiver and, unless `runid` is `*`, a vote for `runid`
+    /// to lead the failover of `epoch`.
+    IsMasterDownByAddr { ip: String, port: u16, epoch: u64, runid: String },
+    Masters,
+    Master(String),
+    Replicas(String),
+    Sentinels(String),
+    MyId,
+}
+
+impl SentinelCommand {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SentinelCommand, Error> {
+        let subcommand = parse.next_string()?;
+        match subcommand.to_lowercase().as_str() {
+            "get-master-addr-by-name" => Ok(SentinelCommand::GetMasterAddrByName(parse.next_string()?)),
+            "is-master-down-by-addr" => {
+                let ip = parse.next_string()?;
+                let port = parse.next_string()?.parse().map_err(|_| Error::Other("Invalid port".into()))?;
+                let epoch = parse.next_string()?.parse().map_err(|_| Error::Other("Invalid epoch".into()))?;
+                let runid = parse.next_string()?;
+                Ok(SentinelCommand::IsMasterDownByAddr { ip, port, epoch, runid })
+            }
+            "masters" => Ok(SentinelCommand::Masters),
+            "master" => Ok(SentinelCommand::Master(parse.next_string()?)),
+            "replicas" | "slaves" => Ok(SentinelCommand::Replicas(parse.next_string()?)),
+            "sentinels" => Ok(SentinelCommand::Sentinels(parse.next_string()?)),
+            "myid" => Ok(SentinelCommand::MyId),
+            _ => Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+        let mut frames = vec![bulk("SENTINEL")];
+        match self {
+            SentinelCommand::GetMasterAddrByName(name) => {
+                frames.push(bulk("GET-MASTER-ADDR-BY-NAME"));
+                frames.push(bulk(&name));
+            }
+            SentinelCommand::IsMasterDownByAddr { ip, port, epoch, runid } => {
+                frames.push(bulk("IS-MASTER-DOWN-BY-ADDR"));
+                frames.push(bulk(&ip));
+                frames.push(bulk(&port.to_string()));
+                frames.push(bulk(&epoch.to_string()));
+                frames.push(bulk(&runid));
+            }
+            SentinelCommand::Masters => frames.push(bulk("MASTERS")),
+            SentinelCommand::Master(name) => {
+                frames.push(bulk("MASTER"));
+                frames.push(bulk(&name));
+            }
+            SentinelCommand::Replicas(name) => {
+                frames.push(bulk("REPLICAS"));
+                frames.push(bulk(&name));
+            }
+            SentinelCommand::Sentinels(name) => {
+                frames.push(bulk("SENTINELS"));
+                frames.push(bulk(&name));
+            }
+            SentinelCommand::MyId => frames.push(bulk("MYID")),
+        }
+        Frame::Array(frames)
+    }
+}
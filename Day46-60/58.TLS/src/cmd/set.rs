@@ -1,18 +1,75 @@
 use super::Parse;
-use crate::Error;
+use super::expire::Expiry;
+use crate::db::SetCondition;
+use crate::{Db, Error, Frame};
 use bytes::Bytes;
 
 #[derive(Debug, Clone)]
 pub struct Set {
     pub key: String,
     pub value: Bytes,
+    pub expire: Option<Expiry>,
+    /// `KEEPTTL`: retain the TTL of the existing key.
+    pub keep_ttl: bool,
+    pub condition: SetCondition,
 }
 
 impl Set {
+    /// A plain `SET key value` without options.
+    pub fn new(key: impl Into<String>, value: Bytes) -> Set {
+        Set {
+            key: key.into(),
+            value,
+            expire: None,
+            keep_ttl: false,
+            condition: SetCondition::Always,
+        }
+    }
+
     pub fn parse_frames(parse: &mut Parse) -> Result<Set, Error> {
         let key = parse.next_string()?;
         let value = parse.next_bytes()?;
+        let mut set = Set::new(key, value);
+
+        while parse.remaining() > 0 {
+            let option = parse.next_string()?.to_uppercase();
+            match option.as_str() {
+                "EX" | "PX" | "EXAT" | "PXAT" => {
+                    if set.expire.is_some() || set.keep_ttl {
+                        return Err(Error::Other("syntax error".into()));
+                    }
+                    let n = parse.next_int()?;
+                    if n <= 0 {
+                        return Err(Error::Other("invalid expire time in 'set' command".into()));
+                    }
+                    set.expire = Some(match option.as_str() {
+                        "EX" => Expiry::Ex(n),
+                        "PX" => Expiry::Px(n),
+                        "EXAT" => Expiry::ExAt(n),
+                        _ => Expiry::PxAt(n),
+                    });
+                }
+                "KEEPTTL" if set.expire.is_none() => set.keep_ttl = true,
+                "NX" if set.condition == SetCondition::Always => set.condition = SetCondition::IfNotExists,
+                "XX" if set.condition == SetCondition::Always => set.condition = SetCondition::IfExists,
+                _ => return Err(Error::Other("syntax error".into())),
+            }
+        }
+
+        Ok(set)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let expires_at = match self.expire.map(|e| e.deadline(crate::db::now_ms())) {
+            None => None,
+            Some(Some(when)) => Some(when.max(0) as u64),
+            Some(None) => return Frame::Error("ERR invalid expire time in 'set' command".to_string()),
+        };
 
-        Ok(Set { key, value })
+        if db.set_with_options(self.key, self.value, expires_at, self.keep_ttl, self.condition) {
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Null
+        }
     }
 }
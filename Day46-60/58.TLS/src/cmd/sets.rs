@@ -0,0 +1,119 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct SAdd {
+    pub key: String,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SRem {
+    pub key: String,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SMembers {
+    pub key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SIsMember {
+    pub key: String,
+    pub member: Bytes,
+}
+
+/// Parses `key member [member ...]`.
+fn parse_members(parse: &mut Parse) -> Result<(String, Vec<Bytes>), Error> {
+    let key = parse.next_string()?;
+    let mut members = vec![parse.next_bytes()?];
+    while parse.remaining() > 0 {
+        members.push(parse.next_bytes()?);
+    }
+    Ok((key, members))
+}
+
+fn members_frame(name: &str, key: String, members: Vec<Bytes>) -> Frame {
+    let mut frames = vec![Frame::Bulk(Bytes::from(name.to_string())), Frame::Bulk(Bytes::from(key))];
+    frames.extend(members.into_iter().map(Frame::Bulk));
+    Frame::Array(frames)
+}
+
+impl SAdd {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SAdd, Error> {
+        let (key, members) = parse_members(parse)?;
+
+        Ok(SAdd { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.sadd(&self.key, self.members) {
+            Ok(added) => Frame::Integer(added as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        members_frame("SADD", self.key, self.members)
+    }
+}
+
+impl SRem {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SRem, Error> {
+        let (key, members) = parse_members(parse)?;
+
+        Ok(SRem { key, members })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.srem(&self.key, &self.members) {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        members_frame("SREM", self.key, self.members)
+    }
+}
+
+impl SMembers {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SMembers, Error> {
+        let key = parse.next_string()?;
+
+        Ok(SMembers { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.smembers(&self.key) {
+            Ok(members) => Frame::Set(members.into_iter().map(Frame::Bulk).collect()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        members_frame("SMEMBERS", self.key, vec![])
+    }
+}
+
+impl SIsMember {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SIsMember, Error> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(SIsMember { key, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.sismember(&self.key, &self.member) {
+            Ok(found) => Frame::Integer(found as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        members_frame("SISMEMBER", self.key, vec![self.member])
+    }
+}
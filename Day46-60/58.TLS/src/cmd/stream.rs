@@ -0,0 +1,734 @@
+//! Stream commands: `XADD`, `XRANGE`, `XREAD` and the consumer group
+//! commands built on them.
+
+use super::Parse;
+use crate::db::DbError;
+use crate::stream::{ClaimOptions, Fields, NewId, StreamId};
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct XAdd {
+    pub key: String,
+    pub id: NewId,
+    pub fields: Fields,
+    /// `MAXLEN`: trims the stream to this many entries after adding.
+    pub maxlen: Option<usize>,
+    /// `NOMKSTREAM`: doesn't create a missing stream.
+    pub no_mkstream: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct XLen {
+    pub key: String,
+}
+
+/// `XRANGE` and `XREVRANGE`.
+#[derive(Debug, Clone)]
+pub struct XRange {
+    pub key: String,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: Option<usize>,
+    /// `XREVRANGE`: newest entries first.
+    pub rev: bool,
+}
+
+/// `XREAD` and `XREADGROUP`. With `BLOCK` the server parks the connection
+/// until one of the streams has new entries or the timeout elapses.
+#[derive(Debug, Clone)]
+pub struct XRead {
+    /// `XREADGROUP`: the group and consumer reading.
+    pub group: Option<(String, String)>,
+    /// `NOACK`: delivered entries aren't added to the pending entries list.
+    pub no_ack: bool,
+    pub keys: Vec<String>,
+    /// Where to read each of `keys` from.
+    pub starts: Vec<StreamStart>,
+    pub count: Option<usize>,
+    /// `BLOCK`, a timeout of zero blocking indefinitely.
+    pub block: Option<Duration>,
+}
+
+/// The position `XREAD` / `XREADGROUP` reads a stream from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamStart {
+    /// `$`: only entries added from now on.
+    Last,
+    /// `>`: entries never delivered to the group.
+    New,
+    /// Entries with a greater ID; for a group, the consumer's own pending
+    /// ones.
+    After(StreamId),
+}
+
+#[derive(Debug, Clone)]
+pub enum XGroupCommand {
+    /// `CREATE key group id|$ [MKSTREAM]`, `None` standing for `$`.
+    Create { key: String, group: String, id: Option<StreamId>, mkstream: bool },
+    /// `SETID key group id|$`.
+    SetId { key: String, group: String, id: Option<StreamId> },
+    Destroy { key: String, group: String },
+    CreateConsumer { key: String, group: String, consumer: String },
+    DelConsumer { key: String, group: String, consumer: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct XAck {
+    pub key: String,
+    pub group: String,
+    pub ids: Vec<StreamId>,
+}
+
+/// `XPENDING key group`, a summary, or with a range the pending entries
+/// themselves.
+#[derive(Debug, Clone)]
+pub struct XPending {
+    pub key: String,
+    pub group: String,
+    pub range: Option<PendingRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingRange {
+    /// `IDLE`: only entries idle for at least this many milliseconds.
+    pub min_idle: Option<u64>,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: usize,
+    pub consumer: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XClaim {
+    pub key: String,
+    pub group: String,
+    pub consumer: String,
+    /// Only entries idle for at least this many milliseconds are claimed.
+    pub min_idle: u64,
+    pub ids: Vec<StreamId>,
+    pub options: ClaimOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct XSetId {
+    pub key: String,
+    pub id: StreamId,
+}
+
+fn invalid_id() -> Error {
+    Error::Other("Invalid stream ID specified as stream command argument".into())
+}
+
+/// Parses an ID, with `-` and `+` standing for the smallest and largest
+/// ones. A bare `<ms>` gets `seq` as its sequence number.
+fn parse_id(s: &str, seq: u64) -> Result<StreamId, Error> {
+    match s {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        s => StreamId::parse(s, seq).ok_or_else(invalid_id),
+    }
+}
+
+fn parse_count(parse: &mut Parse) -> Result<usize, Error> {
+    usize::try_from(parse.next_int()?).map_err(|_| Error::Other("value is out of range, must be positive".into()))
+}
+
+fn parse_millis(parse: &mut Parse) -> Result<u64, Error> {
+    u64::try_from(parse.next_int()?).map_err(|_| Error::Other("value is out of range, must be positive".into()))
+}
+
+fn bulk(s: impl ToString) -> Frame {
+    Frame::Bulk(Bytes::from(s.to_string()))
+}
+
+/// `[id, [field, value, ...]]`, or `[id, nil]` for an entry that was deleted.
+fn entry_frame(id: StreamId, fields: Option<Fields>) -> Frame {
+    let fields = fields.map_or(Frame::Null, |fields| {
+        Frame::Array(fields.into_iter().flat_map(|(field, value)| [Frame::Bulk(field), Frame::Bulk(value)]).collect())
+    });
+    Frame::Array(vec![bulk(id), fields])
+}
+
+fn entries_frame(entries: Vec<(StreamId, Fields)>) -> Frame {
+    Frame::Array(entries.into_iter().map(|(id, fields)| entry_frame(id, Some(fields))).collect())
+}
+
+fn reply_id(frame: &Frame) -> Option<StreamId> {
+    match frame {
+        Frame::Bulk(id) => StreamId::parse(std::str::from_utf8(id).ok()?, 0),
+        _ => None,
+    }
+}
+
+/// The IDs in a reply made of entries or, with `JUSTID`, bare IDs.
+fn reply_ids(response: &Frame) -> Vec<StreamId> {
+    let Frame::Array(items) = response else { return vec![] };
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Frame::Array(entry) => entry.first().and_then(reply_id),
+            item => reply_id(item),
+        })
+        .collect()
+}
+
+impl XAdd {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XAdd, Error> {
+        let key = parse.next_string()?;
+        let mut maxlen = None;
+        let mut no_mkstream = false;
+
+        let id = loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NOMKSTREAM" => no_mkstream = true,
+                "MAXLEN" => {
+                    // Trimming is always exact, so `~` is taken as `=`
+                    let mut threshold = parse.next_string()?;
+                    if threshold == "=" || threshold == "~" {
+                        threshold = parse.next_string()?;
+                    }
+                    let threshold = threshold.parse::<usize>().map_err(|_| {
+                        Error::Other("The MAXLEN argument must be >= 0.".into())
+                    })?;
+                    maxlen = Some(threshold);
+                }
+                "*" => break NewId::Auto,
+                _ => match arg.strip_suffix("-*") {
+                    Some(ms) => break NewId::AutoSeq(ms.parse().map_err(|_| invalid_id())?),
+                    None => break NewId::Explicit(StreamId::parse(&arg, 0).ok_or_else(invalid_id)?),
+                },
+            }
+        };
+
+        let mut fields = Vec::new();
+        while parse.remaining() > 0 {
+            let field = parse.next_bytes()?;
+            if parse.remaining() == 0 {
+                return Err(Error::Other("wrong number of arguments for 'xadd' command".into()));
+            }
+            fields.push((field, parse.next_bytes()?));
+        }
+        if fields.is_empty() {
+            return Err(Error::Other("wrong number of arguments for 'xadd' command".into()));
+        }
+
+        Ok(XAdd { key, id, fields, maxlen, no_mkstream })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xadd(&self.key, self.id, self.fields, self.maxlen, self.no_mkstream) {
+            Ok(Some(id)) => bulk(id),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    /// The command with the ID it was given pinned, so replaying it from the
+    /// AOF adds the same entry. `None` if it didn't add anything.
+    pub(crate) fn with_outcome(mut self, response: &Frame) -> Option<XAdd> {
+        self.id = NewId::Explicit(reply_id(response)?);
+        Some(self)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk("XADD"), bulk(self.key)];
+        if self.no_mkstream {
+            frames.push(bulk("NOMKSTREAM"));
+        }
+        if let Some(maxlen) = self.maxlen {
+            frames.push(bulk("MAXLEN"));
+            frames.push(bulk(maxlen));
+        }
+        frames.push(match self.id {
+            NewId::Auto => bulk("*"),
+            NewId::AutoSeq(ms) => bulk(format!("{}-*", ms)),
+            NewId::Explicit(id) => bulk(id),
+        });
+        for (field, value) in self.fields {
+            frames.push(Frame::Bulk(field));
+            frames.push(Frame::Bulk(value));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl XLen {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XLen, Error> {
+        Ok(XLen { key: parse.next_string()? })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("XLEN"), bulk(self.key)])
+    }
+}
+
+impl XRange {
+    pub fn parse_frames(parse: &mut Parse, rev: bool) -> Result<XRange, Error> {
+        let key = parse.next_string()?;
+        let (first, second) = (parse.next_string()?, parse.next_string()?);
+        let (start, end) = if rev { (second, first) } else { (first, second) };
+        let start = parse_id(&start, 0)?;
+        let end = parse_id(&end, u64::MAX)?;
+
+        let count = match parse.remaining() {
+            0 => None,
+            _ if parse.next_string()?.eq_ignore_ascii_case("count") => Some(parse_count(parse)?),
+            _ => return Err(Error::Other("syntax error".into())),
+        };
+
+        Ok(XRange { key, start, end, count, rev })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xrange(&self.key, self.start, self.end, self.count, self.rev) {
+            Ok(entries) => entries_frame(entries),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = match self.rev {
+            true => vec![bulk("XREVRANGE"), bulk(self.key), bulk(self.end), bulk(self.start)],
+            false => vec![bulk("XRANGE"), bulk(self.key), bulk(self.start), bulk(self.end)],
+        };
+        if let Some(count) = self.count {
+            frames.push(bulk("COUNT"));
+            frames.push(bulk(count));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl XRead {
+    pub fn parse_frames(parse: &mut Parse, grouped: bool) -> Result<XRead, Error> {
+        let name = if grouped { "xreadgroup" } else { "xread" };
+        let mut group = None;
+        let mut no_ack = false;
+        let mut count = None;
+        let mut block = None;
+
+        loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "GROUP" if grouped => group = Some((parse.next_string()?, parse.next_string()?)),
+                "NOACK" if grouped => no_ack = true,
+                "COUNT" => count = Some(parse_count(parse)?),
+                "BLOCK" => {
+                    let millis = parse.next_int()?;
+                    if millis < 0 {
+                        return Err(Error::Other("timeout is negative".into()));
+                    }
+                    block = Some(Duration::from_millis(millis as u64));
+                }
+                "STREAMS" => break,
+                _ => return Err(Error::Other("syntax error".into())),
+            }
+        }
+        if grouped && group.is_none() {
+            return Err(Error::Other("Missing GROUP option for XREADGROUP".into()));
+        }
+
+        let mut args = Vec::new();
+        while parse.remaining() > 0 {
+            args.push(parse.next_string()?);
+        }
+        if args.is_empty() || args.len() % 2 != 0 {
+            return Err(Error::Other(format!(
+                "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+                name,
+                if grouped { ">" } else { "$" }
+            )));
+        }
+
+        let ids = args.split_off(args.len() / 2);
+        let starts = ids
+            .iter()
+            .map(|id| match id.as_str() {
+                "$" if !grouped => Ok(StreamStart::Last),
+                ">" if grouped => Ok(StreamStart::New),
+                "$" | ">" => Err(Error::Other(format!("The {} ID is meaningless in the context of {}", id, name.to_uppercase()))),
+                id => Ok(StreamStart::After(StreamId::parse(id, 0).ok_or_else(invalid_id)?)),
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(XRead { group, no_ack, keys: args, starts, count, block })
+    }
+
+    /// Whether the server has to wait for entries when there are none yet.
+    /// Reading a consumer's pending entries never blocks.
+    pub fn blocks(&self) -> bool {
+        self.block.is_some() && (self.group.is_none() || self.starts.iter().all(|start| *start == StreamStart::New))
+    }
+
+    /// Replaces `$` with the stream's current last ID, so a blocked read
+    /// only returns entries added after it was issued.
+    pub(crate) fn resolve_last(mut self, db: &Db) -> Result<XRead, DbError> {
+        for (key, start) in self.keys.iter().zip(self.starts.iter_mut()) {
+            if *start == StreamStart::Last {
+                *start = StreamStart::After(db.xlast_id(key)?);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Reads without blocking. Replies with `[key, entries]` for every stream
+    /// that had entries, or null if none had.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut streams = Vec::new();
+        for (key, start) in self.keys.into_iter().zip(self.starts) {
+            let entries = match (&self.group, start) {
+                (None, StreamStart::Last | StreamStart::New) => continue,
+                (None, StreamStart::After(id)) => db.xread(&key, id, self.count).map(|entries| {
+                    entries.into_iter().map(|(id, fields)| (id, Some(fields))).collect()
+                }),
+                (Some((group, consumer)), start) => {
+                    let start = match start {
+                        StreamStart::After(id) => Some(id),
+                        _ => None,
+                    };
+                    db.xreadgroup(&key, group, consumer, start, self.count, self.no_ack)
+                }
+            };
+            match entries {
+                // A consumer's history is replied with even when empty
+                Ok(entries) if entries.is_empty() && !matches!((&self.group, start), (Some(_), StreamStart::After(_))) => {}
+                Ok(entries) => streams.push(Frame::Array(vec![
+                    bulk(key),
+                    Frame::Array(entries.into_iter().map(|(id, fields)| entry_frame(id, fields)).collect()),
+                ])),
+                Err(e) => return Frame::Error(e.to_string()),
+            }
+        }
+
+        match streams.is_empty() {
+            true => Frame::Null,
+            false => Frame::Array(streams),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![];
+        match self.group {
+            Some((group, consumer)) => {
+                frames.extend([bulk("XREADGROUP"), bulk("GROUP"), bulk(group), bulk(consumer)]);
+            }
+            None => frames.push(bulk("XREAD")),
+        }
+        if let Some(count) = self.count {
+            frames.push(bulk("COUNT"));
+            frames.push(bulk(count));
+        }
+        if let Some(block) = self.block {
+            frames.push(bulk("BLOCK"));
+            frames.push(bulk(block.as_millis()));
+        }
+        if self.no_ack {
+            frames.push(bulk("NOACK"));
+        }
+        frames.push(bulk("STREAMS"));
+        frames.extend(self.keys.into_iter().map(bulk));
+        frames.extend(self.starts.into_iter().map(|start| match start {
+            StreamStart::Last => bulk("$"),
+            StreamStart::New => bulk(">"),
+            StreamStart::After(id) => bulk(id),
+        }));
+        Frame::Array(frames)
+    }
+}
+
+impl XGroupCommand {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XGroupCommand, Error> {
+        let subcommand = parse.next_string()?;
+        let last_id = |id: String| match id.as_str() {
+            "$" => Ok(None),
+            id => parse_id(id, 0).map(Some),
+        };
+        match subcommand.to_uppercase().as_str() {
+            "CREATE" => {
+                let key = parse.next_string()?;
+                let group = parse.next_string()?;
+                let id = last_id(parse.next_string()?)?;
+                let mkstream = match parse.remaining() {
+                    0 => false,
+                    _ if parse.next_string()?.eq_ignore_ascii_case("mkstream") => true,
+                    _ => return Err(Error::Other("syntax error".into())),
+                };
+                Ok(XGroupCommand::Create { key, group, id, mkstream })
+            }
+            "SETID" => Ok(XGroupCommand::SetId {
+                key: parse.next_string()?,
+                group: parse.next_string()?,
+                id: last_id(parse.next_string()?)?,
+            }),
+            "DESTROY" => Ok(XGroupCommand::Destroy { key: parse.next_string()?, group: parse.next_string()? }),
+            "CREATECONSUMER" => Ok(XGroupCommand::CreateConsumer {
+                key: parse.next_string()?,
+                group: parse.next_string()?,
+                consumer: parse.next_string()?,
+            }),
+            "DELCONSUMER" => Ok(XGroupCommand::DelConsumer {
+                key: parse.next_string()?,
+                group: parse.next_string()?,
+                consumer: parse.next_string()?,
+            }),
+            _ => Err(Error::Other(format!("unknown subcommand '{}'", subcommand))),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        match self {
+            XGroupCommand::Create { key, .. }
+            | XGroupCommand::SetId { key, .. }
+            | XGroupCommand::Destroy { key, .. }
+            | XGroupCommand::CreateConsumer { key, .. }
+            | XGroupCommand::DelConsumer { key, .. } => key,
+        }
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ok = |result: Result<(), DbError>| result.map(|()| Frame::Simple("OK".to_string()));
+        let result = match self {
+            XGroupCommand::Create { key, group, id, mkstream } => ok(db.xgroup_create(&key, &group, id, mkstream)),
+            XGroupCommand::SetId { key, group, id } => ok(db.xgroup_setid(&key, &group, id)),
+            XGroupCommand::Destroy { key, group } => db.xgroup_destroy(&key, &group).map(|destroyed| Frame::Integer(destroyed as i64)),
+            XGroupCommand::CreateConsumer { key, group, consumer } => {
+                db.xgroup_createconsumer(&key, &group, &consumer).map(|created| Frame::Integer(created as i64))
+            }
+            XGroupCommand::DelConsumer { key, group, consumer } => {
+                db.xgroup_delconsumer(&key, &group, &consumer).map(|pending| Frame::Integer(pending as i64))
+            }
+        };
+        result.unwrap_or_else(|e| Frame::Error(e.to_string()))
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let last_id = |id: Option<StreamId>| id.map_or(bulk("$"), bulk);
+        let frames = match self {
+            XGroupCommand::Create { key, group, id, mkstream } => {
+                let mut frames = vec![bulk("CREATE"), bulk(key), bulk(group), last_id(id)];
+                if mkstream {
+                    frames.push(bulk("MKSTREAM"));
+                }
+                frames
+            }
+            XGroupCommand::SetId { key, group, id } => vec![bulk("SETID"), bulk(key), bulk(group), last_id(id)],
+            XGroupCommand::Destroy { key, group } => vec![bulk("DESTROY"), bulk(key), bulk(group)],
+            XGroupCommand::CreateConsumer { key, group, consumer } => {
+                vec![bulk("CREATECONSUMER"), bulk(key), bulk(group), bulk(consumer)]
+            }
+            XGroupCommand::DelConsumer { key, group, consumer } => {
+                vec![bulk("DELCONSUMER"), bulk(key), bulk(group), bulk(consumer)]
+            }
+        };
+        Frame::Array([vec![bulk("XGROUP")], frames].concat())
+    }
+}
+
+impl XAck {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XAck, Error> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let mut ids = vec![parse_id(&parse.next_string()?, 0)?];
+        while parse.remaining() > 0 {
+            ids.push(parse_id(&parse.next_string()?, 0)?);
+        }
+
+        Ok(XAck { key, group, ids })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xack(&self.key, &self.group, &self.ids) {
+            Ok(acked) => Frame::Integer(acked as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk("XACK"), bulk(self.key), bulk(self.group)];
+        frames.extend(self.ids.into_iter().map(bulk));
+        Frame::Array(frames)
+    }
+}
+
+impl XPending {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XPending, Error> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        if parse.remaining() == 0 {
+            return Ok(XPending { key, group, range: None });
+        }
+
+        let mut start = parse.next_string()?;
+        let mut min_idle = None;
+        if start.eq_ignore_ascii_case("idle") {
+            min_idle = Some(parse_millis(parse)?);
+            start = parse.next_string()?;
+        }
+        let start = parse_id(&start, 0)?;
+        let end = parse_id(&parse.next_string()?, u64::MAX)?;
+        let count = parse_count(parse)?;
+        let consumer = match parse.remaining() {
+            0 => None,
+            _ => Some(parse.next_string()?),
+        };
+
+        Ok(XPending { key, group, range: Some(PendingRange { min_idle, start, end, count, consumer }) })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let Some(range) = self.range else {
+            return match db.xpending_summary(&self.key, &self.group) {
+                Ok(summary) => {
+                    let (min, max) = summary.range.map_or((Frame::Null, Frame::Null), |(min, max)| (bulk(min), bulk(max)));
+                    let consumers = match summary.consumers.is_empty() {
+                        true => Frame::Null,
+                        false => Frame::Array(
+                            summary
+                                .consumers
+                                .into_iter()
+                                .map(|(consumer, count)| Frame::Array(vec![bulk(consumer), bulk(count)]))
+                                .collect(),
+                        ),
+                    };
+                    Frame::Array(vec![Frame::Integer(summary.count as i64), min, max, consumers])
+                }
+                Err(e) => Frame::Error(e.to_string()),
+            };
+        };
+
+        let pending = db.xpending(
+            &self.key,
+            &self.group,
+            range.start,
+            range.end,
+            range.count,
+            range.consumer.as_deref(),
+            range.min_idle.unwrap_or(0),
+        );
+        match pending {
+            Ok(pending) => Frame::Array(
+                pending
+                    .into_iter()
+                    .map(|(id, consumer, idle, deliveries)| {
+                        Frame::Array(vec![bulk(id), bulk(consumer), Frame::Integer(idle as i64), Frame::Integer(deliveries as i64)])
+                    })
+                    .collect(),
+            ),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk("XPENDING"), bulk(self.key), bulk(self.group)];
+        if let Some(range) = self.range {
+            if let Some(min_idle) = range.min_idle {
+                frames.push(bulk("IDLE"));
+                frames.push(bulk(min_idle));
+            }
+            frames.extend([bulk(range.start), bulk(range.end), bulk(range.count)]);
+            frames.extend(range.consumer.map(bulk));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl XClaim {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XClaim, Error> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let min_idle = parse_millis(parse)?;
+
+        // IDs come first; the first argument that isn't one is an option
+        let mut ids = Vec::new();
+        let mut options = ClaimOptions::default();
+        while parse.remaining() > 0 {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "IDLE" if !ids.is_empty() => options.idle = Some(parse_millis(parse)?),
+                "TIME" if !ids.is_empty() => options.time = Some(parse_millis(parse)?),
+                "RETRYCOUNT" if !ids.is_empty() => options.retry_count = Some(parse_millis(parse)?),
+                "FORCE" if !ids.is_empty() => options.force = true,
+                "JUSTID" if !ids.is_empty() => options.just_id = true,
+                _ if options == ClaimOptions::default() => ids.push(parse_id(&arg, 0)?),
+                _ => return Err(Error::Other("syntax error".into())),
+            }
+        }
+        if ids.is_empty() {
+            return Err(Error::Other("wrong number of arguments for 'xclaim' command".into()));
+        }
+
+        Ok(XClaim { key, group, consumer, min_idle, ids, options })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xclaim(&self.key, &self.group, &self.consumer, self.min_idle, &self.ids, &self.options) {
+            Ok(claimed) if self.options.just_id => Frame::Array(claimed.into_iter().map(|(id, _)| bulk(id)).collect()),
+            Ok(claimed) => entries_frame(claimed),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    /// The command narrowed down to the entries it claimed, which are claimed
+    /// regardless of their idle time on replay. `None` if nothing was claimed.
+    pub(crate) fn with_outcome(mut self, response: &Frame) -> Option<XClaim> {
+        self.ids = reply_ids(response);
+        self.min_idle = 0;
+        (!self.ids.is_empty()).then_some(self)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk("XCLAIM"), bulk(self.key), bulk(self.group), bulk(self.consumer), bulk(self.min_idle)];
+        frames.extend(self.ids.into_iter().map(bulk));
+        let options = self.options;
+        if let Some(idle) = options.idle {
+            frames.push(bulk("IDLE"));
+            frames.push(bulk(idle));
+        }
+        if let Some(time) = options.time {
+            frames.push(bulk("TIME"));
+            frames.push(bulk(time));
+        }
+        if let Some(retry_count) = options.retry_count {
+            frames.push(bulk("RETRYCOUNT"));
+            frames.push(bulk(retry_count));
+        }
+        if options.force {
+            frames.push(bulk("FORCE"));
+        }
+        if options.just_id {
+            frames.push(bulk("JUSTID"));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl XSetId {
+    pub fn parse_frames(parse: &mut Parse) -> Result<XSetId, Error> {
+        let key = parse.next_string()?;
+        let id = parse_id(&parse.next_string()?, 0)?;
+
+        Ok(XSetId { key, id })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.xsetid(&self.key, self.id) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("XSETID"), bulk(self.key), bulk(self.id)])
+    }
+}
@@ -0,0 +1,306 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+/// `INCR`, `DECR`, `INCRBY` and `DECRBY`, all logged as `INCRBY`.
+#[derive(Debug, Clone)]
+pub struct IncrBy {
+    pub key: String,
+    pub delta: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncrByFloat {
+    pub key: String,
+    pub delta: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Append {
+    pub key: String,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct Strlen {
+    pub key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MGet {
+    pub keys: Vec<String>,
+}
+
+/// `MSET` and `MSETNX`.
+#[derive(Debug, Clone)]
+pub struct MSet {
+    pub pairs: Vec<(String, Bytes)>,
+    /// Only set the keys if none of them exists (`MSETNX`).
+    pub nx: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetSet {
+    pub key: String,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetDel {
+    pub key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetRange {
+    pub key: String,
+    pub offset: usize,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetRange {
+    pub key: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+fn bulk(s: impl Into<Bytes>) -> Frame {
+    Frame::Bulk(s.into())
+}
+
+impl IncrBy {
+    /// Parses `INCR key` / `DECR key`, which change the value by `delta`.
+    pub fn parse_unit(parse: &mut Parse, delta: i64) -> Result<IncrBy, Error> {
+        let key = parse.next_string()?;
+
+        Ok(IncrBy { key, delta })
+    }
+
+    /// Parses `INCRBY key delta`, or `DECRBY key delta` with `negate`.
+    pub fn parse_frames(parse: &mut Parse, negate: bool) -> Result<IncrBy, Error> {
+        let key = parse.next_string()?;
+        let delta = parse.next_int()?;
+        let delta = if negate {
+            delta.checked_neg().ok_or_else(|| Error::Other("decrement would overflow".into()))?
+        } else {
+            delta
+        };
+
+        Ok(IncrBy { key, delta })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.incr_by(&self.key, self.delta) {
+            Ok(value) => Frame::Integer(value),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("INCRBY"), bulk(self.key), bulk(self.delta.to_string())])
+    }
+}
+
+impl IncrByFloat {
+    pub fn parse_frames(parse: &mut Parse) -> Result<IncrByFloat, Error> {
+        let key = parse.next_string()?;
+        let delta = parse
+            .next_string()?
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .ok_or_else(|| Error::Other("value is not a valid float".into()))?;
+
+        Ok(IncrByFloat { key, delta })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.incr_by_float(&self.key, self.delta) {
+            Ok(value) => Frame::Bulk(value),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("INCRBYFLOAT"), bulk(self.key), bulk(self.delta.to_string())])
+    }
+}
+
+impl Append {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Append, Error> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(Append { key, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.append(&self.key, &self.value) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("APPEND"), bulk(self.key), Frame::Bulk(self.value)])
+    }
+}
+
+impl Strlen {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Strlen, Error> {
+        let key = parse.next_string()?;
+
+        Ok(Strlen { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.strlen(&self.key) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("STRLEN"), bulk(self.key)])
+    }
+}
+
+impl MGet {
+    pub fn parse_frames(parse: &mut Parse) -> Result<MGet, Error> {
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+
+        Ok(MGet { keys })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let values = db.mget(&self.keys);
+        Frame::Array(values.into_iter().map(|value| value.map_or(Frame::Null, Frame::Bulk)).collect())
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk("MGET")];
+        frames.extend(self.keys.into_iter().map(bulk));
+        Frame::Array(frames)
+    }
+}
+
+impl MSet {
+    pub fn parse_frames(parse: &mut Parse, nx: bool) -> Result<MSet, Error> {
+        if parse.remaining() == 0 || !parse.remaining().is_multiple_of(2) {
+            let name = if nx { "msetnx" } else { "mset" };
+            return Err(Error::Other(format!("wrong number of arguments for '{}' command", name)));
+        }
+
+        let mut pairs = Vec::with_capacity(parse.remaining() / 2);
+        while parse.remaining() > 0 {
+            pairs.push((parse.next_string()?, parse.next_bytes()?));
+        }
+
+        Ok(MSet { pairs, nx })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let set = db.mset(self.pairs, self.nx);
+        if self.nx {
+            Frame::Integer(set as i64)
+        } else {
+            Frame::Simple("OK".to_string())
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![bulk(if self.nx { "MSETNX" } else { "MSET" })];
+        for (key, value) in self.pairs {
+            frames.push(bulk(key));
+            frames.push(Frame::Bulk(value));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl GetSet {
+    pub fn parse_frames(parse: &mut Parse) -> Result<GetSet, Error> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+
+        Ok(GetSet { key, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.getset(&self.key, self.value) {
+            Ok(prev) => prev.map_or(Frame::Null, Frame::Bulk),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("GETSET"), bulk(self.key), Frame::Bulk(self.value)])
+    }
+}
+
+impl GetDel {
+    pub fn parse_frames(parse: &mut Parse) -> Result<GetDel, Error> {
+        let key = parse.next_string()?;
+
+        Ok(GetDel { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.getdel(&self.key) {
+            Ok(value) => value.map_or(Frame::Null, Frame::Bulk),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("GETDEL"), bulk(self.key)])
+    }
+}
+
+impl SetRange {
+    pub fn parse_frames(parse: &mut Parse) -> Result<SetRange, Error> {
+        let key = parse.next_string()?;
+        let offset = parse.next_int()?;
+        if offset < 0 {
+            return Err(Error::Other("offset is out of range".into()));
+        }
+        let value = parse.next_bytes()?;
+
+        Ok(SetRange { key, offset: offset as usize, value })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.setrange(&self.key, self.offset, &self.value) {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("SETRANGE"), bulk(self.key), bulk(self.offset.to_string()), Frame::Bulk(self.value)])
+    }
+}
+
+impl GetRange {
+    pub fn parse_frames(parse: &mut Parse) -> Result<GetRange, Error> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let end = parse.next_int()?;
+
+        Ok(GetRange { key, start, end })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.getrange(&self.key, self.start, self.end) {
+            Ok(value) => Frame::Bulk(value),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![bulk("GETRANGE"), bulk(self.key), bulk(self.start.to_string()), bulk(self.end.to_string())])
+    }
+}
@@ -1,45 +1,57 @@
+//! `SUBSCRIBE`, `PSUBSCRIBE`, `UNSUBSCRIBE` and `PUNSUBSCRIBE`. They change
+//! what the connection listens to, so the server executes them.
+
 use super::Parse;
-use crate::Error;
+use crate::{Error, Frame};
 use bytes::Bytes;
 
 #[derive(Debug, Clone)]
 pub struct Subscribe {
     pub channels: Vec<String>,
+    /// `PSUBSCRIBE`: the channels are glob patterns.
+    pub pattern: bool,
 }
 
-impl Subscribe {
-    pub fn parse_frames(parse: &mut Parse) -> Result<Subscribe, Error> {
-        let mut channels = Vec::new();
+/// Without any channel, unsubscribes from all of them.
+#[derive(Debug, Clone)]
+pub struct Unsubscribe {
+    pub channels: Vec<String>,
+    /// `PUNSUBSCRIBE`: the channels are glob patterns.
+    pub pattern: bool,
+}
 
-        loop {
-            match parse.next_string() {
-                Ok(s) => channels.push(s),
-                Err(crate::Error::Other(_)) => break, // End of frame or invalid string, assuming loop consumption until error/end
-                Err(_) => break,
-            }
+impl Subscribe {
+    pub fn parse_frames(parse: &mut Parse, pattern: bool) -> Result<Subscribe, Error> {
+        let mut channels = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            channels.push(parse.next_string()?);
         }
 
-        // The `Parse` struct logic in `mod.rs` is strict: `next_string` calls `next`.
-        // `next` returns Error if no more frames.
-        // So we need to handle "End of Stream" gracefully or check length first.
-        // But `Parse` iterates over `std::vec::IntoIter`.
-        // Let's rely on the fact that `Parse` consumes the iterator.
-
-        // Actually, `Parse` struct as defined in `src/cmd/mod.rs` consumes the iterator one by one.
-        // If we want to consume *all remaining* args as channels:
-
-        Ok(Subscribe { channels })
+        Ok(Subscribe { channels, pattern })
     }
 
-    // We need a custom parse implementation because `Parse::next` errors on empty.
-    // Let's just consume until we get an error.
+    pub fn into_frame(self) -> Frame {
+        channels_frame(if self.pattern { "PSUBSCRIBE" } else { "SUBSCRIBE" }, self.channels)
+    }
+}
 
-    pub fn into_frame(self) -> crate::Frame {
-        let mut frames = Vec::new();
-        frames.push(crate::Frame::Bulk(Bytes::from("SUBSCRIBE")));
-        for channel in self.channels {
-            frames.push(crate::Frame::Bulk(Bytes::from(channel)));
+impl Unsubscribe {
+    pub fn parse_frames(parse: &mut Parse, pattern: bool) -> Result<Unsubscribe, Error> {
+        let mut channels = Vec::new();
+        while parse.remaining() > 0 {
+            channels.push(parse.next_string()?);
         }
-        crate::Frame::Array(frames)
+
+        Ok(Unsubscribe { channels, pattern })
     }
+
+    pub fn into_frame(self) -> Frame {
+        channels_frame(if self.pattern { "PUNSUBSCRIBE" } else { "UNSUBSCRIBE" }, self.channels)
+    }
+}
+
+fn channels_frame(name: &str, channels: Vec<String>) -> Frame {
+    let mut frames = vec![Frame::Bulk(Bytes::from(name.to_string()))];
+    frames.extend(channels.into_iter().map(|channel| Frame::Bulk(Bytes::from(channel))));
+    Frame::Array(frames)
 }
@@ -0,0 +1,80 @@
+//! `MULTI`, `EXEC`, `DISCARD`, `WATCH` and `UNWATCH`. They act on the
+//! connection's transaction state, so the server executes them itself.
+
+use super::Parse;
+use crate::{Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct Multi;
+
+#[derive(Debug, Clone)]
+pub struct Exec;
+
+#[derive(Debug, Clone)]
+pub struct Discard;
+
+#[derive(Debug, Clone)]
+pub struct Watch {
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Unwatch;
+
+impl Multi {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<Multi, Error> {
+        Ok(Multi)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from("MULTI"))])
+    }
+}
+
+impl Exec {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<Exec, Error> {
+        Ok(Exec)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from("EXEC"))])
+    }
+}
+
+impl Discard {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<Discard, Error> {
+        Ok(Discard)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from("DISCARD"))])
+    }
+}
+
+impl Watch {
+    pub fn parse_frames(parse: &mut Parse) -> Result<Watch, Error> {
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+
+        Ok(Watch { keys })
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("WATCH"))];
+        frames.extend(self.keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))));
+        Frame::Array(frames)
+    }
+}
+
+impl Unwatch {
+    pub fn parse_frames(_parse: &mut Parse) -> Result<Unwatch, Error> {
+        Ok(Unwatch)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from("UNWATCH"))])
+    }
+}
@@ -0,0 +1,37 @@
+use super::Parse;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+/// `TTL` and `PTTL`.
+#[derive(Debug, Clone)]
+pub struct Ttl {
+    pub key: String,
+    /// Report the TTL in milliseconds (`PTTL`) rather than seconds.
+    pub millis: bool,
+}
+
+impl Ttl {
+    pub fn parse_frames(parse: &mut Parse, millis: bool) -> Result<Ttl, Error> {
+        let key = parse.next_string()?;
+
+        Ok(Ttl { key, millis })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.ttl(&self.key) {
+            None => Frame::Integer(-2),
+            Some(None) => Frame::Integer(-1),
+            Some(Some(ms)) if self.millis => Frame::Integer(ms as i64),
+            // Round to the nearest second, as Redis does
+            Some(Some(ms)) => Frame::Integer(((ms + 500) / 1000) as i64),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let name = if self.millis { "PTTL" } else { "TTL" };
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(name)),
+            Frame::Bulk(Bytes::from(self.key)),
+        ])
+    }
+}
@@ -9,4 +9,8 @@ impl Unknown {
             command_name: key.into(),
         }
     }
+
+    pub(crate) fn apply(self) -> crate::Frame {
+        crate::Frame::Error(format!("unknown command '{}'", self.command_name))
+    }
 }
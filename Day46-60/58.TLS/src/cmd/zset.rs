@@ -0,0 +1,203 @@
+use super::Parse;
+use crate::db::SetCondition;
+use crate::{Db, Error, Frame};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct ZAdd {
+    pub key: String,
+    pub members: Vec<(f64, Bytes)>,
+    /// `NX` / `XX`: only add new members or only update existing ones.
+    pub condition: SetCondition,
+    /// `CH`: count updated members in the reply, not just added ones.
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZRange {
+    pub key: String,
+    pub start: i64,
+    pub stop: i64,
+    pub with_scores: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZRank {
+    pub key: String,
+    pub member: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZScore {
+    pub key: String,
+    pub member: Bytes,
+}
+
+/// Formats a score the way Redis replies with it (`1`, `1.5`, `inf`).
+pub(crate) fn format_score(score: f64) -> Bytes {
+    Bytes::from(score.to_string())
+}
+
+impl ZAdd {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ZAdd, Error> {
+        let key = parse.next_string()?;
+        let (mut nx, mut xx) = (false, false);
+        let mut changed = false;
+
+        // Options come first; the first argument that isn't one is a score
+        let mut members = Vec::new();
+        while parse.remaining() > 0 {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NX" if members.is_empty() => nx = true,
+                "XX" if members.is_empty() => xx = true,
+                "CH" if members.is_empty() => changed = true,
+                _ => {
+                    let score = parse_score(&arg)?;
+                    members.push((score, parse.next_bytes()?));
+                    while parse.remaining() > 0 {
+                        let score = parse_score(&parse.next_string()?)?;
+                        members.push((score, parse.next_bytes()?));
+                    }
+                }
+            }
+        }
+
+        let condition = match (nx, xx) {
+            (true, true) => return Err(Error::Other("XX and NX options at the same time are not compatible".into())),
+            (true, false) => SetCondition::IfNotExists,
+            (false, true) => SetCondition::IfExists,
+            (false, false) => SetCondition::Always,
+        };
+        if members.is_empty() {
+            return Err(Error::Other("wrong number of arguments for 'zadd' command".into()));
+        }
+
+        Ok(ZAdd { key, members, condition, changed })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.zadd(&self.key, self.members, self.condition, self.changed) {
+            Ok(count) => Frame::Integer(count as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![Frame::Bulk(Bytes::from("ZADD")), Frame::Bulk(Bytes::from(self.key))];
+        match self.condition {
+            SetCondition::Always => {}
+            SetCondition::IfNotExists => frames.push(Frame::Bulk(Bytes::from("NX"))),
+            SetCondition::IfExists => frames.push(Frame::Bulk(Bytes::from("XX"))),
+        }
+        if self.changed {
+            frames.push(Frame::Bulk(Bytes::from("CH")));
+        }
+        for (score, member) in self.members {
+            frames.push(Frame::Bulk(format_score(score)));
+            frames.push(Frame::Bulk(member));
+        }
+        Frame::Array(frames)
+    }
+}
+
+fn parse_score(arg: &str) -> Result<f64, Error> {
+    match arg.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err(Error::Other("value is not a valid float".into())),
+    }
+}
+
+impl ZRange {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ZRange, Error> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+        let with_scores = match parse.remaining() {
+            0 => false,
+            _ if parse.next_string()?.eq_ignore_ascii_case("withscores") => true,
+            _ => return Err(Error::Other("syntax error".into())),
+        };
+
+        Ok(ZRange { key, start, stop, with_scores })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.zrange(&self.key, self.start, self.stop) {
+            Ok(members) => {
+                let mut frames = Vec::new();
+                for (member, score) in members {
+                    frames.push(Frame::Bulk(member));
+                    if self.with_scores {
+                        frames.push(Frame::Bulk(format_score(score)));
+                    }
+                }
+                Frame::Array(frames)
+            }
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        let mut frames = vec![
+            Frame::Bulk(Bytes::from("ZRANGE")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(Bytes::from(self.start.to_string())),
+            Frame::Bulk(Bytes::from(self.stop.to_string())),
+        ];
+        if self.with_scores {
+            frames.push(Frame::Bulk(Bytes::from("WITHSCORES")));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl ZRank {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ZRank, Error> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZRank { key, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.zrank(&self.key, &self.member) {
+            Ok(Some(rank)) => Frame::Integer(rank as i64),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("ZRANK")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(self.member),
+        ])
+    }
+}
+
+impl ZScore {
+    pub fn parse_frames(parse: &mut Parse) -> Result<ZScore, Error> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+
+        Ok(ZScore { key, member })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.zscore(&self.key, &self.member) {
+            Ok(Some(score)) => Frame::Double(score),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("ZSCORE")),
+            Frame::Bulk(Bytes::from(self.key)),
+            Frame::Bulk(self.member),
+        ])
+    }
+}
@@ -0,0 +1,523 @@
+//! RESP2 / RESP3 encoding and decoding of `Frame` values, shared by
+//! `Connection` and the AOF.
+use crate::{Error, Frame};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io::Cursor;
+
+/// Longest bulk string accepted from a peer, as Redis' `proto-max-bulk-len`.
+pub const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// How deep aggregate frames may nest. Keeps a hostile peer from overflowing
+/// the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// Longest inline command accepted, as in Redis.
+pub const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// The protocol version a connection speaks, negotiated with `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+/// Appends the encoding of `frame` in `protocol` to `dst`.
+///
+/// RESP2 has no equivalent for most RESP3 types, so they are sent the way
+/// Redis sends them to RESP2 clients: maps as flat arrays of keys and values,
+/// sets and pushes as arrays, doubles, big numbers and verbatim strings as
+/// bulk strings, and booleans as `1` / `0`. Attributes are left out. The null
+/// array `*-1` decodes to `Frame::Null` like the null bulk string, which is
+/// what is written for it.
+///
+/// Line breaks in simple strings and errors can't be represented and are
+/// replaced with spaces.
+pub fn encode(frame: &Frame, protocol: Protocol, dst: &mut BytesMut) {
+    let resp3 = protocol == Protocol::Resp3;
+    match frame {
+        Frame::Simple(val) => {
+            dst.put_u8(b'+');
+            put_line(val, dst);
+        }
+        Frame::Error(val) => {
+            dst.put_u8(b'-');
+            put_line(val, dst);
+        }
+        Frame::Integer(val) => put_header(b':', *val, dst),
+        Frame::Bulk(val) => put_bulk(b'$', val, dst),
+        Frame::Null if resp3 => dst.put_slice(b"_\r\n"),
+        Frame::Null => dst.put_slice(b"$-1\r\n"),
+        Frame::Array(val) => put_aggregate(b'*', val, protocol, dst),
+        Frame::Map(pairs) if resp3 => {
+            put_header(b'%', pairs.len() as i64, dst);
+            put_pairs(pairs, protocol, dst);
+        }
+        Frame::Map(pairs) => {
+            put_header(b'*', 2 * pairs.len() as i64, dst);
+            put_pairs(pairs, protocol, dst);
+        }
+        Frame::Set(val) => put_aggregate(if resp3 { b'~' } else { b'*' }, val, protocol, dst),
+        Frame::Push(val) => put_aggregate(if resp3 { b'>' } else { b'*' }, val, protocol, dst),
+        Frame::Double(val) if resp3 => {
+            dst.put_u8(b',');
+            dst.put_slice(format_double(*val).as_bytes());
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Double(val) => put_bulk(b'$', format_double(*val).as_bytes(), dst),
+        Frame::Boolean(val) if resp3 => dst.put_slice(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+        Frame::Boolean(val) => put_header(b':', *val as i64, dst),
+        Frame::BigNumber(val) if resp3 => {
+            dst.put_u8(b'(');
+            dst.put_slice(val.as_bytes());
+            dst.put_slice(b"\r\n");
+        }
+        Frame::BigNumber(val) => put_bulk(b'$', val.as_bytes(), dst),
+        Frame::Verbatim(format, text) if resp3 => {
+            put_header(b'=', (format.len() + 1 + text.len()) as i64, dst);
+            dst.put_slice(format.as_bytes());
+            dst.put_u8(b':');
+            dst.put_slice(text);
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Verbatim(_, text) => put_bulk(b'$', text, dst),
+        Frame::Attribute(pairs, frame) => {
+            if resp3 {
+                put_header(b'|', pairs.len() as i64, dst);
+                put_pairs(pairs, protocol, dst);
+            }
+            encode(frame, protocol, dst);
+        }
+    }
+}
+
+/// Formats a double the way both RESP3 and Redis' RESP2 replies spell it.
+pub fn format_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+fn put_header(kind: u8, len: i64, dst: &mut BytesMut) {
+    dst.put_u8(kind);
+    dst.put_slice(len.to_string().as_bytes());
+    dst.put_slice(b"\r\n");
+}
+
+fn put_bulk(kind: u8, val: &[u8], dst: &mut BytesMut) {
+    put_header(kind, val.len() as i64, dst);
+    dst.put_slice(val);
+    dst.put_slice(b"\r\n");
+}
+
+fn put_aggregate(kind: u8, val: &[Frame], protocol: Protocol, dst: &mut BytesMut) {
+    put_header(kind, val.len() as i64, dst);
+    for entry in val {
+        encode(entry, protocol, dst);
+    }
+}
+
+fn put_pairs(pairs: &[(Frame, Frame)], protocol: Protocol, dst: &mut BytesMut) {
+    for (key, value) in pairs {
+        encode(key, protocol, dst);
+        encode(value, protocol, dst);
+    }
+}
+
+fn put_line(line: &str, dst: &mut BytesMut) {
+    if line.contains(['\r', '\n']) {
+        dst.put_slice(line.replace(['\r', '\n'], " ").as_bytes());
+    } else {
+        dst.put_slice(line.as_bytes());
+    }
+    dst.put_slice(b"\r\n");
+}
+
+/// Whether `byte` starts a RESP frame. Anything else sent to the server
+/// starts an inline command.
+pub fn is_type_byte(byte: u8) -> bool {
+    matches!(byte, b'+' | b'-' | b':' | b'$' | b'*' | b'_' | b'#' | b',' | b'(' | b'!' | b'=' | b'%' | b'~' | b'>' | b'|')
+}
+
+/// Tries to decode one inline command (`GET "my key"`, as typed into telnet)
+/// from the start of `buffer`, returning its arguments along with the number
+/// of bytes the line occupies. Blank lines have no arguments.
+pub fn decode_inline(buffer: &[u8]) -> Result<Option<(Vec<Bytes>, usize)>, Error> {
+    let Some(end) = buffer.iter().position(|&b| b == b'\n') else {
+        if buffer.len() > MAX_INLINE_LEN {
+            return Err(Error::Other("too big inline request".into()));
+        }
+        return Ok(None);
+    };
+    if end > MAX_INLINE_LEN {
+        return Err(Error::Other("too big inline request".into()));
+    }
+
+    let line = &buffer[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(Some((split_args(line)?, end + 1)))
+}
+
+/// Splits a line into arguments the way `redis-cli` and Redis' inline
+/// protocol do: on whitespace, with `"double quoted"` arguments supporting
+/// C-style escapes (`\n`, `\x41`, ...) and `'single quoted'` ones only `\'`.
+pub fn split_args(line: &[u8]) -> Result<Vec<Bytes>, Error> {
+    let unbalanced = || Error::Other("unbalanced quotes in request".into());
+    let mut args = vec![];
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = vec![];
+        let quote = match line[i] {
+            q @ (b'"' | b'\'') => {
+                i += 1;
+                Some(q)
+            }
+            _ => None,
+        };
+
+        loop {
+            let Some(&c) = line.get(i) else {
+                if quote.is_some() {
+                    return Err(unbalanced());
+                }
+                break;
+            };
+
+            match quote {
+                None if c.is_ascii_whitespace() => break,
+                None => arg.push(c),
+                Some(q) if c == q => {
+                    // The closing quote must end the argument
+                    if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return Err(unbalanced());
+                    }
+                    i += 1;
+                    break;
+                }
+                Some(b'"') if c == b'\\' && i + 1 < line.len() => {
+                    i += 1;
+                    match line[i] {
+                        b'x' if line.len() > i + 2 => match std::str::from_utf8(&line[i + 1..i + 3])
+                            .ok()
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        {
+                            Some(byte) => {
+                                arg.push(byte);
+                                i += 2;
+                            }
+                            None => arg.push(b'x'),
+                        },
+                        b'n' => arg.push(b'\n'),
+                        b'r' => arg.push(b'\r'),
+                        b't' => arg.push(b'\t'),
+                        b'b' => arg.push(0x08),
+                        b'a' => arg.push(0x07),
+                        other => arg.push(other),
+                    }
+                }
+                Some(b'\'') if c == b'\\' && line.get(i + 1) == Some(&b'\'') => {
+                    i += 1;
+                    arg.push(b'\'');
+                }
+                Some(_) => arg.push(c),
+            }
+            i += 1;
+        }
+
+        args.push(Bytes::from(arg));
+    }
+}
+
+/// Bulk payloads at least this long are handed out as slices of the read
+/// buffer instead of being copied. A slice keeps the whole buffer allocation
+/// alive, so smaller values, which would pin a buffer many times their size
+/// once stored in the keyspace, are still copied.
+pub const SHARE_MIN_LEN: usize = 4 * 1024;
+
+/// An incremental RESP2 / RESP3 decoder.
+///
+/// Every call consumes as much of the buffer as it can and remembers where it
+/// is inside a partially received frame, so no byte is parsed twice however
+/// the frame is split across reads. Large bulk payloads are split off the
+/// buffer without copying (see `SHARE_MIN_LEN`).
+///
+/// The decoder must always be handed the same buffer, with new data appended
+/// at the end.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Aggregates whose elements are still being received, outermost first.
+    stack: Vec<Partial>,
+    /// Type and length of a bulk payload whose header has been consumed.
+    bulk: Option<(u8, usize)>,
+    /// How much of the buffer has been searched for the end of the current
+    /// line.
+    scanned: usize,
+}
+
+#[derive(Debug)]
+struct Partial {
+    kind: u8,
+    items: Vec<Frame>,
+    expected: usize,
+}
+
+enum Step {
+    /// More data is needed.
+    Incomplete,
+    /// An aggregate or bulk header was consumed.
+    Header,
+    Frame(Frame),
+}
+
+/// What a line-terminated header turned out to be.
+enum Line {
+    Frame(Frame),
+    Bulk(usize),
+    Aggregate(usize),
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Returns `true` when no frame is partially decoded.
+    pub fn is_idle(&self) -> bool {
+        self.stack.is_empty() && self.bulk.is_none()
+    }
+
+    /// Decodes the next frame from the start of `buf`, consuming its bytes.
+    /// Returns `None`, having consumed what it could, when more data is
+    /// needed.
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        loop {
+            let mut frame = match self.step(buf)? {
+                Step::Incomplete => return Ok(None),
+                Step::Header => continue,
+                Step::Frame(frame) => frame,
+            };
+
+            // Hand the frame to the aggregate it belongs to, closing every
+            // aggregate it completes
+            loop {
+                let Some(mut partial) = self.stack.pop() else { return Ok(Some(frame)) };
+                partial.items.push(frame);
+                if partial.items.len() < partial.expected {
+                    self.stack.push(partial);
+                    break;
+                }
+                frame = finish(partial.kind, partial.items);
+            }
+        }
+    }
+
+    fn step(&mut self, buf: &mut BytesMut) -> Result<Step, Error> {
+        if let Some((kind, len)) = self.bulk {
+            if buf.len() < len + 2 {
+                return Ok(Step::Incomplete);
+            }
+            if &buf[len..len + 2] != b"\r\n" {
+                return Err(Error::Other("bulk string not terminated by CRLF".into()));
+            }
+            let data = if len >= SHARE_MIN_LEN {
+                buf.split_to(len).freeze()
+            } else {
+                let data = Bytes::copy_from_slice(&buf[..len]);
+                buf.advance(len);
+                data
+            };
+            buf.advance(2);
+            self.bulk = None;
+            return bulk_frame(kind, data).map(Step::Frame);
+        }
+
+        let Some(end) = self.find_line(buf)? else { return Ok(Step::Incomplete) };
+        let kind = buf[0];
+        let line = parse_line(kind, &buf[1..end]);
+        buf.advance(end + 2);
+        self.scanned = 0;
+
+        match line? {
+            Line::Frame(frame) => Ok(Step::Frame(frame)),
+            Line::Bulk(len) => {
+                self.bulk = Some((kind, len));
+                Ok(Step::Header)
+            }
+            Line::Aggregate(len) => {
+                let expected = match kind {
+                    b'%' => len.checked_mul(2),
+                    b'|' => len.checked_mul(2).and_then(|n| n.checked_add(1)),
+                    _ => Some(len),
+                }
+                .ok_or_else(|| Error::Other(format!("invalid length {}", len)))?;
+
+                if expected == 0 {
+                    return Ok(Step::Frame(finish(kind, vec![])));
+                }
+                if self.stack.len() >= MAX_DEPTH {
+                    return Err(Error::Other("frames nested too deeply".into()));
+                }
+                // The length comes from the peer, so don't trust it for the
+                // allocation
+                self.stack.push(Partial { kind, items: Vec::with_capacity(expected.min(1024)), expected });
+                Ok(Step::Header)
+            }
+        }
+    }
+
+    /// Finds the `\r\n` ending the line at the start of `buf`, resuming the
+    /// search where the previous call left off.
+    fn find_line(&mut self, buf: &[u8]) -> Result<Option<usize>, Error> {
+        let from = self.scanned.max(1);
+        match buf.get(from..).and_then(|rest| rest.windows(2).position(|w| w == b"\r\n")) {
+            Some(pos) => Ok(Some(from + pos)),
+            None => {
+                if buf.len() > MAX_INLINE_LEN {
+                    return Err(Error::Other("line too long".into()));
+                }
+                // The last byte may be the `\r` of a split `\r\n`
+                self.scanned = buf.len().saturating_sub(1);
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn parse_line(kind: u8, line: &[u8]) -> Result<Line, Error> {
+    let required = |len: Option<usize>| len.ok_or_else(|| Error::Other("invalid length -1".into()));
+    let frame = match kind {
+        b'+' => Frame::Simple(utf8(line)?),
+        b'-' => Frame::Error(utf8(line)?),
+        b':' => Frame::Integer(decimal(line)?),
+        b'$' => match length(line, MAX_BULK_LEN)? {
+            None => Frame::Null,
+            Some(len) => return Ok(Line::Bulk(len)),
+        },
+        b'!' | b'=' => return Ok(Line::Bulk(required(length(line, MAX_BULK_LEN)?)?)),
+        b'*' => match length(line, i64::MAX)? {
+            None => Frame::Null,
+            Some(len) => return Ok(Line::Aggregate(len)),
+        },
+        b'%' | b'~' | b'>' | b'|' => return Ok(Line::Aggregate(required(length(line, i64::MAX)?)?)),
+        b'_' if line.is_empty() => Frame::Null,
+        b'_' => return Err(Error::Other("invalid null".into())),
+        b'#' => match line {
+            b"t" => Frame::Boolean(true),
+            b"f" => Frame::Boolean(false),
+            _ => return Err(Error::Other("invalid boolean".into())),
+        },
+        b',' => Frame::Double(
+            std::str::from_utf8(line)
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .ok_or_else(|| Error::Other("invalid double".into()))?,
+        ),
+        b'(' => {
+            let digits = line.strip_prefix(b"-").unwrap_or(line);
+            if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                return Err(Error::Other("invalid big number".into()));
+            }
+            Frame::BigNumber(utf8(line)?)
+        }
+        b => return Err(Error::Other(format!("invalid frame type byte `{}`", b as char))),
+    };
+    Ok(Line::Frame(frame))
+}
+
+fn bulk_frame(kind: u8, data: Bytes) -> Result<Frame, Error> {
+    match kind {
+        b'!' => Ok(Frame::Error(String::from_utf8_lossy(&data).into_owned())),
+        b'=' => {
+            if data.len() < 4 || data[3] != b':' {
+                return Err(Error::Other("verbatim string without a format".into()));
+            }
+            Ok(Frame::Verbatim(String::from_utf8_lossy(&data[..3]).into_owned(), data.slice(4..)))
+        }
+        _ => Ok(Frame::Bulk(data)),
+    }
+}
+
+fn finish(kind: u8, mut items: Vec<Frame>) -> Frame {
+    fn pairs(items: Vec<Frame>) -> Vec<(Frame, Frame)> {
+        let mut items = items.into_iter();
+        let mut pairs = Vec::with_capacity(items.len() / 2);
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((key, value));
+        }
+        pairs
+    }
+
+    match kind {
+        b'~' => Frame::Set(items),
+        b'>' => Frame::Push(items),
+        b'%' => Frame::Map(pairs(items)),
+        b'|' => {
+            let frame = items.pop().unwrap_or(Frame::Null);
+            Frame::Attribute(pairs(items), Box::new(frame))
+        }
+        _ => Frame::Array(items),
+    }
+}
+
+fn utf8(line: &[u8]) -> Result<String, Error> {
+    std::str::from_utf8(line)
+        .map(str::to_string)
+        .map_err(|_| Error::Other("invalid UTF-8 in simple string".into()))
+}
+
+fn decimal(line: &[u8]) -> Result<i64, Error> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(|| Error::Other("invalid integer".into()))
+}
+
+/// Parses a bulk or aggregate length; `None` for the `-1` null marker.
+fn length(line: &[u8], max: i64) -> Result<Option<usize>, Error> {
+    match decimal(line)? {
+        -1 => Ok(None),
+        len if (0..=max).contains(&len) => Ok(Some(len as usize)),
+        len => Err(Error::Other(format!("invalid length {}", len))),
+    }
+}
+
+/// Decodes one complete frame from the start of `buffer`, returning it along
+/// with the number of bytes it occupies, or `None` if it is cut short.
+///
+/// For one-off decoding; streams should keep a `Decoder` around instead.
+pub fn decode(buffer: &[u8]) -> Result<Option<(Frame, usize)>, Error> {
+    let mut buf = BytesMut::from(buffer);
+    match Decoder::new().decode(&mut buf)? {
+        Some(frame) => Ok(Some((frame, buffer.len() - buf.len()))),
+        None => Ok(None),
+    }
+}
+
+/// Checks whether a complete frame can be decoded from `src`, advancing past
+/// it if so.
+pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    parse(src).map(|_| ())
+}
+
+/// Decodes a frame from `src`, advancing past it. Returns
+/// `Error::Incomplete` if the frame is cut short.
+pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+    let start = src.position() as usize;
+    match decode(&src.get_ref()[start..])? {
+        Some((frame, len)) => {
+            src.set_position((start + len) as u64);
+            Ok(frame)
+        }
+        None => Err(Error::Incomplete),
+    }
+}
@@ -0,0 +1,374 @@
+//! Server configuration: a `redis.conf` style file, command line overrides
+//! and the parameters `CONFIG GET` / `CONFIG SET` work on.
+//!
+//! The file has one `name value` directive per line. Values may be quoted
+//! like arguments of inline commands, and lines starting with `#` are
+//! comments. On the command line the file path comes first, followed by any
+//! number of `--name value` overrides:
+//!
+//! ```text
+//! mini-redis redis.conf --port 6380 --maxmemory 100mb
+//! ```
+
+use crate::{codec, glob, EvictionPolicy, FsyncPolicy, KeyspaceEvents};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// Every parameter, in the order `CONFIG GET` and `CONFIG REWRITE` list
+/// them, along with whether `CONFIG SET` may change it at runtime.
+const PARAMETERS: &[(&str, bool)] = &[
+    ("bind", false),
+    ("port", false),
+    ("appendfilename", false),
+    ("dbfilename", false),
+    ("appendfsync", true),
+    ("auto-aof-rewrite-percentage", true),
+    ("auto-aof-rewrite-min-size", true),
+    ("aof-use-rdb-preamble", true),
+    ("tls-cert-file", false),
+    ("tls-key-file", false),
+    ("maxmemory", true),
+    ("maxmemory-policy", true),
+    ("maxclients", true),
+    ("pubsub-lag-hard-limit", true),
+    ("notify-keyspace-events", true),
+    ("requirepass", true),
+    ("aclfile", false),
+    ("replicaof", false),
+    ("masteruser", true),
+    ("masterauth", true),
+    ("repl-backlog-size", true),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind: String,
+    pub port: u16,
+    pub appendfilename: String,
+    pub dbfilename: String,
+    pub appendfsync: FsyncPolicy,
+    pub auto_aof_rewrite_percentage: u64,
+    pub auto_aof_rewrite_min_size: u64,
+    pub aof_use_rdb_preamble: bool,
+    /// Certificate chain and private key in PEM format. When both are set the
+    /// server only accepts TLS connections.
+    pub tls_cert_file: String,
+    pub tls_key_file: String,
+    /// Memory limit in bytes; `0` means no limit.
+    pub maxmemory: u64,
+    pub maxmemory_policy: EvictionPolicy,
+    /// Connections beyond this many are refused.
+    pub maxclients: usize,
+    /// A subscriber that misses more than this many messages of a channel at
+    /// once is disconnected; `0` means it never is. Smaller gaps are only
+    /// reported to it.
+    pub pubsub_lag_hard_limit: u64,
+    /// Which keyspace notifications are published; none by default.
+    pub notify_keyspace_events: KeyspaceEvents,
+    /// Password of the `default` user; empty means none is needed.
+    pub requirepass: String,
+    /// Where `ACL SAVE` writes users and `ACL LOAD` reads them from. Users
+    /// are loaded from it at startup if it exists.
+    pub aclfile: String,
+    /// The master to replicate at startup, given as `"host port"`. `REPLICAOF`
+    /// changes it at runtime.
+    pub replicaof: Option<(String, u16)>,
+    /// The user and password to authenticate to the master with; an empty
+    /// password means none.
+    pub masteruser: String,
+    pub masterauth: String,
+    /// How many bytes of the replication stream are kept for replicas to
+    /// continue from after a disconnection.
+    pub repl_backlog_size: u64,
+    /// The file the configuration was read from, which `CONFIG REWRITE`
+    /// updates.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            appendfilename: "appendonly.aof".to_string(),
+            dbfilename: "dump.rdb".to_string(),
+            appendfsync: FsyncPolicy::EverySec,
+            auto_aof_rewrite_percentage: 100,
+            auto_aof_rewrite_min_size: 64 * 1024 * 1024,
+            aof_use_rdb_preamble: true,
+            tls_cert_file: String::new(),
+            tls_key_file: String::new(),
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxclients: 10000,
+            pubsub_lag_hard_limit: 8192,
+            notify_keyspace_events: KeyspaceEvents::default(),
+            requirepass: String::new(),
+            aclfile: String::new(),
+            replicaof: None,
+            masteruser: String::new(),
+            masterauth: String::new(),
+            repl_backlog_size: 1024 * 1024,
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the command line arguments, without the
+    /// program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Config> {
+        let mut args = args.into_iter().peekable();
+        let mut config = match args.next_if(|arg| !arg.starts_with("--")) {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                anyhow::bail!("unexpected argument '{}'", arg);
+            };
+            let value = args.next().with_context(|| format!("missing value for --{}", name))?;
+            config.set(name, &value).map_err(|e| anyhow::anyhow!("--{}: {}", name, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Reads a configuration file. Parameters it doesn't mention keep their
+    /// default value.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Config> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut config = Config { path: Some(path.to_path_buf()), ..Config::default() };
+        for (number, line) in contents.lines().enumerate() {
+            let Some((name, value)) = parse_line(line).map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, e))? else {
+                continue;
+            };
+            config.set(&name, &value).map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    /// The address the server listens on.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    /// Whether connections must use TLS.
+    pub fn tls(&self) -> bool {
+        !self.tls_cert_file.is_empty() && !self.tls_key_file.is_empty()
+    }
+
+    /// Returns the value of a parameter, as `CONFIG GET` shows it.
+    pub fn get(&self, name: &str) -> Option<String> {
+        Some(match name.to_lowercase().as_str() {
+            "bind" => self.bind.clone(),
+            "port" => self.port.to_string(),
+            "appendfilename" => self.appendfilename.clone(),
+            "dbfilename" => self.dbfilename.clone(),
+            "appendfsync" => self.appendfsync.to_string(),
+            "auto-aof-rewrite-percentage" => self.auto_aof_rewrite_percentage.to_string(),
+            "auto-aof-rewrite-min-size" => self.auto_aof_rewrite_min_size.to_string(),
+            "aof-use-rdb-preamble" => yes_no(self.aof_use_rdb_preamble),
+            "tls-cert-file" => self.tls_cert_file.clone(),
+            "tls-key-file" => self.tls_key_file.clone(),
+            "maxmemory" => self.maxmemory.to_string(),
+            "maxmemory-policy" => self.maxmemory_policy.to_string(),
+            "maxclients" => self.maxclients.to_string(),
+            "pubsub-lag-hard-limit" => self.pubsub_lag_hard_limit.to_string(),
+            "notify-keyspace-events" => self.notify_keyspace_events.to_string(),
+            "requirepass" => self.requirepass.clone(),
+            "aclfile" => self.aclfile.clone(),
+            "replicaof" => self.replicaof.as_ref().map(|(host, port)| format!("{} {}", host, port)).unwrap_or_default(),
+            "masteruser" => self.masteruser.clone(),
+            "masterauth" => self.masterauth.clone(),
+            "repl-backlog-size" => self.repl_backlog_size.to_string(),
+            _ => return None,
+        })
+    }
+
+    /// Returns every parameter whose name matches the glob `pattern`.
+    pub fn matching(&self, pattern: &str) -> Vec<(String, String)> {
+        let pattern = pattern.to_lowercase();
+        PARAMETERS
+            .iter()
+            .filter(|(name, _)| glob::matches(pattern.as_bytes(), name.as_bytes()))
+            .filter_map(|(name, _)| Some((name.to_string(), self.get(name)?)))
+            .collect()
+    }
+
+    /// Sets a parameter, whether or not it may change at runtime. Returns why
+    /// if the name is unknown or the value invalid.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = value.parse().map_err(|_| "argument must be a port number")?,
+            "appendfilename" => self.appendfilename = value.to_string(),
+            "dbfilename" => self.dbfilename = value.to_string(),
+            "appendfsync" => self.appendfsync = value.parse().map_err(|e: anyhow::Error| e.to_string())?,
+            "auto-aof-rewrite-percentage" => {
+                self.auto_aof_rewrite_percentage = value.parse().map_err(|_| "argument must be an integer")?
+            }
+            "auto-aof-rewrite-min-size" => self.auto_aof_rewrite_min_size = parse_memory(value)?,
+            "aof-use-rdb-preamble" => self.aof_use_rdb_preamble = parse_bool(value)?,
+            "tls-cert-file" => self.tls_cert_file = value.to_string(),
+            "tls-key-file" => self.tls_key_file = value.to_string(),
+            "maxmemory" => self.maxmemory = parse_memory(value)?,
+            "maxmemory-policy" => self.maxmemory_policy = value.parse()?,
+            "maxclients" => match value.parse() {
+                Ok(n) if n > 0 => self.maxclients = n,
+                _ => return Err("argument must be a positive integer".to_string()),
+            },
+            "pubsub-lag-hard-limit" => {
+                self.pubsub_lag_hard_limit = value.parse().map_err(|_| "argument must be a non-negative integer")?
+            }
+            "notify-keyspace-events" => self.notify_keyspace_events = value.parse()?,
+            "requirepass" => self.requirepass = value.to_string(),
+            "aclfile" => self.aclfile = value.to_string(),
+            "replicaof" => self.replicaof = parse_replicaof(value)?,
+            "masteruser" => self.masteruser = value.to_string(),
+            "masterauth" => self.masterauth = value.to_string(),
+            "repl-backlog-size" => match parse_memory(value)? {
+                0 => return Err("argument must be a positive memory value".to_string()),
+                size => self.repl_backlog_size = size,
+            },
+            _ => return Err(format!("unknown parameter '{}'", name)),
+        }
+        Ok(())
+    }
+
+    /// Whether `CONFIG SET` may change the parameter; `None` if it doesn't
+    /// exist.
+    pub fn is_mutable(name: &str) -> Option<bool> {
+        let name = name.to_lowercase();
+        PARAMETERS.iter().find(|(param, _)| *param == name).map(|(_, mutable)| *mutable)
+    }
+
+    /// Writes the current configuration back to the file it was read from,
+    /// like `CONFIG REWRITE`. Comments and unknown lines are kept, the first
+    /// directive for each parameter is updated in place and later duplicates
+    /// are dropped. Parameters missing from the file are appended if they
+    /// differ from their default.
+    pub fn rewrite(&self) -> anyhow::Result<()> {
+        let path = self.path.as_ref().context("The server is running without a config file")?;
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = vec![];
+        let mut written = vec![];
+        for line in contents.lines() {
+            let name = match parse_line(line) {
+                Ok(Some((name, _))) => name.to_lowercase(),
+                _ => {
+                    lines.push(line.to_string());
+                    continue;
+                }
+            };
+            match self.get(&name) {
+                Some(_) if written.contains(&name) => {}
+                Some(value) => {
+                    lines.push(directive(&name, &value));
+                    written.push(name);
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        let defaults = Config::default();
+        let mut added = PARAMETERS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| !written.iter().any(|written| written == name) && self.get(name) != defaults.get(name))
+            .peekable();
+        if added.peek().is_some() {
+            lines.push("# Generated by CONFIG REWRITE".to_string());
+            lines.extend(added.map(|name| directive(name, &self.get(name).unwrap())));
+        }
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        // Replace the file atomically, so a crash can't leave it half written
+        let temp_path = path.with_extension("conf.tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Splits a line of the file into a parameter name and value. Returns `None`
+/// for blank lines and comments.
+fn parse_line(line: &str) -> Result<Option<(String, String)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let args = codec::split_args(line.as_bytes()).map_err(|e| e.to_string())?;
+    let args = args
+        .into_iter()
+        .map(|arg| String::from_utf8(arg.to_vec()).map_err(|_| "invalid UTF-8".to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match <[String; 2]>::try_from(args) {
+        Ok([name, value]) => Ok(Some((name, value))),
+        Err(_) => Err("expected a parameter name followed by a single value".to_string()),
+    }
+}
+
+/// Formats a line of the file, quoting the value if it must be.
+fn directive(name: &str, value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        format!("{} \"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("{} {}", name, value)
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+/// Parses the master of `replicaof`: `"host port"`, or `""` / `"no one"` for
+/// none.
+fn parse_replicaof(value: &str) -> Result<Option<(String, u16)>, String> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    match parts[..] {
+        [] => Ok(None),
+        [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => Ok(None),
+        [host, port] => Ok(Some((host.to_string(), port.parse().map_err(|_| "invalid master port")?))),
+        _ => Err("argument must be 'host port'".to_string()),
+    }
+}
+
+/// Parses an amount of memory with an optional unit, as Redis does: `k`, `m`
+/// and `g` are powers of 1000, `kb`, `mb` and `gb` powers of 1024.
+pub fn parse_memory(value: &str) -> Result<u64, String> {
+    let lower = value.to_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match &lower[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".to_string()),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| "argument must be a memory value".to_string())
+}
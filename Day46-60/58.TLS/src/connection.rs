@@ -1,13 +1,15 @@
 use bytes::{Buf, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use crate::{Frame, Error};
-use std::io::Cursor;
+use crate::{codec, Frame, Error, Protocol};
+use crate::codec::Decoder;
 use crate::server::AsyncStream;
 
 /// Send and receive `Frame` values from a remote peer.
 pub struct Connection {
     stream: BufWriter<Box<dyn AsyncStream>>,
     buffer: BytesMut,
+    decoder: Decoder,
+    protocol: Protocol,
 }
 
 impl Connection {
@@ -17,9 +19,21 @@ impl Connection {
         Connection {
             stream: BufWriter::new(Box::new(socket)),
             buffer: BytesMut::with_capacity(4096),
+            decoder: Decoder::new(),
+            protocol: Protocol::default(),
         }
     }
 
+    /// The protocol frames are written in. Connections start out speaking
+    /// RESP2 until `HELLO` switches them.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     pub async fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
         loop {
@@ -29,11 +43,15 @@ impl Connection {
                 return Ok(Some(frame));
             }
 
-            // There is not enough buffered data to read a frame. Attempt to
-            // read more data from the socket.
+            // There is not enough buffered data to read a frame. Replies to
+            // the frames read so far are sent before waiting on the peer, so
+            // a pipelined batch is answered with a single write.
+            self.flush().await?;
+
+            // Attempt to read more data from the socket.
             if 0 == self.stream.read_buf(&mut self.buffer).await.map_err(|e| Error::Other(e.to_string()))? {
                 // The remote closed the connection.
-                if self.buffer.is_empty() {
+                if self.buffer.is_empty() && self.decoder.is_idle() {
                     return Ok(None);
                 } else {
                     return Err(Error::Other("connection reset by peer".into()));
@@ -42,78 +60,62 @@ impl Connection {
         }
     }
 
-    /// Tries to parse a frame from the buffer.
-    fn parse_frame(&mut self) -> Result<Option<Frame>, Error> {
-        let mut buf = Cursor::new(&self.buffer[..]);
-
-        match crate::check(&mut buf) {
-            Ok(_) => {
-                let len = buf.position() as usize;
-                buf.set_position(0);
-                let frame = crate::parse(&mut buf)?;
-                self.buffer.advance(len);
-                Ok(Some(frame))
-            },
-            Err(Error::Incomplete) => Ok(None),
-            Err(e) => Err(e),
-        }
+    /// Waits until the peer closes the connection. Anything received in the
+    /// meantime is kept for the next `read_frame`, so this is cancel safe.
+    pub async fn closed(&mut self) -> Result<(), Error> {
+        while self.stream.read_buf(&mut self.buffer).await.map_err(|e| Error::Other(e.to_string()))? != 0 {}
+        Ok(())
     }
 
-    /// Write a single `Frame` value to the underlying stream.
-    pub async fn write_frame(&mut self, frame: &Frame) -> Result<(), Error> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val.as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-            }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val.as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-            }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val.to_string().as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-            }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-            }
-            Frame::Bulk(val) => {
-                self.stream.write_u8(b'$').await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val.len().to_string().as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-            }
-            Frame::Array(val) => {
-                self.stream.write_u8(b'*').await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(val.len().to_string().as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-                for entry in val {
-                    // Simple recursive write
-                    match entry {
-                        Frame::Bulk(v) => {
-                             self.stream.write_u8(b'$').await.map_err(|e| Error::Other(e.to_string()))?;
-                             self.stream.write_all(v.len().to_string().as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                             self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-                             self.stream.write_all(v).await.map_err(|e| Error::Other(e.to_string()))?;
-                             self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
+    /// Tries to parse a frame from the buffer.
+    ///
+    /// Inline commands are turned into the array of bulk strings a client
+    /// library would have sent, so they go through the same pipeline.
+    fn parse_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            match self.buffer.first() {
+                Some(&byte) if self.decoder.is_idle() && !codec::is_type_byte(byte) => {
+                    match codec::decode_inline(&self.buffer)? {
+                        Some((args, len)) => {
+                            self.buffer.advance(len);
+                            // Blank lines are skipped
+                            if !args.is_empty() {
+                                return Ok(Some(Frame::Array(args.into_iter().map(Frame::Bulk).collect())));
+                            }
                         }
-                         Frame::Integer(v) => {
-                            self.stream.write_u8(b':').await.map_err(|e| Error::Other(e.to_string()))?;
-                            self.stream.write_all(v.to_string().as_bytes()).await.map_err(|e| Error::Other(e.to_string()))?;
-                            self.stream.write_all(b"\r\n").await.map_err(|e| Error::Other(e.to_string()))?;
-                         }
-                        _ => return Err(Error::Other("Nested arrays or other types not supported in Array yet".into())),
+                        None => return Ok(None),
                     }
                 }
+                _ => return self.decoder.decode(&mut self.buffer),
             }
         }
+    }
 
-        self.stream.flush().await.map_err(|e| Error::Other(e.to_string()))?;
+    /// Write a single `Frame` value to the underlying stream.
+    pub async fn write_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        self.buffer_frame(frame).await?;
+        self.flush().await
+    }
 
-        Ok(())
+    /// Write a `Frame` to the write buffer without flushing it. It is sent by
+    /// the next `flush`, `write_frame` or `read_frame` that has to wait for
+    /// more data, or once the buffer fills up.
+    pub async fn buffer_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        let mut encoded = BytesMut::new();
+        codec::encode(frame, self.protocol, &mut encoded);
+
+        self.stream.write_all(&encoded).await.map_err(|e| Error::Other(e.to_string()))
+    }
+
+    /// Write data that is already encoded, such as the replication stream,
+    /// and send it.
+    pub async fn write_bytes(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.stream.write_all(data).await.map_err(|e| Error::Other(e.to_string()))?;
+        self.flush().await
+    }
+
+    /// Send everything in the write buffer.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.stream.flush().await.map_err(|e| Error::Other(e.to_string()))
     }
 }
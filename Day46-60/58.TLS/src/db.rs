@@ -1,7 +1,19 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
 use bytes::Bytes;
-use tokio::sync::broadcast;
+use indexmap::IndexMap;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration, Instant};
+use crate::evict::{self, Access, EvictionPolicy, EVICTION_SAMPLES};
+use crate::notify::{KeyspaceEvents, Notifier};
+use crate::pubsub::PubSub;
+use crate::zset::ZSet;
+use crate::stream::{ClaimOptions, Fields, NewId, PendingSummary, Stream, StreamId};
+use crate::{glob, Frame};
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 /// A wrapper around the `Shared` state of the database.
 #[derive(Clone)]
@@ -10,68 +22,1965 @@ pub struct Db {
 }
 
 struct Shared {
-    state: RwLock<State>,
+    /// The keyspace, split by key hash so that commands on different keys
+    /// rarely wait for each other.
+    shards: Box<[RwLock<Shard>]>,
+    /// Owns the pub/sub channels; shared with every shard.
+    notifier: Arc<Notifier>,
+    /// Wakes the purge task when an earlier expiration is scheduled or the
+    /// database is dropped.
+    background_task: Arc<Notify>,
+    /// Makes `EXEC` blocks atomic: single commands run while holding it
+    /// shared, a transaction holds it exclusively for the whole block.
+    exec_gate: RwLock<()>,
+    /// One lock per shard, held by single writes to the shard while they run
+    /// and queue their AOF entries; see `Db::write_access`.
+    write_order: Box<[Mutex<()>]>,
+    /// AOF entries of writes that ran under `write_access`, in an order
+    /// consistent with the order the writes were applied in, until the server
+    /// appends them.
+    unlogged: Mutex<Vec<Frame>>,
+    /// Memory limit in bytes; `0` means no limit.
+    maxmemory: AtomicU64,
+    eviction_policy: RwLock<EvictionPolicy>,
+    /// Number of keys evicted to stay under `maxmemory`.
+    evicted_keys: AtomicU64,
 }
 
-struct State {
-    entries: HashMap<String, Bytes>,
-    /// The pub/sub key-space. Redis uses a **separate** key space for pub/sub.
-    /// We verify this by checking Redis docs: "Pub/Sub has no relation to the key space".
-    /// Map: Channel Name -> Broadcast Sender
-    pub_sub: HashMap<String, broadcast::Sender<Bytes>>,
+/// Number of shards the keyspace is split into by `Db::new`.
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// One slice of the keyspace. Everything tracked per key lives in the shard
+/// the key hashes to.
+#[derive(Default)]
+struct Shard {
+    /// Indexed, so eviction can sample random keys.
+    entries: IndexMap<String, Entry>,
+    /// Approximate memory used by `entries`; see `entry_size`.
+    used_memory: usize,
+    /// Keys with a TTL, ordered by expiration time (Unix milliseconds), so the
+    /// purge task can find the next key to expire without scanning `entries`.
+    expirations: BTreeSet<(u64, String)>,
+    /// Every key in `SCAN` order, that is by hash, so a scan can resume at its
+    /// cursor without sorting the keyspace.
+    scan_order: BTreeSet<(u64, String)>,
+    /// Clients blocked in `BLPOP` / `BRPOP`, per key, in the order they
+    /// started waiting. The elements of a list are kept for them in that
+    /// order; see `Db::pop_waiting`.
+    blocked: HashMap<String, VecDeque<Arc<Notify>>>,
+    /// Version counters of the keys clients are `WATCH`ing. Only watched keys
+    /// are tracked; a key's entry is dropped once nobody watches it anymore.
+    watched: HashMap<String, Watched>,
+    /// Publishes the events of keys the shard removes on its own: expired
+    /// keys and emptied collections.
+    notifier: Arc<Notifier>,
+}
+
+struct Watched {
+    version: u64,
+    watchers: usize,
+}
+
+/// A value stored in the database along with its expiration time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub value: Value,
+    /// Unix time in milliseconds at which the key expires.
+    pub expires_at: Option<u64>,
+    pub(crate) access: Access,
+}
+
+/// The typed values a key can hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    Set(HashSet<Bytes>),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+/// Errors returned by operations on typed values, with Redis' wording.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DbError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NotFinite,
+    #[error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")]
+    TooLarge,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+    #[error("ERR The ID specified in XADD must be greater than 0-0")]
+    StreamIdZero,
+    #[error("ERR The stream has exhausted the last possible ID, unable to add more items")]
+    StreamExhausted,
+    #[error("ERR The ID specified in XSETID is smaller than the target stream top item")]
+    StreamIdBelowTop,
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}'")]
+    NoGroup(String, String),
+    #[error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")]
+    NoStream,
+}
+
+/// The largest string `APPEND` and `SETRANGE` may produce, as in Redis.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+impl Value {
+    /// The type name reported by Redis' `TYPE` command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Approximate number of bytes the value takes up. Like Redis' `MEMORY
+    /// USAGE`, collections are estimated from their first few elements.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Value::String(value) => value.len(),
+            Value::List(list) => sampled_size(list.len(), list.iter().map(|v| v.len())),
+            Value::Hash(hash) => sampled_size(hash.len(), hash.iter().map(|(f, v)| f.len() + v.len())),
+            Value::Set(set) => sampled_size(set.len(), set.iter().map(|m| m.len())),
+            Value::ZSet(zset) => sampled_size(zset.len(), zset.iter().map(|(m, _)| m.len() + 8)),
+            Value::Stream(stream) => sampled_size(
+                stream.len(),
+                stream.iter().map(|(_, fields)| 16 + fields.iter().map(|(f, v)| f.len() + v.len()).sum::<usize>()),
+            ),
+        }
+    }
+
+    /// Collections are deleted as soon as they become empty, like in Redis.
+    /// Streams are kept, along with their last ID and consumer groups.
+    fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+        }
+    }
+}
+
+/// Condition under which `SET` writes the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    /// `NX`: only set the key if it does not already exist.
+    IfNotExists,
+    /// `XX`: only set the key if it already exists.
+    IfExists,
+}
+
+/// Rough bookkeeping cost of a key: the map slot, the `Entry` and the
+/// allocations behind the key and value.
+const ENTRY_OVERHEAD: usize = 96;
+
+/// Rough bookkeeping cost of each element of a collection.
+const ELEMENT_OVERHEAD: usize = 48;
+
+/// How many elements of a collection `Value::memory_usage` looks at.
+const SIZE_SAMPLES: usize = 5;
+
+/// Estimates the size of a collection of `len` elements from the sizes of its
+/// first elements.
+fn sampled_size(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let (sampled, total) = sizes.take(SIZE_SAMPLES).fold((0, 0), |(n, total), size| (n + 1, total + size));
+    if sampled == 0 {
+        return 0;
+    }
+    total * len / sampled + len * ELEMENT_OVERHEAD
+}
+
+/// Approximate memory used by a key and its entry, as counted against
+/// `maxmemory`.
+fn entry_size(key: &str, entry: &Entry) -> usize {
+    ENTRY_OVERHEAD + key.len() + entry.value.memory_usage()
+}
+
+/// Returns the current Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl Entry {
+    pub fn new(value: Value, expires_at: Option<u64>) -> Entry {
+        Entry { value, expires_at, access: Access::new(now_ms()) }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(when) if when <= now)
+    }
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Db::new()
+    }
 }
 
 impl Db {
     pub fn new() -> Db {
+        Db::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// Creates a database whose keyspace is split into `shards` independently
+    /// locked parts. A single shard puts every key behind one lock.
+    pub fn with_shards(shards: usize) -> Db {
+        let notifier = Arc::new(Notifier::default());
         let shared = Arc::new(Shared {
-            state: RwLock::new(State {
-                entries: HashMap::new(),
-                pub_sub: HashMap::new(),
-            }),
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Shard { notifier: notifier.clone(), ..Shard::default() }))
+                .collect(),
+            notifier,
+            background_task: Arc::new(Notify::new()),
+            exec_gate: RwLock::new(()),
+            write_order: (0..shards.max(1)).map(|_| Mutex::new(())).collect(),
+            unlogged: Mutex::new(Vec::new()),
+            maxmemory: AtomicU64::new(0),
+            eviction_policy: RwLock::new(EvictionPolicy::default()),
+            evicted_keys: AtomicU64::new(0),
         });
         Db { shared }
     }
 
-    /// Gets the value associated with the key.
+    /// Spawns the task that removes expired keys in the background, so keys
+    /// that are never accessed again still free their memory. The task exits
+    /// once every handle to the database has been dropped.
+    pub fn start_purge_task(&self) -> JoinHandle<()> {
+        let shared = Arc::downgrade(&self.shared);
+        let notify = self.shared.background_task.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let next = match shared.upgrade() {
+                    Some(shared) => Db { shared }.purge_expired_keys(),
+                    None => break,
+                };
+
+                match next {
+                    Some(when) => {
+                        let delay = Duration::from_millis(when.saturating_sub(now_ms()));
+                        tokio::select! {
+                            _ = time::sleep_until(Instant::now() + delay) => {}
+                            _ = notify.notified() => {}
+                        }
+                    }
+                    None => notify.notified().await,
+                }
+            }
+        })
+    }
+
+    /// Removes every key whose expiration time has passed and returns the
+    /// expiration time of the next key to expire, if any.
+    pub fn purge_expired_keys(&self) -> Option<u64> {
+        let now = now_ms();
+        self.shared
+            .shards
+            .iter()
+            .filter_map(|shard| shard.write().unwrap().purge_expired(now))
+            .min()
+    }
+
+    /// Gets the string value associated with the key. Keys holding other
+    /// types are reported as missing; see `get_string`.
     pub fn get(&self, key: &str) -> Option<Bytes> {
-        let state = self.shared.state.read().unwrap();
-        state.entries.get(key).cloned()
+        {
+            let shard = self.shard(key).read().unwrap();
+            match shard.entries.get(key) {
+                None => return None,
+                Some(entry) if !entry.is_expired(now_ms()) => return match &entry.value {
+                    Value::String(value) => {
+                        entry.access.touch(now_ms());
+                        Some(value.clone())
+                    }
+                    _ => None,
+                },
+                Some(_) => {}
+            }
+        }
+
+        // The key has expired: delete it now rather than waiting for the purge task
+        self.shard(key).write().unwrap().remove_if_expired(key, now_ms());
+        None
     }
 
-    /// Sets the value associated with the key.
+    /// Sets the value associated with the key, clearing any previous TTL.
     pub fn set(&self, key: String, value: Bytes) {
-        let mut state = self.shared.state.write().unwrap();
-        state.entries.insert(key, value);
+        self.set_with_options(key, value, None, false, SetCondition::Always);
+    }
+
+    /// Sets the value associated with the key, as `SET` with its options does.
+    ///
+    /// `expires_at` is an absolute Unix time in milliseconds; a time in the
+    /// past deletes the key. With `keep_ttl` an existing TTL is retained.
+    /// Returns `false` if `condition` prevented the write.
+    pub fn set_with_options(
+        &self,
+        key: String,
+        value: Bytes,
+        expires_at: Option<u64>,
+        keep_ttl: bool,
+        condition: SetCondition,
+    ) -> bool {
+        let mut shard = self.shard(&key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(&key, now);
+
+        let exists = shard.entries.contains_key(&key);
+        match condition {
+            SetCondition::IfNotExists if exists => return false,
+            SetCondition::IfExists if !exists => return false,
+            _ => {}
+        }
+
+        let expires_at = if keep_ttl {
+            shard.entries.get(&key).and_then(|e| e.expires_at)
+        } else {
+            expires_at
+        };
+
+        if matches!(expires_at, Some(when) if when <= now) {
+            if shard.remove(&key).is_some() {
+                self.notify(KeyspaceEvents::GENERIC, "del", &key);
+            }
+            return true;
+        }
+
+        let has_ttl = expires_at.is_some();
+        let notify = shard.insert(key.clone(), Entry::new(Value::String(value), expires_at));
+        self.notify(KeyspaceEvents::STRING, "set", &key);
+        if has_ttl && !keep_ttl {
+            self.notify(KeyspaceEvents::GENERIC, "expire", &key);
+        }
+        drop(shard);
+        if notify {
+            self.shared.background_task.notify_one();
+        }
+        true
+    }
+
+    /// Sets the expiration of the key to the given Unix time in milliseconds.
+    /// A time in the past deletes the key. Returns `false` if the key does
+    /// not exist.
+    pub fn expire_at(&self, key: &str, when: i64) -> bool {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        if !shard.entries.contains_key(key) {
+            return false;
+        }
+
+        if when <= now as i64 {
+            shard.remove(key);
+            self.notify(KeyspaceEvents::GENERIC, "del", key);
+            return true;
+        }
+
+        let notify = shard.set_expiry(key, Some(when as u64));
+        self.notify(KeyspaceEvents::GENERIC, "expire", key);
+        drop(shard);
+        if notify {
+            self.shared.background_task.notify_one();
+        }
+        true
+    }
+
+    /// Removes the expiration of the key. Returns `false` if the key does not
+    /// exist or has no TTL.
+    pub fn persist(&self, key: &str) -> bool {
+        let mut shard = self.shard(key).write().unwrap();
+        shard.remove_if_expired(key, now_ms());
+
+        match shard.entries.get(key) {
+            Some(Entry { expires_at: Some(_), .. }) => {
+                shard.set_expiry(key, None);
+                self.notify(KeyspaceEvents::GENERIC, "persist", key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the remaining time to live of the key in milliseconds.
+    ///
+    /// The outer `Option` is `None` if the key does not exist, the inner one
+    /// is `None` if the key exists but has no expiration.
+    pub fn ttl(&self, key: &str) -> Option<Option<u64>> {
+        let shard = self.shard(key).read().unwrap();
+        let now = now_ms();
+
+        match shard.entries.get(key) {
+            Some(entry) if !entry.is_expired(now) => Some(entry.expires_at.map(|when| when - now)),
+            _ => None,
+        }
+    }
+
+    /// Deletes the keys, returning how many existed.
+    pub fn del(&self, keys: &[String]) -> usize {
+        let mut shards = self.write_shards(keys);
+        let now = now_ms();
+        let mut removed = Vec::new();
+        for key in keys {
+            let shard = shards.shard(key);
+            shard.remove_if_expired(key, now);
+            if let Some(entry) = shard.remove(key) {
+                removed.push(entry);
+                self.notify(KeyspaceEvents::GENERIC, "del", key);
+            }
+        }
+        drop(shards);
+
+        // `removed` is dropped here, after the lock has been released
+        removed.len()
+    }
+
+    /// Counts how many of `keys` exist. Keys given several times are counted
+    /// several times, as in Redis.
+    pub fn exists(&self, keys: &[String]) -> usize {
+        let mut shards = self.read_shards(keys);
+        let now = now_ms();
+        keys.iter()
+            .filter(|key| shards.shard(key).entries.get(*key).is_some_and(|entry| !entry.is_expired(now)))
+            .count()
+    }
+
+    /// The type of the value stored at the key, or `"none"`.
+    pub fn key_type(&self, key: &str) -> &'static str {
+        self.read(key, |value| value.map_or("none", Value::type_name))
+    }
+
+    /// Renames `key` to `new_key`, keeping its TTL. With `nx` the rename only
+    /// happens if `new_key` does not exist. Returns whether it was renamed.
+    pub fn rename(&self, key: &str, new_key: &str, nx: bool) -> Result<bool, DbError> {
+        let mut shards = self.write_shards(&[key, new_key]);
+        let now = now_ms();
+        shards.shard(key).remove_if_expired(key, now);
+        shards.shard(new_key).remove_if_expired(new_key, now);
+
+        if !shards.shard(key).entries.contains_key(key) {
+            return Err(DbError::NoSuchKey);
+        }
+        if nx && shards.shard(new_key).entries.contains_key(new_key) {
+            return Ok(false);
+        }
+        if key == new_key {
+            return Ok(true);
+        }
+
+        let entry = shards.shard(key).remove(key).ok_or(DbError::NoSuchKey)?;
+        let shard = shards.shard(new_key);
+        let replaced = shard.remove(new_key);
+        let notify = shard.insert(new_key.to_string(), entry);
+        shard.wake_blocked(new_key);
+        self.notify(KeyspaceEvents::GENERIC, "rename_from", key);
+        self.notify(KeyspaceEvents::GENERIC, "rename_to", new_key);
+        drop(shards);
+
+        if notify {
+            self.shared.background_task.notify_one();
+        }
+
+        drop(replaced);
+        Ok(true)
+    }
+
+    /// Number of keys in the database. Expired keys that the purge task hasn't
+    /// removed yet are included, as in Redis.
+    pub fn dbsize(&self) -> usize {
+        self.shared.shards.iter().map(|shard| shard.read().unwrap().entries.len()).sum()
+    }
+
+    /// Deletes every key.
+    pub fn flush(&self) {
+        let mut shards: Vec<_> = self.shared.shards.iter().map(|shard| shard.write().unwrap()).collect();
+        let mut entries = Vec::with_capacity(shards.len());
+        for shard in shards.iter_mut() {
+            entries.push(std::mem::take(&mut shard.entries));
+            shard.expirations.clear();
+            shard.scan_order.clear();
+            shard.used_memory = 0;
+            for watched in shard.watched.values_mut() {
+                watched.version += 1;
+            }
+        }
+        drop(shards);
+
+        drop(entries);
+    }
+
+    /// Returns every key matching the glob `pattern`.
+    pub fn keys(&self, pattern: &str) -> Vec<String> {
+        let shards = self.read_all();
+        let now = now_ms();
+        shards
+            .iter()
+            .flat_map(|shard| shard.entries.iter())
+            .filter(|(key, entry)| !entry.is_expired(now) && glob::matches(pattern.as_bytes(), key.as_bytes()))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Returns the next batch of about `count` keys, starting at `cursor`,
+    /// that match `pattern`, along with the cursor for the next call (`0`
+    /// once the iteration is complete).
+    ///
+    /// Keys are visited in the order of a fixed hash of their name and the
+    /// cursor is a position in that order, so it stays valid however the map
+    /// changes between calls: every key that exists for the whole iteration
+    /// is returned exactly once. Each shard owns one range of hashes, so a
+    /// call only walks the `count` keys it returns, usually in one shard.
+    pub fn scan(&self, cursor: u64, pattern: Option<&str>, count: usize) -> (u64, Vec<String>) {
+        let shards = &self.shared.shards;
+        let now = now_ms();
+        let mut keys = Vec::new();
+        let mut visited = 0;
+        let mut cursor = cursor;
+
+        for index in shard_of(cursor, shards.len())..shards.len() {
+            cursor = cursor.max(shard_start(index, shards.len()));
+            let shard = shards[index].read().unwrap();
+            let mut batch = shard.scan_order.range((cursor, String::new())..).peekable();
+            while let Some((position, key)) = batch.next() {
+                visited += 1;
+                let expired = shard.entries.get(key).is_none_or(|entry| entry.is_expired(now));
+                if !expired && pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes())) {
+                    keys.push(key.clone());
+                }
+
+                // Never split keys that share a position across two batches
+                if visited >= count && batch.peek().is_none_or(|(next, _)| next != position) {
+                    return (position.checked_add(1).unwrap_or(0), keys);
+                }
+            }
+        }
+        (0, keys)
+    }
+
+    /// Gets the string value associated with the key, failing if the key
+    /// holds another type.
+    pub fn get_string(&self, key: &str) -> Result<Option<Bytes>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Adds `delta` to the integer stored at the key, treating a missing key
+    /// as `0`. Returns the new value.
+    pub fn incr_by(&self, key: &str, delta: i64) -> Result<i64, DbError> {
+        let result = self.update_string(key, |value| {
+            let current = match value {
+                Some(value) => std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .ok_or(DbError::NotInteger)?,
+                None => 0,
+            };
+            let new = current.checked_add(delta).ok_or(DbError::Overflow)?;
+            Ok((Bytes::from(new.to_string()), new))
+        })?;
+        self.notify(KeyspaceEvents::STRING, "incrby", key);
+        Ok(result)
+    }
+
+    /// Adds `delta` to the float stored at the key, treating a missing key
+    /// as `0`. Returns the new value, formatted like Redis does: 17
+    /// significant digits without an exponent, so `10.1` plus `0.2` is
+    /// `10.3` rather than `10.299999999999999`.
+    pub fn incr_by_float(&self, key: &str, delta: f64) -> Result<Bytes, DbError> {
+        let result = self.update_string(key, |value| {
+            let current = match value {
+                Some(value) => std::str::from_utf8(value).ok().filter(|s| is_float(s)).ok_or(DbError::NotFloat)?,
+                None => "0",
+            };
+            if !(current.parse::<f64>().unwrap() + delta).is_finite() {
+                return Err(DbError::NotFinite);
+            }
+            let new = Bytes::from(add_floats(current, delta).ok_or(DbError::NotFloat)?);
+            Ok((new.clone(), new))
+        })?;
+        self.notify(KeyspaceEvents::STRING, "incrbyfloat", key);
+        Ok(result)
+    }
+
+    /// Appends `suffix` to the string at the key. Returns the new length.
+    pub fn append(&self, key: &str, suffix: &[u8]) -> Result<usize, DbError> {
+        let result = self.update_string(key, |value| {
+            let value = value.map_or(&[][..], |v| &v[..]);
+            if value.len() + suffix.len() > MAX_STRING_LEN {
+                return Err(DbError::TooLarge);
+            }
+            let mut buf = Vec::with_capacity(value.len() + suffix.len());
+            buf.extend_from_slice(value);
+            buf.extend_from_slice(suffix);
+            let len = buf.len();
+            Ok((Bytes::from(buf), len))
+        })?;
+        self.notify(KeyspaceEvents::STRING, "append", key);
+        Ok(result)
+    }
+
+    pub fn strlen(&self, key: &str) -> Result<usize, DbError> {
+        Ok(self.get_string(key)?.map_or(0, |value| value.len()))
+    }
+
+    /// Overwrites the string at the key starting at `offset`, zero-padding it
+    /// if needed. Returns the new length.
+    pub fn setrange(&self, key: &str, offset: usize, data: &[u8]) -> Result<usize, DbError> {
+        if data.is_empty() {
+            // Nothing to write: don't create the key
+            return self.strlen(key);
+        }
+        if offset + data.len() > MAX_STRING_LEN {
+            return Err(DbError::TooLarge);
+        }
+
+        let len = self.update_string(key, |value| {
+            let mut buf = value.map_or_else(Vec::new, |v| v.to_vec());
+            if buf.len() < offset + data.len() {
+                buf.resize(offset + data.len(), 0);
+            }
+            buf[offset..offset + data.len()].copy_from_slice(data);
+            let len = buf.len();
+            Ok((Bytes::from(buf), len))
+        })?;
+        self.notify(KeyspaceEvents::STRING, "setrange", key);
+        Ok(len)
+    }
+
+    /// Returns the substring between `start` and `end` (inclusive, negative
+    /// offsets count from the end).
+    pub fn getrange(&self, key: &str, start: i64, end: i64) -> Result<Bytes, DbError> {
+        let value = self.get_string(key)?.unwrap_or_default();
+        Ok(match range(start, end, value.len()) {
+            Some((start, end)) => value.slice(start..=end),
+            None => Bytes::new(),
+        })
+    }
+
+    /// Gets the string values of `keys`. Keys that are missing or hold
+    /// another type are `None`.
+    pub fn mget(&self, keys: &[String]) -> Vec<Option<Bytes>> {
+        let mut shards = self.read_shards(keys);
+        let now = now_ms();
+        keys.iter()
+            .map(|key| match shards.shard(key).entries.get(key) {
+                Some(entry @ Entry { value: Value::String(value), .. }) if !entry.is_expired(now) => {
+                    entry.access.touch(now);
+                    Some(value.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sets all `pairs` at once, clearing their TTLs. With `only_if_none_exist`
+    /// (`MSETNX`) nothing is written if any of the keys exists. Returns
+    /// whether the keys were set.
+    pub fn mset(&self, pairs: Vec<(String, Bytes)>, only_if_none_exist: bool) -> bool {
+        let keys: Vec<&str> = pairs.iter().map(|(key, _)| key.as_str()).collect();
+        let mut shards = self.write_shards(&keys);
+        let now = now_ms();
+        for key in &keys {
+            shards.shard(key).remove_if_expired(key, now);
+        }
+        if only_if_none_exist && keys.iter().any(|key| shards.shard(key).entries.contains_key(*key)) {
+            return false;
+        }
+
+        for (key, value) in pairs {
+            shards.shard(&key).insert(key.clone(), Entry::new(Value::String(value), None));
+            self.notify(KeyspaceEvents::STRING, "set", &key);
+        }
+        true
+    }
+
+    /// Sets the string value of the key and returns the previous one,
+    /// clearing any TTL.
+    pub fn getset(&self, key: &str, value: Bytes) -> Result<Option<Bytes>, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        shard.remove_if_expired(key, now_ms());
+
+        let prev = match shard.entries.get(key) {
+            None => None,
+            Some(Entry { value: Value::String(prev), .. }) => Some(prev.clone()),
+            Some(_) => return Err(DbError::WrongType),
+        };
+        shard.insert(key.to_string(), Entry::new(Value::String(value), None));
+        self.notify(KeyspaceEvents::STRING, "set", key);
+        Ok(prev)
+    }
+
+    /// Deletes the key and returns its string value.
+    pub fn getdel(&self, key: &str) -> Result<Option<Bytes>, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        shard.remove_if_expired(key, now_ms());
+
+        match shard.entries.get(key) {
+            None => Ok(None),
+            Some(Entry { value: Value::String(_), .. }) => match shard.remove(key) {
+                Some(Entry { value: Value::String(value), .. }) => {
+                    self.notify(KeyspaceEvents::GENERIC, "del", key);
+                    Ok(Some(value))
+                }
+                _ => Ok(None),
+            },
+            Some(_) => Err(DbError::WrongType),
+        }
+    }
+
+    /// Pushes `values` onto the head (`left`) or tail of the list, creating it
+    /// if needed. Returns the new length of the list.
+    pub fn push(&self, key: &str, values: Vec<Bytes>, left: bool) -> Result<usize, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        shard.remove_if_expired(key, now_ms());
+
+        let before = shard.size_of(key);
+        let entry = shard.entry_or_insert_with(key, || Value::List(VecDeque::new()));
+        entry.access.touch(now_ms());
+        let Value::List(list) = &mut entry.value else { return Err(DbError::WrongType) };
+        for value in values {
+            if left {
+                list.push_front(value);
+            } else {
+                list.push_back(value);
+            }
+        }
+        let len = list.len();
+
+        shard.resized(key, before);
+        shard.wake_blocked(key);
+        self.notify(KeyspaceEvents::LIST, if left { "lpush" } else { "rpush" }, key);
+        Ok(len)
+    }
+
+    /// Registers `waiter` to be woken when values are pushed onto any of
+    /// `keys`. Waiters are woken in the order they registered; a waiter that
+    /// is already registered for a key keeps its place.
+    pub fn block(&self, keys: &[String], waiter: &Arc<Notify>) {
+        for key in keys {
+            let mut shard = self.shard(key).write().unwrap();
+            let queue = shard.blocked.entry(key.clone()).or_default();
+            if !queue.iter().any(|w| Arc::ptr_eq(w, waiter)) {
+                queue.push_back(waiter.clone());
+            }
+        }
+    }
+
+    /// Removes `waiter` from the queues of `keys`. Values it was woken for but
+    /// didn't take are passed on to the next waiters.
+    pub fn unblock(&self, keys: &[String], waiter: &Arc<Notify>) {
+        for key in keys {
+            let mut shard = self.shard(key).write().unwrap();
+            if let Some(queue) = shard.blocked.get_mut(key) {
+                queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                if queue.is_empty() {
+                    shard.blocked.remove(key);
+                }
+            }
+            shard.wake_blocked(key);
+        }
+    }
+
+    /// Pops up to `count` values from the head (`left`) or tail of the list.
+    /// Returns `None` if the key does not exist.
+    ///
+    /// Elements owed to clients blocked on the key are left for them, so a
+    /// list whose elements are all spoken for pops like a missing key.
+    pub fn pop(&self, key: &str, count: usize, left: bool) -> Result<Option<Vec<Bytes>>, DbError> {
+        self.pop_for(key, count, left, None)
+    }
+
+    /// Pops a value for `waiter`, a client blocked on `key`. Blocked clients
+    /// take their turn in the order they started waiting: the pop only
+    /// succeeds if the list has an element for every client queued ahead of
+    /// `waiter` and one more, so a client that just arrived can't take the
+    /// element a push was meant to hand to an earlier one.
+    pub fn pop_waiting(&self, key: &str, left: bool, waiter: &Arc<Notify>) -> Result<Option<Bytes>, DbError> {
+        Ok(self.pop_for(key, 1, left, Some(waiter))?.and_then(|mut values| values.pop()))
+    }
+
+    fn pop_for(&self, key: &str, count: usize, left: bool, waiter: Option<&Arc<Notify>>) -> Result<Option<Vec<Bytes>>, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        let ahead = shard.waiting_ahead(key, waiter);
+        let before = shard.size_of(key);
+        let result = match shard.entries.get_mut(key) {
+            None => Ok(None),
+            Some(entry) => {
+                entry.access.touch(now);
+                match &mut entry.value {
+                    Value::List(list) if ahead >= list.len() => Ok(None),
+                    Value::List(list) => {
+                        let n = count.min(list.len() - ahead);
+                        let popped = if left {
+                            list.drain(..n).collect()
+                        } else {
+                            list.drain(list.len() - n..).rev().collect()
+                        };
+                        if n > 0 {
+                            self.notify(KeyspaceEvents::LIST, if left { "lpop" } else { "rpop" }, key);
+                        }
+                        Ok(Some(popped))
+                    }
+                    _ => Err(DbError::WrongType),
+                }
+            }
+        };
+        shard.resized(key, before);
+        shard.remove_if_empty(key);
+        result
+    }
+
+    /// Returns the elements of the list between `start` and `stop`
+    /// (inclusive, negative indexes count from the end).
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(vec![]),
+            Some(Value::List(list)) => Ok(match range(start, stop, list.len()) {
+                Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+                None => vec![],
+            }),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Sets fields of the hash. Returns the number of fields that were added.
+    pub fn hset(&self, key: &str, pairs: Vec<(Bytes, Bytes)>) -> Result<usize, DbError> {
+        self.modify_or_insert(key, || Value::Hash(HashMap::new()), |value| {
+            let Value::Hash(hash) = value else { return Err(DbError::WrongType) };
+            let added = pairs.into_iter().filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none()).count();
+            self.notify(KeyspaceEvents::HASH, "hset", key);
+            Ok(added)
+        })
+    }
+
+    pub fn hget(&self, key: &str, field: &[u8]) -> Result<Option<Bytes>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(None),
+            Some(Value::Hash(hash)) => Ok(hash.get(field).cloned()),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    pub fn hgetall(&self, key: &str) -> Result<Vec<(Bytes, Bytes)>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(vec![]),
+            Some(Value::Hash(hash)) => Ok(hash.iter().map(|(f, v)| (f.clone(), v.clone())).collect()),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Removes fields from the hash. Returns the number of fields removed.
+    pub fn hdel(&self, key: &str, fields: &[Bytes]) -> Result<usize, DbError> {
+        self.modify(key, |value| match value {
+            None => Ok(0),
+            Some(Value::Hash(hash)) => {
+                let removed = fields.iter().filter(|f| hash.remove(*f).is_some()).count();
+                if removed > 0 {
+                    self.notify(KeyspaceEvents::HASH, "hdel", key);
+                }
+                Ok(removed)
+            }
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Adds members to the set. Returns the number of members that were added.
+    pub fn sadd(&self, key: &str, members: Vec<Bytes>) -> Result<usize, DbError> {
+        self.modify_or_insert(key, || Value::Set(HashSet::new()), |value| {
+            let Value::Set(set) = value else { return Err(DbError::WrongType) };
+            let added = members.into_iter().filter(|m| set.insert(m.clone())).count();
+            if added > 0 {
+                self.notify(KeyspaceEvents::SET, "sadd", key);
+            }
+            Ok(added)
+        })
+    }
+
+    /// Removes members from the set. Returns the number of members removed.
+    pub fn srem(&self, key: &str, members: &[Bytes]) -> Result<usize, DbError> {
+        self.modify(key, |value| match value {
+            None => Ok(0),
+            Some(Value::Set(set)) => {
+                let removed = members.iter().filter(|m| set.remove(*m)).count();
+                if removed > 0 {
+                    self.notify(KeyspaceEvents::SET, "srem", key);
+                }
+                Ok(removed)
+            }
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    pub fn smembers(&self, key: &str) -> Result<Vec<Bytes>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(vec![]),
+            Some(Value::Set(set)) => Ok(set.iter().cloned().collect()),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    pub fn sismember(&self, key: &str, member: &[u8]) -> Result<bool, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(false),
+            Some(Value::Set(set)) => Ok(set.contains(member)),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Adds members to the sorted set or updates their scores, subject to
+    /// `condition` (`NX` / `XX`). Returns the number of members added, or
+    /// added plus updated when `changed` (`CH`) is set.
+    pub fn zadd(&self, key: &str, members: Vec<(f64, Bytes)>, condition: SetCondition, changed: bool) -> Result<usize, DbError> {
+        let update = |value: &mut Value| {
+            let Value::ZSet(zset) = value else { return Err(DbError::WrongType) };
+            let mut count = 0;
+            let mut written = false;
+            for (score, member) in members {
+                let prev = zset.score(&member);
+                match (condition, prev) {
+                    (SetCondition::IfNotExists, Some(_)) | (SetCondition::IfExists, None) => continue,
+                    _ => {}
+                }
+                zset.insert(member, score);
+                written |= prev != Some(score);
+                match prev {
+                    None => count += 1,
+                    Some(prev) if changed && prev != score => count += 1,
+                    Some(_) => {}
+                }
+            }
+            if written {
+                self.notify(KeyspaceEvents::ZSET, "zadd", key);
+            }
+            Ok(count)
+        };
+
+        if condition == SetCondition::IfExists {
+            self.modify(key, |value| value.map_or(Ok(0), update))
+        } else {
+            self.modify_or_insert(key, || Value::ZSet(ZSet::new()), update)
+        }
+    }
+
+    /// Returns members of the sorted set by rank, between `start` and `stop`
+    /// (inclusive, negative indexes count from the end).
+    pub fn zrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<(Bytes, f64)>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(vec![]),
+            Some(Value::ZSet(zset)) => Ok(match range(start, stop, zset.len()) {
+                Some((start, stop)) => zset
+                    .iter()
+                    .skip(start)
+                    .take(stop - start + 1)
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect(),
+                None => vec![],
+            }),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    pub fn zrank(&self, key: &str, member: &[u8]) -> Result<Option<usize>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(None),
+            Some(Value::ZSet(zset)) => Ok(zset.rank(member)),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    pub fn zscore(&self, key: &str, member: &[u8]) -> Result<Option<f64>, DbError> {
+        self.read(key, |value| match value {
+            None => Ok(None),
+            Some(Value::ZSet(zset)) => Ok(zset.score(member)),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// Appends an entry to the stream, creating it unless `no_mkstream`, and
+    /// trims the stream to `maxlen` entries. Returns the new entry's ID, or
+    /// `None` if the stream doesn't exist and wasn't created.
+    pub fn xadd(&self, key: &str, id: NewId, fields: Fields, maxlen: Option<usize>, no_mkstream: bool) -> Result<Option<StreamId>, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        let before = shard.size_of(key);
+        let id = match shard.entries.get_mut(key) {
+            Some(entry) => {
+                entry.access.touch(now);
+                let Value::Stream(stream) = &mut entry.value else { return Err(DbError::WrongType) };
+                let id = stream.add(id, fields, now)?;
+                stream.trim(maxlen.unwrap_or(usize::MAX));
+                shard.resized(key, before);
+                id
+            }
+            None if no_mkstream => return Ok(None),
+            None => {
+                // The stream is only created once the ID turned out valid
+                let mut stream = Stream::new();
+                let id = stream.add(id, fields, now)?;
+                stream.trim(maxlen.unwrap_or(usize::MAX));
+                shard.insert(key.to_string(), Entry::new(Value::Stream(stream), None));
+                id
+            }
+        };
+
+        shard.wake_blocked(key);
+        self.notify(KeyspaceEvents::STREAM, "xadd", key);
+        Ok(Some(id))
+    }
+
+    pub fn xlen(&self, key: &str) -> Result<usize, DbError> {
+        self.read_stream(key, |stream| Ok(stream.map_or(0, Stream::len)))
+    }
+
+    /// Entries of the stream with IDs between `start` and `end` inclusive,
+    /// newest first if `rev`.
+    pub fn xrange(&self, key: &str, start: StreamId, end: StreamId, count: Option<usize>, rev: bool) -> Result<Vec<(StreamId, Fields)>, DbError> {
+        self.read_stream(key, |stream| Ok(stream.map_or(vec![], |stream| stream.range(start, end, count, rev))))
+    }
+
+    /// Entries of the stream with IDs greater than `id`.
+    pub fn xread(&self, key: &str, id: StreamId, count: Option<usize>) -> Result<Vec<(StreamId, Fields)>, DbError> {
+        self.read_stream(key, |stream| Ok(stream.map_or(vec![], |stream| stream.after(id, count))))
+    }
+
+    /// The largest ID ever added to the stream, `0-0` if it doesn't exist.
+    pub fn xlast_id(&self, key: &str) -> Result<StreamId, DbError> {
+        self.read_stream(key, |stream| Ok(stream.map_or(StreamId::MIN, Stream::last_id)))
+    }
+
+    pub fn xsetid(&self, key: &str, id: StreamId) -> Result<(), DbError> {
+        self.modify_stream(key, |stream| stream.ok_or(DbError::NoSuchKey)?.set_last_id(id))?;
+        self.notify(KeyspaceEvents::STREAM, "xsetid", key);
+        Ok(())
+    }
+
+    /// Creates a consumer group, delivering entries after `last_delivered`
+    /// (only new ones if `None`). With `mkstream` a missing stream is created.
+    pub fn xgroup_create(&self, key: &str, group: &str, last_delivered: Option<StreamId>, mkstream: bool) -> Result<(), DbError> {
+        let create = |stream: &mut Stream| stream.create_group(group, last_delivered);
+        match mkstream {
+            true => self.modify_or_insert(key, || Value::Stream(Stream::new()), |value| match value {
+                Value::Stream(stream) => create(stream),
+                _ => Err(DbError::WrongType),
+            }),
+            false => self.modify_stream(key, |stream| create(stream.ok_or(DbError::NoStream)?)),
+        }?;
+        self.notify(KeyspaceEvents::STREAM, "xgroup-create", key);
+        Ok(())
+    }
+
+    /// Moves the group's cursor to `last_delivered`, or to the end of the
+    /// stream if `None`.
+    pub fn xgroup_setid(&self, key: &str, group: &str, last_delivered: Option<StreamId>) -> Result<(), DbError> {
+        self.modify_group(key, group, |stream, name| {
+            let last_id = stream.last_id();
+            let group = stream.group_mut(name).expect("group exists");
+            group.last_delivered = last_delivered.unwrap_or(last_id);
+        })?;
+        self.notify(KeyspaceEvents::STREAM, "xgroup-setid", key);
+        Ok(())
+    }
+
+    pub fn xgroup_destroy(&self, key: &str, group: &str) -> Result<bool, DbError> {
+        let destroyed = self.modify_stream(key, |stream| Ok(stream.ok_or(DbError::NoStream)?.destroy_group(group)))?;
+        if destroyed {
+            self.notify(KeyspaceEvents::STREAM, "xgroup-destroy", key);
+        }
+        Ok(destroyed)
+    }
+
+    /// Adds a consumer to the group. Returns `false` if it already existed.
+    pub fn xgroup_createconsumer(&self, key: &str, group: &str, consumer: &str) -> Result<bool, DbError> {
+        let created = self.modify_group(key, group, |stream, name| {
+            let consumers = &mut stream.group_mut(name).expect("group exists").consumers;
+            match consumers.contains_key(consumer) {
+                true => false,
+                false => consumers.insert(consumer.to_string(), now_ms()).is_none(),
+            }
+        })?;
+        if created {
+            self.notify(KeyspaceEvents::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(created)
+    }
+
+    /// Removes a consumer from the group, returning how many entries were
+    /// pending for it.
+    pub fn xgroup_delconsumer(&self, key: &str, group: &str, consumer: &str) -> Result<usize, DbError> {
+        let pending = self.modify_group(key, group, |stream, name| stream.group_mut(name).expect("group exists").delete_consumer(consumer))?;
+        self.notify(KeyspaceEvents::STREAM, "xgroup-delconsumer", key);
+        Ok(pending)
+    }
+
+    /// Reads entries on behalf of a consumer of the group; see
+    /// `Stream::read_group`.
+    pub fn xreadgroup(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        start: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<Vec<(StreamId, Option<Fields>)>, DbError> {
+        self.modify_stream(key, |stream| {
+            stream
+                .and_then(|stream| stream.read_group(group, consumer, start, count, no_ack, now_ms()))
+                .ok_or_else(|| DbError::NoGroup(key.to_string(), group.to_string()))
+        })
+    }
+
+    /// Acknowledges pending entries, returning how many were pending.
+    pub fn xack(&self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize, DbError> {
+        self.modify_stream(key, |stream| Ok(stream.map_or(0, |stream| stream.ack(group, ids))))
+    }
+
+    pub fn xpending_summary(&self, key: &str, group: &str) -> Result<PendingSummary, DbError> {
+        self.read_stream(key, |stream| {
+            let summary = stream.and_then(|stream| stream.group(group)).map(|group| group.summary());
+            summary.ok_or_else(|| DbError::NoGroup(key.to_string(), group.to_string()))
+        })
+    }
+
+    /// Pending entries with IDs between `start` and `end`, optionally only
+    /// those of `consumer` or idle for at least `min_idle` milliseconds:
+    /// `(id, consumer, idle time, deliveries)`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn xpending(
+        &self,
+        key: &str,
+        group: &str,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<&str>,
+        min_idle: u64,
+    ) -> Result<Vec<(StreamId, String, u64, u64)>, DbError> {
+        self.read_stream(key, |stream| {
+            let no_group = || DbError::NoGroup(key.to_string(), group.to_string());
+            let group = stream.and_then(|stream| stream.group(group)).ok_or_else(no_group)?;
+            if start > end {
+                return Ok(vec![]);
+            }
+            let now = now_ms();
+            Ok(group
+                .pending
+                .range(start..=end)
+                .map(|(id, pending)| (*id, pending, now.saturating_sub(pending.delivered_at)))
+                .filter(|(_, pending, idle)| consumer.is_none_or(|c| c == pending.consumer) && *idle >= min_idle)
+                .take(count)
+                .map(|(id, pending, idle)| (id, pending.consumer.clone(), idle, pending.deliveries))
+                .collect())
+        })
+    }
+
+    /// Transfers pending entries to `consumer`; see `Stream::claim`.
+    pub fn xclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId],
+        options: &ClaimOptions,
+    ) -> Result<Vec<(StreamId, Fields)>, DbError> {
+        self.modify_stream(key, |stream| {
+            stream
+                .and_then(|stream| stream.claim(group, consumer, min_idle, ids, options, now_ms()))
+                .ok_or_else(|| DbError::NoGroup(key.to_string(), group.to_string()))
+        })
     }
 
-    /// Returns a `Receiver` for the requested channel.
-    /// The returned `Receiver` is used to receive values broadcast by `PUBLISH` commands.
-    pub fn subscribe(&self, channel_name: String) -> broadcast::Receiver<Bytes> {
-        use std::collections::hash_map::Entry;
+    /// `read` for commands that only work on streams.
+    fn read_stream<T>(&self, key: &str, f: impl FnOnce(Option<&Stream>) -> Result<T, DbError>) -> Result<T, DbError> {
+        self.read(key, |value| match value {
+            None => f(None),
+            Some(Value::Stream(stream)) => f(Some(stream)),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// `modify` for commands that only work on streams.
+    fn modify_stream<T>(&self, key: &str, f: impl FnOnce(Option<&mut Stream>) -> Result<T, DbError>) -> Result<T, DbError> {
+        self.modify(key, |value| match value {
+            None => f(None),
+            Some(Value::Stream(stream)) => f(Some(stream)),
+            Some(_) => Err(DbError::WrongType),
+        })
+    }
+
+    /// `modify_stream` for `XGROUP` subcommands, which need both the stream
+    /// and the group to exist.
+    fn modify_group<T>(&self, key: &str, group: &str, f: impl FnOnce(&mut Stream, &str) -> T) -> Result<T, DbError> {
+        self.modify_stream(key, |stream| {
+            let stream = stream.ok_or(DbError::NoStream)?;
+            if stream.group(group).is_none() {
+                return Err(DbError::NoGroup(key.to_string(), group.to_string()));
+            }
+            Ok(f(stream, group))
+        })
+    }
+
+    /// Runs `f` against the live value at `key` under the read lock.
+    fn read<T>(&self, key: &str, f: impl FnOnce(Option<&Value>) -> T) -> T {
+        let shard = self.shard(key).read().unwrap();
+        let now = now_ms();
+        let entry = shard.entries.get(key).filter(|e| !e.is_expired(now));
+        if let Some(entry) = entry {
+            entry.access.touch(now);
+        }
+        f(entry.map(|e| &e.value))
+    }
+
+    /// Runs `f` against the live value at `key` under the write lock. A key
+    /// left holding an empty collection is removed.
+    fn modify<T>(&self, key: &str, f: impl FnOnce(Option<&mut Value>) -> T) -> T {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        let before = shard.size_of(key);
+        let result = f(shard.entries.get_mut(key).map(|e| {
+            e.access.touch(now);
+            &mut e.value
+        }));
+        shard.resized(key, before);
+        shard.remove_if_empty(key);
+        result
+    }
+
+    /// Like `modify`, but first creates the key holding `default()` if it
+    /// does not exist.
+    fn modify_or_insert<T>(&self, key: &str, default: impl FnOnce() -> Value, f: impl FnOnce(&mut Value) -> T) -> T {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        let before = shard.size_of(key);
+        let entry = shard.entry_or_insert_with(key, default);
+        entry.access.touch(now);
+        let result = f(&mut entry.value);
+        shard.resized(key, before);
+        shard.remove_if_empty(key);
+        result
+    }
+
+    /// Read-modify-write of the string at `key` under the write lock. `f`
+    /// receives the current value (`None` if the key is missing) and returns
+    /// the new value along with the result. The key's TTL is kept.
+    fn update_string<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(Option<&Bytes>) -> Result<(Bytes, T), DbError>,
+    ) -> Result<T, DbError> {
+        let mut shard = self.shard(key).write().unwrap();
+        let now = now_ms();
+        shard.remove_if_expired(key, now);
+
+        let before = shard.size_of(key);
+        match shard.entries.get_mut(key) {
+            Some(Entry { value: Value::String(value), access, .. }) => {
+                let (new, result) = f(Some(value))?;
+                *value = new;
+                access.touch(now);
+                shard.resized(key, before);
+                Ok(result)
+            }
+            Some(_) => Err(DbError::WrongType),
+            None => {
+                let (new, result) = f(None)?;
+                shard.insert(key.to_string(), Entry::new(Value::String(new), None));
+                Ok(result)
+            }
+        }
+    }
 
-        let mut state = self.shared.state.write().unwrap();
+    /// Starts watching `key`, returning its current version.
+    pub fn watch(&self, key: &str) -> u64 {
+        let mut shard = self.shard(key).write().unwrap();
+        let watched = shard
+            .watched
+            .entry(key.to_string())
+            .or_insert(Watched { version: 0, watchers: 0 });
+        watched.watchers += 1;
+        watched.version
+    }
 
-        match state.pub_sub.entry(channel_name) {
-            Entry::Occupied(e) => e.get().subscribe(),
-            Entry::Vacant(e) => {
-                // Create a new broadcast channel
-                let (tx, rx) = broadcast::channel(1024);
-                e.insert(tx);
-                rx
+    /// Stops watching `keys`; each key must have been passed to `watch` once.
+    pub fn unwatch(&self, keys: &[String]) {
+        for key in keys {
+            let mut shard = self.shard(key).write().unwrap();
+            if let Some(watched) = shard.watched.get_mut(key) {
+                watched.watchers -= 1;
+                if watched.watchers == 0 {
+                    shard.watched.remove(key);
+                }
             }
         }
     }
 
+    /// The current version of a watched key. It changes whenever the key is
+    /// modified, deleted or expires.
+    pub fn version(&self, key: &str) -> Option<u64> {
+        self.shard(key).read().unwrap().watched.get(key).map(|watched| watched.version)
+    }
+
+    /// Marks `keys` as modified for clients watching them. Called by the
+    /// server after every successful write command.
+    pub fn touch(&self, keys: &[&str]) {
+        for key in keys {
+            self.shard(key).write().unwrap().touch(key);
+        }
+    }
+
+    /// Lets a single command run. Blocks while an `EXEC` is in progress.
+    pub(crate) fn shared_access(&self) -> RwLockReadGuard<'_, ()> {
+        self.shared.exec_gate.read().unwrap()
+    }
+
+    /// Keeps every other command out until the guard is dropped, so the
+    /// commands of an `EXEC` block run as one atomic unit.
+    pub(crate) fn exclusive_access(&self) -> RwLockWriteGuard<'_, ()> {
+        self.shared.exec_gate.write().unwrap()
+    }
+
+    /// Lets a single write to `keys` run. Writes to the same shards take
+    /// turns, so they queue their AOF entries with `log` in the order they
+    /// were applied in, while writes to other shards run alongside. A write
+    /// without keys, such as `FLUSHDB`, keeps every other command out.
+    pub(crate) fn write_access<K: AsRef<str>>(&self, keys: &[K]) -> WriteAccess<'_> {
+        if keys.is_empty() {
+            return WriteAccess::All { _gate: self.exclusive_access() };
+        }
+        let gate = self.shared_access();
+        let count = self.shared.shards.len();
+        let indexes: BTreeSet<usize> = keys.iter().map(|key| shard_index(key.as_ref(), count)).collect();
+        let order = indexes.into_iter().map(|index| self.shared.write_order[index].lock().unwrap()).collect();
+        WriteAccess::Keys { _gate: gate, _order: order }
+    }
+
+    /// Queues the AOF entries of the write `_access` was taken for.
+    pub(crate) fn log(&self, _access: &WriteAccess<'_>, frames: impl IntoIterator<Item = Frame>) {
+        self.shared.unlogged.lock().unwrap().extend(frames);
+    }
+
+    /// Takes the queued AOF entries. Under `exclusive_access` these are all
+    /// the writes applied so far that haven't been appended yet.
+    pub(crate) fn take_unlogged(&self) -> Vec<Frame> {
+        std::mem::take(&mut *self.shared.unlogged.lock().unwrap())
+    }
+
+    /// Returns a point-in-time copy of every live key.
+    pub fn snapshot(&self) -> Vec<(String, Entry)> {
+        let shards = self.read_all();
+        let now = now_ms();
+        shards
+            .iter()
+            .flat_map(|shard| shard.entries.iter())
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Inserts an entry loaded from a snapshot. Entries that expired while the
+    /// server was down are skipped.
+    pub fn restore(&self, key: String, entry: Entry) {
+        if entry.is_expired(now_ms()) {
+            return;
+        }
+        let notify = self.shard(&key).write().unwrap().insert(key, entry);
+        if notify {
+            self.shared.background_task.notify_one();
+        }
+    }
+
+    /// The pub/sub channels. `SUBSCRIBE` and friends go through the
+    /// connection's `Subscriptions`, which release channels nobody listens
+    /// to anymore.
+    pub fn pub_sub(&self) -> &PubSub {
+        self.shared.notifier.pub_sub()
+    }
+
     /// Publishes a message to the channel. Returns the number of subscribers listening on the channel.
     pub fn publish(&self, channel_name: &str, message: Bytes) -> usize {
-        let state = self.shared.state.read().unwrap();
+        self.pub_sub().publish(channel_name, message)
+    }
+
+    /// The keyspace events that are published, as set by
+    /// `notify-keyspace-events`.
+    pub fn keyspace_events(&self) -> KeyspaceEvents {
+        self.shared.notifier.events()
+    }
+
+    pub fn set_keyspace_events(&self, events: KeyspaceEvents) {
+        self.shared.notifier.set_events(events);
+    }
+
+    /// Publishes a keyspace notification, if `class` is enabled.
+    fn notify(&self, class: KeyspaceEvents, event: &str, key: &str) {
+        self.shared.notifier.notify(class, event, key);
+    }
+
+    /// Approximate memory used by the keys and their values, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.shared.shards.iter().map(|shard| shard.read().unwrap().used_memory).sum()
+    }
+
+    /// Number of keys with a TTL.
+    pub fn expires(&self) -> usize {
+        self.shared.shards.iter().map(|shard| shard.read().unwrap().expirations.len()).sum()
+    }
+
+    pub fn maxmemory(&self) -> u64 {
+        self.shared.maxmemory.load(Ordering::Relaxed)
+    }
+
+    /// Sets the memory limit in bytes; `0` removes it. Keys are only evicted
+    /// by `evict`, so the limit may be exceeded until the next write.
+    pub fn set_maxmemory(&self, bytes: u64) {
+        self.shared.maxmemory.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        *self.shared.eviction_policy.read().unwrap()
+    }
+
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        *self.shared.eviction_policy.write().unwrap() = policy;
+    }
+
+    /// Number of keys evicted so far.
+    pub fn evicted_keys(&self) -> u64 {
+        self.shared.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if keys must be evicted to get back under `maxmemory`.
+    pub fn over_maxmemory(&self) -> bool {
+        let maxmemory = self.maxmemory();
+        maxmemory != 0 && self.used_memory() as u64 > maxmemory
+    }
+
+    /// Evicts keys as the eviction policy says until the memory in use is
+    /// back under `maxmemory`. The server calls it before commands that may
+    /// need more memory. Evicted keys are added to `evicted`, so they can be
+    /// logged as deleted.
+    ///
+    /// Fails with `OutOfMemory` if the limit is exceeded and no (more) keys
+    /// can be evicted.
+    pub fn evict(&self, evicted: &mut Vec<String>) -> Result<(), DbError> {
+        let maxmemory = self.maxmemory();
+        if maxmemory == 0 {
+            return Ok(());
+        }
+
+        let policy = self.eviction_policy();
+        while self.used_memory() as u64 > maxmemory {
+            if policy == EvictionPolicy::NoEviction {
+                return Err(DbError::OutOfMemory);
+            }
+            let Some((index, key)) = self.eviction_candidate(policy) else {
+                return Err(DbError::OutOfMemory);
+            };
+
+            let removed = self.shared.shards[index].write().unwrap().remove(&key);
+            // Someone else may have deleted it in the meantime
+            if removed.is_some() {
+                self.shared.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                self.notify(KeyspaceEvents::EVICTED, "evicted", &key);
+                evicted.push(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Samples keys from random shards and returns the best one to evict
+    /// under `policy`, along with its shard.
+    fn eviction_candidate(&self, policy: EvictionPolicy) -> Option<(usize, String)> {
+        let now = now_ms();
+        let count = self.shared.shards.len();
+        // Higher is a better victim
+        let score = |entry: &Entry| match policy {
+            EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => entry.access.idle(now),
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => u8::MAX as u64 - entry.access.frequency(now) as u64,
+            EvictionPolicy::VolatileTtl => u64::MAX - entry.expires_at.unwrap_or(u64::MAX),
+            _ => 0,
+        };
+
+        let mut best: Option<(u64, usize, String)> = None;
+        for _ in 0..EVICTION_SAMPLES {
+            let index = evict::random() as usize % count;
+            let shard = self.shared.shards[index].read().unwrap();
+            if let Some((key, entry)) = shard.sample(policy) {
+                let score = score(entry);
+                if best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+                    best = Some((score, index, key.clone()));
+                }
+            }
+        }
+        if let Some((_, index, key)) = best {
+            return Some((index, key));
+        }
+
+        // The sampled shards were all empty: take any key there is
+        (0..count).find_map(|index| {
+            let shard = self.shared.shards[index].read().unwrap();
+            shard.sample(policy).map(|(key, _)| (index, key.clone()))
+        })
+    }
+
+    /// The shard holding `key`.
+    fn shard(&self, key: &str) -> &RwLock<Shard> {
+        &self.shared.shards[shard_index(key, self.shared.shards.len())]
+    }
+
+    /// Read-locks the shards holding `keys`, for commands that read several
+    /// keys at once.
+    fn read_shards<K: AsRef<str>>(&self, keys: &[K]) -> Shards<RwLockReadGuard<'_, Shard>> {
+        self.lock_shards(keys, |shard| shard.read().unwrap())
+    }
+
+    /// Write-locks the shards holding `keys`, so a multi-key command changes
+    /// all of them atomically.
+    fn write_shards<K: AsRef<str>>(&self, keys: &[K]) -> Shards<RwLockWriteGuard<'_, Shard>> {
+        self.lock_shards(keys, |shard| shard.write().unwrap())
+    }
+
+    /// Locks are always taken in shard order, so two commands locking
+    /// overlapping sets of shards can't deadlock.
+    fn lock_shards<'a, K: AsRef<str>, G>(&'a self, keys: &[K], lock: impl Fn(&'a RwLock<Shard>) -> G) -> Shards<G> {
+        let count = self.shared.shards.len();
+        let indexes: BTreeSet<usize> = keys.iter().map(|key| shard_index(key.as_ref(), count)).collect();
+        let guards = indexes.into_iter().map(|index| (index, lock(&self.shared.shards[index]))).collect();
+        Shards { count, guards }
+    }
+
+    /// Read-locks every shard, for commands that walk the whole keyspace.
+    fn read_all(&self) -> Vec<RwLockReadGuard<'_, Shard>> {
+        self.shared.shards.iter().map(|shard| shard.read().unwrap()).collect()
+    }
+}
+
+/// Access to the database for a single write; see `Db::write_access`.
+pub(crate) enum WriteAccess<'a> {
+    /// The write order locks of the shards holding the keys.
+    Keys { _gate: RwLockReadGuard<'a, ()>, _order: Vec<MutexGuard<'a, ()>> },
+    All { _gate: RwLockWriteGuard<'a, ()> },
+}
+
+/// Guards over the shards holding a set of keys.
+struct Shards<G> {
+    count: usize,
+    guards: BTreeMap<usize, G>,
+}
+
+impl<G> Shards<G> {
+    /// The guard of the shard holding `key`, which must be one of the keys
+    /// the shards were locked for.
+    fn shard(&mut self, key: &str) -> &mut G {
+        self.guards.get_mut(&shard_index(key, self.count)).expect("key's shard is not locked")
+    }
+}
+
+impl Shard {
+    /// Removes every key whose expiration time has passed and returns the
+    /// expiration time of the next key to expire, if any.
+    fn purge_expired(&mut self, now: u64) -> Option<u64> {
+        while let Some((when, key)) = self.expirations.first().cloned() {
+            if when > now {
+                return Some(when);
+            }
+            self.expire(&key);
+        }
+        None
+    }
+
+    /// Inserts the entry, keeping the expiration index in sync. Returns `true`
+    /// if the new expiration is now the earliest one, in which case the purge
+    /// task must be woken so it doesn't oversleep.
+    fn insert(&mut self, key: String, entry: Entry) -> bool {
+        self.used_memory += entry_size(&key, &entry);
+        let earliest = self.expirations.first().map(|(when, _)| *when);
+        let notify = match entry.expires_at {
+            Some(when) => earliest.is_none_or(|earliest| when < earliest),
+            None => false,
+        };
+
+        if let Some(when) = entry.expires_at {
+            self.expirations.insert((when, key.clone()));
+        }
+        if !self.entries.contains_key(&key) {
+            self.scan_order.insert((key_hash(&key), key.clone()));
+        }
+        if let Some(prev) = self.entries.insert(key.clone(), entry) {
+            self.used_memory -= entry_size(&key, &prev);
+            if let Some(when) = prev.expires_at {
+                // Don't drop the index entry just added for the same deadline
+                if self.entries[&key].expires_at != Some(when) {
+                    self.expirations.remove(&(when, key));
+                }
+            }
+        }
+        notify
+    }
+
+    /// The entry at `key`, created without a TTL and holding `default()` if
+    /// it is missing.
+    fn entry_or_insert_with(&mut self, key: &str, default: impl FnOnce() -> Value) -> &mut Entry {
+        if !self.entries.contains_key(key) {
+            self.scan_order.insert((key_hash(key), key.to_string()));
+        }
+        self.entries.entry(key.to_string()).or_insert_with(|| Entry::new(default(), None))
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.swap_remove(key)?;
+        self.used_memory -= entry_size(key, &entry);
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+        self.scan_order.remove(&(key_hash(key), key.to_string()));
+        self.touch(key);
+        Some(entry)
+    }
+
+    /// Approximate memory used by `key` and its entry, `0` if it is missing.
+    fn size_of(&self, key: &str) -> usize {
+        self.entries.get(key).map_or(0, |entry| entry_size(key, entry))
+    }
+
+    /// Updates `used_memory` after the entry at `key`, which used `before`
+    /// bytes, was changed in place.
+    fn resized(&mut self, key: &str, before: usize) {
+        self.used_memory = self.used_memory - before + self.size_of(key);
+    }
+
+    /// Picks a random key that `policy` allows to evict, if there is one.
+    /// `volatile-ttl` picks the shard's key closest to expiring instead.
+    fn sample(&self, policy: EvictionPolicy) -> Option<(&String, &Entry)> {
+        let key = match policy {
+            EvictionPolicy::VolatileTtl => &self.expirations.first()?.1,
+            // Keys with a TTL are sampled at a random point of the timeline
+            policy if policy.is_volatile() => {
+                let first = self.expirations.first()?.0;
+                let last = self.expirations.last()?.0;
+                let when = first + evict::random() % (last - first + 1);
+                &self.expirations.range((when, String::new())..).next()?.1
+            }
+            _ if self.entries.is_empty() => return None,
+            _ => return self.entries.get_index(evict::random() as usize % self.entries.len()),
+        };
+        self.entries.get_key_value(key)
+    }
+
+    /// Marks a watched key as modified.
+    fn touch(&mut self, key: &str) {
+        if let Some(watched) = self.watched.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+
+    /// Changes the expiration of an existing key. Returns `true` if the purge
+    /// task must be woken, as for `insert`.
+    fn set_expiry(&mut self, key: &str, expires_at: Option<u64>) -> bool {
+        let earliest = self.expirations.first().map(|(when, _)| *when);
+        let Some(entry) = self.entries.get_mut(key) else { return false };
+
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.to_string()));
+        }
+        entry.expires_at = expires_at;
 
-        if let Some(tx) = state.pub_sub.get(channel_name) {
-            // Send method returns the number of receivers
-            // If send fails (no receivers), it returns an error, but we just return 0.
-            tx.send(message).unwrap_or(0)
+        match expires_at {
+            Some(when) => {
+                self.expirations.insert((when, key.to_string()));
+                earliest.is_none_or(|earliest| when < earliest)
+            }
+            None => false,
+        }
+    }
+
+    /// Number of clients blocked on `key` that are ahead of `waiter` in line,
+    /// or all of them for a client that isn't blocked on it.
+    fn waiting_ahead(&self, key: &str, waiter: Option<&Arc<Notify>>) -> usize {
+        let Some(queue) = self.blocked.get(key) else { return 0 };
+        waiter
+            .and_then(|waiter| queue.iter().position(|w| Arc::ptr_eq(w, waiter)))
+            .unwrap_or(queue.len())
+    }
+
+    /// Wakes as many clients blocked on `key` as the list has elements. They
+    /// stay queued until they have popped a value, which keeps the elements
+    /// reserved for them until they do. Reading a stream doesn't use its
+    /// entries up, so every client blocked on one is woken.
+    fn wake_blocked(&mut self, key: &str) {
+        let len = match self.entries.get(key) {
+            Some(Entry { value: Value::List(list), .. }) => list.len(),
+            Some(Entry { value: Value::Stream(_), .. }) => usize::MAX,
+            _ => return,
+        };
+        if let Some(queue) = self.blocked.get(key) {
+            for waiter in queue.iter().take(len) {
+                waiter.notify_one();
+            }
+        }
+    }
+
+    fn remove_if_empty(&mut self, key: &str) {
+        if self.entries.get(key).is_some_and(|entry| entry.value.is_empty_collection()) {
+            self.remove(key);
+            self.notifier.notify(KeyspaceEvents::GENERIC, "del", key);
+        }
+    }
+
+    fn remove_if_expired(&mut self, key: &str, now: u64) {
+        if self.entries.get(key).is_some_and(|entry| entry.is_expired(now)) {
+            self.expire(key);
+        }
+    }
+
+    /// Removes a key whose TTL has run out.
+    fn expire(&mut self, key: &str) {
+        self.remove(key);
+        self.notifier.notify(KeyspaceEvents::EXPIRED, "expired", key);
+    }
+}
+
+/// A hash of `key`. `DefaultHasher::new()` always uses the same keys, so it
+/// is stable for the lifetime of the process.
+fn key_hash(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The shard `key` belongs to, out of `count`.
+fn shard_index(key: &str, count: usize) -> usize {
+    shard_of(key_hash(key), count)
+}
+
+/// The shard keys hashing to `hash` belong to, out of `count`. Each shard
+/// owns a contiguous range of hashes, so `SCAN` order walks one shard after
+/// the other.
+fn shard_of(hash: u64, count: usize) -> usize {
+    ((hash as u128 * count as u128) >> 64) as usize
+}
+
+/// The smallest hash that belongs to shard `index`, out of `count`.
+fn shard_start(index: usize, count: usize) -> u64 {
+    ((index as u128) << 64).div_ceil(count as u128) as u64
+}
+
+/// Clamps the inclusive index range `start..=stop` of a collection of length
+/// `len`, with negative indexes counting from the end. Returns `None` if the
+/// range is empty.
+fn range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Whether `INCRBYFLOAT` takes `s` as a float: finite, and not so small that
+/// it underflows to zero, which Redis rejects through `strtold`'s `ERANGE`.
+/// This keeps the exponents `add_floats` lines up within a few hundred digits
+/// of each other, whatever exponent the string spells out.
+fn is_float(s: &str) -> bool {
+    match s.parse::<f64>() {
+        Ok(f) if f.is_finite() => f != 0.0 || Decimal::parse(s).is_some_and(|d| d.digits.is_empty()),
+        _ => false,
+    }
+}
+
+/// `current + delta`, computed exactly in decimal and rounded to 17
+/// significant digits. Redis gets the same digits by adding `long double`s;
+/// adding `f64`s would show their rounding error in the last digits.
+/// `current` must pass `is_float`, and `delta` be finite; `None` if an
+/// exponent is out of range anyway.
+fn add_floats(current: &str, delta: f64) -> Option<String> {
+    let (a, b) = (Decimal::parse(current)?, Decimal::parse(&format!("{:e}", delta))?);
+    Some(a.add(b)?.round(17).to_string())
+}
+
+/// An exact decimal number: `digits` (most significant first) times
+/// `10^exp`.
+#[derive(Debug, Clone, PartialEq)]
+struct Decimal {
+    negative: bool,
+    digits: Vec<u8>,
+    exp: i64,
+}
+
+impl Decimal {
+    /// Parses `[+-]digits[.digits][e[+-]digits]`.
+    fn parse(s: &str) -> Option<Decimal> {
+        let (negative, s) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mantissa, exp) = match s.split_once(['e', 'E']) {
+            Some((mantissa, exp)) => (mantissa, exp.parse::<i64>().ok()?),
+            None => (s, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if int.is_empty() && frac.is_empty() {
+            return None;
+        }
+        let mut digits = Vec::with_capacity(int.len() + frac.len());
+        for c in int.bytes().chain(frac.bytes()) {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+            digits.push(c - b'0');
+        }
+        let exp = exp.checked_sub(i64::try_from(frac.len()).ok()?)?;
+        Some(Decimal { negative, digits, exp }.normalized())
+    }
+
+    /// Strips leading and trailing zero digits; zero has no digits.
+    fn normalized(mut self) -> Decimal {
+        let leading = self.digits.iter().take_while(|d| **d == 0).count();
+        self.digits.drain(..leading);
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+            self.exp += 1;
+        }
+        if self.digits.is_empty() {
+            self.negative = false;
+            self.exp = 0;
+        }
+        self
+    }
+
+    /// The exact sum; `None` if the exponents are too far apart to line up.
+    fn add(self, other: Decimal) -> Option<Decimal> {
+        // Line both up on the smallest exponent, least significant digit
+        // first
+        let exp = self.exp.min(other.exp);
+        let align = |d: &Decimal| {
+            let mut digits: Vec<u8> = vec![0; usize::try_from(d.exp.checked_sub(exp)?).ok()?];
+            digits.extend(d.digits.iter().rev());
+            Some(digits)
+        };
+        let (a, b) = (align(&self)?, align(&other)?);
+        let len = a.len().max(b.len()) + 1;
+        let digit = |v: &Vec<u8>, i: usize| *v.get(i).unwrap_or(&0) as i8;
+
+        let (negative, digits) = if self.negative == other.negative {
+            let mut sum = Vec::with_capacity(len);
+            let mut carry = 0;
+            for i in 0..len {
+                let d = digit(&a, i) + digit(&b, i) + carry;
+                sum.push((d % 10) as u8);
+                carry = d / 10;
+            }
+            (self.negative, sum)
+        } else {
+            // Subtract the smaller magnitude from the larger
+            let a_larger = (0..len).rev().map(|i| digit(&a, i).cmp(&digit(&b, i))).find(|o| o.is_ne())
+                != Some(std::cmp::Ordering::Less);
+            let (larger, smaller, negative) =
+                if a_larger { (&a, &b, self.negative) } else { (&b, &a, other.negative) };
+            let mut difference = Vec::with_capacity(len);
+            let mut borrow = 0;
+            for i in 0..len {
+                let mut d = digit(larger, i) - digit(smaller, i) - borrow;
+                borrow = (d < 0) as i8;
+                if d < 0 {
+                    d += 10;
+                }
+                difference.push(d as u8);
+            }
+            (negative, difference)
+        };
+        Some(Decimal { negative, digits: digits.into_iter().rev().collect(), exp }.normalized())
+    }
+
+    /// Rounds half away from zero to at most `precision` significant digits.
+    fn round(mut self, precision: usize) -> Decimal {
+        if self.digits.len() <= precision {
+            return self;
+        }
+        let dropped = self.digits.len() - precision;
+        let round_up = self.digits[precision] >= 5;
+        self.digits.truncate(precision);
+        self.exp += dropped as i64;
+        if round_up {
+            let mut i = precision;
+            loop {
+                if i == 0 {
+                    self.digits.insert(0, 1);
+                    break;
+                }
+                i -= 1;
+                if self.digits[i] == 9 {
+                    self.digits[i] = 0;
+                } else {
+                    self.digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        self.normalized()
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    /// Plain notation, without an exponent or trailing zeros.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.digits.is_empty() {
+            return f.write_str("0");
+        }
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let digits: String = self.digits.iter().map(|d| (b'0' + d) as char).collect();
+        if self.exp >= 0 {
+            return write!(f, "{}{}", digits, "0".repeat(self.exp as usize));
+        }
+        let frac_len = (-self.exp) as usize;
+        if frac_len >= digits.len() {
+            write!(f, "0.{}{}", "0".repeat(frac_len - digits.len()), digits)
         } else {
-            0
+            let (int, frac) = digits.split_at(digits.len() - frac_len);
+            write!(f, "{}.{}", int, frac)
         }
     }
 }
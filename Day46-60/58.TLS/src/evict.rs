@@ -0,0 +1,157 @@
+//! `maxmemory` eviction: the policies, and the access statistics kept for
+//! every key so the least recently or least frequently used ones can be
+//! picked.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Which keys are evicted once `maxmemory` is reached, named as in Redis'
+/// `maxmemory-policy`. `volatile-*` policies only evict keys with a TTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Refuse commands that need more memory with an `OOM` error.
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    /// Evict the keys closest to expiring first.
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only keys with a TTL may be evicted.
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru | EvictionPolicy::VolatileLfu | EvictionPolicy::VolatileRandom | EvictionPolicy::VolatileTtl
+        )
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EvictionPolicy, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "noeviction" => EvictionPolicy::NoEviction,
+            "allkeys-lru" => EvictionPolicy::AllKeysLru,
+            "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+            "allkeys-random" => EvictionPolicy::AllKeysRandom,
+            "volatile-lru" => EvictionPolicy::VolatileLru,
+            "volatile-lfu" => EvictionPolicy::VolatileLfu,
+            "volatile-random" => EvictionPolicy::VolatileRandom,
+            "volatile-ttl" => EvictionPolicy::VolatileTtl,
+            _ => return Err(format!("invalid maxmemory-policy '{}'", s)),
+        })
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How many keys are looked at to pick each victim. Like Redis, eviction is
+/// approximate: the best of a few random keys is evicted, not the best of all.
+pub const EVICTION_SAMPLES: usize = 5;
+
+/// The counter new keys start with, so they aren't evicted right away.
+const LFU_INIT_VAL: u8 = 5;
+/// The higher, the more hits it takes to grow the counter (`lfu-log-factor`).
+const LFU_LOG_FACTOR: u64 = 10;
+/// The counter drops by one for every period of this many milliseconds the
+/// key isn't accessed (`lfu-decay-time`, one minute).
+const LFU_DECAY_MS: u64 = 60_000;
+
+/// When a key was last used and how often, updated on every access. Reads
+/// only hold their shard's read lock, hence the atomics; concurrent updates
+/// may lose a hit, which doesn't matter for an estimate.
+#[derive(Debug)]
+pub(crate) struct Access {
+    /// Unix time in milliseconds of the last access.
+    last: AtomicU64,
+    /// Logarithmic access frequency, as in Redis' LFU.
+    counter: AtomicU8,
+}
+
+impl Access {
+    pub(crate) fn new(now: u64) -> Access {
+        Access { last: AtomicU64::new(now), counter: AtomicU8::new(LFU_INIT_VAL) }
+    }
+
+    /// Records a read or write of the key.
+    pub(crate) fn touch(&self, now: u64) {
+        let counter = lfu_increment(self.frequency(now));
+        self.counter.store(counter, Ordering::Relaxed);
+        self.last.store(now, Ordering::Relaxed);
+    }
+
+    /// Milliseconds since the last access.
+    pub(crate) fn idle(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last.load(Ordering::Relaxed))
+    }
+
+    /// The access counter, decayed for the time the key has been idle.
+    pub(crate) fn frequency(&self, now: u64) -> u8 {
+        let periods = self.idle(now) / LFU_DECAY_MS;
+        self.counter.load(Ordering::Relaxed).saturating_sub(periods.min(255) as u8)
+    }
+}
+
+impl Clone for Access {
+    fn clone(&self) -> Access {
+        Access {
+            last: AtomicU64::new(self.last.load(Ordering::Relaxed)),
+            counter: AtomicU8::new(self.counter.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Access statistics aren't part of a key's value, so two entries compare
+/// equal whatever their statistics.
+impl PartialEq for Access {
+    fn eq(&self, _: &Access) -> bool {
+        true
+    }
+}
+
+/// Grows the counter with a probability that shrinks as it gets larger, so
+/// eight bits are enough to tell apart keys hit millions of times.
+fn lfu_increment(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as u64;
+    if random().is_multiple_of(base * LFU_LOG_FACTOR + 1) {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// A random number, good enough to sample keys with.
+pub(crate) fn random() -> u64 {
+    // Every `RandomState` is seeded differently
+    RandomState::new().build_hasher().finish()
+}
@@ -0,0 +1,102 @@
+//! Redis-style glob matching, used by `KEYS` and `SCAN ... MATCH`.
+//!
+//! Supports `*`, `?`, character classes (`[abc]`, `[^abc]`, `[a-z]`) and
+//! backslash escapes.
+
+/// Returns `true` if `text` matches `pattern`.
+pub fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    // Position to resume from after a `*`: (pattern index, text index)
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, len)) = match_class(&pattern[p..], text[t]) {
+                        if matched {
+                            p += len;
+                            t += 1;
+                            continue;
+                        }
+                    } else if text[t] == b'[' {
+                        // Unterminated class: match the bracket literally
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // Mismatch: let the last `*` swallow one more character
+        match backtrack {
+            Some((star, start)) => {
+                backtrack = Some((star, start + 1));
+                p = star + 1;
+                t = start + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches `c` against the class at the start of `pattern` (which begins with
+/// `[`). Returns whether it matched and the length of the class, or `None` if
+/// the class is not terminated.
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    loop {
+        match *pattern.get(i)? {
+            b']' => break,
+            b'\\' => {
+                matched |= *pattern.get(i + 1)? == c;
+                i += 2;
+            }
+            start if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&end| end != b']') => {
+                let end = pattern[i + 2];
+                let (lo, hi) = if start <= end { (start, end) } else { (end, start) };
+                matched |= (lo..=hi).contains(&c);
+                i += 3;
+            }
+            other => {
+                matched |= other == c;
+                i += 1;
+            }
+        }
+    }
+
+    Some((matched != negate, i + 1))
+}
@@ -1,4 +1,5 @@
-use mini_redis_tls::server;
+use mini_redis_tls::sentinel::{self, SentinelConfig};
+use mini_redis_tls::{server, Config};
 use tracing::info;
 use tokio::sync::broadcast;
 
@@ -6,11 +7,16 @@ use tokio::sync::broadcast;
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    // Default configuration
-    let addr = "127.0.0.1:6379";
-    let aof_path = "appendonly.aof";
-
-    info!("Starting server via main.rs wrapper");
+    // `mini-redis [config-file] [--name value ...]`, or
+    // `mini-redis sentinel.conf --sentinel`
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let sentinel_mode = match args.iter().position(|arg| arg == "--sentinel") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
 
     let (tx, rx) = broadcast::channel(1);
 
@@ -22,5 +28,14 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    server::run(addr, aof_path, rx, None).await
+    if sentinel_mode {
+        let [path] = args.as_slice() else {
+            anyhow::bail!("sentinel mode needs exactly one argument: the configuration file");
+        };
+        return sentinel::run(SentinelConfig::load(path)?, rx).await;
+    }
+
+    let config = Config::from_args(args)?;
+    info!("Starting server via main.rs wrapper");
+    server::run(config, rx).await
 }
@@ -0,0 +1,151 @@
+//! Keyspace notifications: pub/sub messages published when keys are written,
+//! deleted, expire or are evicted. Which of them are published is set by
+//! `notify-keyspace-events`, using Redis' flag characters:
+//!
+//! ```text
+//! K  keyspace events, published to __keyspace@0__:<key> with the event as message
+//! E  keyevent events, published to __keyevent@0__:<event> with the key as message
+//! g  generic commands: DEL, EXPIRE, RENAME, ...
+//! $  string commands
+//! l  list commands
+//! s  set commands
+//! h  hash commands
+//! z  sorted set commands
+//! t  stream commands
+//! x  keys expiring
+//! e  keys evicted for maxmemory
+//! A  alias for g$lshztxe
+//! ```
+//!
+//! At least one of `K` or `E` must be given for anything to be published.
+
+use crate::pubsub::PubSub;
+use bytes::Bytes;
+use std::fmt;
+use std::ops::BitOr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A set of `notify-keyspace-events` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyspaceEvents(u32);
+
+impl KeyspaceEvents {
+    pub const KEYSPACE: KeyspaceEvents = KeyspaceEvents(1 << 0);
+    pub const KEYEVENT: KeyspaceEvents = KeyspaceEvents(1 << 1);
+    pub const GENERIC: KeyspaceEvents = KeyspaceEvents(1 << 2);
+    pub const STRING: KeyspaceEvents = KeyspaceEvents(1 << 3);
+    pub const LIST: KeyspaceEvents = KeyspaceEvents(1 << 4);
+    pub const SET: KeyspaceEvents = KeyspaceEvents(1 << 5);
+    pub const HASH: KeyspaceEvents = KeyspaceEvents(1 << 6);
+    pub const ZSET: KeyspaceEvents = KeyspaceEvents(1 << 7);
+    pub const STREAM: KeyspaceEvents = KeyspaceEvents(1 << 8);
+    pub const EXPIRED: KeyspaceEvents = KeyspaceEvents(1 << 9);
+    pub const EVICTED: KeyspaceEvents = KeyspaceEvents(1 << 10);
+    /// Every event class, what `A` stands for.
+    pub const ALL: KeyspaceEvents = KeyspaceEvents((1 << 11) - (1 << 2));
+
+    /// Whether every flag of `other` is set.
+    pub fn contains(self, other: KeyspaceEvents) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for KeyspaceEvents {
+    type Output = KeyspaceEvents;
+
+    fn bitor(self, other: KeyspaceEvents) -> KeyspaceEvents {
+        KeyspaceEvents(self.0 | other.0)
+    }
+}
+
+/// The flag characters, in the order `CONFIG GET` lists them.
+const FLAGS: &[(char, KeyspaceEvents)] = &[
+    ('g', KeyspaceEvents::GENERIC),
+    ('$', KeyspaceEvents::STRING),
+    ('l', KeyspaceEvents::LIST),
+    ('s', KeyspaceEvents::SET),
+    ('h', KeyspaceEvents::HASH),
+    ('z', KeyspaceEvents::ZSET),
+    ('x', KeyspaceEvents::EXPIRED),
+    ('e', KeyspaceEvents::EVICTED),
+    ('t', KeyspaceEvents::STREAM),
+    ('K', KeyspaceEvents::KEYSPACE),
+    ('E', KeyspaceEvents::KEYEVENT),
+];
+
+impl FromStr for KeyspaceEvents {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeyspaceEvents, String> {
+        s.chars().try_fold(KeyspaceEvents::default(), |events, c| {
+            let flag = match c {
+                'A' => KeyspaceEvents::ALL,
+                c => FLAGS
+                    .iter()
+                    .find(|(flag, _)| *flag == c)
+                    .map(|(_, flag)| *flag)
+                    .ok_or_else(|| "Invalid event class character. Use 'Ag$lshzxetKE'.".to_string())?,
+            };
+            Ok(events | flag)
+        })
+    }
+}
+
+impl fmt::Display for KeyspaceEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = self.contains(KeyspaceEvents::ALL);
+        if all {
+            f.write_str("A")?;
+        }
+        for (c, flag) in FLAGS {
+            // `A` already stands for the event classes
+            if all && KeyspaceEvents::ALL.contains(*flag) {
+                continue;
+            }
+            if self.contains(*flag) {
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Publishes keyspace notifications. The database and each of its shards
+/// share one, so events can be sent from wherever a key changes, including
+/// the purge task and eviction.
+#[derive(Default)]
+pub struct Notifier {
+    events: AtomicU32,
+    pub_sub: PubSub,
+}
+
+impl Notifier {
+    /// The channels notifications, and every other message, are published
+    /// to.
+    pub fn pub_sub(&self) -> &PubSub {
+        &self.pub_sub
+    }
+
+    pub fn events(&self) -> KeyspaceEvents {
+        KeyspaceEvents(self.events.load(Ordering::Relaxed))
+    }
+
+    pub fn set_events(&self, events: KeyspaceEvents) {
+        self.events.store(events.0, Ordering::Relaxed);
+    }
+
+    /// Publishes that `event` happened to `key`, if its `class` is enabled.
+    pub fn notify(&self, class: KeyspaceEvents, event: &str, key: &str) {
+        let events = self.events();
+        if !events.contains(class) {
+            return;
+        }
+        if events.contains(KeyspaceEvents::KEYSPACE) {
+            self.pub_sub.publish(&format!("__keyspace@0__:{}", key), Bytes::from(event.to_string()));
+        }
+        if events.contains(KeyspaceEvents::KEYEVENT) {
+            self.pub_sub.publish(&format!("__keyevent@0__:{}", event), Bytes::from(key.to_string()));
+        }
+    }
+}
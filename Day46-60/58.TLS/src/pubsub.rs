@@ -0,0 +1,309 @@
+//! Channels for `PUBLISH` / `SUBSCRIBE`. Pub/sub has no relation to the
+//! keyspace, so it lives behind its own lock rather than in a shard.
+
+use crate::{glob, Db, Frame};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::task::Poll;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// How many messages a channel buffers for subscribers that fall behind.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A published message. Pattern subscribers need to know which channel it
+/// was published to.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel: String,
+    pub payload: Bytes,
+}
+
+#[derive(Default)]
+pub struct PubSub {
+    /// Map: Channel Name -> Broadcast Sender
+    channels: RwLock<HashMap<String, broadcast::Sender<Message>>>,
+    /// Map: Glob Pattern -> Broadcast Sender
+    patterns: RwLock<HashMap<String, broadcast::Sender<Message>>>,
+}
+
+impl PubSub {
+    pub fn new() -> PubSub {
+        PubSub::default()
+    }
+
+    /// Returns a `Receiver` for the requested channel, creating the channel
+    /// if needed.
+    pub fn subscribe(&self, channel_name: String) -> broadcast::Receiver<Message> {
+        subscribe(&self.channels, channel_name)
+    }
+
+    /// Returns a `Receiver` for the messages published to any channel
+    /// matching `pattern`.
+    pub fn psubscribe(&self, pattern: String) -> broadcast::Receiver<Message> {
+        subscribe(&self.patterns, pattern)
+    }
+
+    /// Forgets the channel once its last receiver has been dropped.
+    pub fn unsubscribe(&self, channel_name: &str) {
+        release(&self.channels, channel_name);
+    }
+
+    /// Forgets the pattern once its last receiver has been dropped.
+    pub fn punsubscribe(&self, pattern: &str) {
+        release(&self.patterns, pattern);
+    }
+
+    /// Publishes a message to the channel. Returns the number of subscribers
+    /// it was delivered to, counting pattern subscribers.
+    pub fn publish(&self, channel_name: &str, payload: Bytes) -> usize {
+        let message = Message { channel: channel_name.to_string(), payload };
+
+        // Sending fails when nobody is listening anymore
+        let mut receivers = match self.channels.read().unwrap().get(channel_name) {
+            Some(tx) => tx.send(message.clone()).unwrap_or(0),
+            None => 0,
+        };
+        for (pattern, tx) in self.patterns.read().unwrap().iter() {
+            if glob::matches(pattern.as_bytes(), channel_name.as_bytes()) {
+                receivers += tx.send(message.clone()).unwrap_or(0);
+            }
+        }
+        receivers
+    }
+
+    /// The channels with at least one subscriber, optionally only those
+    /// matching `pattern`.
+    pub fn channels(&self, pattern: Option<&str>) -> Vec<String> {
+        let mut channels: Vec<String> = self
+            .channels
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), channel.as_bytes())))
+            .cloned()
+            .collect();
+        channels.sort();
+        channels
+    }
+
+    /// The number of subscribers of each channel, pattern subscribers not
+    /// included.
+    pub fn numsub(&self, channels: &[String]) -> Vec<(String, usize)> {
+        let senders = self.channels.read().unwrap();
+        channels
+            .iter()
+            .map(|channel| (channel.clone(), senders.get(channel).map_or(0, |tx| tx.receiver_count())))
+            .collect()
+    }
+
+    /// The number of patterns at least one connection is subscribed to.
+    pub fn numpat(&self) -> usize {
+        self.patterns.read().unwrap().len()
+    }
+}
+
+fn subscribe(senders: &RwLock<HashMap<String, broadcast::Sender<Message>>>, name: String) -> broadcast::Receiver<Message> {
+    use std::collections::hash_map::Entry;
+
+    match senders.write().unwrap().entry(name) {
+        Entry::Occupied(e) => e.get().subscribe(),
+        Entry::Vacant(e) => {
+            let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
+            e.insert(tx);
+            rx
+        }
+    }
+}
+
+fn release(senders: &RwLock<HashMap<String, broadcast::Sender<Message>>>, name: &str) {
+    // Subscribing takes the write lock too, so nobody can be about to
+    // receive from a sender that is removed here
+    let mut senders = senders.write().unwrap();
+    if senders.get(name).is_some_and(|tx| tx.receiver_count() == 0) {
+        senders.remove(name);
+    }
+}
+
+/// Waits for the next message on a receiver, handing the receiver back so
+/// it can wait again.
+type Recv = Pin<Box<dyn Future<Output = (broadcast::Receiver<Message>, Result<Message, RecvError>)> + Send>>;
+
+fn recv(mut rx: broadcast::Receiver<Message>) -> Recv {
+    Box::pin(async move {
+        let result = rx.recv().await;
+        (rx, result)
+    })
+}
+
+/// What a subscribed connection is sent.
+#[derive(Debug)]
+pub(crate) enum Delivery {
+    Message(Message),
+    PMessage { pattern: String, message: Message },
+    /// The connection fell so far behind on a channel or pattern that
+    /// `skipped` of its messages were dropped.
+    Lagged { name: String, skipped: u64 },
+}
+
+/// The channels and patterns one connection is subscribed to.
+pub(crate) struct Subscriptions {
+    db: Db,
+    channels: BTreeMap<String, Recv>,
+    patterns: BTreeMap<String, Recv>,
+    /// Messages already received but not yet handed out by `recv`.
+    ready: VecDeque<Delivery>,
+}
+
+impl Subscriptions {
+    pub(crate) fn new(db: Db) -> Subscriptions {
+        Subscriptions { db, channels: BTreeMap::new(), patterns: BTreeMap::new(), ready: VecDeque::new() }
+    }
+
+    /// The number of channels and patterns subscribed to.
+    pub(crate) fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Subscribes to `channels`, returning the confirmation for each.
+    pub(crate) fn subscribe(&mut self, channels: Vec<String>) -> Vec<Frame> {
+        let mut replies = Vec::with_capacity(channels.len());
+        for channel in channels {
+            if !self.channels.contains_key(&channel) {
+                let rx = self.db.pub_sub().subscribe(channel.clone());
+                self.channels.insert(channel.clone(), recv(rx));
+            }
+            replies.push(self.confirmation("subscribe", Some(channel)));
+        }
+        replies
+    }
+
+    pub(crate) fn psubscribe(&mut self, patterns: Vec<String>) -> Vec<Frame> {
+        let mut replies = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            if !self.patterns.contains_key(&pattern) {
+                let rx = self.db.pub_sub().psubscribe(pattern.clone());
+                self.patterns.insert(pattern.clone(), recv(rx));
+            }
+            replies.push(self.confirmation("psubscribe", Some(pattern)));
+        }
+        replies
+    }
+
+    /// Unsubscribes from `channels`, or from every channel if none is given.
+    pub(crate) fn unsubscribe(&mut self, channels: Vec<String>) -> Vec<Frame> {
+        let channels = match channels.is_empty() {
+            true => self.channels.keys().cloned().collect(),
+            false => channels,
+        };
+        if channels.is_empty() {
+            return vec![self.confirmation("unsubscribe", None)];
+        }
+
+        let mut replies = Vec::with_capacity(channels.len());
+        for channel in channels {
+            // Dropping the receiver before releasing lets the channel go
+            if self.channels.remove(&channel).is_some() {
+                self.db.pub_sub().unsubscribe(&channel);
+            }
+            replies.push(self.confirmation("unsubscribe", Some(channel)));
+        }
+        replies
+    }
+
+    pub(crate) fn punsubscribe(&mut self, patterns: Vec<String>) -> Vec<Frame> {
+        let patterns = match patterns.is_empty() {
+            true => self.patterns.keys().cloned().collect(),
+            false => patterns,
+        };
+        if patterns.is_empty() {
+            return vec![self.confirmation("punsubscribe", None)];
+        }
+
+        let mut replies = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            if self.patterns.remove(&pattern).is_some() {
+                self.db.pub_sub().punsubscribe(&pattern);
+            }
+            replies.push(self.confirmation("punsubscribe", Some(pattern)));
+        }
+        replies
+    }
+
+    /// `[kind, channel, number of subscriptions left]`, sent for every
+    /// channel or pattern (un)subscribed from.
+    fn confirmation(&self, kind: &str, name: Option<String>) -> Frame {
+        Frame::Push(vec![
+            Frame::Bulk(Bytes::from(kind.to_string())),
+            name.map_or(Frame::Null, |name| Frame::Bulk(Bytes::from(name))),
+            Frame::Integer(self.count() as i64),
+        ])
+    }
+
+    /// Waits for the next message on any of the subscriptions. Never
+    /// resolves without any. Cancel safe: messages received but not returned
+    /// are kept for the next call.
+    pub(crate) async fn recv(&mut self) -> Delivery {
+        future::poll_fn(|cx| {
+            if let Some(delivery) = self.ready.pop_front() {
+                return Poll::Ready(delivery);
+            }
+
+            // Every receiver is polled, so a busy channel can't starve the others
+            for (channel, pending) in self.channels.iter_mut() {
+                if let Poll::Ready((rx, result)) = pending.as_mut().poll(cx) {
+                    *pending = recv(rx);
+                    self.ready.extend(delivery(channel, result, Delivery::Message));
+                }
+            }
+            for (pattern, pending) in self.patterns.iter_mut() {
+                if let Poll::Ready((rx, result)) = pending.as_mut().poll(cx) {
+                    *pending = recv(rx);
+                    self.ready.extend(delivery(pattern, result, |message| Delivery::PMessage {
+                        pattern: pattern.clone(),
+                        message,
+                    }));
+                }
+            }
+
+            // The receivers just replaced are polled by the next call
+            match self.ready.pop_front() {
+                Some(delivery) => Poll::Ready(delivery),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+/// Turns what a receiver got into what the connection is sent, if anything.
+fn delivery(
+    name: &str,
+    result: Result<Message, RecvError>,
+    message: impl FnOnce(Message) -> Delivery,
+) -> Option<Delivery> {
+    match result {
+        Ok(msg) => Some(message(msg)),
+        // The receiver carries on with the oldest message still buffered
+        Err(RecvError::Lagged(skipped)) => Some(Delivery::Lagged { name: name.to_string(), skipped }),
+        // Senders are only dropped once nobody is subscribed
+        Err(RecvError::Closed) => None,
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        // Release the channels and patterns of a connection that goes away
+        // while subscribed
+        let channels: Vec<String> = std::mem::take(&mut self.channels).into_keys().collect();
+        let patterns: Vec<String> = std::mem::take(&mut self.patterns).into_keys().collect();
+        for channel in channels {
+            self.db.pub_sub().unsubscribe(&channel);
+        }
+        for pattern in patterns {
+            self.db.pub_sub().punsubscribe(&pattern);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Point-in-time binary snapshots of the whole `Db`, in the spirit of Redis' RDB files.
+//!
+//! Layout:
+//!
+//! ```text
+//! "MREDIS0001"                       magic + format version
+//! { [0xFC expire:u64] type:u8 key value }*
+//!                                    one record per key, optionally preceded
+//!                                    by its expiration (Unix ms, little endian)
+//! 0xFF                               end of records
+//! crc32:u32 (little endian)          checksum of everything before it
+//! ```
+//!
+//! Lengths are LEB128 varints, so small keys and values only pay one byte of
+//! overhead. A key is `len bytes`, a string value is `len bytes`. Lists and
+//! sets are a `count` followed by their elements, hashes a `count` of
+//! field/value pairs and sorted sets a `count` of `member score:f64` pairs.
+//! Stream IDs are two varints, `ms seq`. A stream is a `count` of entries,
+//! each `id count field value ...`, followed by its last ID and a `count` of
+//! consumer groups, each `name last_delivered`, a `count` of `consumer
+//! seen_ms` and a `count` of pending entries `id consumer delivered_ms
+//! deliveries`.
+//!
+//! The same encoding is used as the preamble of a rewritten AOF, so a restart
+//! only has to replay the commands appended since the last rewrite.
+
+use crate::Db;
+use crate::db::{now_ms, Entry, Value};
+use crate::stream::{Fields, Group, Pending, Stream, StreamId};
+use crate::zset::ZSet;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+pub const MAGIC: &[u8] = b"MREDIS0001";
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_HASH: u8 = 2;
+const TYPE_SET: u8 = 3;
+const TYPE_ZSET: u8 = 4;
+const TYPE_STREAM: u8 = 5;
+const OP_EXPIRE_MS: u8 = 0xFC;
+const OP_EOF: u8 = 0xFF;
+
+/// Saves snapshots of a `Db` to a file and loads them back.
+pub struct Rdb {
+    path: PathBuf,
+    bgsave_in_progress: AtomicBool,
+    /// Unix time in seconds of the last successful save, or of startup.
+    last_save: AtomicU64,
+}
+
+impl Rdb {
+    pub fn new(path: impl AsRef<Path>) -> Rdb {
+        Rdb {
+            path: path.as_ref().to_path_buf(),
+            bgsave_in_progress: AtomicBool::new(false),
+            last_save: AtomicU64::new(now_ms() / 1000),
+        }
+    }
+
+    /// Loads the snapshot into `db`, returning the number of keys loaded.
+    /// A missing file is treated as an empty dataset.
+    pub async fn load(&self, db: &Db) -> anyhow::Result<usize> {
+        let path = self.path.clone();
+        let db = db.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let file = match std::fs::File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(e.into()),
+            };
+
+            let mut loaded = 0;
+            decode(BufReader::new(file), |key, entry| {
+                db.restore(key, entry);
+                loaded += 1;
+            })
+            .map_err(|e| anyhow::anyhow!("failed to load snapshot {}: {}", path.display(), e))?;
+
+            info!("Loaded {} keys from snapshot {}", loaded, path.display());
+            Ok(loaded)
+        })
+        .await?
+    }
+
+    /// Writes a snapshot of `db`, waiting until it is on disk. The dataset is
+    /// copied on a blocking thread, like it is written, so the runtime keeps
+    /// serving other clients meanwhile.
+    pub async fn save(&self, db: &Db) -> anyhow::Result<()> {
+        let db = db.clone();
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || write_file(&path, &db.snapshot())).await??;
+        self.last_save.store(now_ms() / 1000, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Takes a snapshot of `db` and writes it to disk in the background.
+    ///
+    /// Both happen on a blocking thread, so copying a large dataset doesn't
+    /// stall the runtime. The call returns once the copy is taken, so the
+    /// file holds the dataset as of the call; the handle finishes once it is
+    /// on disk.
+    pub async fn save_in_background(rdb: Arc<Rdb>, db: &Db) -> anyhow::Result<JoinHandle<()>> {
+        if rdb.bgsave_in_progress.swap(true, Ordering::SeqCst) {
+            anyhow::bail!("Background save already in progress");
+        }
+
+        let db = db.clone();
+        let (taken_tx, taken_rx) = oneshot::channel();
+        let handle = tokio::task::spawn_blocking(move || {
+            let snapshot = db.snapshot();
+            info!("Background saving started with {} keys", snapshot.len());
+            let _ = taken_tx.send(());
+
+            match write_file(&rdb.path, &snapshot) {
+                Ok(()) => {
+                    rdb.last_save.store(now_ms() / 1000, Ordering::SeqCst);
+                    info!("Background saving terminated with success");
+                }
+                Err(e) => error!("Background saving failed: {:?}", e),
+            }
+            rdb.bgsave_in_progress.store(false, Ordering::SeqCst);
+        });
+        let _ = taken_rx.await;
+        Ok(handle)
+    }
+
+    /// Returns `true` while a background save is running.
+    pub fn is_saving(&self) -> bool {
+        self.bgsave_in_progress.load(Ordering::SeqCst)
+    }
+
+    /// Unix time in seconds of the last successful save.
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::SeqCst)
+    }
+}
+
+/// Writes `entries` to a temporary file next to `path`, then renames it over
+/// `path` so readers never observe a partially written snapshot.
+fn write_file(path: &Path, entries: &[(String, Entry)]) -> io::Result<()> {
+    let temp_path = path.with_extension("rdb.tmp");
+
+    let result = (|| {
+        let mut writer = BufWriter::new(std::fs::File::create(&temp_path)?);
+        encode(&mut writer, entries)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Encodes `entries` in the snapshot format.
+pub fn encode<W: Write>(writer: W, entries: &[(String, Entry)]) -> io::Result<()> {
+    let mut writer = ChecksumWriter { inner: writer, crc: Crc32::new() };
+
+    writer.write_all(MAGIC)?;
+    for (key, entry) in entries {
+        if let Some(when) = entry.expires_at {
+            writer.write_all(&[OP_EXPIRE_MS])?;
+            writer.write_all(&when.to_le_bytes())?;
+        }
+        write_value(&mut writer, key, &entry.value)?;
+    }
+    writer.write_all(&[OP_EOF])?;
+
+    let crc = writer.crc.finish();
+    writer.inner.write_all(&crc.to_le_bytes())?;
+    writer.inner.flush()
+}
+
+/// Decodes a snapshot, calling `f` for every key. Returns the number of bytes
+/// consumed, so a snapshot can be followed by other data (e.g. AOF commands).
+pub fn decode<R: Read>(reader: R, mut f: impl FnMut(String, Entry)) -> anyhow::Result<u64> {
+    let mut reader = ChecksumReader { inner: reader, crc: Crc32::new(), consumed: 0 };
+
+    let mut magic = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        anyhow::bail!("not a snapshot file (bad magic)");
+    }
+
+    let mut expires_at = None;
+    loop {
+        match read_u8(&mut reader)? {
+            OP_EXPIRE_MS => {
+                let mut when = [0u8; 8];
+                reader.read_exact(&mut when)?;
+                expires_at = Some(u64::from_le_bytes(when));
+            }
+            OP_EOF => break,
+            tag @ (TYPE_STRING | TYPE_LIST | TYPE_HASH | TYPE_SET | TYPE_ZSET | TYPE_STREAM) => {
+                let key = String::from_utf8(read_bytes(&mut reader)?)
+                    .map_err(|_| anyhow::anyhow!("invalid UTF-8 key"))?;
+                let value = read_value(&mut reader, tag)?;
+                f(key, Entry::new(value, expires_at.take()));
+            }
+            tag => anyhow::bail!("unknown record type {:#04x}", tag),
+        }
+    }
+
+    let expected = reader.crc.finish();
+    let mut trailer = [0u8; 4];
+    reader.inner.read_exact(&mut trailer)?;
+    if u32::from_le_bytes(trailer) != expected {
+        anyhow::bail!("checksum mismatch");
+    }
+
+    Ok(reader.consumed + trailer.len() as u64)
+}
+
+fn write_value<W: Write>(writer: &mut W, key: &str, value: &Value) -> io::Result<()> {
+    let tag = match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Hash(_) => TYPE_HASH,
+        Value::Set(_) => TYPE_SET,
+        Value::ZSet(_) => TYPE_ZSET,
+        Value::Stream(_) => TYPE_STREAM,
+    };
+    writer.write_all(&[tag])?;
+    write_bytes(writer, key.as_bytes())?;
+
+    match value {
+        Value::String(value) => write_bytes(writer, value)?,
+        Value::List(list) => {
+            write_len(writer, list.len() as u64)?;
+            for item in list {
+                write_bytes(writer, item)?;
+            }
+        }
+        Value::Hash(hash) => {
+            write_len(writer, hash.len() as u64)?;
+            for (field, value) in hash {
+                write_bytes(writer, field)?;
+                write_bytes(writer, value)?;
+            }
+        }
+        Value::Set(set) => {
+            write_len(writer, set.len() as u64)?;
+            for member in set {
+                write_bytes(writer, member)?;
+            }
+        }
+        Value::ZSet(zset) => {
+            write_len(writer, zset.len() as u64)?;
+            for (member, score) in zset.iter() {
+                write_bytes(writer, member)?;
+                writer.write_all(&score.to_le_bytes())?;
+            }
+        }
+        Value::Stream(stream) => {
+            write_len(writer, stream.len() as u64)?;
+            for (id, fields) in stream.iter() {
+                write_id(writer, *id)?;
+                write_len(writer, fields.len() as u64)?;
+                for (field, value) in fields {
+                    write_bytes(writer, field)?;
+                    write_bytes(writer, value)?;
+                }
+            }
+            write_id(writer, stream.last_id())?;
+
+            write_len(writer, stream.groups().count() as u64)?;
+            for (name, group) in stream.groups() {
+                write_bytes(writer, name.as_bytes())?;
+                write_id(writer, group.last_delivered)?;
+                write_len(writer, group.consumers.len() as u64)?;
+                for (consumer, seen) in &group.consumers {
+                    write_bytes(writer, consumer.as_bytes())?;
+                    write_len(writer, *seen)?;
+                }
+                write_len(writer, group.pending.len() as u64)?;
+                for (id, pending) in &group.pending {
+                    write_id(writer, *id)?;
+                    write_bytes(writer, pending.consumer.as_bytes())?;
+                    write_len(writer, pending.delivered_at)?;
+                    write_len(writer, pending.deliveries)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_value<R: Read>(reader: &mut R, tag: u8) -> anyhow::Result<Value> {
+    if tag == TYPE_STRING {
+        return Ok(Value::String(Bytes::from(read_bytes(reader)?)));
+    }
+
+    // As with `read_bytes`, the count is only trusted as far as the data goes
+    let count = read_len(reader)?;
+    Ok(match tag {
+        TYPE_LIST => Value::List((0..count).map(|_| read_bytes(reader).map(Bytes::from)).collect::<anyhow::Result<_>>()?),
+        TYPE_HASH => Value::Hash(
+            (0..count)
+                .map(|_| Ok((Bytes::from(read_bytes(reader)?), Bytes::from(read_bytes(reader)?))))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        TYPE_SET => Value::Set((0..count).map(|_| read_bytes(reader).map(Bytes::from)).collect::<anyhow::Result<_>>()?),
+        TYPE_STREAM => Value::Stream(read_stream(reader, count)?),
+        _ => {
+            let mut zset = ZSet::new();
+            for _ in 0..count {
+                let member = Bytes::from(read_bytes(reader)?);
+                let mut score = [0u8; 8];
+                reader.read_exact(&mut score)?;
+                zset.insert(member, f64::from_le_bytes(score));
+            }
+            Value::ZSet(zset)
+        }
+    })
+}
+
+fn read_stream<R: Read>(reader: &mut R, count: u64) -> anyhow::Result<Stream> {
+    let mut entries = BTreeMap::new();
+    for _ in 0..count {
+        let id = read_id(reader)?;
+        let fields = (0..read_len(reader)?)
+            .map(|_| Ok((Bytes::from(read_bytes(reader)?), Bytes::from(read_bytes(reader)?))))
+            .collect::<anyhow::Result<Fields>>()?;
+        entries.insert(id, fields);
+    }
+    let last_id = read_id(reader)?;
+
+    let mut groups = BTreeMap::new();
+    for _ in 0..read_len(reader)? {
+        let name = read_string(reader)?;
+        let mut group = Group { last_delivered: read_id(reader)?, ..Group::default() };
+        for _ in 0..read_len(reader)? {
+            let consumer = read_string(reader)?;
+            group.consumers.insert(consumer, read_len(reader)?);
+        }
+        for _ in 0..read_len(reader)? {
+            let id = read_id(reader)?;
+            let pending = Pending { consumer: read_string(reader)?, delivered_at: read_len(reader)?, deliveries: read_len(reader)? };
+            group.pending.insert(id, pending);
+        }
+        groups.insert(name, group);
+    }
+
+    Ok(Stream::from_parts(entries, last_id, groups))
+}
+
+fn write_id<W: Write>(writer: &mut W, id: StreamId) -> io::Result<()> {
+    write_len(writer, id.ms)?;
+    write_len(writer, id.seq)
+}
+
+fn read_id<R: Read>(reader: &mut R) -> anyhow::Result<StreamId> {
+    Ok(StreamId::new(read_len(reader)?, read_len(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> anyhow::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| anyhow::anyhow!("invalid UTF-8 string"))
+}
+
+fn write_len<W: Write>(writer: &mut W, mut len: u64) -> io::Result<()> {
+    loop {
+        let byte = (len & 0x7F) as u8;
+        len >>= 7;
+        if len == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    write_len(writer, data.len() as u64)?;
+    writer.write_all(data)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_len<R: Read>(reader: &mut R) -> anyhow::Result<u64> {
+    let mut len = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(reader)?;
+        len |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(len);
+        }
+    }
+    anyhow::bail!("length prefix too long")
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> anyhow::Result<Vec<u8>> {
+    let len = read_len(reader)? as usize;
+    let mut data = Vec::new();
+    // Don't trust `len` for the allocation: a corrupt prefix would otherwise
+    // allocate arbitrarily much memory before hitting EOF.
+    reader.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(data)
+}
+
+struct ChecksumWriter<W> {
+    inner: W,
+    crc: Crc32,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ChecksumReader<R> {
+    inner: R,
+    crc: Crc32,
+    consumed: u64,
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        self.consumed += n as u64;
+        Ok(n)
+    }
+}
+
+/// CRC-32 (IEEE 802.3), the same checksum used by zlib and PNG.
+struct Crc32(u32);
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.0 ^ 0xFFFF_FFFF
+    }
+}
@@ -0,0 +1,746 @@
+//! Master-replica replication, in the style of Redis' `REPLICAOF` / `PSYNC`.
+//!
+//! Every write a node logs to its AOF is also fed, encoded exactly as in the
+//! file, to its replication stream. The stream is identified by a random
+//! replication id and an offset counting its bytes; the last
+//! `repl-backlog-size` bytes are kept in the backlog.
+//!
+//! A replica connects to its master, announces itself with `REPLCONF` and
+//! asks with `PSYNC <replid> <offset>` to continue the stream from the first
+//! byte it is missing. If the master still has that part of its own stream
+//! in the backlog it replies `+CONTINUE` and sends it from there. Otherwise
+//! it replies `+FULLRESYNC <replid> <offset>` followed by a snapshot (see
+//! `rdb`) of its dataset as of that offset, and streams from there. The
+//! replica applies what it receives and feeds it to its own stream, so its
+//! offset keeps matching its master's and it can serve replicas in turn.
+//!
+//! When a replica is promoted with `REPLICAOF NO ONE` it starts a new stream
+//! and remembers the previous id as `replid2`, so the other replicas of its
+//! former master can continue from it without a full synchronization.
+
+use crate::cmd::replication::Psync;
+use crate::{codec, evict, rdb, Aof, Command, Connection, Db, Frame, Protocol};
+use bytes::{Bytes, BytesMut};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::net::TcpStream;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How often a master without writes sends `PING` down the stream, so its
+/// replicas can tell a quiet master from a dead one.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// How often a replica acknowledges the offset it has processed.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+/// A master that sends nothing for this long is considered gone.
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a replica waits before reconnecting to its master.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The tail of a node's replication stream.
+#[derive(Debug)]
+pub struct Backlog {
+    replid: String,
+    /// The id of the stream this one continues, if it was promoted.
+    replid2: String,
+    /// The first offset (see `PSYNC`) that is not part of the `replid2`
+    /// stream, or -1.
+    second_replid_offset: i64,
+    /// Number of bytes in the stream so far.
+    offset: u64,
+    /// The last bytes of the stream.
+    buffer: VecDeque<u8>,
+    size: usize,
+}
+
+impl Backlog {
+    /// Starts a new stream, keeping up to `size` bytes of it.
+    pub fn new(size: usize) -> Backlog {
+        Backlog {
+            replid: new_replid(),
+            replid2: NO_REPLID.to_string(),
+            second_replid_offset: -1,
+            offset: 0,
+            buffer: VecDeque::new(),
+            size,
+        }
+    }
+
+    pub fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    /// Number of bytes in the stream so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of bytes kept.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Changes how many bytes are kept, dropping the oldest ones if needed.
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size;
+        self.trim();
+    }
+
+    /// Appends `data` to the stream.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.offset += data.len() as u64;
+        self.buffer.extend(data);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.buffer.len().saturating_sub(self.size);
+        self.buffer.drain(..excess);
+    }
+
+    /// The bytes of the stream from `from` on, or `None` if they are no
+    /// longer kept.
+    pub fn read(&self, from: u64) -> Option<Vec<u8>> {
+        let start = self.offset - self.buffer.len() as u64;
+        if from < start || from > self.offset {
+            return None;
+        }
+        Some(self.buffer.range((from - start) as usize..).copied().collect())
+    }
+
+    /// Whether a replica asking for `PSYNC <replid> <psync_offset>` can
+    /// continue from the backlog.
+    pub fn can_continue(&self, replid: &str, psync_offset: i64) -> bool {
+        let same_stream = replid == self.replid || (replid == self.replid2 && psync_offset <= self.second_replid_offset);
+        // The replica has every byte before `psync_offset`
+        let received = psync_offset - 1;
+        let start = (self.offset - self.buffer.len() as u64) as i64;
+        same_stream && (start..=self.offset as i64).contains(&received)
+    }
+
+    /// Continues the stream of another node, after receiving its dataset as
+    /// of `offset`.
+    pub fn reset(&mut self, replid: String, offset: u64) {
+        self.replid = replid;
+        self.replid2 = NO_REPLID.to_string();
+        self.second_replid_offset = -1;
+        self.offset = offset;
+        self.buffer.clear();
+    }
+
+    /// Continues the current stream under a new id. Replicas can still
+    /// continue from the old id up to the current offset.
+    pub fn shift_replid(&mut self, replid: String) {
+        self.replid2 = std::mem::replace(&mut self.replid, replid);
+        self.second_replid_offset = self.offset as i64 + 1;
+    }
+}
+
+/// The id reported for a missing `replid2`.
+const NO_REPLID: &str = "0000000000000000000000000000000000000000";
+
+/// A random replication id: 40 hex characters.
+pub(crate) fn new_replid() -> String {
+    (0..3).fold(String::new(), |mut id, _| {
+        let _ = write!(id, "{:016x}", evict::random());
+        id
+    })[..40]
+        .to_string()
+}
+
+/// A replica connected to this node.
+#[derive(Debug)]
+pub(crate) struct ReplicaLink {
+    ip: String,
+    port: u16,
+    /// The offset it acknowledged last.
+    offset: u64,
+    last_ack: Instant,
+}
+
+impl ReplicaLink {
+    pub(crate) fn new(ip: String, port: u16) -> ReplicaLink {
+        ReplicaLink { ip, port, offset: 0, last_ack: Instant::now() }
+    }
+}
+
+/// The master this node replicates.
+#[derive(Debug)]
+struct MasterLink {
+    host: String,
+    port: u16,
+    up: bool,
+    sync_in_progress: bool,
+    last_io: Option<Instant>,
+}
+
+/// The task following the master.
+struct Follower {
+    stop: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+/// The replication state of a node. It is a master unless it follows one.
+pub struct Replication {
+    backlog: Mutex<Backlog>,
+    /// Bumped whenever the backlog changes, waking the tasks streaming it to
+    /// replicas.
+    changed: watch::Sender<()>,
+    /// The port this node listens on, announced to its master.
+    listening_port: u16,
+    /// `masteruser` and `masterauth`, if a password is set.
+    master_auth: Mutex<Option<(Option<String>, String)>>,
+    replicas: Mutex<BTreeMap<u64, ReplicaLink>>,
+    master: Mutex<Option<MasterLink>>,
+    follower: tokio::sync::Mutex<Option<Follower>>,
+    /// Full synchronizations served, and requests to continue that were
+    /// granted or refused.
+    pub(crate) sync_full: AtomicU64,
+    pub(crate) sync_partial_ok: AtomicU64,
+    pub(crate) sync_partial_err: AtomicU64,
+}
+
+impl Replication {
+    pub fn new(listening_port: u16, backlog_size: usize) -> Replication {
+        Replication {
+            backlog: Mutex::new(Backlog::new(backlog_size)),
+            changed: watch::Sender::new(()),
+            listening_port,
+            master_auth: Mutex::new(None),
+            replicas: Mutex::new(BTreeMap::new()),
+            master: Mutex::new(None),
+            follower: tokio::sync::Mutex::new(None),
+            sync_full: AtomicU64::new(0),
+            sync_partial_ok: AtomicU64::new(0),
+            sync_partial_err: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_backlog_size(&self, size: usize) {
+        self.backlog.lock().unwrap().set_size(size);
+    }
+
+    /// Sets the credentials used to connect to the master; no password means
+    /// none.
+    pub fn set_master_auth(&self, user: &str, password: &str) {
+        *self.master_auth.lock().unwrap() = match password.is_empty() {
+            true => None,
+            false => Some((Some(user.to_string()).filter(|user| !user.is_empty()), password.to_string())),
+        };
+    }
+
+    /// Whether this node follows a master, and so refuses writes from
+    /// clients.
+    pub fn is_replica(&self) -> bool {
+        self.master.lock().unwrap().is_some()
+    }
+
+    /// The role as `HELLO` reports it.
+    pub fn role(&self) -> &'static str {
+        if self.is_replica() { "replica" } else { "master" }
+    }
+
+    /// Appends already encoded writes to the replication stream. Callers hold
+    /// the AOF lock, so the stream has writes in the order they were applied.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        self.backlog.lock().unwrap().feed(data);
+        self.changed.send_replace(());
+    }
+
+    /// Encodes `frames` the way the AOF does and appends them to the stream.
+    fn feed_frames(&self, frames: &[Frame]) {
+        let mut encoded = BytesMut::new();
+        for frame in frames {
+            codec::encode(frame, Protocol::Resp2, &mut encoded);
+        }
+        self.feed(&encoded);
+    }
+
+    pub(crate) fn reset_stats(&self) {
+        self.sync_full.store(0, Ordering::Relaxed);
+        self.sync_partial_ok.store(0, Ordering::Relaxed);
+        self.sync_partial_err.store(0, Ordering::Relaxed);
+    }
+
+    /// The fields of the `Replication` section of `INFO`.
+    pub(crate) fn info(&self) -> Vec<(String, String)> {
+        let mut fields = vec![];
+        let mut field = |name: &str, value: String| fields.push((name.to_string(), value));
+
+        match &*self.master.lock().unwrap() {
+            None => field("role", "master".to_string()),
+            Some(master) => {
+                field("role", "slave".to_string());
+                field("master_host", master.host.clone());
+                field("master_port", master.port.to_string());
+                field("master_link_status", if master.up { "up" } else { "down" }.to_string());
+                let last_io = master.last_io.map(|last_io| last_io.elapsed().as_secs() as i64).unwrap_or(-1);
+                field("master_last_io_seconds_ago", last_io.to_string());
+                field("master_sync_in_progress", flag(master.sync_in_progress));
+                field("slave_repl_offset", self.backlog.lock().unwrap().offset.to_string());
+                field("slave_read_only", "1".to_string());
+            }
+        }
+
+        let replicas = self.replicas.lock().unwrap();
+        field("connected_slaves", replicas.len().to_string());
+        for (i, replica) in replicas.values().enumerate() {
+            field(
+                &format!("slave{}", i),
+                format!(
+                    "ip={},port={},state=online,offset={},lag={}",
+                    replica.ip,
+                    replica.port,
+                    replica.offset,
+                    replica.last_ack.elapsed().as_secs()
+                ),
+            );
+        }
+        drop(replicas);
+
+        let backlog = self.backlog.lock().unwrap();
+        field("master_replid", backlog.replid.clone());
+        field("master_replid2", backlog.replid2.clone());
+        field("master_repl_offset", backlog.offset.to_string());
+        field("second_repl_offset", backlog.second_replid_offset.to_string());
+        field("repl_backlog_active", "1".to_string());
+        field("repl_backlog_size", backlog.size.to_string());
+        field("repl_backlog_first_byte_offset", (backlog.offset - backlog.buffer.len() as u64 + 1).to_string());
+        field("repl_backlog_histlen", backlog.buffer.len().to_string());
+        fields
+    }
+
+    /// Spawns the task sending `PING` to the replicas every `PING_INTERVAL`.
+    /// It exits once the `Aof` has been dropped.
+    pub(crate) fn start_ping_task(replication: &Arc<Replication>, aof: &Arc<tokio::sync::Mutex<Aof>>) -> JoinHandle<()> {
+        let replication = replication.clone();
+        let aof: Weak<tokio::sync::Mutex<Aof>> = Arc::downgrade(aof);
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(PING_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let Some(aof) = aof.upgrade() else { break };
+                // A replica passes on its master's pings instead
+                if replication.is_replica() || replication.replicas.lock().unwrap().is_empty() {
+                    continue;
+                }
+                let _guard = aof.lock().await;
+                replication.feed_frames(&[bulks(&["PING"])]);
+            }
+        })
+    }
+
+    /// Serves a replica that sent `PSYNC` on `connection`: sends it what it
+    /// is missing, then keeps streaming writes to it until it disconnects.
+    /// `id` identifies the connection.
+    pub(crate) async fn serve_replica(
+        &self,
+        connection: &mut Connection,
+        db: &Db,
+        aof: &tokio::sync::Mutex<Aof>,
+        psync: Psync,
+        id: u64,
+        link: ReplicaLink,
+    ) -> anyhow::Result<()> {
+        let mut changed = self.changed.subscribe();
+
+        // Under the AOF lock no write can slip in between the snapshot and
+        // the offset it is taken at
+        let mut guard = aof.lock().await;
+        let continues = self.backlog.lock().unwrap().can_continue(&psync.replid, psync.offset);
+        let snapshot = match continues {
+            true => None,
+            false => {
+                // The writes in the snapshot must be in the stream before its
+                // offset, too
+                let (unlogged, snapshot) = {
+                    let _access = db.exclusive_access();
+                    (db.take_unlogged(), db.snapshot())
+                };
+                if let Err(e) = guard.append_frames(unlogged).await {
+                    error!("Failed to append to AOF: {:?}", e);
+                }
+                Some(snapshot)
+            }
+        };
+        let (replid, offset) = {
+            let backlog = self.backlog.lock().unwrap();
+            match continues {
+                true => (backlog.replid.clone(), psync.offset as u64 - 1),
+                false => (backlog.replid.clone(), backlog.offset),
+            }
+        };
+        drop(guard);
+
+        match snapshot {
+            None => {
+                self.sync_partial_ok.fetch_add(1, Ordering::Relaxed);
+                info!("Partial resynchronization of replica {}:{} from offset {}", link.ip, link.port, offset);
+                connection.write_frame(&Frame::Simple(format!("CONTINUE {}", replid))).await?;
+            }
+            Some(snapshot) => {
+                if psync.replid != "?" {
+                    self.sync_partial_err.fetch_add(1, Ordering::Relaxed);
+                }
+                self.sync_full.fetch_add(1, Ordering::Relaxed);
+                info!("Full resynchronization of replica {}:{} with {} keys", link.ip, link.port, snapshot.len());
+                connection.write_frame(&Frame::Simple(format!("FULLRESYNC {} {}", replid, offset))).await?;
+                let encoded = tokio::task::spawn_blocking(move || {
+                    let mut encoded = Vec::new();
+                    rdb::encode(&mut encoded, &snapshot).map(|_| encoded)
+                })
+                .await??;
+                connection.write_frame(&Frame::Bulk(Bytes::from(encoded))).await?;
+            }
+        }
+
+        let _registered = Registered::new(self, id, link);
+        let mut sent = offset;
+        loop {
+            let pending = {
+                let backlog = self.backlog.lock().unwrap();
+                if backlog.replid != replid {
+                    // The stream was replaced; the replica has to ask again
+                    return Ok(());
+                }
+                backlog.read(sent)
+            };
+            let Some(pending) = pending else {
+                anyhow::bail!("replica fell behind the replication backlog");
+            };
+            if !pending.is_empty() {
+                connection.write_bytes(&pending).await?;
+                sent += pending.len() as u64;
+            }
+
+            tokio::select! {
+                _ = changed.changed() => {}
+                frame = connection.read_frame() => match frame? {
+                    Some(frame) => self.acknowledge(id, frame),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// Records a `REPLCONF ACK <offset>` from the replica `id`. Anything else
+    /// it sends is ignored.
+    fn acknowledge(&self, id: u64, frame: Frame) {
+        let Ok(Command::ReplConf(cmd)) = Command::from_frame(frame) else { return };
+        let Some(Ok(offset)) = cmd.get("ack").map(str::parse) else { return };
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&id) {
+            replica.offset = offset;
+            replica.last_ack = Instant::now();
+        }
+    }
+
+    /// Makes this node a replica of `master`, or a master again with `None`.
+    /// Returns `false` if it already replicates `master`.
+    pub(crate) async fn replicate(self: &Arc<Self>, master: Option<(String, u16)>, db: &Db, aof: &Arc<tokio::sync::Mutex<Aof>>) -> bool {
+        let mut follower = self.follower.lock().await;
+        let current = self.master.lock().unwrap().as_ref().map(|link| (link.host.clone(), link.port));
+        if master.is_some() && current == master {
+            return false;
+        }
+
+        if let Some(follower) = follower.take() {
+            follower.stop.notify_one();
+            let _ = follower.task.await;
+        }
+        *self.master.lock().unwrap() = None;
+
+        match master {
+            None if current.is_some() => {
+                // Replicas of the former master can continue from this one
+                let _guard = aof.lock().await;
+                self.backlog.lock().unwrap().shift_replid(new_replid());
+                self.changed.send_replace(());
+                info!("Promoted to master");
+            }
+            None => {}
+            Some((host, port)) => {
+                info!("Replicating {}:{}", host, port);
+                *self.master.lock().unwrap() = Some(MasterLink { host: host.clone(), port, up: false, sync_in_progress: false, last_io: None });
+                let stop = Arc::new(Notify::new());
+                let task = tokio::spawn(self.clone().follow(host, port, db.clone(), aof.clone(), stop.clone()));
+                *follower = Some(Follower { stop, task });
+            }
+        }
+        true
+    }
+
+    /// Stops following the master, if there is one, without becoming a
+    /// master.
+    pub(crate) async fn stop(&self) {
+        if let Some(follower) = self.follower.lock().await.take() {
+            follower.stop.notify_one();
+            let _ = follower.task.await;
+        }
+    }
+
+    /// Keeps replicating `host:port` until `stop` is notified, reconnecting
+    /// whenever the link breaks.
+    async fn follow(self: Arc<Self>, host: String, port: u16, db: Db, aof: Arc<tokio::sync::Mutex<Aof>>, stop: Arc<Notify>) {
+        loop {
+            match self.sync_with_master(&host, port, &db, &aof, &stop).await {
+                Ok(()) => return,
+                Err(e) => warn!("Replication link with {}:{} broke: {}", host, port, e),
+            }
+            self.update_link(|link| {
+                link.up = false;
+                link.sync_in_progress = false;
+            });
+
+            tokio::select! {
+                _ = stop.notified() => return,
+                _ = time::sleep(RETRY_INTERVAL) => {}
+            }
+        }
+    }
+
+    fn update_link(&self, f: impl FnOnce(&mut MasterLink)) {
+        if let Some(link) = self.master.lock().unwrap().as_mut() {
+            f(link);
+        }
+    }
+
+    /// Connects to the master, synchronizes with it and applies what it
+    /// streams. Returns `Ok` once `stop` is notified, and an error when the
+    /// link breaks.
+    async fn sync_with_master(&self, host: &str, port: u16, db: &Db, aof: &Arc<tokio::sync::Mutex<Aof>>, stop: &Notify) -> anyhow::Result<()> {
+        let socket = tokio::select! {
+            _ = stop.notified() => return Ok(()),
+            socket = time::timeout(TIMEOUT, TcpStream::connect((host, port))) => socket??,
+        };
+        let mut link = MasterConnection { connection: Connection::new(socket), stop, last_io: Instant::now() };
+
+        let auth = self.master_auth.lock().unwrap().clone();
+        if let Some((user, password)) = auth {
+            let mut args = vec!["AUTH"];
+            args.extend(user.as_deref());
+            args.push(&password);
+            if link.request(&args).await?.is_none() {
+                return Ok(());
+            }
+        }
+        let listening_port = self.listening_port.to_string();
+        for args in [&["PING"][..], &["REPLCONF", "listening-port", &listening_port]] {
+            if link.request(args).await?.is_none() {
+                return Ok(());
+            }
+        }
+
+        let (replid, offset) = {
+            let backlog = self.backlog.lock().unwrap();
+            (backlog.replid.clone(), backlog.offset)
+        };
+        let Some(reply) = link.request(&["PSYNC", &replid, &(offset + 1).to_string()]).await? else {
+            return Ok(());
+        };
+        let reply = match reply {
+            Frame::Simple(reply) => reply,
+            frame => anyhow::bail!("unexpected reply to PSYNC: {:?}", frame),
+        };
+        let parts: Vec<&str> = reply.split(' ').collect();
+        match parts[..] {
+            ["FULLRESYNC", replid, offset] => {
+                let offset: u64 = offset.parse()?;
+                self.update_link(|link| link.sync_in_progress = true);
+                let Some(frame) = link.read().await? else { return Ok(()) };
+                let Frame::Bulk(payload) = frame else {
+                    anyhow::bail!("expected the master's dataset, got {:?}", frame);
+                };
+                self.load_dataset(payload, replid.to_string(), offset, db, aof).await?;
+            }
+            ["CONTINUE", new_replid] => {
+                let _guard = aof.lock().await;
+                let mut backlog = self.backlog.lock().unwrap();
+                if backlog.replid != new_replid {
+                    // The master was promoted and started a new stream
+                    backlog.shift_replid(new_replid.to_string());
+                    drop(backlog);
+                    self.changed.send_replace(());
+                }
+                info!("Partial resynchronization with {}:{} from offset {}", host, port, offset);
+            }
+            ["CONTINUE"] => info!("Partial resynchronization with {}:{} from offset {}", host, port, offset),
+            _ => anyhow::bail!("unexpected reply to PSYNC: {}", reply),
+        }
+        self.update_link(|link| {
+            link.up = true;
+            link.sync_in_progress = false;
+            link.last_io = Some(Instant::now());
+        });
+
+        let mut ack = time::interval(ACK_INTERVAL);
+        // A `MULTI` block received from the master, applied once its `EXEC`
+        // arrives
+        let mut block: Option<Vec<(Frame, Command)>> = None;
+        loop {
+            let frame = tokio::select! {
+                _ = ack.tick() => {
+                    let offset = self.backlog.lock().unwrap().offset;
+                    link.connection.write_frame(&bulks(&["REPLCONF", "ACK", &offset.to_string()])).await?;
+                    continue;
+                }
+                frame = link.read() => frame?,
+            };
+            let Some(frame) = frame else { return Ok(()) };
+            self.update_link(|link| link.last_io = Some(Instant::now()));
+            self.apply(frame, &mut block, db, aof).await?;
+        }
+    }
+
+    /// Replaces the dataset with the snapshot the master sent, which is its
+    /// stream `replid` as of `offset`.
+    async fn load_dataset(&self, payload: Bytes, replid: String, offset: u64, db: &Db, aof: &Arc<tokio::sync::Mutex<Aof>>) -> anyhow::Result<()> {
+        let entries = tokio::task::spawn_blocking(move || {
+            let mut entries = vec![];
+            rdb::decode(&payload[..], |key, entry| entries.push((key, entry))).map(|_| entries)
+        })
+        .await??;
+        info!("Full resynchronization from offset {} with {} keys", offset, entries.len());
+
+        let mut guard = aof.lock().await;
+        // Logged first, as restoring consumes the entries
+        if let Err(e) = guard.log_dataset(&entries).await {
+            error!("Failed to append to AOF: {:?}", e);
+        }
+        {
+            let _access = db.exclusive_access();
+            db.flush();
+            for (key, entry) in entries {
+                db.restore(key, entry);
+            }
+        }
+        self.backlog.lock().unwrap().reset(replid, offset);
+        self.changed.send_replace(());
+        Ok(())
+    }
+
+    /// Applies a frame streamed by the master and feeds it to this node's own
+    /// stream. The commands of a `MULTI` block are collected in `block` and
+    /// applied together.
+    async fn apply(&self, frame: Frame, block: &mut Option<Vec<(Frame, Command)>>, db: &Db, aof: &tokio::sync::Mutex<Aof>) -> anyhow::Result<()> {
+        let command = Command::from_frame(frame.clone())?;
+        match (command, block.as_mut()) {
+            (Command::Multi(multi), None) => *block = Some(vec![(frame, Command::Multi(multi))]),
+            (Command::Exec(_), Some(_)) => {
+                let mut frames = vec![];
+                let mut guard = aof.lock().await;
+                {
+                    let _access = db.exclusive_access();
+                    for (frame, command) in block.take().unwrap_or_default() {
+                        if command.is_write() {
+                            apply_write(db, command);
+                        }
+                        frames.push(frame);
+                    }
+                }
+                frames.push(frame);
+                // The frames as received, so the offset matches the master's
+                if let Err(e) = guard.append_frames(frames).await {
+                    error!("Failed to append to AOF: {:?}", e);
+                }
+            }
+            (command, Some(commands)) => commands.push((frame, command)),
+            (command, None) if command.is_write() => {
+                let mut guard = aof.lock().await;
+                {
+                    let _access = db.shared_access();
+                    apply_write(db, command);
+                }
+                if let Err(e) = guard.append_frames(vec![frame]).await {
+                    error!("Failed to append to AOF: {:?}", e);
+                }
+            }
+            // Pings only go to the replicas of this node
+            (_, None) => {
+                let _guard = aof.lock().await;
+                self.feed_frames(&[frame]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies a write streamed by the master, marking its keys as modified.
+fn apply_write(db: &Db, command: Command) {
+    let written = command.clone();
+    if !matches!(command.apply(db), Frame::Error(_)) {
+        db.touch(&written.keys());
+    }
+}
+
+/// The connection of a replica to its master.
+struct MasterConnection<'a> {
+    connection: Connection,
+    stop: &'a Notify,
+    last_io: Instant,
+}
+
+impl MasterConnection<'_> {
+    /// Reads the next frame. Returns `None` once `stop` is notified, and an
+    /// error if the master closed the connection or stayed silent for
+    /// `TIMEOUT`.
+    async fn read(&mut self) -> anyhow::Result<Option<Frame>> {
+        tokio::select! {
+            _ = self.stop.notified() => Ok(None),
+            _ = time::sleep_until(self.last_io + TIMEOUT) => anyhow::bail!("timeout talking to the master"),
+            frame = self.connection.read_frame() => match frame? {
+                Some(frame) => {
+                    self.last_io = Instant::now();
+                    Ok(Some(frame))
+                }
+                None => anyhow::bail!("the master closed the connection"),
+            },
+        }
+    }
+
+    /// Sends a command of the handshake and reads the reply, which must not
+    /// be an error.
+    async fn request(&mut self, args: &[&str]) -> anyhow::Result<Option<Frame>> {
+        self.connection.write_frame(&bulks(args)).await?;
+        match self.read().await? {
+            Some(Frame::Error(e)) => anyhow::bail!("{} failed: {}", args[0], e),
+            reply => Ok(reply),
+        }
+    }
+}
+
+/// Lists a replica in `INFO` while it is being served.
+struct Registered<'a> {
+    replication: &'a Replication,
+    id: u64,
+}
+
+impl<'a> Registered<'a> {
+    fn new(replication: &'a Replication, id: u64, link: ReplicaLink) -> Registered<'a> {
+        replication.replicas.lock().unwrap().insert(id, link);
+        Registered { replication, id }
+    }
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        self.replication.replicas.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn bulks(args: &[&str]) -> Frame {
+    Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect())
+}
+
+fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
@@ -0,0 +1,1048 @@
+//! Sentinel: a separate mode of the server that monitors masters and their
+//! replicas, and promotes a replica when a master fails.
+//!
+//! Sentinels find each other through the nodes they monitor: every second each
+//! one publishes a hello message to `__sentinel__:hello` on the master and its
+//! replicas, and reads the hello messages of the others there.
+//!
+//! A master that doesn't answer `PING` for `down-after-milliseconds` is
+//! subjectively down (SDOWN) for a sentinel. Once at least `quorum` sentinels
+//! say so, as asked with `SENTINEL is-master-down-by-addr`, it is objectively
+//! down (ODOWN) and a failover starts: the sentinel bumps the epoch and asks
+//! the others to vote for it, each voting once per epoch for the first that
+//! asks. With a majority of the votes it promotes the replica with the most
+//! data using `REPLICAOF NO ONE`, points the other replicas at it, and
+//! publishes the new configuration. Its epoch being the highest, the other
+//! sentinels adopt it. A former master that comes back is made a replica of
+//! the new one.
+//!
+//! Clients ask any sentinel for the current master with
+//! `SENTINEL get-master-addr-by-name`.
+//!
+//! The configuration file uses Redis' directives:
+//!
+//! ```text
+//! port 26379
+//! sentinel monitor mymaster 127.0.0.1 6379 2
+//! sentinel down-after-milliseconds mymaster 30000
+//! sentinel failover-timeout mymaster 180000
+//! sentinel auth-pass mymaster secret
+//! ```
+
+use crate::cmd::ping::Ping;
+use crate::cmd::sentinel::SentinelCommand;
+use crate::cmd::subscribe::Subscribe;
+use crate::cmd::Parse;
+use crate::replication::new_replid;
+use crate::{codec, evict, Client, Connection, Error, Frame};
+use anyhow::Context;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{self, Instant};
+use tracing::{error, info, warn};
+
+/// The channel sentinels announce themselves and their configuration on.
+const HELLO_CHANNEL: &str = "__sentinel__:hello";
+
+/// How often the nodes are asked for `INFO`, and hello messages published.
+const INFO_PERIOD: Duration = Duration::from_secs(1);
+const HELLO_PERIOD: Duration = Duration::from_secs(1);
+
+/// How often the other sentinels are asked about a master in SDOWN, and for
+/// how long their answers count.
+const ASK_PERIOD: Duration = Duration::from_secs(1);
+const ASK_VALIDITY: Duration = Duration::from_secs(5);
+
+/// How often the state of each master is checked.
+const TICK: Duration = Duration::from_millis(100);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Upper bound of the random delay before asking for votes, so sentinels that
+/// see the master fail together don't split the votes between them.
+const MAX_DESYNC_MS: u64 = 1000;
+
+const MAX_ELECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a node must report the wrong role or master before it is
+/// reconfigured, which leaves time for the hello messages of a failover to
+/// reach every sentinel.
+const RECONFIGURE_DELAY: Duration = Duration::from_secs(4);
+
+/// A master group to monitor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorConfig {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// How many sentinels must agree the master is down to fail it over.
+    pub quorum: usize,
+    pub down_after: Duration,
+    pub failover_timeout: Duration,
+    /// The password of the master and its replicas, if they need one.
+    pub auth_pass: String,
+}
+
+impl MonitorConfig {
+    pub fn new(name: &str, host: &str, port: u16, quorum: usize) -> MonitorConfig {
+        MonitorConfig {
+            name: name.to_string(),
+            host: host.to_string(),
+            port,
+            quorum,
+            down_after: Duration::from_secs(30),
+            failover_timeout: Duration::from_secs(180),
+            auth_pass: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentinelConfig {
+    pub bind: String,
+    pub port: u16,
+    /// The address the other sentinels reach this one at; `bind` if empty.
+    pub announce_ip: String,
+    pub masters: Vec<MonitorConfig>,
+}
+
+impl Default for SentinelConfig {
+    fn default() -> SentinelConfig {
+        SentinelConfig {
+            bind: "127.0.0.1".to_string(),
+            port: 26379,
+            announce_ip: String::new(),
+            masters: vec![],
+        }
+    }
+}
+
+impl SentinelConfig {
+    /// Reads a sentinel configuration file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<SentinelConfig> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut config = SentinelConfig::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            config.apply(line).map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    /// The address the sentinel listens on.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    fn apply(&mut self, line: &str) -> Result<(), String> {
+        let args = codec::split_args(line.as_bytes()).map_err(|e| e.to_string())?;
+        let args = args
+            .into_iter()
+            .map(|arg| String::from_utf8(arg.to_vec()).map_err(|_| "invalid UTF-8".to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keyword = |i: usize| args[i].to_lowercase();
+
+        match args.len() {
+            2 if keyword(0) == "bind" => self.bind = args[1].clone(),
+            2 if keyword(0) == "port" => self.port = args[1].parse().map_err(|_| "invalid port".to_string())?,
+            3 if keyword(0) == "sentinel" && keyword(1) == "announce-ip" => self.announce_ip = args[2].clone(),
+            6 if keyword(0) == "sentinel" && keyword(1) == "monitor" => {
+                let port = args[4].parse().map_err(|_| "invalid port".to_string())?;
+                let quorum = args[5].parse().ok().filter(|quorum| *quorum > 0).ok_or("Quorum must be 1 or greater.")?;
+                if self.masters.iter().any(|master| master.name == args[2]) {
+                    return Err("Duplicated master name.".to_string());
+                }
+                self.masters.push(MonitorConfig::new(&args[2], &args[3], port, quorum));
+            }
+            4 if keyword(0) == "sentinel" => {
+                let master = self
+                    .masters
+                    .iter_mut()
+                    .find(|master| master.name == args[2])
+                    .ok_or("No such master with specified name.")?;
+                let millis = || args[3].parse().map(Duration::from_millis).map_err(|_| "invalid number of milliseconds".to_string());
+                match keyword(1).as_str() {
+                    "down-after-milliseconds" => master.down_after = millis()?,
+                    "failover-timeout" => master.failover_timeout = millis()?,
+                    "auth-pass" => master.auth_pass = args[3].clone(),
+                    option => return Err(format!("unknown sentinel option '{}'", option)),
+                }
+            }
+            _ => return Err(format!("bad directive '{}'", line)),
+        }
+        Ok(())
+    }
+}
+
+/// Runs a sentinel until `shutdown` fires.
+pub async fn run(config: SentinelConfig, mut shutdown: broadcast::Receiver<()>) -> anyhow::Result<()> {
+    let addr = config.addr();
+    info!("Sentinel starting on {}", addr);
+    let listener = TcpListener::bind(&addr).await?;
+
+    let sentinel = Arc::new(Sentinel::new(&config));
+    info!("Sentinel ID is {}", sentinel.id);
+    let mut monitors = JoinSet::new();
+    for master in &config.masters {
+        info!("+monitor master {} {} {} quorum {}", master.name, master.host, master.port, master.quorum);
+        monitors.spawn(sentinel.clone().monitor(master.name.clone()));
+    }
+
+    loop {
+        tokio::select! {
+            res = listener.accept() => {
+                let (socket, _) = res?;
+                let sentinel = sentinel.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = sentinel.serve(socket).await {
+                        error!("Connection error: {:?}", e);
+                    }
+                });
+            }
+            _ = shutdown.recv() => {
+                info!("Sentinel at {} shutting down", addr);
+                sentinel.closing.send_replace(true);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+type Addr = (String, u16);
+
+/// What a node reports about itself in `INFO replication`.
+#[derive(Debug, Clone, Default)]
+struct NodeInfo {
+    is_master: bool,
+    /// The master it replicates, if it is a replica.
+    master: Option<Addr>,
+    link_up: bool,
+    offset: u64,
+    /// The replicas it lists, if it is a master.
+    replicas: Vec<Addr>,
+}
+
+impl NodeInfo {
+    fn parse(info: &str) -> NodeInfo {
+        let mut node = NodeInfo::default();
+        let (mut host, mut port) = (None, None);
+        for line in info.lines() {
+            let Some((field, value)) = line.trim_end().split_once(':') else {
+                continue;
+            };
+            match field {
+                "role" => node.is_master = value == "master",
+                "master_host" => host = Some(value.to_string()),
+                "master_port" => port = value.parse().ok(),
+                "master_link_status" => node.link_up = value == "up",
+                "slave_repl_offset" => node.offset = value.parse().unwrap_or(0),
+                // slave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=0
+                field if field.strip_prefix("slave").is_some_and(|n| n.parse::<u32>().is_ok()) => {
+                    let (mut ip, mut port) = (None, None);
+                    for pair in value.split(',') {
+                        match pair.split_once('=') {
+                            Some(("ip", value)) => ip = Some(value.to_string()),
+                            Some(("port", value)) => port = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                    if let Some(replica) = ip.zip(port) {
+                        node.replicas.push(replica);
+                    }
+                }
+                _ => {}
+            }
+        }
+        node.master = host.zip(port);
+        node
+    }
+}
+
+/// A master or replica, as seen by this sentinel.
+struct Node {
+    /// When it last answered `PING`.
+    last_pong: Instant,
+    info: Option<NodeInfo>,
+    /// Since when it reports a role or master that doesn't match the
+    /// configuration.
+    misconfigured_since: Option<Instant>,
+}
+
+impl Node {
+    fn new() -> Node {
+        Node { last_pong: Instant::now(), info: None, misconfigured_since: None }
+    }
+
+    fn is_down(&self, down_after: Duration) -> bool {
+        self.last_pong.elapsed() > down_after
+    }
+}
+
+/// Another sentinel monitoring the same master.
+struct Peer {
+    ip: String,
+    port: u16,
+    last_hello: Instant,
+    /// When it last said the master is down, unless it said otherwise since.
+    master_down: Option<Instant>,
+    /// The sentinel it voted for to lead a failover, and in which epoch.
+    leader: Option<(String, u64)>,
+}
+
+/// A monitored master group.
+struct Master {
+    config: MonitorConfig,
+    addr: Addr,
+    /// The epoch of the failover that made `addr` the master.
+    config_epoch: u64,
+    /// Bumped when the master changes, which stops the tasks watching the
+    /// nodes of the former configuration.
+    generation: u64,
+    node: Node,
+    replicas: BTreeMap<Addr, Node>,
+    /// The other sentinels, by id.
+    sentinels: BTreeMap<String, Peer>,
+    sdown: bool,
+    odown: bool,
+    last_ask: Option<Instant>,
+    /// The sentinel this one voted for to lead a failover, and in which epoch.
+    leader: Option<(String, u64)>,
+    /// When this sentinel last started a failover or voted for another to
+    /// lead one. No new failover starts for twice `failover-timeout`.
+    failover_start: Option<Instant>,
+    failover_in_progress: bool,
+}
+
+impl Master {
+    fn new(config: &MonitorConfig) -> Master {
+        Master {
+            config: config.clone(),
+            addr: (config.host.clone(), config.port),
+            config_epoch: 0,
+            generation: 0,
+            node: Node::new(),
+            replicas: BTreeMap::new(),
+            sentinels: BTreeMap::new(),
+            sdown: false,
+            odown: false,
+            last_ask: None,
+            leader: None,
+            failover_start: None,
+            failover_in_progress: false,
+        }
+    }
+
+    fn is_watched(&self, addr: &Addr, generation: u64) -> bool {
+        self.generation == generation && (self.addr == *addr || self.replicas.contains_key(addr))
+    }
+}
+
+struct State {
+    current_epoch: u64,
+    masters: BTreeMap<String, Master>,
+}
+
+struct Sentinel {
+    /// A random id, like a replication id.
+    id: String,
+    ip: String,
+    port: u16,
+    state: Mutex<State>,
+    /// Set on shutdown, to stop every task and connection.
+    closing: watch::Sender<bool>,
+}
+
+/// A hello message: `ip,port,runid,current_epoch,master_name,master_ip,
+/// master_port,master_config_epoch`.
+struct HelloMessage {
+    ip: String,
+    port: u16,
+    runid: String,
+    current_epoch: u64,
+    master_name: String,
+    master: Addr,
+    config_epoch: u64,
+}
+
+impl HelloMessage {
+    fn parse(text: &str) -> Option<HelloMessage> {
+        let parts: Vec<&str> = text.split(',').collect();
+        let [ip, port, runid, current_epoch, master_name, master_ip, master_port, config_epoch] = parts.as_slice() else {
+            return None;
+        };
+        Some(HelloMessage {
+            ip: ip.to_string(),
+            port: port.parse().ok()?,
+            runid: runid.to_string(),
+            current_epoch: current_epoch.parse().ok()?,
+            master_name: master_name.to_string(),
+            master: (master_ip.to_string(), master_port.parse().ok()?),
+            config_epoch: config_epoch.parse().ok()?,
+        })
+    }
+}
+
+/// Aborts a task when dropped.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A connection to a master or replica, and when it was last asked for what.
+struct Watcher {
+    name: String,
+    addr: Addr,
+    generation: u64,
+    auth: String,
+    client: Option<Client>,
+    last_info: Option<Instant>,
+    last_hello: Option<Instant>,
+}
+
+impl Sentinel {
+    fn new(config: &SentinelConfig) -> Sentinel {
+        let ip = if config.announce_ip.is_empty() { config.bind.clone() } else { config.announce_ip.clone() };
+        let masters = config.masters.iter().map(|master| (master.name.clone(), Master::new(master))).collect();
+        Sentinel {
+            id: new_replid(),
+            ip,
+            port: config.port,
+            state: Mutex::new(State { current_epoch: 0, masters }),
+            closing: watch::Sender::new(false),
+        }
+    }
+
+    /// Watches the master group `name` and fails it over when its master is
+    /// objectively down.
+    async fn monitor(self: Arc<Self>, name: String) {
+        if let Some(master) = self.state.lock().unwrap().masters.get(&name) {
+            self.watch_nodes(master);
+        }
+        let mut closing = self.closing.subscribe();
+        let mut interval = time::interval(TICK);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = closing.changed() => return,
+            }
+            let (ask, failover) = self.check_master(&name);
+            if ask {
+                let sentinel = self.clone();
+                let name = name.clone();
+                tokio::spawn(async move { sentinel.ask_sentinels(&name, None).await });
+            }
+            if failover {
+                tokio::spawn(self.clone().failover(name.clone()));
+            }
+        }
+    }
+
+    /// Updates the SDOWN and ODOWN state of the master. Returns whether to ask
+    /// the other sentinels about it, and whether to start a failover.
+    fn check_master(&self, name: &str) -> (bool, bool) {
+        let mut state = self.state.lock().unwrap();
+        let Some(master) = state.masters.get_mut(name) else {
+            return (false, false);
+        };
+        let (ip, port) = master.addr.clone();
+
+        let sdown = master.node.is_down(master.config.down_after);
+        if sdown != master.sdown {
+            info!("{}sdown master {} {} {}", if sdown { "+" } else { "-" }, name, ip, port);
+            master.sdown = sdown;
+        }
+
+        let agreeing = 1 + master
+            .sentinels
+            .values()
+            .filter(|peer| peer.master_down.is_some_and(|when| when.elapsed() < ASK_VALIDITY))
+            .count();
+        let odown = sdown && agreeing >= master.config.quorum;
+        if odown != master.odown {
+            match odown {
+                true => info!("+odown master {} {} {} #quorum {}/{}", name, ip, port, agreeing, master.config.quorum),
+                false => info!("-odown master {} {} {}", name, ip, port),
+            }
+            master.odown = odown;
+        }
+
+        let ask = sdown && !master.sentinels.is_empty() && master.last_ask.is_none_or(|when| when.elapsed() >= ASK_PERIOD);
+        if ask {
+            master.last_ask = Some(Instant::now());
+        }
+        let failover = odown
+            && !master.failover_in_progress
+            && master.failover_start.is_none_or(|when| when.elapsed() > master.config.failover_timeout * 2);
+        if failover {
+            master.failover_in_progress = true;
+            master.failover_start = Some(Instant::now());
+        }
+        (ask, failover)
+    }
+
+    /// Starts watching the master and replicas of `master`.
+    fn watch_nodes(self: &Arc<Self>, master: &Master) {
+        for addr in std::iter::once(&master.addr).chain(master.replicas.keys()) {
+            self.watch_node(master, addr.clone());
+        }
+    }
+
+    fn watch_node(self: &Arc<Self>, master: &Master, addr: Addr) {
+        let watcher = Watcher {
+            name: master.config.name.clone(),
+            addr,
+            generation: master.generation,
+            auth: master.config.auth_pass.clone(),
+            client: None,
+            last_info: None,
+            last_hello: None,
+        };
+        // Pinged often enough that one slow reply doesn't look like SDOWN
+        let period = master.config.down_after.min(Duration::from_secs(1)) / 2;
+        tokio::spawn(self.clone().watch(watcher, period));
+    }
+
+    /// Pings a node every `period`, reads its `INFO` and publishes hello
+    /// messages to it, for as long as it belongs to the configuration the
+    /// watcher was started for.
+    async fn watch(self: Arc<Self>, mut watcher: Watcher, period: Duration) {
+        let listener = tokio::spawn(self.clone().listen_hello(watcher.addr.clone(), watcher.auth.clone()));
+        let _listener = AbortOnDrop(listener);
+        let mut closing = self.closing.subscribe();
+        let mut interval = time::interval(period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = closing.changed() => return,
+            }
+            let watched = {
+                let state = self.state.lock().unwrap();
+                let master = state.masters.get(&watcher.name);
+                master.is_some_and(|master| master.is_watched(&watcher.addr, watcher.generation))
+            };
+            if !watched {
+                return;
+            }
+            // A failed check drops the connection, and the next one reconnects
+            let _ = self.check_node(&mut watcher).await;
+        }
+    }
+
+    async fn check_node(self: &Arc<Self>, watcher: &mut Watcher) -> Result<(), Error> {
+        let mut client = match watcher.client.take() {
+            Some(client) => client,
+            None => connect(&watcher.addr, &watcher.auth).await?,
+        };
+
+        timed(client.ping(None)).await?;
+        self.record_pong(watcher);
+
+        if watcher.last_info.is_none_or(|when| when.elapsed() >= INFO_PERIOD) {
+            watcher.last_info = Some(Instant::now());
+            let info = NodeInfo::parse(&timed(client.info(Some("replication"))).await?);
+            if let Some((host, port)) = self.record_info(watcher, info) {
+                timed(client.replicaof(Some((&host, port)))).await?;
+            }
+        }
+
+        if watcher.last_hello.is_none_or(|when| when.elapsed() >= HELLO_PERIOD) {
+            watcher.last_hello = Some(Instant::now());
+            if let Some(hello) = self.hello(&watcher.name) {
+                timed(client.publish(HELLO_CHANNEL, Bytes::from(hello))).await?;
+            }
+        }
+        watcher.client = Some(client);
+        Ok(())
+    }
+
+    fn record_pong(&self, watcher: &Watcher) {
+        let mut state = self.state.lock().unwrap();
+        let Some(master) = state.masters.get_mut(&watcher.name).filter(|master| master.generation == watcher.generation) else {
+            return;
+        };
+        if master.addr == watcher.addr {
+            master.node.last_pong = Instant::now();
+        } else if let Some(replica) = master.replicas.get_mut(&watcher.addr) {
+            replica.last_pong = Instant::now();
+        }
+    }
+
+    /// Records what a node reported, and starts watching the replicas a master
+    /// lists. Returns the master a node must be pointed at if it reports the
+    /// wrong one.
+    fn record_info(self: &Arc<Self>, watcher: &Watcher, info: NodeInfo) -> Option<Addr> {
+        let mut state = self.state.lock().unwrap();
+        let master = state.masters.get_mut(&watcher.name).filter(|master| master.generation == watcher.generation)?;
+
+        if master.addr == watcher.addr {
+            for replica in &info.replicas {
+                if !master.replicas.contains_key(replica) {
+                    info!("+slave slave {}:{} @ {} {} {}", replica.0, replica.1, master.config.name, master.addr.0, master.addr.1);
+                    master.replicas.insert(replica.clone(), Node::new());
+                    self.watch_node(master, replica.clone());
+                }
+            }
+            master.node.info = Some(info);
+            return None;
+        }
+
+        // While the master is failing, the replicas are about to be
+        // reconfigured anyway
+        let failing = master.sdown || master.failover_in_progress;
+        let wanted = master.addr.clone();
+        let replica = master.replicas.get_mut(&watcher.addr)?;
+        let misconfigured = info.is_master || info.master.as_ref() != Some(&wanted);
+        replica.info = Some(info);
+        if !misconfigured {
+            replica.misconfigured_since = None;
+            return None;
+        }
+        let since = *replica.misconfigured_since.get_or_insert_with(Instant::now);
+        if failing || since.elapsed() < RECONFIGURE_DELAY {
+            return None;
+        }
+        replica.misconfigured_since = None;
+        info!("+fix-slave-config slave {}:{} @ {} {} {}", watcher.addr.0, watcher.addr.1, watcher.name, wanted.0, wanted.1);
+        Some(wanted)
+    }
+
+    /// The hello message announcing this sentinel and its configuration of
+    /// the master group `name`.
+    fn hello(&self, name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let master = state.masters.get(name)?;
+        Some(format!(
+            "{},{},{},{},{},{},{},{}",
+            self.ip, self.port, self.id, state.current_epoch, name, master.addr.0, master.addr.1, master.config_epoch
+        ))
+    }
+
+    /// Reads the hello messages published on the node at `addr`, reconnecting
+    /// when the connection drops. Runs until aborted.
+    async fn listen_hello(self: Arc<Self>, addr: Addr, auth: String) {
+        loop {
+            let _ = self.read_hellos(&addr, &auth).await;
+            time::sleep(HELLO_PERIOD).await;
+        }
+    }
+
+    async fn read_hellos(self: &Arc<Self>, addr: &Addr, auth: &str) -> Result<(), Error> {
+        let mut connection = Connection::new(timed(async { Ok(TcpStream::connect((addr.0.as_str(), addr.1)).await?) }).await?);
+        if !auth.is_empty() {
+            let frame = Frame::Array(vec![Frame::Bulk(Bytes::from("AUTH")), Frame::Bulk(Bytes::from(auth.to_string()))]);
+            connection.write_frame(&frame).await?;
+            if let Some(Frame::Error(msg)) = connection.read_frame().await? {
+                return Err(Error::Other(msg));
+            }
+        }
+        let subscribe = Subscribe { channels: vec![HELLO_CHANNEL.to_string()], pattern: false };
+        connection.write_frame(&subscribe.into_frame()).await?;
+
+        while let Some(frame) = connection.read_frame().await? {
+            if let Frame::Array(items) | Frame::Push(items) = frame {
+                if let [Frame::Bulk(kind), _, Frame::Bulk(payload)] = items.as_slice() {
+                    if kind.as_ref() == b"message" {
+                        self.receive_hello(payload);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers the sentinel that sent a hello message, and adopts its
+    /// configuration if it is newer.
+    fn receive_hello(self: &Arc<Self>, payload: &[u8]) {
+        let Some(hello) = std::str::from_utf8(payload).ok().and_then(HelloMessage::parse) else {
+            return;
+        };
+        if hello.runid == self.id {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if hello.current_epoch > state.current_epoch {
+            state.current_epoch = hello.current_epoch;
+            info!("+new-epoch {}", hello.current_epoch);
+        }
+        let Some(master) = state.masters.get_mut(&hello.master_name) else {
+            return;
+        };
+
+        // A sentinel that restarted comes back with another id
+        master.sentinels.retain(|id, peer| *id == hello.runid || (peer.ip.as_str(), peer.port) != (hello.ip.as_str(), hello.port));
+        let peer = master.sentinels.entry(hello.runid.clone()).or_insert_with(|| {
+            info!("+sentinel sentinel {} {} {} @ {}", hello.runid, hello.ip, hello.port, hello.master_name);
+            Peer { ip: hello.ip.clone(), port: hello.port, last_hello: Instant::now(), master_down: None, leader: None }
+        });
+        peer.last_hello = Instant::now();
+
+        if hello.config_epoch > master.config_epoch {
+            master.config_epoch = hello.config_epoch;
+            if hello.master != master.addr {
+                self.switch_master(master, hello.master);
+            }
+        }
+    }
+
+    /// Makes `addr` the master of the group. The former master is kept as a
+    /// replica, so it is reconfigured if it comes back.
+    fn switch_master(self: &Arc<Self>, master: &mut Master, addr: Addr) {
+        info!("+switch-master {} {} {} {} {}", master.config.name, master.addr.0, master.addr.1, addr.0, addr.1);
+        let former = std::mem::replace(&mut master.addr, addr);
+        master.replicas.remove(&master.addr);
+        master.replicas.insert(former, Node::new());
+        for replica in master.replicas.values_mut() {
+            *replica = Node::new();
+        }
+        master.node = Node::new();
+        master.sdown = false;
+        master.odown = false;
+        for peer in master.sentinels.values_mut() {
+            peer.master_down = None;
+        }
+        master.generation += 1;
+        self.watch_nodes(master);
+    }
+
+    /// Asks every other sentinel whether the master of `name` is down and,
+    /// given an epoch, for its vote to lead the failover of that epoch.
+    async fn ask_sentinels(self: &Arc<Self>, name: &str, epoch: Option<u64>) {
+        let requests: Vec<(String, String, Frame)> = {
+            let state = self.state.lock().unwrap();
+            let Some(master) = state.masters.get(name) else {
+                return;
+            };
+            let command = SentinelCommand::IsMasterDownByAddr {
+                ip: master.addr.0.clone(),
+                port: master.addr.1,
+                epoch: epoch.unwrap_or(state.current_epoch),
+                runid: if epoch.is_some() { self.id.clone() } else { "*".to_string() },
+            };
+            master
+                .sentinels
+                .iter()
+                .map(|(id, peer)| (id.clone(), format!("{}:{}", peer.ip, peer.port), command.clone().into_frame()))
+                .collect()
+        };
+
+        let mut requests: JoinSet<_> = requests
+            .into_iter()
+            .map(|(id, addr, frame)| async move { (id, timed(request(&addr, frame)).await) })
+            .collect();
+        while let Some(Ok((id, reply))) = requests.join_next().await {
+            let reply = match reply {
+                Ok(Frame::Array(items)) => items,
+                _ => continue,
+            };
+            let [Frame::Integer(down), Frame::Bulk(leader), Frame::Integer(leader_epoch)] = reply.as_slice() else {
+                continue;
+            };
+            let mut state = self.state.lock().unwrap();
+            let Some(peer) = state.masters.get_mut(name).and_then(|master| master.sentinels.get_mut(&id)) else {
+                continue;
+            };
+            peer.master_down = (*down == 1).then(Instant::now);
+            if leader.as_ref() != b"*" {
+                peer.leader = Some((String::from_utf8_lossy(leader).into_owned(), *leader_epoch as u64));
+            }
+        }
+    }
+
+    /// Runs a failover of the master group `name`.
+    async fn failover(self: Arc<Self>, name: String) {
+        let result = self.try_failover(&name).await;
+        let mut state = self.state.lock().unwrap();
+        if let Some(master) = state.masters.get_mut(&name) {
+            master.failover_in_progress = false;
+            if let Err(reason) = result {
+                warn!("-failover-abort-{} master {} {} {}", reason, name, master.addr.0, master.addr.1);
+            }
+        }
+    }
+
+    /// The steps of a failover. Errors name the reason it was given up.
+    async fn try_failover(self: &Arc<Self>, name: &str) -> Result<(), &'static str> {
+        // Give a sentinel that noticed the failure first the time to ask for
+        // votes, which this one then grants instead of competing
+        let started = Instant::now();
+        time::sleep(Duration::from_millis(evict::random() % MAX_DESYNC_MS)).await;
+
+        let (epoch, generation, auth, election_timeout, failover_timeout) = {
+            let mut state = self.state.lock().unwrap();
+            let State { current_epoch, masters } = &mut *state;
+            let master = masters.get_mut(name).ok_or("removed")?;
+            if master.failover_start.is_some_and(|when| when > started) {
+                return Err("voted-for-other");
+            }
+            *current_epoch += 1;
+            info!("+new-epoch {}", current_epoch);
+            info!("+try-failover master {} {} {}", name, master.addr.0, master.addr.1);
+            master.leader = Some((self.id.clone(), *current_epoch));
+            let timeout = master.config.failover_timeout;
+            (*current_epoch, master.generation, master.config.auth_pass.clone(), timeout.min(MAX_ELECTION_TIMEOUT), timeout)
+        };
+
+        // A majority of the sentinels, and at least the quorum, must vote for
+        // this one
+        let deadline = Instant::now() + election_timeout;
+        loop {
+            self.ask_sentinels(name, Some(epoch)).await;
+            {
+                let state = self.state.lock().unwrap();
+                let master = state.masters.get(name).filter(|master| master.generation == generation).ok_or("not-elected")?;
+                let voted = |peer: &&Peer| peer.leader.as_ref().is_some_and(|(leader, e)| *leader == self.id && *e == epoch);
+                let votes = 1 + master.sentinels.values().filter(voted).count();
+                let sentinels = master.sentinels.len() + 1;
+                let needed = master.config.quorum.max(sentinels / 2 + 1);
+                if votes >= needed {
+                    info!("+elected-leader master {} {} {} with {} votes", name, master.addr.0, master.addr.1, votes);
+                    break;
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err("not-elected");
+            }
+            time::sleep(TICK).await;
+        }
+
+        // The replica with the most data among those that are alive
+        let promoted = {
+            let state = self.state.lock().unwrap();
+            let master = state.masters.get(name).filter(|master| master.generation == generation).ok_or("not-elected")?;
+            master
+                .replicas
+                .iter()
+                .filter(|(_, node)| !node.is_down(master.config.down_after))
+                .filter_map(|(addr, node)| Some((addr, node.info.as_ref().filter(|info| !info.is_master)?.offset)))
+                // Ties go to the lowest address
+                .max_by(|(a, a_offset), (b, b_offset)| a_offset.cmp(b_offset).then(b.cmp(a)))
+                .map(|(addr, _)| addr.clone())
+                .ok_or("no-good-slave")?
+        };
+        info!("+selected-slave slave {}:{} @ {}", promoted.0, promoted.1, name);
+
+        let mut client = connect(&promoted, &auth).await.map_err(|_| "promotion-failed")?;
+        timed(client.replicaof(None)).await.map_err(|_| "promotion-failed")?;
+        let deadline = Instant::now() + failover_timeout;
+        loop {
+            let info = timed(client.info(Some("replication"))).await.map_err(|_| "promotion-failed")?;
+            if NodeInfo::parse(&info).is_master {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err("promotion-failed");
+            }
+            time::sleep(TICK).await;
+        }
+        info!("+promoted-slave slave {}:{} @ {}", promoted.0, promoted.1, name);
+
+        // The new configuration is announced by the hello messages from here on
+        let replicas: Vec<Addr> = {
+            let mut state = self.state.lock().unwrap();
+            let master = state.masters.get_mut(name).filter(|master| master.generation == generation).ok_or("not-elected")?;
+            master.config_epoch = epoch;
+            self.switch_master(master, promoted.clone());
+            master.replicas.keys().cloned().collect()
+        };
+        for replica in replicas {
+            let reconfigure = async {
+                let mut client = connect(&replica, &auth).await?;
+                timed(client.replicaof(Some((&promoted.0, promoted.1)))).await
+            };
+            // The ones that can't be reached now are reconfigured when they
+            // are back
+            match reconfigure.await {
+                Ok(()) => info!("+slave-reconf-sent slave {}:{} @ {}", replica.0, replica.1, name),
+                Err(e) => warn!("Failed to reconfigure {}:{}: {}", replica.0, replica.1, e),
+            }
+        }
+        info!("+failover-end master {} {} {}", name, promoted.0, promoted.1);
+        Ok(())
+    }
+
+    /// Answers a client until it disconnects or the sentinel shuts down.
+    async fn serve(&self, socket: TcpStream) -> Result<(), Error> {
+        let mut connection = Connection::new(socket);
+        let mut closing = self.closing.subscribe();
+        loop {
+            let frame = tokio::select! {
+                frame = connection.read_frame() => frame?,
+                _ = closing.changed() => return Ok(()),
+            };
+            let Some(frame) = frame else {
+                return Ok(());
+            };
+            let reply = match self.execute(frame) {
+                Ok(reply) => reply,
+                Err(Error::Other(msg)) => Frame::Error(format!("ERR {}", msg)),
+                Err(e) => return Err(e),
+            };
+            connection.write_frame(&reply).await?;
+        }
+    }
+
+    /// Sentinels only know `PING` and `SENTINEL`.
+    fn execute(&self, frame: Frame) -> Result<Frame, Error> {
+        let mut parse = Parse::new(frame)?;
+        let name = parse.next_string()?.to_lowercase();
+        let reply = match name.as_str() {
+            "ping" => Ping::parse_frames(&mut parse)?.apply(),
+            "sentinel" => {
+                let command = SentinelCommand::parse_frames(&mut parse)?;
+                parse.finish()?;
+                return Ok(self.apply(command));
+            }
+            _ => return Ok(Frame::Error(format!("ERR unknown command '{}'", name))),
+        };
+        parse.finish()?;
+        Ok(reply)
+    }
+
+    fn apply(&self, command: SentinelCommand) -> Frame {
+        let mut state = self.state.lock().unwrap();
+        let no_such_master = || Frame::Error("ERR No such master with that name".to_string());
+        match command {
+            SentinelCommand::GetMasterAddrByName(name) => match state.masters.get(&name) {
+                Some(master) => Frame::Array(vec![bulk(&master.addr.0), bulk(&master.addr.1.to_string())]),
+                None => Frame::Null,
+            },
+            SentinelCommand::IsMasterDownByAddr { ip, port, epoch, runid } => {
+                let State { current_epoch, masters } = &mut *state;
+                let Some(master) = masters.values_mut().find(|master| master.addr == (ip.clone(), port)) else {
+                    return Frame::Array(vec![Frame::Integer(0), bulk("*"), Frame::Integer(0)]);
+                };
+                let down = master.sdown;
+                if runid == "*" {
+                    return Frame::Array(vec![Frame::Integer(down as i64), bulk("*"), Frame::Integer(0)]);
+                }
+
+                // One vote per epoch, for the first sentinel that asks
+                if epoch > *current_epoch {
+                    *current_epoch = epoch;
+                    info!("+new-epoch {}", epoch);
+                }
+                if master.leader.as_ref().is_none_or(|(_, e)| *e < epoch) && *current_epoch <= epoch {
+                    info!("+vote-for-leader {} {}", runid, epoch);
+                    if runid != self.id {
+                        master.failover_start = Some(Instant::now());
+                    }
+                    master.leader = Some((runid, epoch));
+                }
+                let (leader, leader_epoch) = master.leader.clone().unwrap_or_else(|| ("*".to_string(), 0));
+                Frame::Array(vec![Frame::Integer(down as i64), bulk(&leader), Frame::Integer(leader_epoch as i64)])
+            }
+            SentinelCommand::Masters => Frame::Array(state.masters.values().map(master_fields).collect()),
+            SentinelCommand::Master(name) => state.masters.get(&name).map(master_fields).unwrap_or_else(no_such_master),
+            SentinelCommand::Replicas(name) => match state.masters.get(&name) {
+                Some(master) => Frame::Array(
+                    master.replicas.iter().map(|(addr, node)| replica_fields(addr, node, master.config.down_after)).collect(),
+                ),
+                None => no_such_master(),
+            },
+            SentinelCommand::Sentinels(name) => match state.masters.get(&name) {
+                Some(master) => Frame::Array(master.sentinels.iter().map(|(id, peer)| sentinel_fields(id, peer)).collect()),
+                None => no_such_master(),
+            },
+            SentinelCommand::MyId => bulk(&self.id),
+        }
+    }
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::from(s.to_string()))
+}
+
+fn fields(pairs: Vec<(&str, String)>) -> Frame {
+    Frame::Map(pairs.into_iter().map(|(field, value)| (bulk(field), bulk(&value))).collect())
+}
+
+fn master_fields(master: &Master) -> Frame {
+    let mut flags = vec!["master"];
+    if master.sdown {
+        flags.push("s_down");
+    }
+    if master.odown {
+        flags.push("o_down");
+    }
+    if master.failover_in_progress {
+        flags.push("failover_in_progress");
+    }
+    fields(vec![
+        ("name", master.config.name.clone()),
+        ("ip", master.addr.0.clone()),
+        ("port", master.addr.1.to_string()),
+        ("flags", flags.join(",")),
+        ("num-slaves", master.replicas.len().to_string()),
+        ("num-other-sentinels", master.sentinels.len().to_string()),
+        ("quorum", master.config.quorum.to_string()),
+        ("config-epoch", master.config_epoch.to_string()),
+        ("down-after-milliseconds", master.config.down_after.as_millis().to_string()),
+        ("failover-timeout", master.config.failover_timeout.as_millis().to_string()),
+    ])
+}
+
+fn replica_fields(addr: &Addr, node: &Node, down_after: Duration) -> Frame {
+    let info = node.info.clone().unwrap_or_default();
+    let (master_host, master_port) = info.master.clone().unwrap_or_default();
+    let role = if info.is_master { "master" } else { "slave" };
+    let flags = if node.is_down(down_after) { format!("{},s_down", role) } else { role.to_string() };
+    fields(vec![
+        ("name", format!("{}:{}", addr.0, addr.1)),
+        ("ip", addr.0.clone()),
+        ("port", addr.1.to_string()),
+        ("flags", flags),
+        ("master-link-status", if info.link_up { "ok" } else { "err" }.to_string()),
+        ("master-host", master_host),
+        ("master-port", master_port.to_string()),
+        ("slave-repl-offset", info.offset.to_string()),
+    ])
+}
+
+fn sentinel_fields(id: &str, peer: &Peer) -> Frame {
+    fields(vec![
+        ("name", id.to_string()),
+        ("ip", peer.ip.clone()),
+        ("port", peer.port.to_string()),
+        ("runid", id.to_string()),
+        ("last-hello-message", peer.last_hello.elapsed().as_millis().to_string()),
+    ])
+}
+
+/// Fails `future` if it takes longer than `REQUEST_TIMEOUT`.
+async fn timed<T>(future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    time::timeout(REQUEST_TIMEOUT, future).await.map_err(|_| Error::Io(io::ErrorKind::TimedOut.into()))?
+}
+
+/// Connects to a master or replica, authenticating with `auth` if not empty.
+async fn connect(addr: &Addr, auth: &str) -> Result<Client, Error> {
+    let mut client = timed(Client::connect(&format!("{}:{}", addr.0, addr.1))).await?;
+    if !auth.is_empty() {
+        timed(client.auth(None, auth)).await?;
+    }
+    Ok(client)
+}
+
+/// Sends one command to another sentinel and returns the reply.
+async fn request(addr: &str, frame: Frame) -> Result<Frame, Error> {
+    let mut connection = Connection::new(TcpStream::connect(addr).await?);
+    connection.write_frame(&frame).await?;
+    connection.read_frame().await?.ok_or_else(|| Error::Io(io::ErrorKind::ConnectionReset.into()))
+}
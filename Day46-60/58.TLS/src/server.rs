@@ -1,42 +1,164 @@
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 use tracing::{info, error};
-use crate::{Command, Db, Connection, Frame, Error, Aof};
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use crate::{Command, Config, Db, Connection, Frame, Error, Aof, Protocol, Rdb};
+use crate::db::{now_ms, DbError};
+use crate::cmd::keys::Del;
+use crate::cmd::list::BPop;
+use crate::cmd::stream::XRead;
+use crate::cmd::connection::{validate_name, Auth, Client, Hello};
+use crate::cmd::acl::AclCommand;
+use crate::acl::{self, Acl};
+use crate::cmd::config::ConfigCommand;
+use crate::cmd::info::{Info, ServerInfo};
+use crate::cmd::replication::ReplicaOf;
+use crate::replication::{ReplicaLink, Replication};
+use crate::pubsub::{Delivery, Subscriptions};
+use anyhow::Context;
+use bytes::Bytes;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{broadcast, watch, Notify};
+use tokio::time::{self, Instant};
+use tokio_rustls::rustls::ServerConfig;
 use tokio_rustls::TlsAcceptor;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 
 // Generic trait for socket that works with Connection
 pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
 impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> AsyncStream for T {}
 
-pub async fn run(addr: &str, aof_path: &str, mut shutdown: broadcast::Receiver<()>, tls_acceptor: Option<TlsAcceptor>) -> anyhow::Result<()> {
+/// Ids handed out to connections, as reported by `HELLO` and `CLIENT ID`.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How long a subscriber being disconnected for lagging gets to take the
+/// last report.
+const LAG_REPORT_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Server state shared by every connection, besides the keyspace and the
+/// persistence files.
+struct State {
+    config: RwLock<Config>,
+    acl: RwLock<Acl>,
+    started: std::time::Instant,
+    connected_clients: AtomicUsize,
+    total_connections_received: AtomicU64,
+    total_commands_processed: AtomicU64,
+    rejected_connections: AtomicU64,
+    /// How often subscribers fell behind, and how many messages they missed.
+    pubsub_lag_events: AtomicU64,
+    pubsub_dropped_messages: AtomicU64,
+    /// Subscribers disconnected for missing more than `pubsub-lag-hard-limit`
+    /// messages at once.
+    pubsub_lag_disconnections: AtomicU64,
+    replication: Arc<Replication>,
+    /// Set on shutdown, so every connection closes before its next request.
+    closing: watch::Sender<bool>,
+}
+
+/// Counts a connection as connected for as long as it is alive.
+struct ClientGuard(Arc<State>);
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs the server until `shutdown` fires. Connections use TLS if the
+/// configuration names a certificate and key.
+pub async fn run(config: Config, mut shutdown: broadcast::Receiver<()>) -> anyhow::Result<()> {
+    let addr = config.addr();
     info!("Mini-Redis Server starting on {}", addr);
+    let tls_acceptor = match config.tls() {
+        true => Some(tls_acceptor(&config.tls_cert_file, &config.tls_key_file)?),
+        false => None,
+    };
     if tls_acceptor.is_some() {
         info!("TLS enabled");
     }
 
-    let listener = TcpListener::bind(addr).await?;
     let db = Db::new();
+    db.start_purge_task();
+
+    let rdb = Arc::new(Rdb::new(&config.dbfilename));
+
+    // Rebuild the dataset before accepting any connections. The AOF is at least
+    // as recent as any snapshot, so the snapshot is only used without one.
+    let aof_path = &config.appendfilename;
+    let has_aof = tokio::fs::metadata(aof_path).await.map(|m| m.len() > 0).unwrap_or(false);
+    if has_aof {
+        Aof::load(aof_path, &db, true).await?;
+    } else {
+        rdb.load(&db).await?;
+    }
+
+    let listener = TcpListener::bind(&addr).await?;
 
     // Initialize AOF
     let aof = Arc::new(Mutex::new(Aof::new(aof_path).await?));
+    let replication = Arc::new(Replication::new(config.port, config.repl_backlog_size as usize));
+    aof.lock().await.set_replication(replication.clone());
+    apply_config(&config, &db, &aof, &replication).await;
+    Aof::start_fsync_task(&aof);
+    Replication::start_ping_task(&replication, &aof);
+    if let Some(master) = config.replicaof.clone() {
+        replication.replicate(Some(master), &db, &aof).await;
+    }
+
+    let mut acl = Acl::new();
+    acl.set_requirepass(&config.requirepass);
+    if !config.aclfile.is_empty() && Path::new(&config.aclfile).exists() {
+        acl = Acl::load(&config.aclfile)?;
+    }
+
+    let state = Arc::new(State {
+        config: RwLock::new(config),
+        acl: RwLock::new(acl),
+        started: std::time::Instant::now(),
+        connected_clients: AtomicUsize::new(0),
+        total_connections_received: AtomicU64::new(0),
+        total_commands_processed: AtomicU64::new(0),
+        rejected_connections: AtomicU64::new(0),
+        pubsub_lag_events: AtomicU64::new(0),
+        pubsub_dropped_messages: AtomicU64::new(0),
+        pubsub_lag_disconnections: AtomicU64::new(0),
+        replication,
+        closing: watch::Sender::new(false),
+    });
 
     loop {
         tokio::select! {
             res = listener.accept() => {
                  match res {
-                     Ok((socket, _)) => {
+                     Ok((mut socket, peer)) => {
+                        state.total_connections_received.fetch_add(1, Ordering::Relaxed);
+                        let maxclients = state.config.read().unwrap().maxclients;
+                        if state.connected_clients.fetch_add(1, Ordering::Relaxed) >= maxclients {
+                            state.connected_clients.fetch_sub(1, Ordering::Relaxed);
+                            state.rejected_connections.fetch_add(1, Ordering::Relaxed);
+                            let _ = socket.write_all(b"-ERR max number of clients reached\r\n").await;
+                            continue;
+                        }
+                        let client = ClientGuard(state.clone());
+
                         let db = db.clone();
                         let aof = aof.clone();
+                        let rdb = rdb.clone();
+                        let state = state.clone();
                         let tls_acceptor = tls_acceptor.clone();
 
                         tokio::spawn(async move {
+                            let _client = client;
                             if let Some(acceptor) = tls_acceptor {
                                 match acceptor.accept(socket).await {
                                     Ok(tls_stream) => {
-                                         if let Err(e) = process(tls_stream, db, aof).await {
+                                         if let Err(e) = process(tls_stream, peer, db, aof, rdb, state).await {
                                              error!("Connection error: {:?}", e);
                                          }
                                     }
@@ -45,7 +167,7 @@ pub async fn run(addr: &str, aof_path: &str, mut shutdown: broadcast::Receiver<(
                                     }
                                 }
                             } else {
-                                if let Err(e) = process(socket, db, aof).await {
+                                if let Err(e) = process(socket, peer, db, aof, rdb, state).await {
                                     error!("Connection error: {:?}", e);
                                 }
                             }
@@ -56,6 +178,12 @@ pub async fn run(addr: &str, aof_path: &str, mut shutdown: broadcast::Receiver<(
             }
             _ = shutdown.recv() => {
                 info!("Server at {} shutting down", addr);
+                state.closing.send_replace(true);
+                state.replication.stop().await;
+                append_unlogged(&aof, &db).await;
+                if let Err(e) = aof.lock().await.sync().await {
+                    error!("Failed to sync AOF on shutdown: {:?}", e);
+                }
                 break;
             }
         }
@@ -63,82 +191,839 @@ pub async fn run(addr: &str, aof_path: &str, mut shutdown: broadcast::Receiver<(
     Ok(())
 }
 
-async fn process<S>(socket: S, db: Db, aof: Arc<Mutex<Aof>>) -> Result<(), Error>
+/// Loads a PEM certificate chain and private key for serving TLS.
+fn tls_acceptor(cert_path: &str, key_path: &str) -> anyhow::Result<TlsAcceptor> {
+    let cert_file = &mut BufReader::new(File::open(cert_path).with_context(|| format!("failed to open {}", cert_path))?);
+    let key_file = &mut BufReader::new(File::open(key_path).with_context(|| format!("failed to open {}", key_path))?);
+
+    let cert_chain = rustls_pemfile::certs(cert_file).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(key_file)?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", key_path))?;
+
+    let server_config = ServerConfig::builder().with_no_client_auth().with_single_cert(cert_chain, key)?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Makes the parameters that can change at runtime take effect.
+async fn apply_config(config: &Config, db: &Db, aof: &Arc<Mutex<Aof>>, replication: &Replication) {
+    db.set_maxmemory(config.maxmemory);
+    db.set_eviction_policy(config.maxmemory_policy);
+    db.set_keyspace_events(config.notify_keyspace_events);
+    replication.set_backlog_size(config.repl_backlog_size as usize);
+    replication.set_master_auth(&config.masteruser, &config.masterauth);
+
+    let mut aof = aof.lock().await;
+    aof.set_fsync_policy(config.appendfsync);
+    aof.set_auto_rewrite(config.auto_aof_rewrite_percentage, config.auto_aof_rewrite_min_size);
+    aof.set_rdb_preamble(config.aof_use_rdb_preamble);
+}
+
+async fn process<S>(socket: S, peer: SocketAddr, db: Db, aof: Arc<Mutex<Aof>>, rdb: Arc<Rdb>, state: Arc<State>) -> Result<(), Error>
 where S: AsyncStream
 {
     let mut connection = Connection::new(socket);
+    let mut transaction = Transaction::new(db.clone());
+    let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut name: Option<String> = None;
+    // Who the connection is authenticated as, if anyone
+    let mut user = state.acl.read().unwrap().initial_user();
+    let mut subscriptions = Subscriptions::new(db.clone());
+    // The port the peer listens on, if it is a replica that said so
+    let mut listening_port: Option<u16> = None;
+    let mut closing = state.closing.subscribe();
 
-    while let Some(frame) = connection.read_frame().await? {
-        let command = Command::from_frame(frame)?;
+    loop {
+        // Messages published to the channels subscribed to are sent as they
+        // arrive, between the replies to commands
+        let frame = tokio::select! {
+            frame = connection.read_frame() => frame,
+            _ = closing.changed() => return Ok(()),
+            delivery = subscriptions.recv() => {
+                let frame = match delivery {
+                    Delivery::Message(message) => Frame::Push(vec![
+                        Frame::Bulk(Bytes::from("message")),
+                        Frame::Bulk(Bytes::from(message.channel)),
+                        Frame::Bulk(message.payload),
+                    ]),
+                    Delivery::PMessage { pattern, message } => Frame::Push(vec![
+                        Frame::Bulk(Bytes::from("pmessage")),
+                        Frame::Bulk(Bytes::from(pattern)),
+                        Frame::Bulk(Bytes::from(message.channel)),
+                        Frame::Bulk(message.payload),
+                    ]),
+                    Delivery::Lagged { name, skipped } => {
+                        state.pubsub_lag_events.fetch_add(1, Ordering::Relaxed);
+                        state.pubsub_dropped_messages.fetch_add(skipped, Ordering::Relaxed);
+                        // Tell the subscriber how many messages it missed
+                        let report = Frame::Push(vec![
+                            Frame::Bulk(Bytes::from("lagged")),
+                            Frame::Bulk(Bytes::from(name.clone())),
+                            Frame::Integer(skipped as i64),
+                        ]);
+                        let limit = state.config.read().unwrap().pubsub_lag_hard_limit;
+                        if limit > 0 && skipped > limit {
+                            state.pubsub_lag_disconnections.fetch_add(1, Ordering::Relaxed);
+                            info!("Subscriber missed {} messages of '{}', closing the connection", skipped, name);
+                            // The subscriber may not be reading at all
+                            let _ = time::timeout(LAG_REPORT_TIMEOUT, connection.write_frame(&report)).await;
+                            return Ok(());
+                        }
+                        report
+                    }
+                };
+                connection.buffer_frame(&frame).await?;
+                continue;
+            }
+        };
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(()),
+            // Tell the peer what was wrong with its request before hanging up
+            Err(Error::Other(msg)) => {
+                let _ = connection.write_frame(&Frame::Error(format!("ERR Protocol error: {}", msg))).await;
+                return Err(Error::Other(msg));
+            }
+            Err(e) => return Err(e),
+        };
 
-        let response = match command {
-            Command::Get(cmd) => {
-                let value = db.get(&cmd.key);
-                match value {
-                    Some(v) => Frame::Bulk(v),
-                    None => Frame::Null,
-                }
+        let invoked = invoked_name(&frame);
+        let command = match Command::from_frame(frame) {
+            Ok(command) => command,
+            Err(Error::Other(msg)) => {
+                // A command that can't be queued dooms the whole transaction
+                transaction.abort();
+                connection.buffer_frame(&Frame::Error(format!("ERR {}", msg))).await?;
+                continue;
             }
-            Command::Set(cmd) => {
-                db.set(cmd.key.clone(), cmd.value.clone());
+            Err(e) => return Err(e),
+        };
+        state.total_commands_processed.fetch_add(1, Ordering::Relaxed);
 
-                let mut aof = aof.lock().await;
-                let persist_cmd = Command::Set(crate::cmd::set::Set { key: cmd.key, value: cmd.value });
-                if let Err(e) = aof.append(persist_cmd).await {
-                    error!("Failed to append to AOF: {:?}", e);
-                }
+        let allowed = match &command {
+            Command::Unknown(_) => state.acl.read().unwrap().check_authenticated(user.as_deref()),
+            command => state.acl.read().unwrap().check(user.as_deref(), &invoked, &command.keys()),
+        };
+        if let Err(e) = allowed {
+            // Like a command that can't be parsed, a denied one dooms the transaction
+            transaction.abort();
+            connection.buffer_frame(&Frame::Error(e)).await?;
+            continue;
+        }
 
+        // A replica only takes writes from its master
+        if state.replication.is_replica() && (command.is_write() || matches!(command, Command::BPop(_))) {
+            transaction.abort();
+            connection.buffer_frame(&Frame::Error("READONLY You can't write against a read only replica.".to_string())).await?;
+            continue;
+        }
+
+        // RESP2 has no way to tell replies from messages, so a subscribed
+        // connection is limited to the commands whose replies look like them
+        let subscribed = subscriptions.count() > 0 && connection.protocol() == Protocol::Resp2;
+        if subscribed && !matches!(command, Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Ping(_)) {
+            let error = format!(
+                "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+                command.name()
+            );
+            connection.buffer_frame(&Frame::Error(error)).await?;
+            continue;
+        }
+
+        let response = match command {
+            Command::Multi(_) => transaction.begin(),
+            Command::Exec(_) => exec(&db, &aof, &mut transaction).await,
+            Command::Discard(_) => transaction.discard(),
+            Command::Watch(cmd) => transaction.watch(cmd.keys),
+            Command::Unwatch(_) => {
+                transaction.unwatch();
                 Frame::Simple("OK".to_string())
             }
-            Command::Publish(cmd) => {
-                let count = db.publish(&cmd.channel, cmd.message);
-                Frame::Integer(count as i64)
+            Command::Unknown(cmd) if transaction.is_queueing() => {
+                transaction.abort();
+                cmd.apply()
+            }
+            command if transaction.is_queueing() => transaction.queue(command),
+            Command::Hello(cmd) => hello(cmd, id, &mut name, &mut user, &state, &mut connection),
+            Command::Auth(cmd) => auth(cmd, &mut user, &state.acl.read().unwrap()),
+            Command::Acl(cmd) => acl_command(cmd, user.as_deref(), &state),
+            Command::Client(Client::Id) => Frame::Integer(id as i64),
+            Command::Client(Client::GetName) => match &name {
+                Some(name) => Frame::Bulk(Bytes::from(name.clone())),
+                None => Frame::Null,
+            },
+            Command::Client(Client::SetName(new_name)) => match validate_name(&new_name) {
+                Ok(()) => {
+                    // An empty name clears it
+                    name = Some(new_name).filter(|name| !name.is_empty());
+                    Frame::Simple("OK".to_string())
+                }
+                Err(e) => e,
+            },
+            Command::Info(cmd) => info(cmd, &db, &aof, &rdb, &state).await,
+            Command::Config(cmd) => config(cmd, &db, &aof, &state).await,
+            Command::ReplicaOf(cmd) => replica_of(cmd, &db, &aof, &state).await,
+            Command::ReplConf(cmd) => match cmd.get("listening-port").map(str::parse) {
+                Some(Err(_)) => Frame::Error("ERR value is not an integer or out of range".to_string()),
+                port => {
+                    if let Some(Ok(port)) = port {
+                        listening_port = Some(port);
+                    }
+                    Frame::Simple("OK".to_string())
+                }
+            },
+            Command::Psync(cmd) => {
+                // From here on the connection carries the replication stream
+                let link = ReplicaLink::new(peer.ip().to_string(), listening_port.unwrap_or(peer.port()));
+                return tokio::select! {
+                    res = state.replication.serve_replica(&mut connection, &db, &aof, cmd, id, link) => {
+                        res.map_err(|e| Error::Other(e.to_string()))
+                    }
+                    _ = closing.changed() => Ok(()),
+                };
             }
             Command::Subscribe(cmd) => {
-                let mut subscriptions = vec![];
-                for channel_name in &cmd.channels {
-                    let rx = db.subscribe(channel_name.clone());
-                    subscriptions.push((channel_name.clone(), rx));
-
-                    let frame = Frame::Array(vec![
-                        Frame::Bulk(bytes::Bytes::from("subscribe")),
-                        Frame::Bulk(bytes::Bytes::from(channel_name.clone())),
-                        Frame::Integer(subscriptions.len() as i64),
-                    ]);
-                    connection.write_frame(&frame).await?;
+                let replies = match cmd.pattern {
+                    true => subscriptions.psubscribe(cmd.channels),
+                    false => subscriptions.subscribe(cmd.channels),
+                };
+                for reply in &replies {
+                    connection.buffer_frame(reply).await?;
                 }
+                continue;
+            }
+            Command::Unsubscribe(cmd) => {
+                let replies = match cmd.pattern {
+                    true => subscriptions.punsubscribe(cmd.channels),
+                    false => subscriptions.unsubscribe(cmd.channels),
+                };
+                for reply in &replies {
+                    connection.buffer_frame(reply).await?;
+                }
+                continue;
+            }
+            Command::Ping(cmd) if subscribed => Frame::Array(vec![
+                Frame::Bulk(Bytes::from("pong")),
+                Frame::Bulk(cmd.msg.unwrap_or_default()),
+            ]),
+            Command::BgRewriteAof(_) => {
+                match Aof::rewrite_in_background(aof.clone(), db.clone()).await {
+                    Ok(_) => Frame::Simple("Background append only file rewriting started".to_string()),
+                    Err(e) => Frame::Error(format!("ERR {}", e)),
+                }
+            }
+            Command::Save(_) => {
+                match rdb.save(&db).await {
+                    Ok(()) => Frame::Simple("OK".to_string()),
+                    Err(e) => Frame::Error(format!("ERR {}", e)),
+                }
+            }
+            Command::BgSave(_) => {
+                match Rdb::save_in_background(rdb.clone(), &db).await {
+                    Ok(_) => Frame::Simple("Background saving started".to_string()),
+                    Err(e) => Frame::Error(format!("ERR {}", e)),
+                }
+            }
+            Command::BPop(cmd) => match blocking_pop(&db, &aof, cmd, &mut connection).await {
+                Some(response) => response,
+                None => return Ok(()),
+            },
+            Command::XRead(cmd) if cmd.blocks() => match blocking_read(&db, &aof, cmd, &mut connection).await {
+                Some(response) => response,
+                None => return Ok(()),
+            },
+            command => {
+                let command = command.with_absolute_expiry(now_ms());
+                if command.is_write() {
+                    apply_write(&aof, &db, command).await
+                } else {
+                    let _access = db.shared_access();
+                    command.apply(&db)
+                }
+            }
+        };
 
-                if let Some((channel_name, mut rx)) = subscriptions.pop() {
-                     loop {
-                         match rx.recv().await {
-                             Ok(msg) => {
-                                 let frame = Frame::Array(vec![
-                                     Frame::Bulk(bytes::Bytes::from("message")),
-                                     Frame::Bulk(bytes::Bytes::from(channel_name.clone())),
-                                     Frame::Bulk(msg),
-                                 ]);
-                                 connection.write_frame(&frame).await?;
-                             }
-                             Err(_) => break, // Channel closed or lagged
-                         }
-                     }
+        // Flushed once the batch of frames already received has been handled
+        connection.buffer_frame(&response).await?;
+    }
+}
+
+/// Negotiates the protocol version of the connection and replies with a
+/// summary of the server, in the newly selected protocol.
+fn hello(cmd: Hello, id: u64, name: &mut Option<String>, user: &mut Option<String>, state: &State, connection: &mut Connection) -> Frame {
+    let acl = state.acl.read().unwrap();
+    let protocol = match cmd.protover {
+        None => connection.protocol(),
+        Some(2) => Protocol::Resp2,
+        Some(3) => Protocol::Resp3,
+        Some(_) => return Frame::Error("NOPROTO unsupported protocol version".to_string()),
+    };
+    match &cmd.auth {
+        Some((username, password)) if !acl.authenticate(username, password) => return wrong_pass(),
+        Some((username, _)) => *user = Some(username.clone()),
+        None if user.is_none() => {
+            return Frame::Error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string(),
+            )
+        }
+        None => {}
+    }
+    if let Some(new_name) = cmd.setname {
+        if let Err(e) = validate_name(&new_name) {
+            return e;
+        }
+        *name = Some(new_name).filter(|name| !name.is_empty());
+    }
+
+    connection.set_protocol(protocol);
+    let field = |name: &str| Frame::Bulk(Bytes::from(name.to_string()));
+    Frame::Map(vec![
+        (field("server"), field("mini-redis")),
+        (field("version"), field(env!("CARGO_PKG_VERSION"))),
+        (field("proto"), Frame::Integer(if protocol == Protocol::Resp3 { 3 } else { 2 })),
+        (field("id"), Frame::Integer(id as i64)),
+        (field("mode"), field("standalone")),
+        (field("role"), field(state.replication.role())),
+        (field("modules"), Frame::Array(vec![])),
+    ])
+}
+
+/// Executes `AUTH`, switching the connection to the user on success.
+fn auth(cmd: Auth, user: &mut Option<String>, acl: &Acl) -> Frame {
+    let username = cmd.username.unwrap_or_else(|| "default".to_string());
+    if username == "default" && acl.user("default").is_some_and(|default| default.nopass) {
+        return Frame::Error(
+            "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".to_string(),
+        );
+    }
+    if !acl.authenticate(&username, &cmd.password) {
+        return wrong_pass();
+    }
+    *user = Some(username);
+    Frame::Simple("OK".to_string())
+}
+
+fn wrong_pass() -> Frame {
+    Frame::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+}
+
+/// Executes `ACL`. `user` is who the connection is authenticated as.
+fn acl_command(cmd: AclCommand, user: Option<&str>, state: &State) -> Frame {
+    let bulk = |s: String| Frame::Bulk(Bytes::from(s));
+    let ok = || Frame::Simple("OK".to_string());
+    match cmd {
+        AclCommand::SetUser { name, rules } => match state.acl.write().unwrap().set_user(&name, &rules) {
+            Ok(()) => ok(),
+            Err(e) => Frame::Error(format!("ERR {}", e)),
+        },
+        AclCommand::GetUser(name) => {
+            let acl = state.acl.read().unwrap();
+            let Some(user) = acl.user(&name) else { return Frame::Null };
+            let mut flags = vec![bulk(if user.enabled { "on" } else { "off" }.to_string())];
+            if user.nopass {
+                flags.push(bulk("nopass".to_string()));
+            }
+            Frame::Map(vec![
+                (bulk("flags".to_string()), Frame::Array(flags)),
+                (bulk("passwords".to_string()), Frame::Array(user.password_hashes().into_iter().map(bulk).collect())),
+                (bulk("commands".to_string()), bulk(user.command_rules())),
+                (bulk("keys".to_string()), bulk(user.key_rules())),
+            ])
+        }
+        AclCommand::DelUser(names) => match state.acl.write().unwrap().del_users(&names) {
+            Ok(deleted) => Frame::Integer(deleted as i64),
+            Err(e) => Frame::Error(format!("ERR {}", e)),
+        },
+        AclCommand::List => Frame::Array(state.acl.read().unwrap().list().into_iter().map(bulk).collect()),
+        AclCommand::Users => Frame::Array(state.acl.read().unwrap().usernames().into_iter().map(bulk).collect()),
+        AclCommand::WhoAmI => bulk(user.unwrap_or("default").to_string()),
+        AclCommand::Cat(None) => Frame::Array(acl::CATEGORIES.iter().map(|category| bulk(category.to_string())).collect()),
+        AclCommand::Cat(Some(category)) => match acl::category_commands(&category.to_lowercase()) {
+            Ok(commands) => Frame::Array(commands.into_iter().map(|command| bulk(command.to_string())).collect()),
+            Err(_) => Frame::Error(format!("ERR Unknown category '{}'", category)),
+        },
+        AclCommand::Load | AclCommand::Save => {
+            let path = state.config.read().unwrap().aclfile.clone();
+            if path.is_empty() {
+                return Frame::Error("ERR This instance is not configured to use an ACL file".to_string());
+            }
+            let result = match cmd {
+                AclCommand::Load => Acl::load(&path).map(|acl| *state.acl.write().unwrap() = acl),
+                _ => state.acl.read().unwrap().save(&path).map_err(anyhow::Error::from),
+            };
+            match result {
+                Ok(()) => ok(),
+                Err(e) => Frame::Error(format!("ERR {}", e)),
+            }
+        }
+    }
+}
+
+/// The name a command was invoked with, as permissions are checked against
+/// it (see `acl::command_id`). It differs from `Command::name` for aliases.
+fn invoked_name(frame: &Frame) -> String {
+    let arg = |frame: &Frame| match frame {
+        Frame::Bulk(arg) => Some(String::from_utf8_lossy(arg).into_owned()),
+        Frame::Simple(arg) => Some(arg.clone()),
+        _ => None,
+    };
+    match frame {
+        Frame::Array(parts) => {
+            let name = parts.first().and_then(arg).unwrap_or_default();
+            acl::command_id(&name, parts.get(1).and_then(arg).as_deref())
+        }
+        _ => String::new(),
+    }
+}
+
+/// Executes `INFO`, gathering what the server knows besides the keyspace.
+async fn info(cmd: Info, db: &Db, aof: &Arc<Mutex<Aof>>, rdb: &Rdb, state: &State) -> Frame {
+    let (aof_current_size, aof_rewrite_in_progress) = {
+        let aof = aof.lock().await;
+        (aof.size(), aof.is_rewriting())
+    };
+    let config = state.config.read().unwrap();
+    let server = ServerInfo {
+        tcp_port: config.port,
+        uptime_in_seconds: state.started.elapsed().as_secs(),
+        config_file: config.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        connected_clients: state.connected_clients.load(Ordering::Relaxed),
+        maxclients: config.maxclients,
+        total_connections_received: state.total_connections_received.load(Ordering::Relaxed),
+        total_commands_processed: state.total_commands_processed.load(Ordering::Relaxed),
+        rejected_connections: state.rejected_connections.load(Ordering::Relaxed),
+        pubsub_lag_events: state.pubsub_lag_events.load(Ordering::Relaxed),
+        pubsub_dropped_messages: state.pubsub_dropped_messages.load(Ordering::Relaxed),
+        pubsub_lag_disconnections: state.pubsub_lag_disconnections.load(Ordering::Relaxed),
+        sync_full: state.replication.sync_full.load(Ordering::Relaxed),
+        sync_partial_ok: state.replication.sync_partial_ok.load(Ordering::Relaxed),
+        sync_partial_err: state.replication.sync_partial_err.load(Ordering::Relaxed),
+        replication: state.replication.info(),
+        aof_current_size,
+        aof_rewrite_in_progress,
+        rdb_bgsave_in_progress: rdb.is_saving(),
+        rdb_last_save_time: rdb.last_save(),
+    };
+    cmd.apply(db, &server)
+}
+
+/// Executes `CONFIG`. `CONFIG SET` validates every pair before changing
+/// anything, so a bad value leaves the configuration untouched.
+async fn config(cmd: ConfigCommand, db: &Db, aof: &Arc<Mutex<Aof>>, state: &State) -> Frame {
+    match cmd {
+        ConfigCommand::Get(patterns) => {
+            let config = state.config.read().unwrap();
+            let mut pairs: Vec<(String, String)> = vec![];
+            for (name, value) in patterns.iter().flat_map(|pattern| config.matching(pattern)) {
+                if !pairs.iter().any(|(seen, _)| *seen == name) {
+                    pairs.push((name, value));
                 }
+            }
+            let bulk = |s: String| Frame::Bulk(Bytes::from(s));
+            Frame::Map(pairs.into_iter().map(|(name, value)| (bulk(name), bulk(value))).collect())
+        }
+        ConfigCommand::Set(pairs) => {
+            let updated = {
+                let mut current = state.config.write().unwrap();
+                let mut config = current.clone();
+                for (name, value) in &pairs {
+                    match Config::is_mutable(name) {
+                        None => return Frame::Error(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name)),
+                        Some(false) => return config_set_error(name, "can't set immutable config"),
+                        Some(true) => {}
+                    }
+                    if let Err(e) = config.set(name, value) {
+                        return config_set_error(name, &e);
+                    }
+                }
+                *current = config.clone();
+                config
+            };
+            apply_config(&updated, db, aof, &state.replication).await;
+            if pairs.iter().any(|(name, _)| name.eq_ignore_ascii_case("requirepass")) {
+                state.acl.write().unwrap().set_requirepass(&updated.requirepass);
+            }
+            Frame::Simple("OK".to_string())
+        }
+        ConfigCommand::Rewrite => {
+            let config = state.config.read().unwrap().clone();
+            if config.path.is_none() {
+                return Frame::Error("ERR The server is running without a config file".to_string());
+            }
+            match tokio::task::spawn_blocking(move || config.rewrite()).await {
+                Ok(Ok(())) => Frame::Simple("OK".to_string()),
+                Ok(Err(e)) => Frame::Error(format!("ERR Rewriting config file: {}", e)),
+                Err(e) => Frame::Error(format!("ERR Rewriting config file: {}", e)),
+            }
+        }
+        ConfigCommand::ResetStat => {
+            state.total_connections_received.store(0, Ordering::Relaxed);
+            state.total_commands_processed.store(0, Ordering::Relaxed);
+            state.rejected_connections.store(0, Ordering::Relaxed);
+            state.pubsub_lag_events.store(0, Ordering::Relaxed);
+            state.pubsub_dropped_messages.store(0, Ordering::Relaxed);
+            state.pubsub_lag_disconnections.store(0, Ordering::Relaxed);
+            state.replication.reset_stats();
+            Frame::Simple("OK".to_string())
+        }
+    }
+}
+
+/// Executes `REPLICAOF`. The new master is kept in the configuration, so
+/// `CONFIG REWRITE` makes it stick across restarts.
+async fn replica_of(cmd: ReplicaOf, db: &Db, aof: &Arc<Mutex<Aof>>, state: &State) -> Frame {
+    state.config.write().unwrap().replicaof = cmd.master.clone();
+    match state.replication.replicate(cmd.master, db, aof).await {
+        true => Frame::Simple("OK".to_string()),
+        false => Frame::Simple("OK Already connected to specified master".to_string()),
+    }
+}
+
+fn config_set_error(name: &str, reason: &str) -> Frame {
+    Frame::Error(format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, reason))
+}
 
-                return Ok(());
+/// Applies a write command and logs it to the AOF, starting an automatic
+/// rewrite if the file has grown past the configured threshold. Commands that
+/// may need more memory first make room under `maxmemory`.
+///
+/// The command runs under `Db::write_access` rather than the AOF lock, so
+/// writes to other shards run alongside it and only appending to the AOF
+/// takes turns.
+async fn apply_write(aof: &Arc<Mutex<Aof>>, db: &Db, command: Command) -> Frame {
+    let persist = command.clone();
+    let response = match command.uses_memory().then(|| evict(db)) {
+        Some(Err(e)) => Frame::Error(e.to_string()),
+        _ => {
+            let access = db.write_access(&persist.keys());
+            let response = command.apply(db);
+            if !matches!(response, Frame::Error(_)) {
+                db.touch(&persist.keys());
+                db.log(&access, persist.with_outcome(&response).map(Command::into_frame));
             }
-            Command::Ping(cmd) => {
-                 match cmd.msg {
-                    Some(msg) => Frame::Bulk(msg),
-                    None => Frame::Simple("PONG".to_string()),
+            response
+        }
+    };
+    append_unlogged(aof, db).await;
+    response
+}
+
+/// Evicts keys to get back under `maxmemory`, logging them as deleted so
+/// replaying the AOF doesn't bring them back. The keys can be in any shard,
+/// so this keeps every other command out.
+fn evict(db: &Db) -> Result<(), DbError> {
+    if !db.over_maxmemory() {
+        return Ok(());
+    }
+    let access = db.write_access::<&str>(&[]);
+    let mut evicted = vec![];
+    let result = db.evict(&mut evicted);
+    if !evicted.is_empty() {
+        db.log(&access, [Command::Del(Del { keys: evicted, unlink: false }).into_frame()]);
+    }
+    result
+}
+
+/// Appends the AOF entries queued by writes so far, then starts an automatic
+/// rewrite if the file has grown past the configured threshold.
+async fn append_unlogged(aof: &Arc<Mutex<Aof>>, db: &Db) {
+    let mut guard = aof.lock().await;
+    // Taken under the lock, so entries reach the file in the order they were
+    // queued in
+    if let Err(e) = guard.append_frames(db.take_unlogged()).await {
+        error!("Failed to append to AOF: {:?}", e);
+    }
+
+    if guard.should_rewrite() {
+        drop(guard);
+        info!("AOF has grown past the rewrite threshold, starting automatic rewrite");
+        if let Err(e) = Aof::rewrite_in_background(aof.clone(), db.clone()).await {
+            error!("Failed to start AOF rewrite: {:?}", e);
+        }
+    }
+}
+
+/// Logs keys evicted to stay under `maxmemory` as deleted, so replaying the
+/// AOF doesn't bring them back.
+async fn log_evictions(aof: &mut Aof, evicted: Vec<String>) {
+    if evicted.is_empty() {
+        return;
+    }
+    if let Err(e) = aof.append(Command::Del(Del { keys: evicted, unlink: false })).await {
+        error!("Failed to append to AOF: {:?}", e);
+    }
+}
+
+/// Executes `BLPOP` / `BRPOP`: pops from the first non-empty list, or waits
+/// for a push to any of the keys until the timeout elapses.
+///
+/// The connection waits in line with other blocked clients and is woken by
+/// `Db::push`. A successful pop is logged as a plain `LPOP` / `RPOP`.
+/// Returns `None` if the client disconnected while waiting.
+async fn blocking_pop(db: &Db, aof: &Arc<Mutex<Aof>>, cmd: BPop, connection: &mut Connection) -> Option<Frame> {
+    let deadline = cmd.timeout.map(|timeout| Instant::now() + timeout);
+    let waiter = Arc::new(Notify::new());
+    block_on_keys(db, &cmd.keys, deadline, connection, &waiter, || try_pop(db, aof, &cmd, &waiter)).await
+}
+
+/// Executes `XREAD` / `XREADGROUP` with `BLOCK`: replies as soon as one of
+/// the streams has entries to read, or with null once the timeout elapses.
+///
+/// `$` is resolved once up front, so only entries added after the command
+/// was issued are returned. Returns `None` if the client disconnected while
+/// waiting.
+async fn blocking_read(db: &Db, aof: &Arc<Mutex<Aof>>, cmd: XRead, connection: &mut Connection) -> Option<Frame> {
+    let deadline = cmd.block.filter(|block| !block.is_zero()).map(|block| Instant::now() + block);
+    let cmd = match cmd.resolve_last(db) {
+        Ok(cmd) => cmd,
+        Err(e) => return Some(Frame::Error(e.to_string())),
+    };
+    let waiter = Arc::new(Notify::new());
+    block_on_keys(db, &cmd.keys, deadline, connection, &waiter, || try_read(db, aof, &cmd)).await
+}
+
+/// Runs `attempt` until it has a reply, waiting in line with other clients
+/// blocked on `keys` in between as `waiter`. Replies with null once
+/// `deadline` passes, and returns `None` if the client disconnected while
+/// waiting.
+async fn block_on_keys<F, Fut>(
+    db: &Db,
+    keys: &[String],
+    deadline: Option<Instant>,
+    connection: &mut Connection,
+    waiter: &Arc<Notify>,
+    mut attempt: F,
+) -> Option<Frame>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<Frame>>,
+{
+    loop {
+        // Register before looking, so a write in between can't be missed
+        db.block(keys, waiter);
+
+        if let Some(response) = attempt().await {
+            db.unblock(keys, waiter);
+            return Some(response);
+        }
+
+        // Send the replies to earlier pipelined commands before blocking
+        if connection.flush().await.is_err() {
+            db.unblock(keys, waiter);
+            return None;
+        }
+
+        let sleep = async {
+            match deadline {
+                Some(deadline) => time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = waiter.notified() => {}
+            _ = sleep => {
+                db.unblock(keys, waiter);
+                return Some(Frame::Null);
+            }
+            _ = connection.closed() => {
+                db.unblock(keys, waiter);
+                return None;
+            }
+        }
+    }
+}
+
+/// Pops a value from the first non-empty list of `cmd` once it is
+/// `waiter`'s turn, logging the pop.
+async fn try_pop(db: &Db, aof: &Arc<Mutex<Aof>>, cmd: &BPop, waiter: &Arc<Notify>) -> Option<Frame> {
+    let response = {
+        let access = db.write_access(&cmd.keys);
+        let response = cmd.clone().apply_waiting(db, waiter);
+        if let Some(pop) = cmd.as_pop(&response) {
+            db.touch(&[&pop.key]);
+            db.log(&access, [Command::Pop(pop).into_frame()]);
+        }
+        response
+    };
+    append_unlogged(aof, db).await;
+
+    match response {
+        Frame::Null => None,
+        response => Some(response),
+    }
+}
+
+/// Reads the streams of `cmd` without blocking. Reads by a consumer group
+/// change the group, so they are logged like any other write.
+async fn try_read(db: &Db, aof: &Arc<Mutex<Aof>>, cmd: &XRead) -> Option<Frame> {
+    let response = match cmd.group {
+        None => {
+            let _access = db.shared_access();
+            cmd.clone().apply(db)
+        }
+        Some(_) => {
+            let command = Command::XRead(cmd.clone());
+            let response = {
+                let access = db.write_access(&command.keys());
+                let response = command.clone().apply(db);
+                if !matches!(response, Frame::Null | Frame::Error(_)) {
+                    db.touch(&command.keys());
+                    db.log(&access, command.with_outcome(&response).map(Command::into_frame));
                 }
+                response
+            };
+            append_unlogged(aof, db).await;
+            response
+        }
+    };
+
+    match response {
+        Frame::Null => None,
+        response => Some(response),
+    }
+}
+
+/// Per-connection `MULTI` / `WATCH` state.
+struct Transaction {
+    db: Db,
+    /// Commands queued since `MULTI`, or `None` outside of a transaction.
+    queued: Option<Vec<Command>>,
+    /// Set when a command could not be queued; `EXEC` then refuses to run.
+    aborted: bool,
+    /// Watched keys and their version at the time of `WATCH`.
+    watched: Vec<(String, Option<u64>)>,
+}
+
+impl Transaction {
+    fn new(db: Db) -> Transaction {
+        Transaction { db, queued: None, aborted: false, watched: vec![] }
+    }
+
+    fn is_queueing(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    fn begin(&mut self) -> Frame {
+        if self.is_queueing() {
+            return Frame::Error("ERR MULTI calls can not be nested".to_string());
+        }
+        self.queued = Some(vec![]);
+        Frame::Simple("OK".to_string())
+    }
+
+    fn queue(&mut self, command: Command) -> Frame {
+        if let Some(queued) = &mut self.queued {
+            queued.push(command);
+        }
+        Frame::Simple("QUEUED".to_string())
+    }
+
+    fn abort(&mut self) {
+        if self.is_queueing() {
+            self.aborted = true;
+        }
+    }
+
+    fn discard(&mut self) -> Frame {
+        if self.queued.take().is_none() {
+            return Frame::Error("ERR DISCARD without MULTI".to_string());
+        }
+        self.aborted = false;
+        self.unwatch();
+        Frame::Simple("OK".to_string())
+    }
+
+    fn watch(&mut self, keys: Vec<String>) -> Frame {
+        if self.is_queueing() {
+            return Frame::Error("ERR WATCH inside MULTI is not allowed".to_string());
+        }
+        for key in keys {
+            if !self.watched.iter().any(|(watched, _)| *watched == key) {
+                let version = self.db.watch(&key);
+                self.watched.push((key, Some(version)));
             }
-            Command::Unknown(cmd) => {
-                Frame::Error(format!("unknown command '{}'", cmd.command_name))
+        }
+        Frame::Simple("OK".to_string())
+    }
+
+    fn unwatch(&mut self) {
+        let keys: Vec<String> = self.watched.drain(..).map(|(key, _)| key).collect();
+        self.db.unwatch(&keys);
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+/// Executes the queued commands as one atomic unit and replies with an array
+/// of their replies, or null if a watched key was modified since `WATCH`.
+///
+/// The write commands are logged between `MULTI` and `EXEC` so the AOF
+/// replays the block all at once, too.
+async fn exec(db: &Db, aof: &Arc<Mutex<Aof>>, transaction: &mut Transaction) -> Frame {
+    let Some(commands) = transaction.queued.take() else {
+        return Frame::Error("ERR EXEC without MULTI".to_string());
+    };
+    if std::mem::take(&mut transaction.aborted) {
+        transaction.unwatch();
+        return Frame::Error("EXECABORT Transaction discarded because of previous errors.".to_string());
+    }
+
+    let mut guard = aof.lock().await;
+    let mut evicted = vec![];
+    let (unlogged, result) = {
+        let _access = db.exclusive_access();
+        // Earlier writes go to the AOF first
+        let unlogged = db.take_unlogged();
+        let result = if transaction.watched.iter().any(|(key, version)| db.version(key) != *version) {
+            Err(Frame::Null)
+        } else if let Some(Err(e)) = commands.iter().any(Command::uses_memory).then(|| db.evict(&mut evicted)) {
+            Err(Frame::Error(e.to_string()))
+        } else {
+            let now = now_ms();
+            let mut responses = Vec::with_capacity(commands.len());
+            let mut log = vec![];
+            for command in commands {
+                let command = command.with_absolute_expiry(now);
+                let persist = match &command {
+                    Command::BPop(cmd) => Some(cmd.clone()),
+                    _ => None,
+                };
+                let write = command.is_write().then(|| command.clone());
+                let response = command.apply(db);
+
+                if !matches!(response, Frame::Error(_)) {
+                    if let Some(write) = write {
+                        db.touch(&write.keys());
+                        log.extend(write.with_outcome(&response));
+                    } else if let Some(pop) = persist.and_then(|cmd| cmd.as_pop(&response)) {
+                        db.touch(&[&pop.key]);
+                        log.push(Command::Pop(pop));
+                    }
+                }
+                responses.push(response);
             }
+            Ok((responses, log))
         };
+        (unlogged, result)
+    };
+    transaction.unwatch();
 
-        connection.write_frame(&response).await?;
+    if let Err(e) = guard.append_frames(unlogged).await {
+        error!("Failed to append to AOF: {:?}", e);
+    }
+    log_evictions(&mut guard, evicted).await;
+    let (responses, log) = match result {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
+    if !log.is_empty() {
+        if let Err(e) = guard.append_transaction(log).await {
+            error!("Failed to append to AOF: {:?}", e);
+        }
     }
 
-    Ok(())
+    Frame::Array(responses)
 }
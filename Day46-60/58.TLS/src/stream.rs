@@ -0,0 +1,366 @@
+//! Streams: append-only logs of field / value entries with increasing IDs,
+//! and the consumer groups that share out their entries.
+
+use crate::db::DbError;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The field / value pairs of a stream entry.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// A stream entry ID: the Unix time in milliseconds it was added at and a
+/// sequence number telling apart entries added in the same millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// Parses `<ms>-<seq>`, or a bare `<ms>` with `seq` as its sequence
+    /// number.
+    pub fn parse(s: &str, seq: u64) -> Option<StreamId> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(s.parse().ok()?, seq)),
+        }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID `XADD` gives a new entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewId {
+    /// `*`: generated from the current time.
+    Auto,
+    /// `<ms>-*`: the given time with the next free sequence number.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// An append-only log of entries ordered by ID, with its consumer groups.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// The largest ID ever added. Trimmed entries don't give their IDs back,
+    /// so new entries must always be greater.
+    last_id: StreamId,
+    groups: BTreeMap<String, Group>,
+}
+
+/// A consumer group: a cursor into the stream shared by its consumers, and
+/// the entries delivered to them that haven't been acknowledged yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    /// The last entry handed out by `XREADGROUP ... >`.
+    pub last_delivered: StreamId,
+    /// The pending entries list (PEL).
+    pub pending: BTreeMap<StreamId, Pending>,
+    /// Consumer names, with the Unix time in milliseconds they were last
+    /// active.
+    pub consumers: BTreeMap<String, u64>,
+}
+
+/// A delivered entry waiting to be acknowledged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    pub consumer: String,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivered_at: u64,
+    pub deliveries: u64,
+}
+
+/// `XPENDING key group` without a range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    /// The smallest and largest pending IDs, if there are any.
+    pub range: Option<(StreamId, StreamId)>,
+    /// Consumers with at least one pending entry, and how many.
+    pub consumers: Vec<(String, usize)>,
+}
+
+/// The options of `XCLAIM`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClaimOptions {
+    /// Sets the idle time of the claimed entries, in milliseconds.
+    pub idle: Option<u64>,
+    /// Sets the delivery time of the claimed entries, as a Unix time in
+    /// milliseconds.
+    pub time: Option<u64>,
+    pub retry_count: Option<u64>,
+    /// Claims entries that exist but aren't pending.
+    pub force: bool,
+    /// Replies with the IDs only and leaves the delivery count alone.
+    pub just_id: bool,
+}
+
+impl Stream {
+    pub fn new() -> Stream {
+        Stream::default()
+    }
+
+    /// Reassembles a stream from its parts, as read back from a snapshot.
+    pub fn from_parts(entries: BTreeMap<StreamId, Fields>, last_id: StreamId, groups: BTreeMap<String, Group>) -> Stream {
+        Stream { entries, last_id, groups }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Entries in ID order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        self.entries.iter()
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&String, &Group)> {
+        self.groups.iter()
+    }
+
+    /// Appends an entry, returning its ID.
+    pub fn add(&mut self, id: NewId, fields: Fields, now: u64) -> Result<StreamId, DbError> {
+        let last = self.last_id;
+        let id = match id {
+            // The clock may go backwards; IDs don't
+            NewId::Auto if now > last.ms => StreamId::new(now, 0),
+            NewId::Auto => last.next().ok_or(DbError::StreamExhausted)?,
+            NewId::AutoSeq(ms) if ms > last.ms => StreamId::new(ms, 0),
+            NewId::AutoSeq(ms) if ms == last.ms => {
+                StreamId::new(ms, last.seq.checked_add(1).ok_or(DbError::StreamIdTooSmall)?)
+            }
+            NewId::AutoSeq(_) => return Err(DbError::StreamIdTooSmall),
+            NewId::Explicit(StreamId::MIN) => return Err(DbError::StreamIdZero),
+            NewId::Explicit(id) => id,
+        };
+        if id <= last {
+            return Err(DbError::StreamIdTooSmall);
+        }
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    /// Removes the oldest entries until at most `maxlen` are left. Returns
+    /// how many were removed.
+    pub fn trim(&mut self, maxlen: usize) -> usize {
+        let excess = self.entries.len().saturating_sub(maxlen);
+        for _ in 0..excess {
+            self.entries.pop_first();
+        }
+        excess
+    }
+
+    /// Entries with IDs between `start` and `end` inclusive, newest first if
+    /// `rev`.
+    pub fn range(&self, start: StreamId, end: StreamId, count: Option<usize>, rev: bool) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return vec![];
+        }
+        let range = self.entries.range(start..=end).map(|(id, fields)| (*id, fields.clone()));
+        let count = count.unwrap_or(usize::MAX);
+        match rev {
+            true => range.rev().take(count).collect(),
+            false => range.take(count).collect(),
+        }
+    }
+
+    /// Entries with IDs greater than `id`, oldest first.
+    pub fn after(&self, id: StreamId, count: Option<usize>) -> Vec<(StreamId, Fields)> {
+        match id.next() {
+            Some(start) => self.range(start, StreamId::MAX, count, false),
+            None => vec![],
+        }
+    }
+
+    /// `XSETID`: changes the last ID, which can't go below the newest entry.
+    pub fn set_last_id(&mut self, id: StreamId) -> Result<(), DbError> {
+        if self.entries.last_key_value().is_some_and(|(last, _)| id < *last) {
+            return Err(DbError::StreamIdBelowTop);
+        }
+        self.last_id = id;
+        Ok(())
+    }
+
+    /// Creates a group that delivers the entries after `last_delivered`, or
+    /// only new ones if `None`.
+    pub fn create_group(&mut self, name: &str, last_delivered: Option<StreamId>) -> Result<(), DbError> {
+        if self.groups.contains_key(name) {
+            return Err(DbError::BusyGroup);
+        }
+        let last_delivered = last_delivered.unwrap_or(self.last_id);
+        self.groups.insert(name.to_string(), Group { last_delivered, ..Group::default() });
+        Ok(())
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.groups.get_mut(name)
+    }
+
+    /// `XREADGROUP`. With `start` `None` (`>`), hands out the entries no
+    /// consumer of the group has seen yet and records them as pending.
+    /// Otherwise replays the consumer's own pending entries after `start`;
+    /// entries trimmed since come back without fields.
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        start: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group)?;
+        group.consumers.insert(consumer.to_string(), now);
+
+        let Some(start) = start else {
+            let delivered = match group.last_delivered.next() {
+                Some(first) => entries
+                    .range(first..)
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|(id, fields)| (*id, fields.clone()))
+                    .collect(),
+                None => vec![],
+            };
+            for (id, _) in &delivered {
+                group.last_delivered = *id;
+                if !no_ack {
+                    let pending = Pending { consumer: consumer.to_string(), delivered_at: now, deliveries: 1 };
+                    group.pending.insert(*id, pending);
+                }
+            }
+            return Some(delivered.into_iter().map(|(id, fields)| (id, Some(fields))).collect());
+        };
+
+        let Some(first) = start.next() else { return Some(vec![]) };
+        Some(
+            group
+                .pending
+                .range(first..)
+                .filter(|(_, pending)| pending.consumer == consumer)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(id, _)| (*id, entries.get(id).cloned()))
+                .collect(),
+        )
+    }
+
+    /// Acknowledges pending entries of the group, returning how many were
+    /// pending.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        match self.groups.get_mut(group) {
+            Some(group) => ids.iter().filter(|id| group.pending.remove(id).is_some()).count(),
+            None => 0,
+        }
+    }
+
+    /// `XCLAIM`: transfers pending entries idle for at least `min_idle`
+    /// milliseconds to `consumer`. Returns the claimed entries; entries
+    /// trimmed from the stream are dropped from the PEL instead.
+    pub fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId],
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let entries = &self.entries;
+        let group = self.groups.get_mut(group)?;
+        group.consumers.insert(consumer.to_string(), now);
+
+        let delivered_at = match (options.time, options.idle) {
+            (Some(time), _) => time,
+            (None, Some(idle)) => now.saturating_sub(idle),
+            (None, None) => now,
+        };
+        let mut claimed = vec![];
+        for id in ids {
+            let Some(fields) = entries.get(id) else {
+                group.pending.remove(id);
+                continue;
+            };
+            let pending = match group.pending.get_mut(id) {
+                Some(pending) if now.saturating_sub(pending.delivered_at) < min_idle => continue,
+                Some(pending) => pending,
+                None if options.force => group.pending.entry(*id).or_insert(Pending {
+                    consumer: consumer.to_string(),
+                    delivered_at: now,
+                    deliveries: 0,
+                }),
+                None => continue,
+            };
+            pending.consumer = consumer.to_string();
+            pending.delivered_at = delivered_at;
+            match options.retry_count {
+                Some(count) => pending.deliveries = count,
+                None if !options.just_id => pending.deliveries += 1,
+                None => {}
+            }
+            claimed.push((*id, fields.clone()));
+        }
+        Some(claimed)
+    }
+}
+
+impl Group {
+    pub fn summary(&self) -> PendingSummary {
+        let mut consumers: BTreeMap<&str, usize> = BTreeMap::new();
+        for pending in self.pending.values() {
+            *consumers.entry(&pending.consumer).or_default() += 1;
+        }
+        PendingSummary {
+            count: self.pending.len(),
+            range: self.pending.first_key_value().zip(self.pending.last_key_value()).map(|(first, last)| (*first.0, *last.0)),
+            consumers: consumers.into_iter().map(|(name, count)| (name.to_string(), count)).collect(),
+        }
+    }
+
+    /// Removes a consumer, returning how many entries were pending for it.
+    /// They are no longer pending for anyone.
+    pub fn delete_consumer(&mut self, consumer: &str) -> usize {
+        self.consumers.remove(consumer);
+        let before = self.pending.len();
+        self.pending.retain(|_, pending| pending.consumer != consumer);
+        before - self.pending.len()
+    }
+}
@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+/// A sorted set: unique members ordered by score, then lexicographically.
+///
+/// Members are indexed twice: by name for O(1) score lookups and by
+/// `(score, member)` for ordered range queries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+/// An `f64` with a total order, so it can be used as a `BTreeSet` key.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl ZSet {
+    pub fn new() -> ZSet {
+        ZSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Adds the member or updates its score. Returns the previous score.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let prev = self.scores.insert(member.clone(), score);
+        if let Some(prev) = prev {
+            self.ordered.remove(&(Score(prev), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        prev
+    }
+
+    /// Removes the member, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.ordered.remove(&(Score(score), member));
+        Some(score)
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Zero-based position of the member in score order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.ordered.range(..(Score(score), Bytes::copy_from_slice(member))).count())
+    }
+
+    /// Members in score order, from lowest to highest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+}
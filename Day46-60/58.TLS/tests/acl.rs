@@ -0,0 +1,227 @@
+mod common;
+
+use common::{b, connect, error, request, simple};
+use mini_redis_tls::acl::{category_commands, Acl, User};
+use mini_redis_tls::{Client, Frame};
+
+fn rules(rules: &[&str]) -> Vec<String> {
+    rules.iter().map(|rule| rule.to_string()).collect()
+}
+
+#[test]
+fn test_user_rules() {
+    let mut user = User::new("alice");
+    assert!(!user.enabled);
+    assert!(!user.can_run("get"));
+
+    for rule in ["on", ">secret", "~cache:*", "+@read", "-keys", "+set"] {
+        user.apply(rule).unwrap();
+    }
+    assert!(user.check_password(b"secret"));
+    assert!(!user.check_password(b"other"));
+    assert!(user.can_run("get") && user.can_run("hgetall") && user.can_run("set"));
+    assert!(!user.can_run("keys") && !user.can_run("del"));
+    assert!(user.can_access("cache:1"));
+    assert!(!user.can_access("other"));
+
+    assert_eq!(user.apply("+nosuchcommand"), Err("Unknown command or category name in ACL".to_string()));
+    assert_eq!(user.apply("+@nosuchcategory"), Err("Unknown command or category name in ACL".to_string()));
+    assert!(user.apply("<wrong").is_err());
+    assert!(user.apply("#abc").is_err());
+    user.apply("<secret").unwrap();
+    assert!(!user.check_password(b"secret"));
+
+    user.apply("reset").unwrap();
+    assert_eq!(user, User::new("alice"));
+}
+
+#[test]
+fn test_setuser_is_atomic() {
+    let mut acl = Acl::new();
+    acl.set_user("bob", &rules(&["on", ">pw"])).unwrap();
+    let err = acl.set_user("bob", &rules(&["off", "bogus"])).unwrap_err();
+    assert_eq!(err, "Error in ACL SETUSER modifier 'bogus': Syntax error");
+    assert!(acl.authenticate("bob", b"pw"));
+    assert!(acl.del_users(&rules(&["default"])).is_err());
+    assert_eq!(acl.del_users(&rules(&["bob", "nobody"])), Ok(1));
+}
+
+#[test]
+fn test_check() {
+    let mut acl = Acl::new();
+    acl.set_user("reader", &rules(&["on", "nopass", "~public:*", "+@read"])).unwrap();
+
+    assert_eq!(acl.check(None, "get", &["key"]), Err("NOAUTH Authentication required.".to_string()));
+    assert_eq!(acl.check(None, "auth", &[]), Ok(()));
+    assert_eq!(acl.check(Some("default"), "flushdb", &[]), Ok(()));
+    assert_eq!(acl.check(Some("reader"), "get", &["public:1"]), Ok(()));
+    assert_eq!(acl.check(Some("reader"), "mget", &["public:1", "private"]), Err("NOPERM No permissions to access a key".to_string()));
+    assert_eq!(
+        acl.check(Some("reader"), "set", &["public:1"]),
+        Err("NOPERM User reader has no permissions to run the 'set' command".to_string())
+    );
+    assert!(acl.check(Some("reader"), "acl|whoami", &[]).is_err());
+    acl.set_user("reader", &rules(&["+acl"])).unwrap();
+    assert_eq!(acl.check(Some("reader"), "acl|whoami", &[]), Ok(()));
+    acl.set_user("reader", &rules(&["+acl|cat", "-acl"])).unwrap();
+    assert!(acl.check(Some("reader"), "acl|cat", &[]).is_err());
+    // Aliases have their own permissions
+    acl.set_user("reader", &rules(&["+incr"])).unwrap();
+    assert_eq!(acl.check(Some("reader"), "incr", &["public:1"]), Ok(()));
+    assert!(acl.check(Some("reader"), "incrby", &["public:1"]).is_err());
+    // Commands missing from the table are denied, even to users allowed
+    // everything
+    assert_eq!(
+        acl.check(Some("default"), "nosuchcommand", &[]),
+        Err("NOPERM User default has no permissions to run the 'nosuchcommand' command".to_string())
+    );
+    assert_eq!(acl.check_authenticated(Some("reader")), Ok(()));
+    assert!(acl.check_authenticated(None).is_err());
+}
+
+#[test]
+fn test_every_command_has_an_acl_entry() {
+    // The names `Command::from_frame` dispatches on, read from its match arms
+    let source = include_str!("../src/cmd/mod.rs");
+    let from_frame = &source[source.find("pub fn from_frame").unwrap()..];
+    let from_frame = &from_frame[..from_frame.find("parse.finish()").unwrap()];
+    let names: Vec<&str> = from_frame
+        .lines()
+        .filter_map(|line| line.trim().split_once(" => Command::"))
+        .flat_map(|(pattern, _)| pattern.split(" | ").map(|name| name.trim_matches('"')))
+        .collect();
+    assert!(names.len() > 80, "{:?}", names);
+
+    let known = category_commands("all").unwrap();
+    let missing: Vec<&&str> = names.iter().filter(|name| !known.contains(**name)).collect();
+    assert!(missing.is_empty(), "commands without an ACL entry: {:?}", missing);
+}
+
+#[test]
+fn test_acl_file_round_trip() {
+    let path = std::env::temp_dir().join(format!("mini-redis-users-{}.acl", std::process::id()));
+    let mut acl = Acl::new();
+    acl.set_requirepass("letmein");
+    acl.set_user("alice", &rules(&["on", ">a", ">b", "~x*", "~y", "+@string", "-append"])).unwrap();
+    acl.set_user("eve", &rules(&["off", "+@all", "-@dangerous"])).unwrap();
+    acl.save(&path).unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("letmein"));
+    assert!(contents.lines().next().unwrap().starts_with("user alice on #"));
+
+    let loaded = Acl::load(&path).unwrap();
+    assert_eq!(loaded, acl);
+    assert!(loaded.authenticate("default", b"letmein"));
+    assert!(!loaded.authenticate("eve", b""));
+
+    std::fs::write(&path, "user alice on\nuser alice off\n").unwrap();
+    assert!(Acl::load(&path).unwrap_err().to_string().ends_with(":2: duplicate user 'alice'"));
+    std::fs::write(&path, "user bob on +nosuchcommand\n").unwrap();
+    assert!(Acl::load(&path).is_err());
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn test_requirepass() {
+    let (addr, shutdown) = common::start_server_with("requirepass", |config| config.requirepass = "secret".to_string()).await;
+    let mut conn = connect(&addr).await;
+
+    assert_eq!(error(request(&mut conn, &["GET", "key"]).await), "NOAUTH Authentication required.");
+    assert!(error(request(&mut conn, &["HELLO", "3"]).await).starts_with("NOAUTH"));
+    assert!(error(request(&mut conn, &["AUTH", "wrong"]).await).starts_with("WRONGPASS"));
+    assert_eq!(request(&mut conn, &["AUTH", "secret"]).await, simple("OK"));
+    assert_eq!(request(&mut conn, &["GET", "key"]).await, Frame::Null);
+
+    // Dropping the password opens the server up again
+    assert_eq!(request(&mut conn, &["CONFIG", "SET", "requirepass", ""]).await, simple("OK"));
+    let mut other = connect(&addr).await;
+    assert_eq!(request(&mut other, &["GET", "key"]).await, Frame::Null);
+    assert!(error(request(&mut other, &["AUTH", "secret"]).await).contains("without any password configured"));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_user_permissions() {
+    let (addr, shutdown) = common::start_server("acl-users").await;
+    let mut admin = Client::connect(&addr).await.unwrap();
+    admin.acl_setuser("app", &["on", ">apppw", "~app:*", "+@read", "+@write", "+@transaction", "-@dangerous", "+acl|whoami"]).await.unwrap();
+
+    let mut conn = connect(&addr).await;
+    assert_eq!(request(&mut conn, &["AUTH", "app", "apppw"]).await, simple("OK"));
+    assert_eq!(request(&mut conn, &["ACL", "WHOAMI"]).await, Frame::Bulk(b("app")));
+    assert_eq!(request(&mut conn, &["SET", "app:1", "x"]).await, simple("OK"));
+    assert_eq!(error(request(&mut conn, &["SET", "other", "x"]).await), "NOPERM No permissions to access a key");
+    assert_eq!(
+        error(request(&mut conn, &["FLUSHDB"]).await),
+        "NOPERM User app has no permissions to run the 'flushdb' command"
+    );
+
+    // A denied command aborts the transaction it was queued in
+    assert_eq!(request(&mut conn, &["MULTI"]).await, simple("OK"));
+    assert_eq!(request(&mut conn, &["SET", "app:2", "y"]).await, simple("QUEUED"));
+    assert!(error(request(&mut conn, &["GET", "secret"]).await).starts_with("NOPERM"));
+    assert!(error(request(&mut conn, &["EXEC"]).await).starts_with("EXECABORT"));
+    assert_eq!(admin.get("app:2").await.unwrap(), None);
+
+    // HELLO can authenticate too
+    let mut hello = connect(&addr).await;
+    assert!(matches!(request(&mut hello, &["HELLO", "2", "AUTH", "app", "apppw"]).await, Frame::Array(_)));
+    assert_eq!(request(&mut hello, &["ACL", "WHOAMI"]).await, Frame::Bulk(b("app")));
+    assert!(error(request(&mut hello, &["HELLO", "2", "AUTH", "app", "nope"]).await).starts_with("WRONGPASS"));
+
+    // Deleting the user logs its connections out
+    assert_eq!(admin.acl_deluser(&["app"]).await.unwrap(), 1);
+    assert!(error(request(&mut conn, &["GET", "app:1"]).await).starts_with("NOAUTH"));
+    assert!(admin.acl_list().await.unwrap().iter().all(|user| !user.starts_with("user app")));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_replicaof_aliases_need_admin() {
+    let (addr, shutdown) = common::start_server("acl-replicaof").await;
+    let mut admin = Client::connect(&addr).await.unwrap();
+    admin.acl_setuser("reader", &["on", ">readerpw", "~*", "+@read"]).await.unwrap();
+
+    let mut conn = connect(&addr).await;
+    assert_eq!(request(&mut conn, &["AUTH", "reader", "readerpw"]).await, simple("OK"));
+    for command in ["REPLICAOF", "SLAVEOF"] {
+        assert_eq!(
+            error(request(&mut conn, &[command, "127.0.0.1", "1"]).await),
+            format!("NOPERM User reader has no permissions to run the '{}' command", command.to_lowercase())
+        );
+    }
+    let info = admin.info(Some("replication")).await.unwrap();
+    assert!(info.contains("role:master"), "{}", info);
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_acl_file_survives_restart() {
+    let path = std::env::temp_dir().join(format!("mini-redis-restart-{}.acl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aclfile = path.to_str().unwrap().to_string();
+
+    let file = aclfile.clone();
+    let (addr, shutdown) = common::start_server_with("acl-save", move |config| config.aclfile = file).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+    client.acl_setuser("default", &["resetpass", ">adminpw"]).await.unwrap();
+    client.acl_setuser("worker", &["on", ">w", "allkeys", "+get", "+acl|whoami"]).await.unwrap();
+    client.acl_save().await.unwrap();
+    let _ = shutdown.send(());
+
+    let (addr, shutdown) = common::start_server_with("acl-load", move |config| config.aclfile = aclfile).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+    assert!(client.get("key").await.unwrap_err().to_string().contains("NOAUTH"));
+    client.auth(Some("worker"), "w").await.unwrap();
+    assert_eq!(client.acl_whoami().await.unwrap(), "worker");
+    assert_eq!(client.get("key").await.unwrap(), None);
+    client.auth(None, "adminpw").await.unwrap();
+    assert_eq!(client.acl_whoami().await.unwrap(), "default");
+
+    let _ = shutdown.send(());
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use common::temp_path;
+use mini_redis_tls::{Aof, Client, Command, Db, FsyncPolicy};
+use mini_redis_tls::cmd::set::Set;
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const TWO_SETS: &[u8] = b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nagain\r\n";
+
+#[tokio::test]
+async fn test_load_replays_commands() {
+    let path = temp_path("replay", "aof");
+    std::fs::write(&path, TWO_SETS).unwrap();
+
+    let db = Db::new();
+    let applied = Aof::load(&path, &db, false).await.unwrap();
+
+    assert_eq!(applied, 2);
+    assert_eq!(db.get("hello"), Some(Bytes::from("again")));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_load_missing_file() {
+    let path = temp_path("missing", "aof");
+
+    let db = Db::new();
+    assert_eq!(Aof::load(&path, &db, false).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_load_truncated_tail() {
+    let path = temp_path("truncated", "aof");
+    let mut contents = TWO_SETS.to_vec();
+    contents.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nba");
+    std::fs::write(&path, &contents).unwrap();
+
+    // Refuse to start when truncation is not allowed
+    let db = Db::new();
+    assert!(Aof::load(&path, &db, false).await.is_err());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), contents.len() as u64);
+
+    // Otherwise drop the partial frame and keep everything before it
+    let db = Db::new();
+    assert_eq!(Aof::load(&path, &db, true).await.unwrap(), 2);
+    assert_eq!(db.get("hello"), Some(Bytes::from("again")));
+    assert_eq!(db.get("foo"), None);
+    assert_eq!(std::fs::read(&path).unwrap(), TWO_SETS);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_load_rejects_corrupt_frame() {
+    let path = temp_path("corrupt", "aof");
+    let mut contents = b"?garbage\r\n".to_vec();
+    contents.extend_from_slice(TWO_SETS);
+    std::fs::write(&path, &contents).unwrap();
+
+    let db = Db::new();
+    assert!(Aof::load(&path, &db, true).await.is_err());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn set(key: &str, value: &str) -> Command {
+    Command::Set(Set::new(key, Bytes::from(value.to_string())))
+}
+
+#[tokio::test]
+async fn test_rewrite_compacts_log() {
+    let path = temp_path("rewrite", "aof");
+    let db = Db::new();
+    let aof = Arc::new(Mutex::new(Aof::new(&path).await.unwrap()));
+
+    for i in 0..100 {
+        let value = format!("value-{}", i);
+        db.set("counter".to_string(), Bytes::from(value.clone()));
+        aof.lock().await.append(set("counter", &value)).await.unwrap();
+    }
+    db.set("other".to_string(), Bytes::from("x"));
+    aof.lock().await.append(set("other", "x")).await.unwrap();
+
+    let before = aof.lock().await.size();
+    let handle = Aof::rewrite_in_background(aof.clone(), db.clone()).await.unwrap();
+
+    // A second rewrite is refused while one is running or pending
+    assert!(aof.lock().await.is_rewriting());
+    assert!(Aof::rewrite_in_background(aof.clone(), db.clone()).await.is_err());
+
+    // Writes made during the rewrite must survive the swap
+    db.set("late".to_string(), Bytes::from("y"));
+    aof.lock().await.append(set("late", "y")).await.unwrap();
+
+    handle.await.unwrap();
+    assert!(!aof.lock().await.is_rewriting());
+    assert!(aof.lock().await.size() < before);
+
+    // Appends after the swap go to the new file
+    aof.lock().await.append(set("after", "z")).await.unwrap();
+    aof.lock().await.sync().await.unwrap();
+
+    let restored = Db::new();
+    Aof::load(&path, &restored, false).await.unwrap();
+    assert_eq!(restored.get("counter"), Some(Bytes::from("value-99")));
+    assert_eq!(restored.get("other"), Some(Bytes::from("x")));
+    assert_eq!(restored.get("late"), Some(Bytes::from("y")));
+    assert_eq!(restored.get("after"), Some(Bytes::from("z")));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_concurrent_writes_replay_in_order() {
+    let path = temp_path("concurrent", "aof");
+    let appendfilename = path.to_str().unwrap().to_string();
+    let (addr, _shutdown) = common::start_server_with("aof_concurrent", |config| config.appendfilename = appendfilename).await;
+
+    // Many clients push onto a few lists at once, so writes to different
+    // shards run alongside each other
+    let mut clients = Vec::new();
+    for client in 0..16 {
+        let addr = addr.clone();
+        clients.push(tokio::spawn(async move {
+            let mut connection = Client::connect(&addr).await.unwrap();
+            for i in 0..50 {
+                let value = Bytes::from(format!("{}:{}", client, i));
+                connection.rpush(&format!("list:{}", i % 4), vec![value]).await.unwrap();
+            }
+        }));
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    // Every write was appended before it was acknowledged, in the order it
+    // was applied in
+    let restored = Db::new();
+    assert_eq!(Aof::load(&path, &restored, false).await.unwrap(), 16 * 50);
+    let mut client = Client::connect(&addr).await.unwrap();
+    for i in 0..4 {
+        let key = format!("list:{}", i);
+        assert_eq!(restored.lrange(&key, 0, -1).unwrap(), client.lrange(&key, 0, -1).await.unwrap());
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_auto_rewrite_threshold() {
+    let path = temp_path("auto-rewrite", "aof");
+    let mut aof = Aof::new(&path).await.unwrap();
+    aof.set_auto_rewrite(100, 64);
+
+    // Below the minimum size nothing triggers
+    aof.append(set("a", "1")).await.unwrap();
+    assert!(!aof.should_rewrite());
+
+    // From an empty base, any file past the minimum size has grown enough
+    for _ in 0..10 {
+        aof.append(set("a", "1")).await.unwrap();
+    }
+    assert!(aof.should_rewrite());
+
+    aof.set_auto_rewrite(0, 64);
+    assert!(!aof.should_rewrite());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_fsync_policy_from_str() {
+    assert_eq!("always".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::Always);
+    assert_eq!("EVERYSEC".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::EverySec);
+    assert_eq!("no".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::No);
+    assert!("sometimes".parse::<FsyncPolicy>().is_err());
+    assert_eq!(FsyncPolicy::EverySec.to_string(), "everysec");
+}
+
+#[tokio::test]
+async fn test_fsync_always_writes_through() {
+    let path = temp_path("fsync-always", "aof");
+    let mut aof = Aof::new(&path).await.unwrap();
+    aof.set_fsync_policy(FsyncPolicy::Always);
+
+    aof.append(set("a", "1")).await.unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), aof.size());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_fsync_everysec_batches_writes() {
+    let path = temp_path("fsync-everysec", "aof");
+    let mut aof = Aof::new(&path).await.unwrap();
+    aof.set_fsync_policy(FsyncPolicy::EverySec);
+    let aof = Arc::new(Mutex::new(aof));
+    let task = Aof::start_fsync_task(&aof);
+
+    aof.lock().await.append(set("a", "1")).await.unwrap();
+    let expected = aof.lock().await.size();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), expected);
+
+    // The background task stops once the AOF is gone
+    drop(aof);
+    tokio::time::timeout(std::time::Duration::from_secs(3), task).await.unwrap().unwrap();
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::start_server;
+use mini_redis_tls::Client;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn test_blpop_returns_available_value() {
+    let (addr, _shutdown) = start_server("blpop-ready").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    client.rpush("b", vec![Bytes::from("1"), Bytes::from("2")]).await.unwrap();
+    let popped = client.blpop(&["a", "b"], Some(Duration::from_secs(1))).await.unwrap();
+    assert_eq!(popped, Some(("b".to_string(), Bytes::from("1"))));
+
+    let popped = client.brpop(&["b"], None).await.unwrap();
+    assert_eq!(popped, Some(("b".to_string(), Bytes::from("2"))));
+}
+
+#[tokio::test]
+async fn test_blpop_times_out() {
+    let (addr, _shutdown) = start_server("blpop-timeout").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    let popped = client.blpop(&["missing"], Some(Duration::from_millis(100))).await.unwrap();
+    assert_eq!(popped, None);
+
+    // The connection is still usable afterwards
+    client.rpush("missing", vec![Bytes::from("x")]).await.unwrap();
+    assert_eq!(client.lpop("missing").await.unwrap(), Some(Bytes::from("x")));
+}
+
+#[tokio::test]
+async fn test_blocked_clients_are_served_in_order() {
+    let (addr, _shutdown) = start_server("blpop-fifo").await;
+
+    let mut waiters = Vec::new();
+    for _ in 0..3 {
+        let mut client = Client::connect(&addr).await.unwrap();
+        waiters.push(tokio::spawn(async move { client.blpop(&["other", "queue"], None).await.unwrap() }));
+        // Give each client time to block before the next one arrives
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let mut producer = Client::connect(&addr).await.unwrap();
+    for job in ["job-1", "job-2", "job-3"] {
+        producer.rpush("queue", vec![Bytes::from(job)]).await.unwrap();
+    }
+
+    for (waiter, job) in waiters.into_iter().zip(["job-1", "job-2", "job-3"]) {
+        let popped = tokio::time::timeout(Duration::from_secs(2), waiter).await.unwrap().unwrap();
+        assert_eq!(popped, Some(("queue".to_string(), Bytes::from(job))));
+    }
+    assert_eq!(producer.lrange("queue", 0, -1).await.unwrap(), Vec::<Bytes>::new());
+}
+
+#[tokio::test]
+async fn test_new_clients_wait_their_turn() {
+    let (addr, _shutdown) = start_server("blpop-turn").await;
+
+    let mut waiters = Vec::new();
+    for _ in 0..2 {
+        let mut client = Client::connect(&addr).await.unwrap();
+        waiters.push(tokio::spawn(async move { client.blpop(&["queue"], None).await.unwrap() }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Pipelined right behind the push, so they run before the waiters are
+    // scheduled: neither a plain LPOP nor a new BLPOP gets the values owed to
+    // the clients already waiting
+    let mut producer = TcpStream::connect(&addr).await.unwrap();
+    producer
+        .write_all(b"*4\r\n$5\r\nRPUSH\r\n$5\r\nqueue\r\n$5\r\njob-1\r\n$5\r\njob-2\r\n*2\r\n$4\r\nLPOP\r\n$5\r\nqueue\r\n*3\r\n$5\r\nBLPOP\r\n$5\r\nqueue\r\n$1\r\n0\r\n")
+        .await
+        .unwrap();
+    let expected = b":2\r\n$-1\r\n";
+    let mut replies = vec![0; expected.len()];
+    producer.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies, expected);
+
+    for (waiter, job) in waiters.into_iter().zip(["job-1", "job-2"]) {
+        let popped = tokio::time::timeout(Duration::from_secs(2), waiter).await.unwrap().unwrap();
+        assert_eq!(popped, Some(("queue".to_string(), Bytes::from(job))));
+    }
+    let mut client = Client::connect(&addr).await.unwrap();
+    client.rpush("queue", vec![Bytes::from("job-3")]).await.unwrap();
+    let expected = b"*2\r\n$5\r\nqueue\r\n$5\r\njob-3\r\n";
+    let mut reply = vec![0; expected.len()];
+    tokio::time::timeout(Duration::from_secs(2), producer.read_exact(&mut reply)).await.unwrap().unwrap();
+    assert_eq!(reply, expected);
+}
+
+#[tokio::test]
+async fn test_disconnected_client_does_not_take_values() {
+    let (addr, _shutdown) = start_server("blpop-disconnect").await;
+
+    let mut gone = Client::connect(&addr).await.unwrap();
+    let waiter = tokio::spawn(async move { gone.blpop(&["queue"], None).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    waiter.abort();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&addr).await.unwrap();
+    client.rpush("queue", vec![Bytes::from("job")]).await.unwrap();
+    let popped = client.blpop(&["queue"], Some(Duration::from_secs(1))).await.unwrap();
+    assert_eq!(popped, Some(("queue".to_string(), Bytes::from("job"))));
+}
@@ -0,0 +1,341 @@
+use mini_redis_tls::{codec, Connection, Error, Frame, Protocol};
+use bytes::{Bytes, BytesMut};
+use proptest::prelude::*;
+
+fn encoded(frame: &Frame) -> Vec<u8> {
+    encoded_in(frame, Protocol::Resp2)
+}
+
+fn encoded_in(frame: &Frame, protocol: Protocol) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    codec::encode(frame, protocol, &mut buf);
+    buf.to_vec()
+}
+
+fn decoded(bytes: &[u8]) -> Result<Option<(Frame, usize)>, Error> {
+    codec::decode(bytes)
+}
+
+fn bulk() -> impl Strategy<Value = Bytes> {
+    proptest::collection::vec(any::<u8>(), 0..64).prop_map(Bytes::from)
+}
+
+/// RESP2 frames that survive an encode/decode round trip: simple strings and
+/// errors are single lines.
+fn frame() -> impl Strategy<Value = Frame> {
+    let line = "[^\r\n]{0,16}";
+    let leaf = prop_oneof![
+        line.prop_map(Frame::Simple),
+        line.prop_map(Frame::Error),
+        any::<i64>().prop_map(Frame::Integer),
+        bulk().prop_map(Frame::Bulk),
+        Just(Frame::Null),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| proptest::collection::vec(inner, 0..8).prop_map(Frame::Array))
+}
+
+/// Any frame, RESP3 types included. NaN is left out as it never compares
+/// equal to itself.
+fn resp3_frame() -> impl Strategy<Value = Frame> {
+    let leaf = prop_oneof![
+        frame(),
+        any::<f64>().prop_filter("NaN", |f| !f.is_nan()).prop_map(Frame::Double),
+        any::<bool>().prop_map(Frame::Boolean),
+        "-?[0-9]{1,40}".prop_map(Frame::BigNumber),
+        ("[a-z]{3}", bulk()).prop_map(|(format, text)| Frame::Verbatim(format, text)),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        let items = proptest::collection::vec(inner.clone(), 0..8);
+        let pairs = proptest::collection::vec((inner.clone(), inner.clone()), 0..4);
+        prop_oneof![
+            items.clone().prop_map(Frame::Array),
+            items.clone().prop_map(Frame::Set),
+            items.prop_map(Frame::Push),
+            pairs.clone().prop_map(Frame::Map),
+            (pairs, inner).prop_map(|(pairs, frame)| Frame::Attribute(pairs, Box::new(frame))),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn roundtrip(frame in frame()) {
+        let bytes = encoded(&frame);
+        let (decoded, len) = decoded(&bytes).unwrap().unwrap();
+        prop_assert_eq!(decoded, frame);
+        prop_assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn resp3_roundtrip(frame in resp3_frame()) {
+        let bytes = encoded_in(&frame, Protocol::Resp3);
+        let (decoded, len) = decoded(&bytes).unwrap().unwrap();
+        prop_assert_eq!(decoded, frame);
+        prop_assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn resp3_frames_downgrade_to_valid_resp2(frame in resp3_frame()) {
+        let bytes = encoded(&frame);
+        let (decoded, len) = decoded(&bytes).unwrap().unwrap();
+        prop_assert_eq!(len, bytes.len());
+        prop_assert_eq!(encoded(&decoded), bytes);
+    }
+
+    #[test]
+    fn every_prefix_is_incomplete(frame in resp3_frame()) {
+        let bytes = encoded_in(&frame, Protocol::Resp3);
+        for end in 0..bytes.len() {
+            prop_assert!(matches!(decoded(&bytes[..end]), Ok(None)), "prefix of {} bytes", end);
+        }
+    }
+
+    #[test]
+    fn decodes_back_to_back_frames(frames in proptest::collection::vec(frame(), 1..8)) {
+        let mut bytes = Vec::new();
+        for frame in &frames {
+            bytes.extend(encoded(frame));
+        }
+
+        let mut rest = &bytes[..];
+        for frame in &frames {
+            let (decoded, len) = decoded(rest).unwrap().unwrap();
+            prop_assert_eq!(&decoded, frame);
+            rest = &rest[len..];
+        }
+        prop_assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn decoder_resumes_across_reads(
+        frames in proptest::collection::vec(resp3_frame(), 1..8),
+        chunk in 1usize..64,
+    ) {
+        let mut bytes = Vec::new();
+        for frame in &frames {
+            bytes.extend(encoded_in(frame, Protocol::Resp3));
+        }
+
+        // Feed the stream in small reads, as a socket might deliver it
+        let mut decoder = codec::Decoder::new();
+        let mut buf = BytesMut::new();
+        let mut decoded = vec![];
+        for piece in bytes.chunks(chunk) {
+            buf.extend_from_slice(piece);
+            while let Some(frame) = decoder.decode(&mut buf).unwrap() {
+                decoded.push(frame);
+            }
+        }
+        prop_assert_eq!(decoded, frames);
+        prop_assert!(buf.is_empty());
+        prop_assert!(decoder.is_idle());
+    }
+
+    #[test]
+    fn garbage_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+        let _ = decoded(&bytes);
+    }
+}
+
+#[test]
+fn test_wire_format() {
+    let frame = Frame::Array(vec![
+        Frame::Simple("OK".into()),
+        Frame::Error("ERR oops".into()),
+        Frame::Integer(-42),
+        Frame::Bulk(Bytes::from("a\r\nb")),
+        Frame::Null,
+        Frame::Array(vec![]),
+    ]);
+    assert_eq!(encoded(&frame), b"*6\r\n+OK\r\n-ERR oops\r\n:-42\r\n$4\r\na\r\nb\r\n$-1\r\n*0\r\n".to_vec());
+
+    // Line breaks can't be sent in a simple string
+    assert_eq!(encoded(&Frame::Error("ERR a\r\nb".into())), b"-ERR a  b\r\n".to_vec());
+}
+
+#[test]
+fn test_resp3_wire_format() {
+    let frame = Frame::Map(vec![
+        (Frame::Simple("null".into()), Frame::Null),
+        (Frame::Simple("double".into()), Frame::Double(1.5)),
+        (Frame::Simple("bool".into()), Frame::Boolean(true)),
+        (Frame::Simple("big".into()), Frame::BigNumber("-12345678901234567890".into())),
+        (Frame::Simple("text".into()), Frame::Verbatim("txt".into(), Bytes::from("hi"))),
+        (Frame::Simple("set".into()), Frame::Set(vec![Frame::Integer(1)])),
+    ]);
+    assert_eq!(
+        encoded_in(&frame, Protocol::Resp3),
+        b"%6\r\n+null\r\n_\r\n+double\r\n,1.5\r\n+bool\r\n#t\r\n+big\r\n(-12345678901234567890\r\n\
+          +text\r\n=6\r\ntxt:hi\r\n+set\r\n~1\r\n:1\r\n"
+            .to_vec()
+    );
+
+    let push = Frame::Push(vec![Frame::Bulk(Bytes::from("message"))]);
+    assert_eq!(encoded_in(&push, Protocol::Resp3), b">1\r\n$7\r\nmessage\r\n".to_vec());
+
+    let attribute = Frame::Attribute(vec![(Frame::Simple("ttl".into()), Frame::Integer(3))], Box::new(Frame::Integer(1)));
+    assert_eq!(encoded_in(&attribute, Protocol::Resp3), b"|1\r\n+ttl\r\n:3\r\n:1\r\n".to_vec());
+
+    for (text, val) in [(&b",inf\r\n"[..], f64::INFINITY), (b",-inf\r\n", f64::NEG_INFINITY)] {
+        assert_eq!(encoded_in(&Frame::Double(val), Protocol::Resp3), text.to_vec());
+        assert_eq!(decoded(text).unwrap(), Some((Frame::Double(val), text.len())));
+    }
+    assert_eq!(encoded_in(&Frame::Double(f64::NAN), Protocol::Resp3), b",nan\r\n".to_vec());
+    assert!(matches!(decoded(b",nan\r\n").unwrap(), Some((Frame::Double(val), 6)) if val.is_nan()));
+
+    // Blob errors decode to plain errors
+    assert_eq!(decoded(b"!8\r\nERR a\r\nb\r\n").unwrap(), Some((Frame::Error("ERR a\r\nb".into()), 14)));
+}
+
+#[test]
+fn test_resp3_downgrade() {
+    let frame = Frame::Array(vec![
+        Frame::Map(vec![(Frame::Bulk(Bytes::from("f")), Frame::Bulk(Bytes::from("v")))]),
+        Frame::Set(vec![Frame::Integer(1)]),
+        Frame::Double(2.5),
+        Frame::Boolean(false),
+        Frame::BigNumber("123".into()),
+        Frame::Verbatim("txt".into(), Bytes::from("hi")),
+        Frame::Push(vec![]),
+        Frame::Attribute(vec![(Frame::Integer(1), Frame::Integer(2))], Box::new(Frame::Null)),
+    ]);
+    assert_eq!(
+        encoded(&frame),
+        b"*8\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n*1\r\n:1\r\n$3\r\n2.5\r\n:0\r\n$3\r\n123\r\n$2\r\nhi\r\n*0\r\n$-1\r\n"
+            .to_vec()
+    );
+}
+
+#[test]
+fn test_null_array() {
+    assert_eq!(decoded(b"*-1\r\n").unwrap(), Some((Frame::Null, 5)));
+    assert_eq!(
+        decoded(b"*2\r\n*-1\r\n$-1\r\n").unwrap(),
+        Some((Frame::Array(vec![Frame::Null, Frame::Null]), 14))
+    );
+}
+
+#[test]
+fn test_rejects_malformed_frames() {
+    assert!(decoded(b"?\r\n").is_err());
+    assert!(decoded(b":12a\r\n").is_err());
+    assert!(decoded(b"$-2\r\n").is_err());
+    assert!(decoded(b"*-5\r\n").is_err());
+    assert!(decoded(b"$3\r\nabcde\r\n").is_err());
+    assert!(decoded(b"$999999999999\r\n").is_err());
+    assert!(decoded(b"#x\r\n").is_err());
+    assert!(decoded(b",1.5.2\r\n").is_err());
+    assert!(decoded(b"(12a\r\n").is_err());
+    assert!(decoded(b"=2\r\nhi\r\n").is_err());
+    assert!(decoded(b"%-1\r\n").is_err());
+
+    let mut deep = b"*1\r\n".repeat(codec::MAX_DEPTH + 1);
+    deep.extend_from_slice(b":1\r\n");
+    assert!(decoded(&deep).is_err());
+}
+
+#[tokio::test]
+async fn test_connection_roundtrip() {
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = Connection::new(client);
+    let mut server = Connection::new(server);
+
+    let frame = Frame::Array(vec![
+        Frame::Array(vec![Frame::Bulk(Bytes::from("0")), Frame::Array(vec![])]),
+        Frame::Null,
+        Frame::Error("WRONGTYPE nope".into()),
+        Frame::Bulk(Bytes::from(vec![0u8; 1000])),
+    ]);
+    let written = frame.clone();
+    let writer = tokio::spawn(async move { client.write_frame(&written).await.unwrap() });
+
+    assert_eq!(server.read_frame().await.unwrap(), Some(frame));
+    writer.await.unwrap();
+    assert_eq!(server.read_frame().await.unwrap(), None);
+}
+
+#[test]
+fn test_decoder_shares_large_payloads() {
+    let large = Bytes::from(vec![b'x'; codec::SHARE_MIN_LEN]);
+    let frame = Frame::Array(vec![Frame::Bulk(Bytes::from("small")), Frame::Bulk(large.clone())]);
+    let mut buf = BytesMut::from(&encoded(&frame)[..]);
+    let payload = buf.as_ptr() as usize + buf.len() - large.len() - 2;
+
+    match codec::Decoder::new().decode(&mut buf).unwrap() {
+        Some(Frame::Array(items)) => match &items[1] {
+            Frame::Bulk(value) => {
+                assert_eq!(value, &large);
+                assert_eq!(value.as_ptr() as usize, payload, "large payloads are not copied");
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        },
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+#[test]
+fn test_decoder_consumes_partial_frames() {
+    let mut decoder = codec::Decoder::new();
+    let mut buf = BytesMut::from(&b"*2\r\n$5\r\nhel"[..]);
+
+    // The headers are consumed; only the unfinished payload is kept
+    assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+    assert_eq!(&buf[..], b"hel");
+    assert!(!decoder.is_idle());
+
+    buf.extend_from_slice(b"lo\r\n:1");
+    assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+    assert_eq!(&buf[..], b":1");
+
+    buf.extend_from_slice(b"\r\n");
+    assert_eq!(
+        decoder.decode(&mut buf).unwrap(),
+        Some(Frame::Array(vec![Frame::Bulk(Bytes::from("hello")), Frame::Integer(1)]))
+    );
+    assert!(decoder.is_idle());
+}
+
+#[test]
+fn test_split_args() {
+    let split = |line: &str| codec::split_args(line.as_bytes()).map(|args| args.into_iter().map(|a| a.to_vec()).collect::<Vec<_>>());
+    let ok = |args: &[&[u8]]| args.iter().map(|a| a.to_vec()).collect::<Vec<_>>();
+
+    assert_eq!(split("GET foo").unwrap(), ok(&[b"GET", b"foo"]));
+    assert_eq!(split("  SET \t k   v  ").unwrap(), ok(&[b"SET", b"k", b"v"]));
+    assert_eq!(split("").unwrap(), ok(&[]));
+    assert_eq!(split(r#"SET k "hello world""#).unwrap(), ok(&[b"SET", b"k", b"hello world"]));
+    assert_eq!(split(r#"SET k "a\"b\n\x41\x4""#).unwrap(), ok(&[b"SET", b"k", b"a\"b\nAx4"]));
+    assert_eq!(split(r#"SET k 'it\'s "raw" \n'"#).unwrap(), ok(&[b"SET", b"k", b"it's \"raw\" \\n"]));
+    assert_eq!(split(r#"SET k """#).unwrap(), ok(&[b"SET", b"k", b""]));
+
+    assert!(split(r#"SET k "open"#).is_err());
+    assert!(split(r#"SET k 'open"#).is_err());
+    assert!(split(r#"SET k "a"b"#).is_err());
+}
+
+#[test]
+fn test_decode_inline() {
+    assert_eq!(codec::decode_inline(b"PING\r\nGET").unwrap(), Some((vec![Bytes::from("PING")], 6)));
+    assert_eq!(codec::decode_inline(b"PING\n").unwrap(), Some((vec![Bytes::from("PING")], 5)));
+    assert_eq!(codec::decode_inline(b"\r\n").unwrap(), Some((vec![], 2)));
+    assert_eq!(codec::decode_inline(b"GET fo").unwrap(), None);
+    assert!(codec::decode_inline(&vec![b'a'; codec::MAX_INLINE_LEN + 1]).is_err());
+}
+
+#[tokio::test]
+async fn test_connection_reads_inline_commands() {
+    use tokio::io::AsyncWriteExt;
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let mut server = Connection::new(server);
+    client.write_all(b"\r\nPING\r\nSET k \"a b\"\nGET k").await.unwrap();
+
+    let command = |args: &[&str]| Frame::Array(args.iter().map(|a| Frame::Bulk(Bytes::from(a.to_string()))).collect());
+    assert_eq!(server.read_frame().await.unwrap(), Some(command(&["PING"])));
+    assert_eq!(server.read_frame().await.unwrap(), Some(command(&["SET", "k", "a b"])));
+
+    // The last line is only complete once its newline arrives
+    client.write_all(b"\r\n*1\r\n$4\r\nPING\r\n").await.unwrap();
+    assert_eq!(server.read_frame().await.unwrap(), Some(command(&["GET", "k"])));
+    assert_eq!(server.read_frame().await.unwrap(), Some(command(&["PING"])));
+}
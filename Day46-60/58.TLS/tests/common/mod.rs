@@ -0,0 +1,148 @@
+// Each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use mini_redis_tls::{server, Client, Command, Config, Connection, Db, Frame, FsyncPolicy};
+use mini_redis_tls::db::{now_ms, SetCondition};
+use bytes::{Bytes, BytesMut};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+pub fn b(s: &str) -> Bytes {
+    Bytes::from(s.to_string())
+}
+
+/// Parses `args` the way the server would, from a RESP array of bulk
+/// strings.
+pub fn try_command(args: &[&str]) -> Result<Command, mini_redis_tls::Error> {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+    let frame = mini_redis_tls::parse(&mut Cursor::new(&buf[..])).unwrap();
+    Command::from_frame(frame)
+}
+
+pub fn command(args: &[&str]) -> Command {
+    try_command(args).unwrap()
+}
+
+pub fn bulks(items: &[&str]) -> Frame {
+    Frame::Array(items.iter().map(|item| Frame::Bulk(b(item))).collect())
+}
+
+pub fn simple(s: &str) -> Frame {
+    Frame::Simple(s.to_string())
+}
+
+pub fn error(frame: Frame) -> String {
+    match frame {
+        Frame::Error(msg) => msg,
+        frame => panic!("expected an error, got {:?}", frame),
+    }
+}
+
+/// A raw connection to the server at `addr`, for sending frames the
+/// `Client` has no method for.
+pub async fn connect(addr: &str) -> Connection {
+    Connection::new(TcpStream::connect(addr).await.unwrap())
+}
+
+pub async fn send(connection: &mut Connection, args: &[&str]) {
+    connection.write_frame(&bulks(args)).await.unwrap();
+}
+
+pub async fn read(connection: &mut Connection) -> Frame {
+    tokio::time::timeout(Duration::from_secs(5), connection.read_frame()).await.unwrap().unwrap().unwrap()
+}
+
+pub async fn request(connection: &mut Connection, args: &[&str]) -> Frame {
+    send(connection, args).await;
+    read(connection).await
+}
+
+/// A path in the temporary directory, unique to `name` and this test
+/// process, with any file left there by an earlier run removed.
+pub fn temp_path(name: &str, ext: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}.{}", name, std::process::id(), ext));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// A keyspace with a key of every type, empty and large strings, and keys
+/// with a TTL.
+pub fn seeded_db() -> Db {
+    let db = Db::new();
+    db.set("hello".to_string(), b("world"));
+    db.set("empty".to_string(), Bytes::new());
+    db.set("large".to_string(), Bytes::from(vec![7u8; 100_000]));
+    db.set_with_options("session".to_string(), b("abc"), Some(now_ms() + 60_000), false, SetCondition::Always);
+    db.push("list", vec![b("a"), b("b")], false).unwrap();
+    db.hset("hash", vec![(b("f"), b("v"))]).unwrap();
+    db.sadd("set", vec![b("m")]).unwrap();
+    db.zadd("zset", vec![(1.5, b("a")), (f64::INFINITY, b("b"))], SetCondition::Always, false).unwrap();
+    db.expire_at("list", (now_ms() + 60_000) as i64);
+    db
+}
+
+/// Starts a server on a free port with its own persistence files and returns
+/// its address along with the handle that shuts it down.
+pub async fn start_server(name: &str) -> (String, broadcast::Sender<()>) {
+    start_server_with(name, |_| {}).await
+}
+
+/// Like `start_server`, letting `configure` change the configuration first.
+pub async fn start_server_with(name: &str, configure: impl FnOnce(&mut Config)) -> (String, broadcast::Sender<()>) {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let aof = temp_path(name, "aof");
+    let rdb = temp_path(name, "rdb");
+
+    let mut config = Config {
+        port,
+        appendfilename: aof.to_str().unwrap().to_string(),
+        dbfilename: rdb.to_str().unwrap().to_string(),
+        appendfsync: FsyncPolicy::No,
+        ..Config::default()
+    };
+    configure(&mut config);
+    let addr = config.addr();
+
+    let (tx, rx) = broadcast::channel(1);
+    tokio::spawn(async move {
+        server::run(config, rx).await.unwrap();
+    });
+    wait_for_listener(&addr).await;
+    (addr, tx)
+}
+
+/// Starts a replica of the server at `master`.
+pub async fn start_replica(name: &str, master: &str) -> (String, broadcast::Sender<()>) {
+    let master_port = port(master);
+    start_server_with(name, |config| config.replicaof = Some(("127.0.0.1".to_string(), master_port))).await
+}
+
+/// Waits, for half a second at most, until something accepts connections
+/// at `addr`.
+pub async fn wait_for_listener(addr: &str) {
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+pub fn port(addr: &str) -> u16 {
+    addr.rsplit(':').next().unwrap().parse().unwrap()
+}
+
+/// The value of `field` in the `INFO` of the server at `addr`, or `None` if
+/// it isn't reported or the server can't be reached.
+pub async fn info_field(addr: &str, field: &str) -> Option<String> {
+    let mut client = Client::connect(addr).await.ok()?;
+    let info = client.info(None).await.ok()?;
+    info.lines().find_map(|line| line.strip_prefix(field)?.strip_prefix(':')).map(str::to_string)
+}
@@ -32,3 +32,44 @@ async fn test_concurrent_access() {
 
     assert_eq!(db.get("key2"), Some(Bytes::from("value2")));
 }
+
+#[test]
+fn test_multi_key_commands_are_atomic_across_shards() {
+    let db = Db::new();
+    let keys: Vec<String> = (0..32).map(|i| format!("key{}", i)).collect();
+    db.mset(keys.iter().map(|key| (key.clone(), Bytes::from("0"))).collect(), false);
+
+    std::thread::scope(|scope| {
+        for writer in 1..=4 {
+            let (db, keys) = (&db, &keys);
+            scope.spawn(move || {
+                for _ in 0..500 {
+                    let value = Bytes::from(writer.to_string());
+                    db.mset(keys.iter().map(|key| (key.clone(), value.clone())).collect(), false);
+                    // Renaming back and forth locks the same two shards with
+                    // the arguments swapped
+                    let moved = format!("moved{}", writer);
+                    db.rename(&keys[writer], &moved, false).unwrap();
+                    db.rename(&moved, &keys[writer], false).unwrap();
+                }
+            });
+        }
+
+        // Readers never see a half-applied MSET
+        for _ in 0..500 {
+            let values = db.mget(&keys[5..]);
+            assert!(values.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", values);
+        }
+    });
+
+    assert_eq!(db.dbsize(), keys.len());
+}
+
+#[test]
+fn test_single_shard() {
+    let db = Db::with_shards(1);
+    db.mset(vec![("a".to_string(), Bytes::from("1")), ("b".to_string(), Bytes::from("2"))], false);
+    db.rename("a", "c", false).unwrap();
+    assert_eq!(db.mget(&["b".to_string(), "c".to_string()]), vec![Some(Bytes::from("2")), Some(Bytes::from("1"))]);
+    assert_eq!(db.keys("*").len(), 2);
+}
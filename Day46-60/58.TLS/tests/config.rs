@@ -0,0 +1,152 @@
+mod common;
+
+use mini_redis_tls::{Client, Config, EvictionPolicy, FsyncPolicy};
+use mini_redis_tls::config::parse_memory;
+use bytes::Bytes;
+use std::path::PathBuf;
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}.conf", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_load_file_with_overrides() {
+    let path = temp_file("load", "# A comment\n\nport 7000\nappendfilename \"my file.aof\"\nmaxmemory 2mb\nAPPENDFSYNC always\n");
+    let config = Config::from_args(args(&[path.to_str().unwrap(), "--port", "7001", "--maxmemory-policy", "allkeys-lfu"])).unwrap();
+
+    assert_eq!(config.port, 7001);
+    assert_eq!(config.appendfilename, "my file.aof");
+    assert_eq!(config.maxmemory, 2 * 1024 * 1024);
+    assert_eq!(config.appendfsync, FsyncPolicy::Always);
+    assert_eq!(config.maxmemory_policy, EvictionPolicy::AllKeysLfu);
+    assert_eq!(config.bind, "127.0.0.1");
+    assert_eq!(config.path, Some(path.clone()));
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_invalid_config_is_rejected() {
+    let path = temp_file("invalid", "port 7000\nmaxmemory lots\n");
+    let err = Config::load(&path).unwrap_err().to_string();
+    assert!(err.ends_with(":2: argument must be a memory value"), "{}", err);
+    let _ = std::fs::remove_file(path);
+
+    assert!(Config::from_args(args(&["--no-such-option", "1"])).is_err());
+    assert!(Config::from_args(args(&["--port"])).is_err());
+    assert_eq!(Config::from_args(vec![]).unwrap(), Config::default());
+}
+
+#[test]
+fn test_parse_memory_units() {
+    assert_eq!(parse_memory("100"), Ok(100));
+    assert_eq!(parse_memory("1k"), Ok(1000));
+    assert_eq!(parse_memory("1KB"), Ok(1024));
+    assert_eq!(parse_memory("3gb"), Ok(3 * 1024 * 1024 * 1024));
+    assert!(parse_memory("1tb").is_err());
+    assert!(parse_memory("-1").is_err());
+}
+
+#[test]
+fn test_rewrite_keeps_comments() {
+    let path = temp_file("rewrite", "# Keep me\nport 7000\nmaxclients 10\nmaxclients 20\n");
+    let mut config = Config::load(&path).unwrap();
+    config.set("maxclients", "30").unwrap();
+    config.set("maxmemory-policy", "volatile-ttl").unwrap();
+    config.set("tls-cert-file", "my cert.pem").unwrap();
+    config.rewrite().unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        contents,
+        "# Keep me\nport 7000\nmaxclients 30\n# Generated by CONFIG REWRITE\ntls-cert-file \"my cert.pem\"\nmaxmemory-policy volatile-ttl\n"
+    );
+    assert_eq!(Config::load(&path).unwrap(), config);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn test_config_get_and_set() {
+    let (addr, shutdown) = common::start_server("config-set").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    let pairs = client.config_get("maxmemory*").await.unwrap();
+    assert_eq!(
+        pairs,
+        vec![("maxmemory".to_string(), "0".to_string()), ("maxmemory-policy".to_string(), "noeviction".to_string())]
+    );
+
+    client.config_set("maxmemory", "1kb").await.unwrap();
+    assert_eq!(client.config_get("maxmemory").await.unwrap(), vec![("maxmemory".to_string(), "1024".to_string())]);
+    let mut oom = None;
+    for i in 0..100 {
+        if let Err(e) = client.set(&format!("key{}", i), Bytes::from(vec![b'x'; 100])).await {
+            oom = Some(e.to_string());
+            break;
+        }
+    }
+    assert!(oom.unwrap().contains("OOM"));
+
+    let err = client.config_set("port", "7000").await.unwrap_err().to_string();
+    assert!(err.contains("can't set immutable config"), "{}", err);
+    let err = client.config_set("maxmemory-policy", "sometimes").await.unwrap_err().to_string();
+    assert!(err.contains("invalid maxmemory-policy"), "{}", err);
+    let err = client.config_set("nosuchparam", "1").await.unwrap_err().to_string();
+    assert!(err.contains("Unknown option"), "{}", err);
+    assert_eq!(client.config_get("maxmemory-policy").await.unwrap()[0].1, "noeviction");
+
+    let err = client.config_rewrite().await.unwrap_err().to_string();
+    assert!(err.contains("without a config file"), "{}", err);
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_info_sections() {
+    let (addr, shutdown) = common::start_server("info").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+    client.set("key", Bytes::from("value")).await.unwrap();
+
+    let info = client.info(None).await.unwrap();
+    for section in ["# Server", "# Clients", "# Memory", "# Persistence", "# Stats", "# Keyspace"] {
+        assert!(info.contains(section), "missing {} in {}", section, info);
+    }
+    assert!(info.contains("connected_clients:1\r\n"));
+    assert!(info.contains("db0:keys=1,expires=0\r\n"));
+    let port = addr.rsplit(':').next().unwrap();
+    assert!(info.contains(&format!("tcp_port:{}\r\n", port)));
+
+    let clients = client.info(Some("CLIENTS")).await.unwrap();
+    assert!(clients.starts_with("# Clients\r\n"));
+    assert!(!clients.contains("# Server"));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_maxclients_rejects_connections() {
+    let (addr, shutdown) = common::start_server_with("maxclients", |config| config.maxclients = 1).await;
+    // Let the server notice start_server's probe connection is gone
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let mut first = Client::connect(&addr).await.unwrap();
+    first.ping(None).await.unwrap();
+
+    let mut second = Client::connect(&addr).await.unwrap();
+    let err = second.ping(None).await.unwrap_err().to_string();
+    assert!(err.contains("max number of clients reached"), "{}", err);
+
+    drop(first);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let mut third = Client::connect(&addr).await.unwrap();
+    third.ping(None).await.unwrap();
+
+    let info = third.info(Some("stats")).await.unwrap();
+    assert!(info.contains("rejected_connections:1\r\n"));
+
+    let _ = shutdown.send(());
+}
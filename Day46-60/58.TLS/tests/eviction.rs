@@ -0,0 +1,161 @@
+mod common;
+
+use mini_redis_tls::{Client, Db, EvictionPolicy};
+use mini_redis_tls::db::{now_ms, SetCondition};
+use bytes::Bytes;
+use std::time::Duration;
+
+fn value() -> Bytes {
+    Bytes::from(vec![b'x'; 100])
+}
+
+#[test]
+fn test_used_memory_accounting() {
+    let db = Db::new();
+    assert_eq!(db.used_memory(), 0);
+
+    db.set("key".to_string(), value());
+    let one = db.used_memory();
+    assert!(one > 100);
+
+    db.append("key", &[b'y'; 1000]).unwrap();
+    assert!(db.used_memory() >= one + 1000);
+
+    db.push("list", vec![value(), value()], false).unwrap();
+    db.del(&["key".to_string(), "list".to_string()]);
+    assert_eq!(db.used_memory(), 0);
+
+    db.set("key".to_string(), value());
+    db.flush();
+    assert_eq!(db.used_memory(), 0);
+}
+
+#[test]
+fn test_noeviction_refuses_writes() {
+    let db = Db::new();
+    for i in 0..10 {
+        db.set(format!("key{}", i), value());
+    }
+    db.set_maxmemory(db.used_memory() as u64 / 2);
+
+    let mut evicted = vec![];
+    let err = db.evict(&mut evicted).unwrap_err();
+    assert!(err.to_string().starts_with("OOM"));
+    assert!(evicted.is_empty());
+    assert_eq!(db.dbsize(), 10);
+}
+
+#[test]
+fn test_allkeys_random_evicts_under_limit() {
+    let db = Db::new();
+    for i in 0..100 {
+        db.set(format!("key{}", i), value());
+    }
+    let limit = db.used_memory() as u64 / 2;
+    db.set_maxmemory(limit);
+    db.set_eviction_policy(EvictionPolicy::AllKeysRandom);
+
+    let mut evicted = vec![];
+    db.evict(&mut evicted).unwrap();
+    assert!(db.used_memory() as u64 <= limit);
+    assert_eq!(db.dbsize() + evicted.len(), 100);
+    assert_eq!(db.evicted_keys(), evicted.len() as u64);
+    assert!(evicted.iter().all(|key| db.get(key).is_none()));
+}
+
+#[tokio::test]
+async fn test_allkeys_lru_prefers_idle_keys() {
+    let db = Db::with_shards(1);
+    for i in 0..100 {
+        db.set(format!("key{}", i), value());
+    }
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    for i in 50..100 {
+        db.get(&format!("key{}", i));
+    }
+
+    db.set_maxmemory(db.used_memory() as u64 * 3 / 4);
+    db.set_eviction_policy(EvictionPolicy::AllKeysLru);
+    let mut evicted = vec![];
+    db.evict(&mut evicted).unwrap();
+
+    // Eviction is approximate, but the idle half must make up most of it
+    let idle = evicted.iter().filter(|key| key[3..].parse::<u32>().unwrap() < 50).count();
+    assert!(idle * 10 >= evicted.len() * 8, "evicted {:?}", evicted);
+}
+
+#[test]
+fn test_volatile_policies_only_evict_keys_with_ttl() {
+    let db = Db::with_shards(1);
+    for i in 0..10 {
+        db.set(format!("key{}", i), value());
+    }
+    let later = now_ms() + 60_000;
+    db.set_with_options("soon".to_string(), value(), Some(later), false, SetCondition::Always);
+    db.set_with_options("late".to_string(), value(), Some(later + 60_000), false, SetCondition::Always);
+    db.set_maxmemory(db.used_memory() as u64 - 1);
+
+    db.set_eviction_policy(EvictionPolicy::VolatileTtl);
+    let mut evicted = vec![];
+    db.evict(&mut evicted).unwrap();
+    assert_eq!(evicted, vec!["soon".to_string()]);
+
+    db.set_maxmemory(1);
+    db.set_eviction_policy(EvictionPolicy::VolatileLru);
+    let mut evicted = vec![];
+    assert!(db.evict(&mut evicted).is_err());
+    assert_eq!(evicted, vec!["late".to_string()]);
+    assert_eq!(db.dbsize(), 10);
+    assert_eq!(db.expires(), 0);
+}
+
+#[tokio::test]
+async fn test_server_oom_and_info() {
+    let (addr, shutdown) = common::start_server_with("evict-oom", |config| config.maxmemory = 2048).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    let mut oom = None;
+    for i in 0..100 {
+        if let Err(e) = client.set(&format!("key{}", i), value()).await {
+            oom = Some(e.to_string());
+            break;
+        }
+    }
+    assert!(oom.unwrap().contains("OOM command not allowed"));
+    // Reads and deletes still work
+    assert_eq!(client.get("key0").await.unwrap(), Some(value()));
+    assert_eq!(client.del(&["key0"]).await.unwrap(), 1);
+
+    let info = client.info(Some("memory")).await.unwrap();
+    assert!(info.contains("maxmemory:2048\r\n"));
+    assert!(info.contains("maxmemory_policy:noeviction\r\n"));
+    assert!(!info.contains("# Stats"));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_server_evicts_and_reports_evicted_keys() {
+    let (addr, shutdown) = common::start_server_with("evict-lru", |config| {
+        config.maxmemory = 4096;
+        config.maxmemory_policy = EvictionPolicy::AllKeysLru;
+    }).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    for i in 0..100 {
+        client.set(&format!("key{}", i), value()).await.unwrap();
+    }
+    assert!(client.dbsize().await.unwrap() < 100);
+
+    let info = client.info(None).await.unwrap();
+    let evicted: u64 = info
+        .lines()
+        .find_map(|line| line.strip_prefix("evicted_keys:"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(evicted + client.dbsize().await.unwrap(), 100);
+    assert!(info.contains("# Keyspace\r\ndb0:keys="));
+
+    let _ = shutdown.send(());
+}
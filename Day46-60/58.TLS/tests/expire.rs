@@ -0,0 +1,110 @@
+mod common;
+
+use common::{command, temp_path, try_command};
+use mini_redis_tls::{Aof, Db};
+use mini_redis_tls::db::{now_ms, SetCondition};
+use bytes::Bytes;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_lazy_expiry() {
+    let db = Db::new();
+    db.set_with_options("key".to_string(), Bytes::from("value"), Some(now_ms() + 50), false, SetCondition::Always);
+    assert_eq!(db.get("key"), Some(Bytes::from("value")));
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.get("key"), None);
+    assert_eq!(db.ttl("key"), None);
+}
+
+#[tokio::test]
+async fn test_purge_task_removes_keys() {
+    let db = Db::new();
+    db.start_purge_task();
+
+    db.set_with_options("later".to_string(), Bytes::from("v"), Some(now_ms() + 10_000), false, SetCondition::Always);
+    // Scheduling an earlier expiration must wake the task
+    db.set_with_options("soon".to_string(), Bytes::from("v"), Some(now_ms() + 50), false, SetCondition::Always);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(db.snapshot().len(), 1);
+    assert_eq!(db.purge_expired_keys().map(|when| when > now_ms()), Some(true));
+}
+
+#[tokio::test]
+async fn test_set_options() {
+    let db = Db::new();
+
+    assert!(!db.set_with_options("k".to_string(), Bytes::from("1"), None, false, SetCondition::IfExists));
+    assert!(db.set_with_options("k".to_string(), Bytes::from("1"), None, false, SetCondition::IfNotExists));
+    assert!(!db.set_with_options("k".to_string(), Bytes::from("2"), None, false, SetCondition::IfNotExists));
+    assert_eq!(db.get("k"), Some(Bytes::from("1")));
+
+    assert!(db.expire_at("k", (now_ms() + 10_000) as i64));
+    // KEEPTTL retains the timeout, a plain SET clears it
+    db.set_with_options("k".to_string(), Bytes::from("3"), None, true, SetCondition::Always);
+    assert!(matches!(db.ttl("k"), Some(Some(_))));
+    db.set("k".to_string(), Bytes::from("4"));
+    assert_eq!(db.ttl("k"), Some(None));
+}
+
+#[tokio::test]
+async fn test_expire_and_persist() {
+    let db = Db::new();
+    db.set("k".to_string(), Bytes::from("v"));
+
+    assert!(!db.persist("k"));
+    assert!(db.expire_at("k", (now_ms() + 100_000) as i64));
+    assert!(db.persist("k"));
+    assert_eq!(db.ttl("k"), Some(None));
+    assert!(!db.expire_at("missing", (now_ms() + 100_000) as i64));
+
+    // A deadline in the past deletes the key
+    assert!(db.expire_at("k", 1));
+    assert_eq!(db.get("k"), None);
+}
+
+#[test]
+fn test_parse_set_options() {
+    assert!(command(&["SET", "k", "v", "EX", "10", "NX"]).is_write());
+    assert_eq!(command(&["PTTL", "k"]).name(), "pttl");
+
+    for args in [
+        &["SET", "k", "v", "EX", "0"][..],
+        &["SET", "k", "v", "EX", "10", "PX", "10"],
+        &["SET", "k", "v", "NX", "XX"],
+        &["SET", "k", "v", "EX", "ten"],
+        &["SET", "k", "v", "BOGUS"],
+    ] {
+        assert!(try_command(args).is_err(), "{:?} should be rejected", args);
+    }
+}
+
+#[tokio::test]
+async fn test_relative_expiry_is_logged_as_absolute() {
+    let path = temp_path("expire", "aof");
+
+    let now = now_ms();
+    let mut aof = Aof::new(&path).await.unwrap();
+    aof.append(command(&["SET", "a", "1", "EX", "100"]).with_absolute_expiry(now)).await.unwrap();
+    aof.append(command(&["SET", "b", "2"]).with_absolute_expiry(now)).await.unwrap();
+    aof.append(command(&["PEXPIRE", "b", "5000"]).with_absolute_expiry(now)).await.unwrap();
+    aof.append(command(&["SET", "c", "3", "PX", "1"]).with_absolute_expiry(now)).await.unwrap();
+    aof.sync().await.unwrap();
+    drop(aof);
+
+    let contents = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+    assert!(contents.contains("PXAT"));
+    assert!(contents.contains("PEXPIREAT"));
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let db = Db::new();
+    Aof::load(&path, &db, false).await.unwrap();
+    assert_eq!(db.ttl("a").unwrap().unwrap() / 1000, (now + 100_000 - now_ms()) / 1000);
+    assert!(db.ttl("b").unwrap().unwrap() <= 5000);
+    // Already expired by the time it was replayed
+    assert_eq!(db.get("c"), None);
+
+    let _ = std::fs::remove_file(&path);
+}
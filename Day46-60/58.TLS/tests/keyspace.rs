@@ -0,0 +1,200 @@
+mod common;
+
+use common::{b, start_server};
+use mini_redis_tls::{glob, Client, Db};
+use mini_redis_tls::db::{now_ms, DbError, SetCondition};
+use std::collections::HashSet;
+
+fn keys(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn test_glob() {
+    let cases: &[(&str, &str, bool)] = &[
+        ("*", "anything", true),
+        ("*", "", true),
+        ("h?llo", "hello", true),
+        ("h?llo", "hllo", false),
+        ("h*llo", "heeeello", true),
+        ("h*llo", "hellow", false),
+        ("h[ae]llo", "hallo", true),
+        ("h[ae]llo", "hillo", false),
+        ("h[^e]llo", "hallo", true),
+        ("h[^e]llo", "hello", false),
+        ("h[a-c]llo", "hbllo", true),
+        ("h[a-c]llo", "hdllo", false),
+        ("user:*:name", "user:42:name", true),
+        ("user:*:name", "user:42:email", false),
+        ("a\\*b", "a*b", true),
+        ("a\\*b", "axb", false),
+        ("*a*b*", "xxaxxbxx", true),
+    ];
+    for (pattern, text, expected) in cases {
+        assert_eq!(glob::matches(pattern.as_bytes(), text.as_bytes()), *expected, "{} vs {}", pattern, text);
+    }
+}
+
+#[test]
+fn test_del_exists_type() {
+    let db = Db::new();
+    db.set("s".to_string(), b("v"));
+    db.push("l", vec![b("a")], false).unwrap();
+    db.hset("h", vec![(b("f"), b("v"))]).unwrap();
+
+    assert_eq!(db.exists(&keys(&["s", "l", "missing", "s"])), 3);
+    assert_eq!(db.key_type("s"), "string");
+    assert_eq!(db.key_type("l"), "list");
+    assert_eq!(db.key_type("h"), "hash");
+    assert_eq!(db.key_type("missing"), "none");
+
+    assert_eq!(db.del(&keys(&["s", "l", "missing"])), 2);
+    assert_eq!(db.dbsize(), 1);
+
+    db.flush();
+    assert_eq!(db.dbsize(), 0);
+}
+
+#[test]
+fn test_rename() {
+    let db = Db::new();
+    db.set_with_options("a".to_string(), b("1"), Some(now_ms() + 60_000), false, SetCondition::Always);
+    db.set("b".to_string(), b("2"));
+
+    assert_eq!(db.rename("missing", "x", false), Err(DbError::NoSuchKey));
+    assert_eq!(db.rename("a", "b", true), Ok(false));
+    assert_eq!(db.rename("a", "c", false), Ok(true));
+    assert_eq!(db.get("a"), None);
+    assert_eq!(db.get("c"), Some(b("1")));
+    assert!(matches!(db.ttl("c"), Some(Some(_))));
+
+    // RENAME overwrites, TTL included
+    assert_eq!(db.rename("b", "c", false), Ok(true));
+    assert_eq!(db.get("c"), Some(b("2")));
+    assert_eq!(db.ttl("c"), Some(None));
+    assert_eq!(db.rename("c", "c", false), Ok(true));
+}
+
+#[test]
+fn test_keys_pattern() {
+    let db = Db::new();
+    for key in ["user:1", "user:2", "session:1"] {
+        db.set(key.to_string(), b("v"));
+    }
+    let mut found = db.keys("user:*");
+    found.sort();
+    assert_eq!(found, keys(&["user:1", "user:2"]));
+    assert_eq!(db.keys("*").len(), 3);
+}
+
+/// Runs a full SCAN, calling `between` after every batch.
+fn full_scan(db: &Db, pattern: Option<&str>, count: usize, mut between: impl FnMut(usize)) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut cursor = 0;
+    let mut round = 0;
+    loop {
+        let (next, batch) = db.scan(cursor, pattern, count);
+        found.extend(batch);
+        between(round);
+        round += 1;
+        if next == 0 {
+            return found;
+        }
+        cursor = next;
+    }
+}
+
+#[test]
+fn test_scan_returns_every_key_once() {
+    let db = Db::new();
+    for i in 0..1000 {
+        db.set(format!("key:{}", i), b("v"));
+    }
+
+    let found = full_scan(&db, None, 10, |_| {});
+    assert_eq!(found.len(), 1000);
+    assert_eq!(found.iter().collect::<HashSet<_>>().len(), 1000);
+
+    let found = full_scan(&db, Some("key:1?"), 100, |_| {});
+    assert_eq!(found.len(), 10);
+}
+
+#[test]
+fn test_scan_batches() {
+    let db = Db::new();
+    for i in 0..100 {
+        db.push(&format!("list:{}", i), vec![b("v")], true).unwrap();
+        db.sadd(&format!("set:{}", i), vec![b("m")]).unwrap();
+    }
+    for i in 0..100 {
+        db.pop(&format!("list:{}", i), 1, true).unwrap();
+    }
+
+    // Each call returns about `count` keys, never the rest of the keyspace
+    let mut cursor = 0;
+    let mut found = Vec::new();
+    loop {
+        let (next, batch) = db.scan(cursor, None, 3);
+        assert!(batch.len() <= 3, "{:?}", batch);
+        found.extend(batch);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    found.sort();
+    let mut expected: Vec<_> = (0..100).map(|i| format!("set:{}", i)).collect();
+    expected.sort();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn test_scan_while_mutating() {
+    let db = Db::new();
+    for i in 0..500 {
+        db.set(format!("stable:{}", i), b("v"));
+    }
+
+    // Keys come and go between calls, forcing the map to grow and rehash
+    let found = full_scan(&db, None, 7, |round| {
+        for i in 0..20 {
+            db.set(format!("new:{}:{}", round, i), b("v"));
+        }
+        db.del(&[format!("new:{}:0", round.saturating_sub(1))]);
+    });
+
+    let stable: Vec<_> = found.iter().filter(|key| key.starts_with("stable:")).collect();
+    assert_eq!(stable.len(), 500);
+    assert_eq!(stable.iter().collect::<HashSet<_>>().len(), 500);
+}
+
+#[tokio::test]
+async fn test_client_keyspace_commands() {
+    let (addr, _shutdown) = start_server("keyspace").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    client.set("a", b("1")).await.unwrap();
+    client.set("b", b("2")).await.unwrap();
+    client.sadd("s", vec![b("m")]).await.unwrap();
+
+    assert_eq!(client.exists(&["a", "b", "c"]).await.unwrap(), 2);
+    assert_eq!(client.key_type("s").await.unwrap(), "set");
+    assert_eq!(client.dbsize().await.unwrap(), 3);
+
+    client.rename("a", "c").await.unwrap();
+    assert!(!client.renamenx("b", "c").await.unwrap());
+    assert!(client.rename("missing", "x").await.unwrap_err().to_string().contains("no such key"));
+
+    let mut found = client.keys("*").await.unwrap();
+    found.sort();
+    assert_eq!(found, keys(&["b", "c", "s"]));
+
+    let (cursor, batch) = client.scan(0, Some("[bc]"), 100).await.unwrap();
+    assert_eq!(cursor, 0);
+    assert_eq!(batch.len(), 2);
+
+    assert_eq!(client.del(&["b", "missing"]).await.unwrap(), 1);
+    assert_eq!(client.unlink(&["c"]).await.unwrap(), 1);
+    client.flushdb().await.unwrap();
+    assert_eq!(client.dbsize().await.unwrap(), 0);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::{b, bulks};
+use mini_redis_tls::db::now_ms;
+use mini_redis_tls::pubsub::Message;
+use mini_redis_tls::{Client, Connection, Db, EvictionPolicy, KeyspaceEvents};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Receiver;
+
+/// A database publishing the given events, along with a receiver for all of
+/// its keyspace notifications.
+fn db_with_events(flags: &str) -> (Db, Receiver<Message>) {
+    let db = Db::new();
+    db.set_keyspace_events(flags.parse().unwrap());
+    let rx = db.pub_sub().psubscribe("__key*@0__:*".to_string());
+    (db, rx)
+}
+
+/// The notifications received so far, as `(channel, message)`.
+fn received(rx: &mut Receiver<Message>) -> Vec<(String, String)> {
+    std::iter::from_fn(|| rx.try_recv().ok())
+        .map(|message| (message.channel, String::from_utf8(message.payload.to_vec()).unwrap()))
+        .collect()
+}
+
+fn events(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items.iter().map(|(channel, message)| (channel.to_string(), message.to_string())).collect()
+}
+
+#[test]
+fn test_flags() {
+    let events: KeyspaceEvents = "Kg$".parse().unwrap();
+    assert!(events.contains(KeyspaceEvents::KEYSPACE | KeyspaceEvents::GENERIC | KeyspaceEvents::STRING));
+    assert!(!events.contains(KeyspaceEvents::KEYEVENT));
+    assert_eq!(events.to_string(), "g$K");
+
+    // `A` stands for every event class
+    let all: KeyspaceEvents = "EKA".parse().unwrap();
+    assert_eq!(all.to_string(), "AKE");
+    assert_eq!("g$lshzxetKE".parse::<KeyspaceEvents>().unwrap(), all);
+
+    assert_eq!(KeyspaceEvents::default().to_string(), "");
+    assert!("Kq".parse::<KeyspaceEvents>().is_err());
+}
+
+#[test]
+fn test_keyspace_and_keyevent_channels() {
+    let (db, mut rx) = db_with_events("KEA");
+    db.set("key".to_string(), b("value"));
+    db.del(&["key".to_string(), "missing".to_string()]);
+    assert_eq!(
+        received(&mut rx),
+        events(&[
+            ("__keyspace@0__:key", "set"),
+            ("__keyevent@0__:set", "key"),
+            ("__keyspace@0__:key", "del"),
+            ("__keyevent@0__:del", "key"),
+        ])
+    );
+
+    // Only the classes that are enabled are published
+    db.set_keyspace_events("Kl".parse().unwrap());
+    db.set("key".to_string(), b("value"));
+    db.push("list", vec![b("a")], true).unwrap();
+    assert_eq!(received(&mut rx), events(&[("__keyspace@0__:list", "lpush")]));
+
+    // Without `K` or `E` nothing is published
+    db.set_keyspace_events("A".parse().unwrap());
+    db.set("key".to_string(), b("value"));
+    assert!(received(&mut rx).is_empty());
+}
+
+#[test]
+fn test_write_events() {
+    let (db, mut rx) = db_with_events("KA");
+    db.set_with_options("key".to_string(), b("1"), Some(now_ms() + 60_000), false, Default::default());
+    db.incr_by("key", 1).unwrap();
+    db.persist("key");
+    db.rename("key", "other", false).unwrap();
+    db.hset("hash", vec![(b("field"), b("value"))]).unwrap();
+    db.sadd("set", vec![b("member")]).unwrap();
+    // Nothing changes, so nothing is published
+    db.sadd("set", vec![b("member")]).unwrap();
+    assert_eq!(
+        received(&mut rx),
+        events(&[
+            ("__keyspace@0__:key", "set"),
+            ("__keyspace@0__:key", "expire"),
+            ("__keyspace@0__:key", "incrby"),
+            ("__keyspace@0__:key", "persist"),
+            ("__keyspace@0__:key", "rename_from"),
+            ("__keyspace@0__:other", "rename_to"),
+            ("__keyspace@0__:hash", "hset"),
+            ("__keyspace@0__:set", "sadd"),
+        ])
+    );
+}
+
+#[test]
+fn test_emptied_collection_is_deleted_after_its_event() {
+    let (db, mut rx) = db_with_events("KA");
+    db.push("list", vec![b("a"), b("b")], false).unwrap();
+    db.pop("list", 2, true).unwrap();
+    db.pop("list", 1, true).unwrap();
+    assert_eq!(
+        received(&mut rx),
+        events(&[
+            ("__keyspace@0__:list", "rpush"),
+            ("__keyspace@0__:list", "lpop"),
+            ("__keyspace@0__:list", "del"),
+        ])
+    );
+}
+
+#[test]
+fn test_expired_events() {
+    let (db, mut rx) = db_with_events("Ex");
+    db.set_with_options("read".to_string(), b("value"), Some(now_ms() + 10), false, Default::default());
+    db.set_with_options("purged".to_string(), b("value"), Some(now_ms() + 10), false, Default::default());
+    std::thread::sleep(Duration::from_millis(20));
+
+    // Accessing an expired key removes it, and so does the purge task
+    assert_eq!(db.get("read"), None);
+    assert_eq!(received(&mut rx), events(&[("__keyevent@0__:expired", "read")]));
+    db.purge_expired_keys();
+    assert_eq!(received(&mut rx), events(&[("__keyevent@0__:expired", "purged")]));
+}
+
+#[test]
+fn test_evicted_events() {
+    let (db, mut rx) = db_with_events("Ee");
+    for i in 0..10 {
+        db.set(format!("key{}", i), b("value"));
+    }
+    db.set_maxmemory(db.used_memory() as u64 / 2);
+    db.set_eviction_policy(EvictionPolicy::AllKeysRandom);
+
+    let mut evicted = vec![];
+    db.evict(&mut evicted).unwrap();
+    let expected: Vec<(String, String)> = evicted.iter().map(|key| ("__keyevent@0__:evicted".to_string(), key.clone())).collect();
+    assert_eq!(received(&mut rx), expected);
+}
+
+#[tokio::test]
+async fn test_config_set_enables_notifications() {
+    let (addr, _shutdown) = common::start_server("notifications_config").await;
+    let mut subscriber = Connection::new(TcpStream::connect(&addr).await.unwrap());
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    subscriber.write_frame(&bulks(&["PSUBSCRIBE", "__keyspace@0__:*"])).await.unwrap();
+    subscriber.read_frame().await.unwrap().unwrap();
+
+    // Off by default
+    assert_eq!(client.config_get("notify-keyspace-events").await.unwrap(), vec![("notify-keyspace-events".to_string(), String::new())]);
+    client.set("before", b("value")).await.unwrap();
+
+    client.config_set("notify-keyspace-events", "K$").await.unwrap();
+    assert_eq!(client.config_get("notify-keyspace-events").await.unwrap(), vec![("notify-keyspace-events".to_string(), "$K".to_string())]);
+    client.set("after", b("value")).await.unwrap();
+
+    let message = tokio::time::timeout(Duration::from_secs(5), subscriber.read_frame()).await.unwrap().unwrap().unwrap();
+    assert_eq!(message, bulks(&["pmessage", "__keyspace@0__:*", "__keyspace@0__:after", "set"]));
+
+    assert!(client.config_set("notify-keyspace-events", "K?").await.is_err());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{b, start_server};
+use mini_redis_tls::cmd::keys::DbSize;
+use mini_redis_tls::{Client, Command, Frame};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn test_client_pipeline() {
+    let (addr, shutdown) = start_server("pipeline").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    let mut pipeline = client.pipeline();
+    pipeline.set("a", b("1")).incr_by("a", 2).rpush("a", vec![b("x")]).get("a").command(Command::DbSize(DbSize));
+    assert_eq!(pipeline.len(), 5);
+
+    // A failing command keeps its slot and doesn't stop the rest
+    let replies = pipeline.execute().await.unwrap();
+    assert_eq!(replies[0], Frame::Simple("OK".into()));
+    assert_eq!(replies[1], Frame::Integer(3));
+    assert!(matches!(&replies[2], Frame::Error(e) if e.starts_with("WRONGTYPE")));
+    assert_eq!(replies[3], Frame::Bulk(b("3")));
+    assert_eq!(replies[4], Frame::Integer(1));
+    assert!(pipeline.is_empty());
+
+    // Large batches come back whole and in order
+    for i in 0..1000 {
+        pipeline.rpush("list", vec![b(&i.to_string())]);
+    }
+    let replies = pipeline.execute().await.unwrap();
+    assert_eq!(replies, (1..=1000).map(Frame::Integer).collect::<Vec<_>>());
+    assert!(client.pipeline().execute().await.unwrap().is_empty());
+    assert_eq!(client.lrange("list", -1, -1).await.unwrap(), vec![b("999")]);
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_server_answers_a_batch_at_once() {
+    let (addr, shutdown) = start_server("pipeline-batch").await;
+    let mut socket = TcpStream::connect(&addr).await.unwrap();
+
+    let mut request = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..100 {
+        request.extend_from_slice(b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n");
+        expected.extend_from_slice(format!(":{}\r\n", i + 1).as_bytes());
+    }
+    // A bad frame in the middle of the batch is answered in its place
+    request.extend_from_slice(b"*1\r\n$7\r\nNOSUCHC\r\nPING\r\n");
+    expected.extend_from_slice(b"-unknown command 'nosuchc'\r\n+PONG\r\n");
+    socket.write_all(&request).await.unwrap();
+
+    let mut replies = vec![0; expected.len()];
+    socket.read_exact(&mut replies).await.unwrap();
+    assert_eq!(String::from_utf8_lossy(&replies), String::from_utf8_lossy(&expected));
+
+    let _ = shutdown.send(());
+}
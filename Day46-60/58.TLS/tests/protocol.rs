@@ -0,0 +1,141 @@
+mod common;
+
+use common::{b, connect, request, start_server};
+use mini_redis_tls::{Client, Frame, Protocol};
+use bytes::Bytes;
+use tokio::net::TcpStream;
+
+fn field<'a>(reply: &'a Frame, name: &str) -> &'a Frame {
+    match reply {
+        Frame::Map(pairs) => &pairs.iter().find(|(key, _)| *key == Frame::Bulk(b(name))).unwrap().1,
+        frame => panic!("expected a map, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn test_hello_negotiates_protocol() {
+    let (addr, shutdown) = start_server("hello").await;
+    let mut conn = connect(&addr).await;
+
+    // Connections start on RESP2, so the reply is a flat array
+    request(&mut conn, &["HSET", "h", "f", "v"]).await;
+    assert_eq!(request(&mut conn, &["HGETALL", "h"]).await, Frame::Array(vec![Frame::Bulk(b("f")), Frame::Bulk(b("v"))]));
+    assert_eq!(request(&mut conn, &["GET", "missing"]).await, Frame::Null);
+    match request(&mut conn, &["HELLO"]).await {
+        Frame::Array(items) => assert_eq!(&items[4..6], &[Frame::Bulk(b("proto")), Frame::Integer(2)]),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+
+    let reply = request(&mut conn, &["HELLO", "3"]).await;
+    assert_eq!(field(&reply, "server"), &Frame::Bulk(b("mini-redis")));
+    assert_eq!(field(&reply, "proto"), &Frame::Integer(3));
+    assert!(matches!(field(&reply, "id"), Frame::Integer(_)));
+
+    assert_eq!(request(&mut conn, &["HGETALL", "h"]).await, Frame::Map(vec![(Frame::Bulk(b("f")), Frame::Bulk(b("v")))]));
+    request(&mut conn, &["SADD", "s", "m"]).await;
+    assert_eq!(request(&mut conn, &["SMEMBERS", "s"]).await, Frame::Set(vec![Frame::Bulk(b("m"))]));
+    request(&mut conn, &["ZADD", "z", "1.5", "m"]).await;
+    assert_eq!(request(&mut conn, &["ZSCORE", "z", "m"]).await, Frame::Double(1.5));
+
+    // RESP3 nulls are sent as `_`, which `read_frame` decodes the same way
+    assert_eq!(request(&mut conn, &["GET", "missing"]).await, Frame::Null);
+    assert_eq!(conn.protocol(), Protocol::Resp2);
+
+    // And back
+    request(&mut conn, &["HELLO", "2"]).await;
+    assert_eq!(request(&mut conn, &["ZSCORE", "z", "m"]).await, Frame::Bulk(b("1.5")));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_hello_errors_and_options() {
+    let (addr, shutdown) = start_server("hello-options").await;
+    let mut conn = connect(&addr).await;
+
+    assert!(matches!(request(&mut conn, &["HELLO", "4"]).await, Frame::Error(e) if e.starts_with("NOPROTO")));
+    assert!(matches!(request(&mut conn, &["HELLO", "three"]).await, Frame::Error(e) if e.starts_with("ERR")));
+    assert!(matches!(request(&mut conn, &["HELLO", "3", "BOGUS"]).await, Frame::Error(e) if e.contains("Syntax error")));
+    assert!(matches!(request(&mut conn, &["HELLO", "3", "AUTH", "nobody", "pw"]).await, Frame::Error(e) if e.starts_with("WRONGPASS")));
+    assert!(matches!(request(&mut conn, &["HELLO", "3", "SETNAME", "a b"]).await, Frame::Error(_)));
+
+    // None of the failures above switched the protocol
+    assert_eq!(request(&mut conn, &["SMEMBERS", "missing"]).await, Frame::Array(vec![]));
+
+    let reply = request(&mut conn, &["HELLO", "3", "AUTH", "default", "pw", "SETNAME", "worker-1"]).await;
+    let id = field(&reply, "id").clone();
+    assert_eq!(request(&mut conn, &["CLIENT", "GETNAME"]).await, Frame::Bulk(b("worker-1")));
+    assert_eq!(request(&mut conn, &["CLIENT", "ID"]).await, id);
+
+    assert_eq!(request(&mut conn, &["CLIENT", "SETNAME", ""]).await, Frame::Simple("OK".into()));
+    assert_eq!(request(&mut conn, &["CLIENT", "GETNAME"]).await, Frame::Null);
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_pubsub_messages_are_pushes_on_resp3() {
+    let (addr, shutdown) = start_server("hello-pubsub").await;
+    let mut subscriber = connect(&addr).await;
+    let mut publisher = Client::connect(&addr).await.unwrap();
+
+    request(&mut subscriber, &["HELLO", "3"]).await;
+    assert_eq!(
+        request(&mut subscriber, &["SUBSCRIBE", "news"]).await,
+        Frame::Push(vec![Frame::Bulk(b("subscribe")), Frame::Bulk(b("news")), Frame::Integer(1)])
+    );
+
+    publisher.publish("news", b("hi")).await.unwrap();
+    assert_eq!(
+        subscriber.read_frame().await.unwrap().unwrap(),
+        Frame::Push(vec![Frame::Bulk(b("message")), Frame::Bulk(b("news")), Frame::Bulk(b("hi"))])
+    );
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_client_handles_both_protocols() {
+    let (addr, shutdown) = start_server("hello-client").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    client.hset("h", vec![(b("f"), b("v"))]).await.unwrap();
+    client.zadd("z", vec![(2.5, b("m"))]).await.unwrap();
+    for protover in [2, 3] {
+        let info = client.hello(protover).await.unwrap();
+        assert!(info.contains(&("proto".to_string(), Frame::Integer(protover as i64))));
+        assert_eq!(client.hgetall("h").await.unwrap(), vec![(b("f"), b("v"))]);
+        assert_eq!(client.zscore("z", b("m")).await.unwrap(), Some(2.5));
+        assert_eq!(client.smembers("missing").await.unwrap(), Vec::<Bytes>::new());
+    }
+    assert!(client.hello(5).await.is_err());
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_inline_commands() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (addr, shutdown) = start_server("inline").await;
+    let mut socket = TcpStream::connect(&addr).await.unwrap();
+
+    async fn reply(socket: &mut TcpStream, expected: &[u8]) {
+        let mut buf = vec![0; expected.len()];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&buf), String::from_utf8_lossy(expected));
+    }
+
+    socket.write_all(b"PING\r\n").await.unwrap();
+    reply(&mut socket, b"+PONG\r\n").await;
+    socket.write_all(b"set greeting \"hello world\"\nGET greeting\n").await.unwrap();
+    reply(&mut socket, b"+OK\r\n$11\r\nhello world\r\n").await;
+
+    // A protocol error is reported before the connection is closed
+    socket.write_all(b"GET \"oops\r\n").await.unwrap();
+    let mut rest = vec![];
+    socket.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"-ERR Protocol error: unbalanced quotes in request\r\n");
+
+    let _ = shutdown.send(());
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use common::{b, bulks, connect, info_field, read, request, send};
+use mini_redis_tls::{Client, Connection, Db, Frame};
+use bytes::Bytes;
+use std::time::Duration;
+
+/// A `subscribe`-style confirmation: kind, channel and subscription count.
+fn confirmation(kind: &str, channel: &str, count: i64) -> Frame {
+    Frame::Array(vec![Frame::Bulk(b(kind)), Frame::Bulk(b(channel)), Frame::Integer(count)])
+}
+
+#[tokio::test]
+async fn test_subscribe_to_several_channels() {
+    let (addr, _shutdown) = common::start_server("pubsub_channels").await;
+    let mut subscriber = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    send(&mut subscriber, &["SUBSCRIBE", "a", "b"]).await;
+    assert_eq!(read(&mut subscriber).await, confirmation("subscribe", "a", 1));
+    assert_eq!(read(&mut subscriber).await, confirmation("subscribe", "b", 2));
+
+    // Every channel is listened to, not only the last one
+    assert_eq!(client.publish("a", b("first")).await.unwrap(), 1);
+    assert_eq!(client.publish("b", b("second")).await.unwrap(), 1);
+    assert_eq!(client.publish("c", b("nobody")).await.unwrap(), 0);
+    assert_eq!(read(&mut subscriber).await, bulks(&["message", "a", "first"]));
+    assert_eq!(read(&mut subscriber).await, bulks(&["message", "b", "second"]));
+
+    // Subscribing twice to a channel doesn't deliver its messages twice
+    send(&mut subscriber, &["SUBSCRIBE", "a"]).await;
+    assert_eq!(read(&mut subscriber).await, confirmation("subscribe", "a", 2));
+    assert_eq!(client.publish("a", b("once")).await.unwrap(), 1);
+    assert_eq!(read(&mut subscriber).await, bulks(&["message", "a", "once"]));
+}
+
+#[tokio::test]
+async fn test_unsubscribe_leaves_subscriber_mode() {
+    let (addr, _shutdown) = common::start_server("pubsub_unsubscribe").await;
+    let mut subscriber = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    send(&mut subscriber, &["SUBSCRIBE", "a", "b", "c"]).await;
+    for _ in 0..3 {
+        read(&mut subscriber).await;
+    }
+
+    assert_eq!(request(&mut subscriber, &["UNSUBSCRIBE", "a"]).await, confirmation("unsubscribe", "a", 2));
+    assert_eq!(client.publish("a", b("gone")).await.unwrap(), 0);
+
+    // Without channels, every remaining one is unsubscribed from
+    send(&mut subscriber, &["UNSUBSCRIBE"]).await;
+    assert_eq!(read(&mut subscriber).await, confirmation("unsubscribe", "b", 1));
+    assert_eq!(read(&mut subscriber).await, confirmation("unsubscribe", "c", 0));
+    assert_eq!(
+        request(&mut subscriber, &["UNSUBSCRIBE"]).await,
+        Frame::Array(vec![Frame::Bulk(b("unsubscribe")), Frame::Null, Frame::Integer(0)])
+    );
+
+    // Back to a regular connection
+    assert_eq!(request(&mut subscriber, &["SET", "key", "value"]).await, Frame::Simple("OK".to_string()));
+    assert_eq!(request(&mut subscriber, &["GET", "key"]).await, Frame::Bulk(b("value")));
+}
+
+#[tokio::test]
+async fn test_psubscribe() {
+    let (addr, _shutdown) = common::start_server("pubsub_patterns").await;
+    let mut subscriber = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    send(&mut subscriber, &["PSUBSCRIBE", "news.*"]).await;
+    assert_eq!(read(&mut subscriber).await, confirmation("psubscribe", "news.*", 1));
+    assert_eq!(request(&mut subscriber, &["SUBSCRIBE", "news.tech"]).await, confirmation("subscribe", "news.tech", 2));
+
+    // Delivered once per matching subscription
+    assert_eq!(client.publish("news.tech", b("rust")).await.unwrap(), 2);
+    assert_eq!(client.publish("weather", b("rain")).await.unwrap(), 0);
+    let mut received = vec![read(&mut subscriber).await, read(&mut subscriber).await];
+    received.sort_by_key(|frame| format!("{:?}", frame));
+    assert_eq!(
+        received,
+        vec![bulks(&["message", "news.tech", "rust"]), bulks(&["pmessage", "news.*", "news.tech", "rust"])]
+    );
+
+    assert_eq!(request(&mut subscriber, &["PUNSUBSCRIBE", "news.*"]).await, confirmation("punsubscribe", "news.*", 1));
+    assert_eq!(client.publish("news.sport", b("goal")).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_commands_while_subscribed() {
+    let (addr, _shutdown) = common::start_server("pubsub_subscribed").await;
+    let mut subscriber = connect(&addr).await;
+
+    request(&mut subscriber, &["SUBSCRIBE", "a"]).await;
+    assert_eq!(request(&mut subscriber, &["PING"]).await, bulks(&["pong", ""]));
+    assert_eq!(request(&mut subscriber, &["PING", "hi"]).await, bulks(&["pong", "hi"]));
+    match request(&mut subscriber, &["GET", "key"]).await {
+        Frame::Error(msg) => assert!(msg.starts_with("ERR Can't execute 'get'"), "{}", msg),
+        frame => panic!("expected an error, got {:?}", frame),
+    }
+
+    // RESP3 can tell pushed messages from replies, so anything goes
+    let mut subscriber = connect(&addr).await;
+    request(&mut subscriber, &["HELLO", "3"]).await;
+    assert_eq!(request(&mut subscriber, &["SUBSCRIBE", "a"]).await, Frame::Push(vec![Frame::Bulk(b("subscribe")), Frame::Bulk(b("a")), Frame::Integer(1)]));
+    assert_eq!(request(&mut subscriber, &["PING"]).await, Frame::Simple("PONG".to_string()));
+    assert_eq!(request(&mut subscriber, &["GET", "key"]).await, Frame::Null);
+}
+
+#[tokio::test]
+async fn test_pubsub_introspection() {
+    let (addr, _shutdown) = common::start_server("pubsub_introspection").await;
+    let mut first = connect(&addr).await;
+    let mut second = connect(&addr).await;
+    let mut client = connect(&addr).await;
+
+    send(&mut first, &["SUBSCRIBE", "news", "weather"]).await;
+    read(&mut first).await;
+    read(&mut first).await;
+    request(&mut second, &["SUBSCRIBE", "news"]).await;
+    request(&mut second, &["PSUBSCRIBE", "n*"]).await;
+
+    assert_eq!(request(&mut client, &["PUBSUB", "CHANNELS"]).await, bulks(&["news", "weather"]));
+    assert_eq!(request(&mut client, &["PUBSUB", "CHANNELS", "w*"]).await, bulks(&["weather"]));
+    assert_eq!(
+        request(&mut client, &["PUBSUB", "NUMSUB", "news", "weather", "other"]).await,
+        Frame::Array(vec![
+            Frame::Bulk(b("news")),
+            Frame::Integer(2),
+            Frame::Bulk(b("weather")),
+            Frame::Integer(1),
+            Frame::Bulk(b("other")),
+            Frame::Integer(0),
+        ])
+    );
+    assert_eq!(request(&mut client, &["PUBSUB", "NUMPAT"]).await, Frame::Integer(1));
+
+    // Channels nobody listens to anymore are forgotten, also when the
+    // subscriber just goes away
+    request(&mut first, &["UNSUBSCRIBE", "weather"]).await;
+    assert_eq!(request(&mut client, &["PUBSUB", "CHANNELS"]).await, bulks(&["news"]));
+    drop(first);
+    drop(second);
+    let mut channels = Frame::Null;
+    for _ in 0..100 {
+        channels = request(&mut client, &["PUBSUB", "CHANNELS"]).await;
+        if channels == Frame::Array(vec![]) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(channels, Frame::Array(vec![]));
+    assert_eq!(request(&mut client, &["PUBSUB", "NUMPAT"]).await, Frame::Integer(0));
+}
+
+#[test]
+fn test_empty_channels_are_released() {
+    let db = Db::new();
+    let pub_sub = db.pub_sub();
+
+    let first = pub_sub.subscribe("a".to_string());
+    let second = pub_sub.subscribe("a".to_string());
+    assert_eq!(pub_sub.publish("a", b("hello")), 2);
+
+    // Still listened to
+    drop(first);
+    pub_sub.unsubscribe("a");
+    assert_eq!(pub_sub.channels(None), vec!["a".to_string()]);
+
+    drop(second);
+    pub_sub.unsubscribe("a");
+    assert!(pub_sub.channels(None).is_empty());
+    assert_eq!(pub_sub.publish("a", b("hello")), 0);
+}
+
+/// Publishes `count` large messages to `channel` in one batch, so a
+/// subscriber that doesn't read falls behind.
+async fn flood(addr: &str, channel: &str, count: usize) {
+    let mut publisher = connect(addr).await;
+    let payload = Bytes::from(vec![b'x'; 16 * 1024]);
+    for _ in 0..count {
+        let frame = Frame::Array(vec![Frame::Bulk(b("PUBLISH")), Frame::Bulk(b(channel)), Frame::Bulk(payload.clone())]);
+        publisher.buffer_frame(&frame).await.unwrap();
+    }
+    publisher.flush().await.unwrap();
+    for _ in 0..count {
+        read(&mut publisher).await;
+    }
+}
+
+/// Reads what the subscriber was sent until the first lag report, returning
+/// how many messages it missed.
+async fn skipped(subscriber: &mut Connection) -> i64 {
+    loop {
+        match read(subscriber).await {
+            Frame::Array(parts) if parts[0] == Frame::Bulk(b("message")) => {}
+            Frame::Array(parts) => {
+                assert_eq!(parts[..2], [Frame::Bulk(b("lagged")), Frame::Bulk(b("busy"))]);
+                match parts[2] {
+                    Frame::Integer(skipped) => return skipped,
+                    ref frame => panic!("expected a count, got {:?}", frame),
+                }
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+}
+
+/// A counter from the `INFO` of the server at `addr`.
+async fn counter(addr: &str, field: &str) -> u64 {
+    info_field(addr, field).await.unwrap_or_else(|| panic!("no {} in INFO", field)).parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_lagging_subscriber_is_told_what_it_missed() {
+    let (addr, _shutdown) = common::start_server_with("pubsub_lagged", |config| config.pubsub_lag_hard_limit = 0).await;
+    let mut subscriber = connect(&addr).await;
+    request(&mut subscriber, &["SUBSCRIBE", "busy"]).await;
+
+    flood(&addr, "busy", 3000).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+    assert_eq!(client.publish("busy", b("last")).await.unwrap(), 1);
+
+    // Still subscribed afterwards, and whatever wasn't dropped is delivered
+    let (mut received, mut skipped) = (0, 0);
+    loop {
+        match read(&mut subscriber).await {
+            frame if frame == bulks(&["message", "busy", "last"]) => break,
+            Frame::Array(parts) if parts[0] == Frame::Bulk(b("message")) => received += 1,
+            Frame::Array(parts) if parts[..2] == [Frame::Bulk(b("lagged")), Frame::Bulk(b("busy"))] => {
+                match parts[2] {
+                    Frame::Integer(n) => skipped += n,
+                    ref frame => panic!("expected a count, got {:?}", frame),
+                }
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+    assert!(skipped > 0);
+    assert_eq!(received + skipped, 3000);
+
+    assert!(counter(&addr, "pubsub_lag_events").await >= 1);
+    assert_eq!(counter(&addr, "pubsub_dropped_messages").await, skipped as u64);
+    assert_eq!(counter(&addr, "pubsub_lag_disconnections").await, 0);
+}
+
+#[tokio::test]
+async fn test_lagging_subscriber_is_disconnected_past_the_hard_limit() {
+    let (addr, _shutdown) = common::start_server_with("pubsub_lag_limit", |config| config.pubsub_lag_hard_limit = 10).await;
+    let mut subscriber = connect(&addr).await;
+    request(&mut subscriber, &["SUBSCRIBE", "busy"]).await;
+
+    flood(&addr, "busy", 3000).await;
+    assert!(skipped(&mut subscriber).await > 10);
+    let closed = tokio::time::timeout(Duration::from_secs(5), subscriber.read_frame()).await.unwrap();
+    assert!(matches!(closed, Ok(None) | Err(_)), "{:?}", closed);
+
+    assert_eq!(counter(&addr, "pubsub_lag_disconnections").await, 1);
+    assert_eq!(counter(&addr, "pubsub_channels").await, 0);
+}
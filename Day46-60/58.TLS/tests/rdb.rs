@@ -0,0 +1,97 @@
+mod common;
+
+use common::{seeded_db, temp_path};
+use mini_redis_tls::{rdb, Aof, Db, Rdb};
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[test]
+fn test_encode_decode_roundtrip() {
+    let entries = seeded_db().snapshot();
+
+    let mut encoded = Vec::new();
+    rdb::encode(&mut encoded, &entries).unwrap();
+
+    let mut decoded = Vec::new();
+    let len = rdb::decode(&encoded[..], |k, v| decoded.push((k, v))).unwrap();
+    assert_eq!(len, encoded.len() as u64);
+
+    decoded.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected = entries.clone();
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn test_decode_detects_corruption() {
+    let entries = seeded_db().snapshot();
+    let mut encoded = Vec::new();
+    rdb::encode(&mut encoded, &entries).unwrap();
+
+    // Flipped bit in the payload
+    let mut corrupt = encoded.clone();
+    corrupt[rdb::MAGIC.len() + 3] ^= 0x01;
+    assert!(rdb::decode(&corrupt[..], |_, _| {}).is_err());
+
+    // Missing trailer
+    assert!(rdb::decode(&encoded[..encoded.len() - 2], |_, _| {}).is_err());
+
+    // Not a snapshot at all
+    assert!(rdb::decode(&b"*1\r\n$4\r\nPING\r\n"[..], |_, _| {}).is_err());
+}
+
+#[tokio::test]
+async fn test_save_and_load() {
+    let path = temp_path("save", "rdb");
+    let rdb = Arc::new(Rdb::new(&path));
+    let db = seeded_db();
+
+    rdb.save(&db).await.unwrap();
+
+    let restored = Db::new();
+    assert_eq!(rdb.load(&restored).await.unwrap(), 8);
+    assert_eq!(restored.get("hello"), Some(Bytes::from("world")));
+    assert!(restored.ttl("session").unwrap().unwrap() > 59_000);
+    assert_eq!(restored.get("large"), Some(Bytes::from(vec![7u8; 100_000])));
+
+    // Background saves capture the dataset at the time of the call
+    let handle = Rdb::save_in_background(rdb.clone(), &db).await.unwrap();
+    db.set("hello".to_string(), Bytes::from("changed"));
+    handle.await.unwrap();
+    assert!(!rdb.is_saving());
+
+    let restored = Db::new();
+    rdb.load(&restored).await.unwrap();
+    assert_eq!(restored.get("hello"), Some(Bytes::from("world")));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_load_missing_snapshot() {
+    let rdb = Rdb::new(temp_path("missing", "rdb"));
+    assert_eq!(rdb.load(&Db::new()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_aof_rewrite_with_preamble() {
+    let path = temp_path("preamble", "aof");
+    let db = seeded_db();
+    let aof = Arc::new(Mutex::new(Aof::new(&path).await.unwrap()));
+
+    Aof::rewrite_in_background(aof.clone(), db.clone()).await.unwrap().await.unwrap();
+    assert!(std::fs::read(&path).unwrap().starts_with(rdb::MAGIC));
+
+    // Commands appended after the rewrite follow the snapshot
+    let set = mini_redis_tls::Command::Set(mini_redis_tls::cmd::set::Set::new("hello", Bytes::from("again")));
+    aof.lock().await.append(set).await.unwrap();
+    aof.lock().await.sync().await.unwrap();
+
+    let restored = Db::new();
+    assert_eq!(Aof::load(&path, &restored, false).await.unwrap(), 1);
+    assert_eq!(restored.get("hello"), Some(Bytes::from("again")));
+    assert_eq!(restored.get("large"), Some(Bytes::from(vec![7u8; 100_000])));
+
+    let _ = std::fs::remove_file(&path);
+}
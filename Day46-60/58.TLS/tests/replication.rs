@@ -0,0 +1,170 @@
+mod common;
+
+use common::{b, info_field, port, start_replica};
+use mini_redis_tls::replication::Backlog;
+use mini_redis_tls::Client;
+use std::time::Duration;
+
+/// Waits until `field` of the `INFO` of the server at `addr` reads `value`.
+async fn wait_for_info(addr: &str, field: &str, value: &str) {
+    for _ in 0..500 {
+        if info_field(addr, field).await.as_deref() == Some(value) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{} never became {}", field, value);
+}
+
+/// Waits until the replica has processed everything the master streamed.
+async fn wait_for_sync(master: &str, replica: &str) {
+    wait_for_info(replica, "master_link_status", "up").await;
+    let offset = info_field(master, "master_repl_offset").await.unwrap();
+    wait_for_info(replica, "slave_repl_offset", &offset).await;
+}
+
+#[test]
+fn test_backlog() {
+    let mut backlog = Backlog::new(8);
+    let replid = backlog.replid().to_string();
+    assert_eq!(replid.len(), 40);
+
+    backlog.feed(b"abcdef");
+    assert_eq!(backlog.read(2), Some(b"cdef".to_vec()));
+    assert_eq!(backlog.read(6), Some(vec![]));
+    // A replica that has every byte can continue; `PSYNC` asks for the first
+    // missing one
+    assert!(backlog.can_continue(&replid, 7));
+    assert!(backlog.can_continue(&replid, 1));
+    assert!(!backlog.can_continue("?", 1));
+
+    // Only the last 8 bytes are kept
+    backlog.feed(b"ghij");
+    assert_eq!(backlog.offset(), 10);
+    assert_eq!(backlog.len(), 8);
+    assert_eq!(backlog.read(1), None);
+    assert_eq!(backlog.read(2), Some(b"cdefghij".to_vec()));
+    assert!(!backlog.can_continue(&replid, 2));
+    assert!(backlog.can_continue(&replid, 3));
+
+    // After a promotion, the former stream can be continued up to where it
+    // ended
+    backlog.shift_replid("x".repeat(40));
+    backlog.feed(b"k");
+    assert!(backlog.can_continue(&replid, 11));
+    assert!(!backlog.can_continue(&replid, 12));
+    assert!(backlog.can_continue(&"x".repeat(40), 12));
+
+    // A full synchronization starts over from the master's offset
+    backlog.reset("y".repeat(40), 100);
+    assert!(backlog.is_empty());
+    assert!(backlog.can_continue(&"y".repeat(40), 101));
+    assert!(!backlog.can_continue(&replid, 11));
+}
+
+#[tokio::test]
+async fn test_full_sync_and_command_stream() {
+    let (master_addr, _master_shutdown) = common::start_server("replication_master").await;
+    let mut master = Client::connect(&master_addr).await.unwrap();
+    master.set("before", b("1")).await.unwrap();
+    master.rpush("list", vec![b("a"), b("b")]).await.unwrap();
+
+    let (replica_addr, _replica_shutdown) = start_replica("replication_replica", &master_addr).await;
+    let mut replica = Client::connect(&replica_addr).await.unwrap();
+
+    // The data that was there before comes with the full synchronization
+    wait_for_sync(&master_addr, &replica_addr).await;
+    assert_eq!(replica.get("before").await.unwrap(), Some(b("1")));
+    assert_eq!(replica.lrange("list", 0, -1).await.unwrap(), vec![b("a"), b("b")]);
+    assert!(master.info(Some("stats")).await.unwrap().contains("sync_full:1"));
+
+    // Later writes are streamed
+    master.set("after", b("2")).await.unwrap();
+    master.del(&["before"]).await.unwrap();
+    let mut pipeline = master.pipeline();
+    pipeline.incr_by("counter", 5).rpush("list", vec![b("c")]);
+    pipeline.execute().await.unwrap();
+    wait_for_sync(&master_addr, &replica_addr).await;
+    assert_eq!(replica.get("after").await.unwrap(), Some(b("2")));
+    assert_eq!(replica.get("before").await.unwrap(), None);
+    assert_eq!(replica.get("counter").await.unwrap(), Some(b("5")));
+    assert_eq!(replica.lrange("list", 0, -1).await.unwrap(), vec![b("a"), b("b"), b("c")]);
+
+    assert_eq!(info_field(&master_addr, "role").await.as_deref(), Some("master"));
+    assert_eq!(info_field(&master_addr, "connected_slaves").await.as_deref(), Some("1"));
+    let listed = info_field(&master_addr, "slave0").await.unwrap();
+    assert!(listed.starts_with(&format!("ip=127.0.0.1,port={},state=online", port(&replica_addr))), "{}", listed);
+    assert_eq!(info_field(&replica_addr, "role").await.as_deref(), Some("slave"));
+    assert_eq!(info_field(&replica_addr, "master_port").await, Some(port(&master_addr).to_string()));
+}
+
+#[tokio::test]
+async fn test_replica_is_read_only() {
+    let (master_addr, _master_shutdown) = common::start_server("replication_read_only_master").await;
+    let (replica_addr, _replica_shutdown) = start_replica("replication_read_only_replica", &master_addr).await;
+    let mut replica = Client::connect(&replica_addr).await.unwrap();
+
+    let err = replica.set("key", b("value")).await.unwrap_err();
+    assert!(err.to_string().contains("READONLY"), "{}", err);
+    assert_eq!(replica.get("key").await.unwrap(), None);
+
+    let hello = replica.hello(3).await.unwrap();
+    let role = hello.iter().find(|(field, _)| field == "role").map(|(_, role)| role.clone());
+    assert_eq!(role, Some(mini_redis_tls::Frame::Bulk(b("replica"))));
+}
+
+#[tokio::test]
+async fn test_promoted_replica_keeps_its_data() {
+    let (master_addr, _master_shutdown) = common::start_server("replication_promote_master").await;
+    let mut master = Client::connect(&master_addr).await.unwrap();
+    let (replica_addr, _replica_shutdown) = common::start_server("replication_promote_replica").await;
+    let mut replica = Client::connect(&replica_addr).await.unwrap();
+
+    master.set("key", b("value")).await.unwrap();
+    replica.replicaof(Some(("127.0.0.1", port(&master_addr)))).await.unwrap();
+    wait_for_sync(&master_addr, &replica_addr).await;
+    assert_eq!(replica.config_get("replicaof").await.unwrap()[0].1, format!("127.0.0.1 {}", port(&master_addr)));
+
+    replica.replicaof(None).await.unwrap();
+    assert_eq!(info_field(&replica_addr, "role").await.as_deref(), Some("master"));
+    assert_eq!(replica.get("key").await.unwrap(), Some(b("value")));
+    replica.set("key", b("changed")).await.unwrap();
+    assert_eq!(replica.get("key").await.unwrap(), Some(b("changed")));
+
+    // The former master no longer streams to it
+    master.set("other", b("value")).await.unwrap();
+    wait_for_info(&master_addr, "connected_slaves", "0").await;
+    assert_eq!(replica.get("other").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_partial_resync_after_promotion() {
+    let (first_addr, _first_shutdown) = common::start_server("replication_psync_a").await;
+    let mut first = Client::connect(&first_addr).await.unwrap();
+    for i in 0..10 {
+        first.set(&format!("key{}", i), b("value")).await.unwrap();
+    }
+    let (promoted_addr, _promoted_shutdown) = start_replica("replication_psync_b", &first_addr).await;
+    let mut promoted = Client::connect(&promoted_addr).await.unwrap();
+    let (other_addr, _other_shutdown) = start_replica("replication_psync_c", &first_addr).await;
+    let mut other = Client::connect(&other_addr).await.unwrap();
+    first.set("streamed", b("value")).await.unwrap();
+    wait_for_sync(&first_addr, &promoted_addr).await;
+    wait_for_sync(&first_addr, &other_addr).await;
+
+    // One replica takes over, and the other follows it without copying the
+    // whole dataset
+    promoted.replicaof(None).await.unwrap();
+    other.replicaof(Some(("127.0.0.1", port(&promoted_addr)))).await.unwrap();
+    wait_for_sync(&promoted_addr, &other_addr).await;
+    let stats = promoted.info(Some("stats")).await.unwrap();
+    assert!(stats.contains("sync_full:0"), "{}", stats);
+    assert!(stats.contains("sync_partial_ok:1"), "{}", stats);
+    assert_eq!(info_field(&other_addr, "master_replid").await, info_field(&promoted_addr, "master_replid").await);
+    assert_eq!(info_field(&promoted_addr, "master_replid2").await, info_field(&first_addr, "master_replid").await);
+
+    promoted.set("after", b("value")).await.unwrap();
+    wait_for_sync(&promoted_addr, &other_addr).await;
+    assert_eq!(other.get("after").await.unwrap(), Some(b("value")));
+    assert_eq!(other.dbsize().await.unwrap(), 12);
+}
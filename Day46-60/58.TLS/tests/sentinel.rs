@@ -0,0 +1,173 @@
+mod common;
+
+use common::{b, info_field, port, start_replica, wait_for_listener};
+use mini_redis_tls::sentinel::{self, MonitorConfig, SentinelConfig};
+use mini_redis_tls::Client;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Starts a sentinel on a free port, monitoring `mymaster` at `master` with
+/// short timeouts.
+async fn start_sentinel(master: &str, quorum: usize) -> (String, broadcast::Sender<()>) {
+    let listen = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let mut monitor = MonitorConfig::new("mymaster", "127.0.0.1", port(master), quorum);
+    monitor.down_after = Duration::from_millis(500);
+    monitor.failover_timeout = Duration::from_secs(3);
+    let config = SentinelConfig { port: listen, masters: vec![monitor], ..SentinelConfig::default() };
+    let addr = config.addr();
+
+    let (tx, rx) = broadcast::channel(1);
+    tokio::spawn(async move {
+        sentinel::run(config, rx).await.unwrap();
+    });
+    wait_for_listener(&addr).await;
+    (addr, tx)
+}
+
+/// Polls `condition` until it holds, for at most `secs` seconds.
+async fn wait_until<F, Fut>(secs: u64, what: &str, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(secs);
+    while !condition().await {
+        assert!(tokio::time::Instant::now() < deadline, "timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+async fn field(sentinel: &str, name: &str) -> String {
+    let mut client = Client::connect(sentinel).await.unwrap();
+    let fields = client.sentinel_master("mymaster").await.unwrap();
+    fields.into_iter().find(|(field, _)| field == name).map(|(_, value)| value).unwrap()
+}
+
+async fn master_addr(sentinel: &str) -> Option<(String, u16)> {
+    let mut client = Client::connect(sentinel).await.unwrap();
+    client.sentinel_get_master_addr_by_name("mymaster").await.unwrap()
+}
+
+#[test]
+fn test_config_file() {
+    let path = std::env::temp_dir().join(format!("mini-redis-sentinel-{}.conf", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Sentinel\n\
+         port 26380\n\
+         sentinel monitor mymaster 10.0.0.1 6379 2\n\
+         sentinel down-after-milliseconds mymaster 5000\n\
+         SENTINEL failover-timeout mymaster 60000\n\
+         sentinel auth-pass mymaster \"s3cret pass\"\n",
+    )
+    .unwrap();
+    let config = SentinelConfig::load(&path).unwrap();
+    assert_eq!(config.addr(), "127.0.0.1:26380");
+    let mut expected = MonitorConfig::new("mymaster", "10.0.0.1", 6379, 2);
+    expected.down_after = Duration::from_secs(5);
+    expected.failover_timeout = Duration::from_secs(60);
+    expected.auth_pass = "s3cret pass".to_string();
+    assert_eq!(config.masters, vec![expected]);
+
+    // Options must name a monitored master, and the quorum can't be 0
+    std::fs::write(&path, "sentinel down-after-milliseconds other 5000\n").unwrap();
+    let err = SentinelConfig::load(&path).unwrap_err().to_string();
+    assert!(err.contains(":1: No such master"), "{}", err);
+    std::fs::write(&path, "sentinel monitor mymaster 10.0.0.1 6379 0\n").unwrap();
+    assert!(SentinelConfig::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_discovery() {
+    let (master, _master_shutdown) = common::start_server("sentinel_discovery_master").await;
+    let (replica, _replica_shutdown) = start_replica("sentinel_discovery_replica", &master).await;
+    let (first, _first_shutdown) = start_sentinel(&master, 2).await;
+    let (second, _second_shutdown) = start_sentinel(&master, 2).await;
+
+    assert_eq!(master_addr(&first).await, Some(("127.0.0.1".to_string(), port(&master))));
+    let mut client = Client::connect(&first).await.unwrap();
+    assert_eq!(client.sentinel_get_master_addr_by_name("other").await.unwrap(), None);
+    assert!(client.sentinel_master("other").await.is_err());
+    assert_eq!(client.ping(None).await.unwrap(), b("PONG"));
+
+    // Replicas are found through the master's INFO, and the other sentinels
+    // through their hello messages
+    wait_until(10, "the replica", || async { field(&first, "num-slaves").await == "1" }).await;
+    wait_until(10, "the other sentinel", || async {
+        field(&first, "num-other-sentinels").await == "1" && field(&second, "num-other-sentinels").await == "1"
+    })
+    .await;
+    assert_eq!(field(&first, "flags").await, "master");
+    assert_eq!(field(&first, "quorum").await, "2");
+    assert_eq!(info_field(&replica, "role").await.as_deref(), Some("slave"));
+}
+
+#[tokio::test]
+async fn test_failover() {
+    let (master, master_shutdown) = common::start_server("sentinel_failover_master").await;
+    let (first, _first_shutdown) = start_replica("sentinel_failover_replica1", &master).await;
+    let (second, _second_shutdown) = start_replica("sentinel_failover_replica2", &master).await;
+    let mut client = Client::connect(&master).await.unwrap();
+    client.set("key", b("value")).await.unwrap();
+    for replica in [&first, &second] {
+        wait_until(10, "the replicas to sync", || async { info_field(replica, "master_link_status").await.as_deref() == Some("up") }).await;
+    }
+
+    let mut sentinels = vec![];
+    for _ in 0..3 {
+        sentinels.push(start_sentinel(&master, 2).await);
+    }
+    let addrs: Vec<String> = sentinels.iter().map(|(addr, _)| addr.clone()).collect();
+    wait_until(10, "the sentinels to find each other and the replicas", || async {
+        for sentinel in &addrs {
+            if field(sentinel, "num-other-sentinels").await != "2" || field(sentinel, "num-slaves").await != "2" {
+                return false;
+            }
+        }
+        true
+    })
+    .await;
+
+    // The master goes away; every sentinel ends up with the same replica as
+    // the new master
+    master_shutdown.send(()).unwrap();
+    wait_until(30, "the failover", || async {
+        let mut masters = vec![];
+        for sentinel in &addrs {
+            masters.push(master_addr(sentinel).await.unwrap().1);
+        }
+        masters.iter().all(|&elected| elected == masters[0] && (elected == port(&first) || elected == port(&second)))
+    })
+    .await;
+    let promoted = master_addr(&addrs[0]).await.unwrap();
+    let promoted_addr = format!("{}:{}", promoted.0, promoted.1);
+    let other = if promoted_addr == first { &second } else { &first };
+    assert_eq!(info_field(&promoted_addr, "role").await.as_deref(), Some("master"));
+    assert_eq!(field(&addrs[0], "config-epoch").await, "1");
+
+    // The other replica follows it, and keeps the data
+    wait_until(10, "the other replica to follow", || async {
+        info_field(other, "master_port").await == Some(promoted.1.to_string())
+            && info_field(other, "master_link_status").await.as_deref() == Some("up")
+    })
+    .await;
+    let mut new_master = Client::connect(&promoted_addr).await.unwrap();
+    assert_eq!(new_master.get("key").await.unwrap(), Some(b("value")));
+    new_master.set("after", b("value")).await.unwrap();
+    let mut other_client = Client::connect(other).await.unwrap();
+    wait_until(10, "the write to replicate", || async {
+        Client::connect(other).await.unwrap().get("after").await.unwrap().is_some()
+    })
+    .await;
+    assert_eq!(other_client.get("key").await.unwrap(), Some(b("value")));
+
+    // The former master comes back as a replica of the new one
+    let former_port = port(&master);
+    let (former, _former_shutdown) = common::start_server_with("sentinel_failover_former", |config| config.port = former_port).await;
+    wait_until(20, "the former master to be reconfigured", || async {
+        info_field(&former, "master_port").await == Some(promoted.1.to_string())
+    })
+    .await;
+}
@@ -0,0 +1,294 @@
+mod common;
+
+use common::{b, bulks, connect, error, read, request, send, temp_path};
+use mini_redis_tls::{rdb, Aof, Client, Db, Frame};
+use mini_redis_tls::stream::{NewId, StreamId};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// `[id, [field, value, ...]]`, an entry as the server replies with it.
+fn entry(id: &str, fields: &[&str]) -> Frame {
+    Frame::Array(vec![Frame::Bulk(b(id)), bulks(fields)])
+}
+
+#[tokio::test]
+async fn test_xadd_and_ranges() {
+    let (addr, _shutdown) = common::start_server("streams_ranges").await;
+    let mut client = Client::connect(&addr).await.unwrap();
+    let mut connection = connect(&addr).await;
+
+    // Generated IDs keep increasing, even within the same millisecond
+    let first = client.xadd("s", vec![(b("n"), b("1"))]).await.unwrap();
+    let second = client.xadd("s", vec![(b("n"), b("2"))]).await.unwrap();
+    assert!(second > first);
+    assert_eq!(client.xlen("s").await.unwrap(), 2);
+    assert_eq!(client.key_type("s").await.unwrap(), "stream");
+
+    // Explicit IDs must be greater than the last one
+    assert_eq!(request(&mut connection, &["XADD", "t", "5-1", "a", "1"]).await, Frame::Bulk(b("5-1")));
+    assert_eq!(request(&mut connection, &["XADD", "t", "5-*", "a", "2"]).await, Frame::Bulk(b("5-2")));
+    assert_eq!(request(&mut connection, &["XADD", "t", "7", "a", "3"]).await, Frame::Bulk(b("7-0")));
+    assert!(error(request(&mut connection, &["XADD", "t", "6-0", "a", "4"]).await).contains("equal or smaller"));
+    assert!(error(request(&mut connection, &["XADD", "u", "0-0", "a", "4"]).await).contains("greater than 0-0"));
+    assert!(error(request(&mut connection, &["XADD", "u", "x-1", "a", "4"]).await).contains("Invalid stream ID"));
+    assert_eq!(request(&mut connection, &["EXISTS", "u"]).await, Frame::Integer(0));
+    assert_eq!(request(&mut connection, &["XADD", "u", "NOMKSTREAM", "*", "a", "4"]).await, Frame::Null);
+
+    assert_eq!(
+        request(&mut connection, &["XRANGE", "t", "-", "+"]).await,
+        Frame::Array(vec![entry("5-1", &["a", "1"]), entry("5-2", &["a", "2"]), entry("7-0", &["a", "3"])])
+    );
+    assert_eq!(
+        request(&mut connection, &["XRANGE", "t", "5", "5", "COUNT", "1"]).await,
+        Frame::Array(vec![entry("5-1", &["a", "1"])])
+    );
+    assert_eq!(
+        request(&mut connection, &["XREVRANGE", "t", "+", "5-2"]).await,
+        Frame::Array(vec![entry("7-0", &["a", "3"]), entry("5-2", &["a", "2"])])
+    );
+
+    // Trimming drops the oldest entries, but their IDs can't be reused
+    assert_eq!(request(&mut connection, &["XADD", "t", "MAXLEN", "~", "2", "8-0", "a", "5"]).await, Frame::Bulk(b("8-0")));
+    assert_eq!(client.xlen("t").await.unwrap(), 2);
+    let ids: Vec<StreamId> = client.xrange("t", StreamId::MIN, StreamId::MAX).await.unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![StreamId::new(7, 0), StreamId::new(8, 0)]);
+    assert_eq!(request(&mut connection, &["XADD", "t", "MAXLEN", "0", "9-0", "a", "6"]).await, Frame::Bulk(b("9-0")));
+    assert_eq!(client.xlen("t").await.unwrap(), 0);
+    assert!(error(request(&mut connection, &["XADD", "t", "9-0", "a", "7"]).await).contains("equal or smaller"));
+
+    client.set("string", b("x")).await.unwrap();
+    assert!(error(request(&mut connection, &["XADD", "string", "*", "a", "1"]).await).starts_with("WRONGTYPE"));
+    assert!(error(request(&mut connection, &["XADD", "s", "*", "a"]).await).contains("wrong number of arguments"));
+}
+
+#[tokio::test]
+async fn test_xread() {
+    let (addr, _shutdown) = common::start_server("streams_xread").await;
+    let mut connection = connect(&addr).await;
+
+    request(&mut connection, &["XADD", "a", "1-0", "k", "1"]).await;
+    request(&mut connection, &["XADD", "a", "2-0", "k", "2"]).await;
+    request(&mut connection, &["XADD", "b", "1-0", "k", "3"]).await;
+
+    assert_eq!(
+        request(&mut connection, &["XREAD", "COUNT", "1", "STREAMS", "a", "b", "0", "0"]).await,
+        Frame::Array(vec![
+            Frame::Array(vec![Frame::Bulk(b("a")), Frame::Array(vec![entry("1-0", &["k", "1"])])]),
+            Frame::Array(vec![Frame::Bulk(b("b")), Frame::Array(vec![entry("1-0", &["k", "3"])])]),
+        ])
+    );
+    // Streams without new entries are left out, and `$` never has any
+    assert_eq!(
+        request(&mut connection, &["XREAD", "STREAMS", "a", "b", "1-0", "$"]).await,
+        Frame::Array(vec![Frame::Array(vec![Frame::Bulk(b("a")), Frame::Array(vec![entry("2-0", &["k", "2"])])])])
+    );
+    assert_eq!(request(&mut connection, &["XREAD", "STREAMS", "a", "missing", "2-0", "0"]).await, Frame::Null);
+    assert!(error(request(&mut connection, &["XREAD", "STREAMS", "a", "b", "0"]).await).contains("Unbalanced"));
+}
+
+#[tokio::test]
+async fn test_blocking_xread() {
+    let (addr, _shutdown) = common::start_server("streams_blocking").await;
+    let mut reader = connect(&addr).await;
+    let mut other = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    request(&mut reader, &["XADD", "s", "1-0", "old", "1"]).await;
+
+    // `$` only returns entries added after the read was issued
+    send(&mut reader, &["XREAD", "BLOCK", "0", "STREAMS", "s", "$"]).await;
+    send(&mut other, &["XREAD", "BLOCK", "0", "STREAMS", "s", "$"]).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let id = client.xadd("s", vec![(b("new"), b("2"))]).await.unwrap();
+
+    // Every blocked reader gets the entry
+    let expected = Frame::Array(vec![Frame::Array(vec![
+        Frame::Bulk(b("s")),
+        Frame::Array(vec![entry(&id.to_string(), &["new", "2"])]),
+    ])]);
+    assert_eq!(read(&mut reader).await, expected);
+    assert_eq!(read(&mut other).await, expected);
+
+    // A stream created while waiting wakes the reader too
+    send(&mut reader, &["XREAD", "BLOCK", "1000", "STREAMS", "fresh", "0"]).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    client.xadd("fresh", vec![(b("f"), b("v"))]).await.unwrap();
+    assert!(matches!(read(&mut reader).await, Frame::Array(_)));
+
+    let started = tokio::time::Instant::now();
+    assert_eq!(request(&mut reader, &["XREAD", "BLOCK", "100", "STREAMS", "s", "$"]).await, Frame::Null);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_consumer_groups() {
+    let (addr, _shutdown) = common::start_server("streams_groups").await;
+    let mut connection = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    assert!(error(request(&mut connection, &["XGROUP", "CREATE", "s", "g", "$"]).await).contains("MKSTREAM"));
+    assert_eq!(request(&mut connection, &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]).await, Frame::Simple("OK".into()));
+    assert!(error(request(&mut connection, &["XGROUP", "CREATE", "s", "g", "0"]).await).starts_with("BUSYGROUP"));
+    assert_eq!(client.xlen("s").await.unwrap(), 0);
+
+    for i in 1..=3 {
+        request(&mut connection, &["XADD", "s", &format!("{}-0", i), "n", &i.to_string()]).await;
+    }
+
+    // Each entry is delivered to one consumer of the group only
+    assert_eq!(
+        request(&mut connection, &["XREADGROUP", "GROUP", "g", "alice", "COUNT", "2", "STREAMS", "s", ">"]).await,
+        Frame::Array(vec![Frame::Array(vec![
+            Frame::Bulk(b("s")),
+            Frame::Array(vec![entry("1-0", &["n", "1"]), entry("2-0", &["n", "2"])]),
+        ])])
+    );
+    assert_eq!(
+        request(&mut connection, &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]).await,
+        Frame::Array(vec![Frame::Array(vec![Frame::Bulk(b("s")), Frame::Array(vec![entry("3-0", &["n", "3"])])])])
+    );
+    assert_eq!(request(&mut connection, &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]).await, Frame::Null);
+
+    assert_eq!(
+        request(&mut connection, &["XPENDING", "s", "g"]).await,
+        Frame::Array(vec![
+            Frame::Integer(3),
+            Frame::Bulk(b("1-0")),
+            Frame::Bulk(b("3-0")),
+            Frame::Array(vec![bulks(&["alice", "2"]), bulks(&["bob", "1"])]),
+        ])
+    );
+
+    // A consumer's history is its own pending entries
+    assert_eq!(
+        request(&mut connection, &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", "0"]).await,
+        Frame::Array(vec![Frame::Array(vec![
+            Frame::Bulk(b("s")),
+            Frame::Array(vec![entry("1-0", &["n", "1"]), entry("2-0", &["n", "2"])]),
+        ])])
+    );
+
+    assert_eq!(client.xack("s", "g", &[StreamId::new(1, 0), StreamId::new(9, 0)]).await.unwrap(), 1);
+    assert_eq!(client.xack("s", "g", &[StreamId::new(1, 0)]).await.unwrap(), 0);
+
+    // Entries idle for long enough can be claimed by another consumer
+    assert_eq!(request(&mut connection, &["XCLAIM", "s", "g", "bob", "60000", "2-0"]).await, Frame::Array(vec![]));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        request(&mut connection, &["XCLAIM", "s", "g", "bob", "10", "2-0"]).await,
+        Frame::Array(vec![entry("2-0", &["n", "2"])])
+    );
+    let Frame::Array(pending) = request(&mut connection, &["XPENDING", "s", "g", "-", "+", "10", "bob"]).await else {
+        panic!("expected pending entries");
+    };
+    assert_eq!(pending.len(), 2);
+    let Frame::Array(first) = &pending[0] else { panic!("expected a pending entry") };
+    assert_eq!(first[0], Frame::Bulk(b("2-0")));
+    assert_eq!(first[1], Frame::Bulk(b("bob")));
+    assert_eq!(first[3], Frame::Integer(2));
+
+    assert_eq!(
+        request(&mut connection, &["XPENDING", "s", "g", "IDLE", "60000", "-", "+", "10"]).await,
+        Frame::Array(vec![])
+    );
+    assert_eq!(request(&mut connection, &["XGROUP", "DELCONSUMER", "s", "g", "bob"]).await, Frame::Integer(2));
+    assert_eq!(
+        request(&mut connection, &["XPENDING", "s", "g"]).await,
+        Frame::Array(vec![Frame::Integer(0), Frame::Null, Frame::Null, Frame::Null])
+    );
+
+    // Rewinding the group delivers the entries again
+    assert_eq!(request(&mut connection, &["XGROUP", "SETID", "s", "g", "0"]).await, Frame::Simple("OK".into()));
+    let Frame::Array(streams) = request(&mut connection, &["XREADGROUP", "GROUP", "g", "carol", "NOACK", "STREAMS", "s", ">"]).await else {
+        panic!("expected entries");
+    };
+    assert_eq!(streams.len(), 1);
+    assert_eq!(request(&mut connection, &["XPENDING", "s", "g"]).await, Frame::Array(vec![Frame::Integer(0), Frame::Null, Frame::Null, Frame::Null]));
+
+    assert!(error(request(&mut connection, &["XREADGROUP", "GROUP", "nope", "c", "STREAMS", "s", ">"]).await).starts_with("NOGROUP"));
+    assert!(error(request(&mut connection, &["XREADGROUP", "GROUP", "g", "c", "STREAMS", "s", "$"]).await).contains("meaningless"));
+    assert_eq!(request(&mut connection, &["XGROUP", "DESTROY", "s", "g"]).await, Frame::Integer(1));
+    assert_eq!(request(&mut connection, &["XGROUP", "DESTROY", "s", "g"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn test_blocking_xreadgroup() {
+    let (addr, _shutdown) = common::start_server("streams_blocking_group").await;
+    let mut alice = connect(&addr).await;
+    let mut bob = connect(&addr).await;
+    let mut client = Client::connect(&addr).await.unwrap();
+
+    request(&mut alice, &["XGROUP", "CREATE", "jobs", "workers", "$", "MKSTREAM"]).await;
+    send(&mut alice, &["XREADGROUP", "GROUP", "workers", "alice", "BLOCK", "0", "STREAMS", "jobs", ">"]).await;
+    send(&mut bob, &["XREADGROUP", "GROUP", "workers", "bob", "BLOCK", "0", "STREAMS", "jobs", ">"]).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // One consumer takes the entry, the other one keeps waiting for the next
+    let delivery = |id: StreamId, job: &str| {
+        Frame::Array(vec![Frame::Array(vec![Frame::Bulk(b("jobs")), Frame::Array(vec![entry(&id.to_string(), &["job", job])])])])
+    };
+    let first = client.xadd("jobs", vec![(b("job"), b("1"))]).await.unwrap();
+    let (reply, waiting) = tokio::select! {
+        reply = read(&mut alice) => (reply, &mut bob),
+        reply = read(&mut bob) => (reply, &mut alice),
+    };
+    assert_eq!(reply, delivery(first, "1"));
+
+    let second = client.xadd("jobs", vec![(b("job"), b("2"))]).await.unwrap();
+    assert_eq!(read(waiting).await, delivery(second, "2"));
+}
+
+#[tokio::test]
+async fn test_streams_survive_restart() {
+    let name = "streams_restart";
+    let (addr, shutdown) = common::start_server(name).await;
+    let mut connection = connect(&addr).await;
+
+    request(&mut connection, &["XADD", "s", "*", "a", "1"]).await;
+    request(&mut connection, &["XADD", "s", "MAXLEN", "2", "*", "a", "2"]).await;
+    request(&mut connection, &["XADD", "s", "MAXLEN", "2", "*", "a", "3"]).await;
+    request(&mut connection, &["XGROUP", "CREATE", "s", "g", "0"]).await;
+    request(&mut connection, &["XREADGROUP", "GROUP", "g", "alice", "COUNT", "1", "STREAMS", "s", ">"]).await;
+    shutdown.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Generated IDs are logged as they were given out
+    let path = std::env::temp_dir().join(format!("mini-redis-{}-{}.aof", name, std::process::id()));
+    let db = Db::new();
+    Aof::load(&path, &db, false).await.unwrap();
+    let entries = db.xrange("s", StreamId::MIN, StreamId::MAX, None, false).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].1, vec![(b("a"), b("2"))]);
+    let summary = db.xpending_summary("s", "g").unwrap();
+    assert_eq!(summary.count, 1);
+    assert_eq!(summary.consumers, vec![("alice".to_string(), 1)]);
+    assert_eq!(db.xlast_id("s").unwrap(), entries[1].0);
+    let _ = std::fs::remove_file(&path);
+
+    // Rewrites, with and without a snapshot preamble, keep groups and their
+    // pending entries
+    db.xgroup_create("empty", "g", None, true).unwrap();
+    for preamble in [true, false] {
+        let path = temp_path(&format!("{}-{}", name, preamble), "aof");
+        let mut aof = Aof::new(&path).await.unwrap();
+        aof.set_rdb_preamble(preamble);
+        let aof = Arc::new(Mutex::new(aof));
+        Aof::rewrite_in_background(aof, db.clone()).await.unwrap().await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap().starts_with(rdb::MAGIC), preamble);
+
+        let restored = Db::new();
+        Aof::load(&path, &restored, false).await.unwrap();
+        assert_eq!(restored.xrange("s", StreamId::MIN, StreamId::MAX, None, false).unwrap(), entries);
+        assert_eq!(restored.xlast_id("s").unwrap(), db.xlast_id("s").unwrap());
+        assert_eq!(restored.xpending_summary("s", "g").unwrap(), summary);
+        assert_eq!(
+            restored.xpending("s", "g", StreamId::MIN, StreamId::MAX, 10, None, 0).unwrap()[0].3,
+            db.xpending("s", "g", StreamId::MIN, StreamId::MAX, 10, None, 0).unwrap()[0].3
+        );
+        assert_eq!(restored.key_type("empty"), "stream");
+        assert_eq!(restored.xlast_id("empty").unwrap(), StreamId::MIN);
+        assert!(restored.xadd("s", NewId::Explicit(entries[1].0), vec![(b("x"), b("y"))], None, false).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::{b, try_command};
+use mini_redis_tls::Db;
+use mini_redis_tls::db::{now_ms, DbError, SetCondition};
+use bytes::Bytes;
+
+#[test]
+fn test_incr_and_overflow() {
+    let db = Db::new();
+    assert_eq!(db.incr_by("n", 1).unwrap(), 1);
+    assert_eq!(db.incr_by("n", -11).unwrap(), -10);
+    assert_eq!(db.get("n"), Some(b("-10")));
+
+    db.set("max".to_string(), Bytes::from(i64::MAX.to_string()));
+    assert_eq!(db.incr_by("max", 1), Err(DbError::Overflow));
+    assert_eq!(db.get("max"), Some(Bytes::from(i64::MAX.to_string())));
+
+    db.set("text".to_string(), b("abc"));
+    assert_eq!(db.incr_by("text", 1), Err(DbError::NotInteger));
+    db.set("spaced".to_string(), b(" 1"));
+    assert_eq!(db.incr_by("spaced", 1), Err(DbError::NotInteger));
+
+    db.push("list", vec![b("a")], false).unwrap();
+    assert_eq!(db.incr_by("list", 1), Err(DbError::WrongType));
+}
+
+#[test]
+fn test_incr_keeps_ttl() {
+    let db = Db::new();
+    db.set_with_options("n".to_string(), b("5"), Some(now_ms() + 60_000), false, SetCondition::Always);
+    assert_eq!(db.incr_by("n", 2).unwrap(), 7);
+    assert!(matches!(db.ttl("n"), Some(Some(_))));
+}
+
+#[test]
+fn test_incr_by_float() {
+    let db = Db::new();
+    assert_eq!(db.incr_by_float("f", 10.5).unwrap(), b("10.5"));
+    assert_eq!(db.incr_by_float("f", 0.1).unwrap(), b("10.6"));
+    assert_eq!(db.get("f"), Some(b("10.6")));
+
+    // Formatted like Redis, without the error of adding binary floats
+    db.set("k".to_string(), b("10.1"));
+    assert_eq!(db.incr_by_float("k", 0.2).unwrap(), b("10.3"));
+    assert_eq!(db.get("k"), Some(b("10.3")));
+    assert_eq!(db.incr_by_float("k", -10.3).unwrap(), b("0"));
+    assert_eq!(db.incr_by_float("k", -0.5).unwrap(), b("-0.5"));
+    db.set("e".to_string(), b("5.0e3"));
+    assert_eq!(db.incr_by_float("e", 2e-3).unwrap(), b("5000.002"));
+    assert_eq!(db.incr_by_float("e", 1e20).unwrap(), b("100000000000000010000"));
+    assert_eq!(db.incr_by_float("third", 1.0 / 3.0).unwrap(), b("0.3333333333333333"));
+    assert_eq!(db.incr_by_float("third", 1.0).unwrap(), b("1.3333333333333333"));
+
+    db.set("big".to_string(), Bytes::from(f64::MAX.to_string()));
+    assert_eq!(db.incr_by_float("big", f64::MAX), Err(DbError::NotFinite));
+    db.set("text".to_string(), b("abc"));
+    assert_eq!(db.incr_by_float("text", 1.0), Err(DbError::NotFloat));
+
+    // Exponents that underflow are rejected rather than spelled out in
+    // decimal, as Redis does; zero may carry any exponent
+    for tiny in ["1e-9999999999", "1e-9223372036854775808", "1e-100000000"] {
+        db.set("tiny".to_string(), b(tiny));
+        assert_eq!(db.incr_by_float("tiny", 1.0), Err(DbError::NotFloat), "{}", tiny);
+        assert_eq!(db.get("tiny"), Some(b(tiny)));
+    }
+    db.set("zero".to_string(), b("0e-9999999999"));
+    assert_eq!(db.incr_by_float("zero", 1.0).unwrap(), b("1"));
+    db.set("small".to_string(), b("1e-300"));
+    assert_eq!(db.incr_by_float("small", 1.0).unwrap(), b("1"));
+}
+
+#[test]
+fn test_append_strlen_and_ranges() {
+    let db = Db::new();
+    assert_eq!(db.append("s", b"Hello").unwrap(), 5);
+    assert_eq!(db.append("s", b" World").unwrap(), 11);
+    assert_eq!(db.strlen("s").unwrap(), 11);
+    assert_eq!(db.strlen("missing").unwrap(), 0);
+
+    assert_eq!(db.getrange("s", 0, 4).unwrap(), b("Hello"));
+    assert_eq!(db.getrange("s", -5, -1).unwrap(), b("World"));
+    assert_eq!(db.getrange("s", 5, 100).unwrap(), b(" World"));
+    assert_eq!(db.getrange("s", 8, 2).unwrap(), b(""));
+
+    assert_eq!(db.setrange("s", 6, b"Redis").unwrap(), 11);
+    assert_eq!(db.get("s"), Some(b("Hello Redis")));
+    assert_eq!(db.setrange("padded", 3, b"x").unwrap(), 4);
+    assert_eq!(db.get("padded"), Some(Bytes::from(&b"\0\0\0x"[..])));
+
+    // An empty write doesn't create the key
+    assert_eq!(db.setrange("empty", 10, b"").unwrap(), 0);
+    assert_eq!(db.get("empty"), None);
+}
+
+#[test]
+fn test_multi_key_commands() {
+    let db = Db::new();
+    assert!(db.mset(vec![("a".to_string(), b("1")), ("b".to_string(), b("2"))], false));
+    db.push("list", vec![b("x")], false).unwrap();
+    assert_eq!(
+        db.mget(&["a".to_string(), "missing".to_string(), "list".to_string(), "b".to_string()]),
+        vec![Some(b("1")), None, None, Some(b("2"))]
+    );
+
+    // MSETNX is all or nothing
+    assert!(!db.mset(vec![("b".to_string(), b("3")), ("c".to_string(), b("3"))], true));
+    assert_eq!(db.get("c"), None);
+    assert!(db.mset(vec![("c".to_string(), b("3")), ("d".to_string(), b("4"))], true));
+}
+
+#[test]
+fn test_getset_and_getdel() {
+    let db = Db::new();
+    db.set_with_options("k".to_string(), b("old"), Some(now_ms() + 60_000), false, SetCondition::Always);
+    assert_eq!(db.getset("k", b("new")).unwrap(), Some(b("old")));
+    assert_eq!(db.ttl("k"), Some(None));
+
+    assert_eq!(db.getdel("k").unwrap(), Some(b("new")));
+    assert_eq!(db.getdel("k").unwrap(), None);
+
+    db.sadd("set", vec![b("m")]).unwrap();
+    assert_eq!(db.getset("set", b("v")), Err(DbError::WrongType));
+    assert_eq!(db.getdel("set"), Err(DbError::WrongType));
+}
+
+#[test]
+fn test_parse_string_commands() {
+    let frame = |args: &[&str]| try_command(args).unwrap().into_frame();
+    // Every increment is logged as INCRBY
+    assert_eq!(frame(&["DECR", "n"]), frame(&["INCRBY", "n", "-1"]));
+    assert_eq!(frame(&["DECRBY", "n", "5"]), frame(&["INCRBY", "n", "-5"]));
+
+    assert!(try_command(&["DECRBY", "n", &i64::MIN.to_string()]).is_err());
+    assert!(try_command(&["INCRBY", "n", "1.5"]).is_err());
+    assert!(try_command(&["INCRBYFLOAT", "n", "nan"]).is_err());
+    assert!(try_command(&["MSET", "a", "1", "b"]).is_err());
+    assert!(try_command(&["SETRANGE", "k", "-1", "x"]).is_err());
+
+    assert!(try_command(&["MSETNX", "a", "1"]).unwrap().is_write());
+    assert!(!try_command(&["GETRANGE", "a", "0", "-1"]).unwrap().is_write());
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use common::{b, connect, request, simple, start_server};
+use mini_redis_tls::{Aof, Db, Frame};
+
+#[tokio::test]
+async fn test_multi_exec() {
+    let (addr, shutdown) = start_server("multi-exec").await;
+    let mut conn = connect(&addr).await;
+
+    assert_eq!(request(&mut conn, &["MULTI"]).await, simple("OK"));
+    assert!(matches!(request(&mut conn, &["MULTI"]).await, Frame::Error(_)));
+    assert_eq!(request(&mut conn, &["SET", "a", "1"]).await, simple("QUEUED"));
+    assert_eq!(request(&mut conn, &["INCR", "a"]).await, simple("QUEUED"));
+    assert_eq!(request(&mut conn, &["LPUSH", "a", "x"]).await, simple("QUEUED"));
+    assert_eq!(request(&mut conn, &["GET", "a"]).await, simple("QUEUED"));
+
+    // Runtime errors don't stop the rest of the block
+    match request(&mut conn, &["EXEC"]).await {
+        Frame::Array(replies) => {
+            assert_eq!(replies[0], simple("OK"));
+            assert_eq!(replies[1], Frame::Integer(2));
+            assert!(matches!(&replies[2], Frame::Error(e) if e.starts_with("WRONGTYPE")));
+            assert_eq!(replies[3], Frame::Bulk(b("2")));
+        }
+        other => panic!("unexpected reply {:?}", other),
+    }
+
+    assert!(matches!(request(&mut conn, &["EXEC"]).await, Frame::Error(e) if e.contains("without MULTI")));
+    assert!(matches!(request(&mut conn, &["DISCARD"]).await, Frame::Error(e) if e.contains("without MULTI")));
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_discard_and_execabort() {
+    let (addr, shutdown) = start_server("discard").await;
+    let mut conn = connect(&addr).await;
+
+    request(&mut conn, &["MULTI"]).await;
+    request(&mut conn, &["SET", "a", "1"]).await;
+    assert_eq!(request(&mut conn, &["DISCARD"]).await, simple("OK"));
+    assert_eq!(request(&mut conn, &["GET", "a"]).await, Frame::Null);
+
+    // A command that can't be queued aborts the whole block
+    request(&mut conn, &["MULTI"]).await;
+    request(&mut conn, &["SET", "a", "1"]).await;
+    assert!(matches!(request(&mut conn, &["NOSUCHCOMMAND"]).await, Frame::Error(_)));
+    assert!(matches!(request(&mut conn, &["EXEC"]).await, Frame::Error(e) if e.starts_with("EXECABORT")));
+    assert_eq!(request(&mut conn, &["GET", "a"]).await, Frame::Null);
+
+    let _ = shutdown.send(());
+}
+
+#[tokio::test]
+async fn test_watch() {
+    let (addr, shutdown) = start_server("watch").await;
+    let mut conn = connect(&addr).await;
+    let mut other = connect(&addr).await;
+
+    // Another client touching a watched key aborts the transaction
+    request(&mut conn, &["SET", "balance", "10"]).await;
+    assert_eq!(request(&mut conn, &["WATCH", "balance"]).await, simple("OK"));
+    request(&mut conn, &["MULTI"]).await;
+    assert!(matches!(request(&mut conn, &["WATCH", "balance"]).await, Frame::Error(_)));
+    request(&mut conn, &["INCRBY", "balance", "5"]).await;
+    request(&mut other, &["INCRBY", "balance", "100"]).await;
+    assert_eq!(request(&mut conn, &["EXEC"]).await, Frame::Null);
+    assert_eq!(request(&mut conn, &["GET", "balance"]).await, Frame::Bulk(b("110")));
+
+    // EXEC clears the watches, so the next transaction runs
+    request(&mut conn, &["MULTI"]).await;
+    request(&mut conn, &["INCRBY", "balance", "5"]).await;
+    request(&mut other, &["INCRBY", "balance", "100"]).await;
+    assert_eq!(request(&mut conn, &["EXEC"]).await, Frame::Array(vec![Frame::Integer(215)]));
+
+    // Untouched watched keys, and UNWATCH, let EXEC through
+    request(&mut conn, &["WATCH", "missing", "balance"]).await;
+    request(&mut other, &["SET", "unrelated", "1"]).await;
+    request(&mut conn, &["MULTI"]).await;
+    request(&mut conn, &["GET", "balance"]).await;
+    assert_eq!(request(&mut conn, &["EXEC"]).await, Frame::Array(vec![Frame::Bulk(b("215"))]));
+
+    request(&mut conn, &["WATCH", "balance"]).await;
+    request(&mut other, &["DEL", "balance"]).await;
+    assert_eq!(request(&mut conn, &["UNWATCH"]).await, simple("OK"));
+    request(&mut conn, &["MULTI"]).await;
+    request(&mut conn, &["GET", "balance"]).await;
+    assert_eq!(request(&mut conn, &["EXEC"]).await, Frame::Array(vec![Frame::Null]));
+
+    let _ = shutdown.send(());
+}
+
+#[test]
+fn test_watch_versions() {
+    let db = Db::new();
+    let version = db.watch("k");
+    assert_eq!(db.version("k"), Some(version));
+
+    db.set("k".to_string(), b("v"));
+    assert_eq!(db.version("k"), Some(version));
+    db.touch(&["k"]);
+    assert_ne!(db.version("k"), Some(version));
+
+    // Deleting and flushing count as modifications
+    let version = db.version("k");
+    db.del(&["k".to_string()]);
+    assert_ne!(db.version("k"), version);
+    let version = db.version("k");
+    db.flush();
+    assert_ne!(db.version("k"), version);
+
+    db.unwatch(&["k".to_string()]);
+    assert_eq!(db.version("k"), None);
+}
+
+const BLOCK: &[u8] = b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1\r\n2\r\n*1\r\n$4\r\nEXEC\r\n";
+
+#[tokio::test]
+async fn test_aof_replays_blocks_whole() {
+    let path = std::env::temp_dir().join(format!("mini-redis-multi-{}.aof", std::process::id()));
+    std::fs::write(&path, BLOCK).unwrap();
+
+    let db = Db::new();
+    Aof::load(&path, &db, false).await.unwrap();
+    assert_eq!(db.get("a"), Some(b("1")));
+    assert_eq!(db.get("b"), Some(b("2")));
+
+    // A block cut short by a crash is dropped entirely
+    let mut contents = BLOCK.to_vec();
+    contents.extend_from_slice(&BLOCK[..BLOCK.len() - 14]);
+    std::fs::write(&path, &contents).unwrap();
+
+    let db = Db::new();
+    assert!(Aof::load(&path, &db, false).await.is_err());
+    Aof::load(&path, &db, true).await.unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), BLOCK);
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{b, command, seeded_db, temp_path, try_command};
+use mini_redis_tls::{rdb, Aof, Db};
+use mini_redis_tls::db::{DbError, SetCondition, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[test]
+fn test_lists() {
+    let db = Db::new();
+    assert_eq!(db.push("q", vec![b("b"), b("a")], true).unwrap(), 2);
+    assert_eq!(db.push("q", vec![b("c"), b("d")], false).unwrap(), 4);
+    assert_eq!(db.lrange("q", 0, -1).unwrap(), vec![b("a"), b("b"), b("c"), b("d")]);
+    assert_eq!(db.lrange("q", -2, 100).unwrap(), vec![b("c"), b("d")]);
+    assert!(db.lrange("q", 3, 1).unwrap().is_empty());
+
+    assert_eq!(db.pop("q", 1, true).unwrap(), Some(vec![b("a")]));
+    assert_eq!(db.pop("q", 2, false).unwrap(), Some(vec![b("d"), b("c")]));
+    assert_eq!(db.pop("q", 5, true).unwrap(), Some(vec![b("b")]));
+
+    // Empty collections are removed
+    assert_eq!(db.pop("q", 1, true).unwrap(), None);
+    assert!(db.snapshot().is_empty());
+}
+
+#[test]
+fn test_hashes() {
+    let db = Db::new();
+    assert_eq!(db.hset("h", vec![(b("a"), b("1")), (b("b"), b("2"))]).unwrap(), 2);
+    assert_eq!(db.hset("h", vec![(b("a"), b("3"))]).unwrap(), 0);
+    assert_eq!(db.hget("h", b"a").unwrap(), Some(b("3")));
+    assert_eq!(db.hget("h", b"missing").unwrap(), None);
+
+    let mut all = db.hgetall("h").unwrap();
+    all.sort();
+    assert_eq!(all, vec![(b("a"), b("3")), (b("b"), b("2"))]);
+
+    assert_eq!(db.hdel("h", &[b("a"), b("missing")]).unwrap(), 1);
+    assert_eq!(db.hdel("h", &[b("b")]).unwrap(), 1);
+    assert!(db.snapshot().is_empty());
+}
+
+#[test]
+fn test_sets() {
+    let db = Db::new();
+    assert_eq!(db.sadd("s", vec![b("x"), b("y"), b("x")]).unwrap(), 2);
+    assert!(db.sismember("s", b"x").unwrap());
+    assert!(!db.sismember("s", b"z").unwrap());
+
+    let mut members = db.smembers("s").unwrap();
+    members.sort();
+    assert_eq!(members, vec![b("x"), b("y")]);
+
+    assert_eq!(db.srem("s", &[b("x"), b("y"), b("z")]).unwrap(), 2);
+    assert!(db.smembers("s").unwrap().is_empty());
+}
+
+#[test]
+fn test_sorted_sets() {
+    let db = Db::new();
+    let added = db.zadd("z", vec![(3.0, b("c")), (1.0, b("a")), (2.0, b("b"))], SetCondition::Always, false);
+    assert_eq!(added.unwrap(), 3);
+
+    assert_eq!(db.zrange("z", 0, -1).unwrap(), vec![(b("a"), 1.0), (b("b"), 2.0), (b("c"), 3.0)]);
+    assert_eq!(db.zrank("z", b"c").unwrap(), Some(2));
+    assert_eq!(db.zscore("z", b"b").unwrap(), Some(2.0));
+    assert_eq!(db.zscore("z", b"missing").unwrap(), None);
+
+    // Updating a score moves the member; CH counts the update
+    assert_eq!(db.zadd("z", vec![(0.5, b("c"))], SetCondition::Always, true).unwrap(), 1);
+    assert_eq!(db.zrank("z", b"c").unwrap(), Some(0));
+
+    // NX never updates, XX never adds
+    assert_eq!(db.zadd("z", vec![(9.0, b("a")), (4.0, b("d"))], SetCondition::IfNotExists, false).unwrap(), 1);
+    assert_eq!(db.zscore("z", b"a").unwrap(), Some(1.0));
+    assert_eq!(db.zadd("z", vec![(9.0, b("e"))], SetCondition::IfExists, false).unwrap(), 0);
+    assert_eq!(db.zscore("z", b"e").unwrap(), None);
+    assert_eq!(db.zadd("other", vec![(1.0, b("a"))], SetCondition::IfExists, false).unwrap(), 0);
+    assert_eq!(db.snapshot().len(), 1);
+}
+
+#[test]
+fn test_wrong_type() {
+    let db = Db::new();
+    db.set("str".to_string(), b("v"));
+    db.push("list", vec![b("a")], false).unwrap();
+
+    assert_eq!(db.push("str", vec![b("a")], true), Err(DbError::WrongType));
+    assert_eq!(db.hget("list", b"a"), Err(DbError::WrongType));
+    assert_eq!(db.sadd("list", vec![b("a")]), Err(DbError::WrongType));
+    assert_eq!(db.zscore("str", b"a"), Err(DbError::WrongType));
+    assert_eq!(db.get_string("list"), Err(DbError::WrongType));
+    assert!(DbError::WrongType.to_string().starts_with("WRONGTYPE "));
+
+    // A failed command leaves the key untouched
+    assert_eq!(db.get("str"), Some(b("v")));
+}
+
+#[test]
+fn test_parse_commands() {
+    assert_eq!(command(&["RPUSH", "q", "a", "b"]).into_frame(), command(&["rpush", "q", "a", "b"]).into_frame());
+    assert!(command(&["LPOP", "q", "2"]).is_write());
+    assert!(!command(&["LRANGE", "q", "0", "-1"]).is_write());
+    assert!(command(&["ZADD", "z", "NX", "CH", "1.5", "a", "-inf", "b"]).is_write());
+    assert_eq!(command(&["ZRANGE", "z", "0", "-1", "WITHSCORES"]).name(), "zrange");
+
+    assert!(try_command(&["HSET", "h", "field"]).is_err());
+    assert!(try_command(&["ZADD", "z", "nan", "a"]).is_err());
+    assert!(try_command(&["ZADD", "z", "NX"]).is_err());
+    let err = try_command(&["ZADD", "z", "NX", "XX", "1", "m"]).unwrap_err();
+    assert!(matches!(err, mini_redis_tls::Error::Other(msg) if msg == "XX and NX options at the same time are not compatible"));
+    assert!(try_command(&["SADD", "s"]).is_err());
+}
+
+fn sorted(mut entries: Vec<(String, mini_redis_tls::db::Entry)>) -> Vec<(String, mini_redis_tls::db::Entry)> {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let entries = seeded_db().snapshot();
+    let mut encoded = Vec::new();
+    rdb::encode(&mut encoded, &entries).unwrap();
+
+    let mut decoded = Vec::new();
+    rdb::decode(&encoded[..], |k, v| decoded.push((k, v))).unwrap();
+    assert_eq!(sorted(decoded), sorted(entries));
+}
+
+#[tokio::test]
+async fn test_aof_rewrite_without_preamble() {
+    let path = temp_path("types", "aof");
+    let db = seeded_db();
+    let mut aof = Aof::new(&path).await.unwrap();
+    aof.set_rdb_preamble(false);
+    let aof = Arc::new(Mutex::new(aof));
+    Aof::rewrite_in_background(aof.clone(), db.clone()).await.unwrap().await.unwrap();
+
+    let restored = Db::new();
+    Aof::load(&path, &restored, false).await.unwrap();
+    assert_eq!(sorted(restored.snapshot()), sorted(db.snapshot()));
+    assert!(matches!(restored.snapshot().iter().find(|(k, _)| k == "zset").unwrap().1.value, Value::ZSet(_)));
+
+    let _ = std::fs::remove_file(&path);
+}